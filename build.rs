@@ -0,0 +1,14 @@
+fn main() {
+    // The gRPC service of the `serve` subcommand, see proto/temperature_extractor.proto.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/temperature_extractor.proto");
+        let protoc =
+            protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this host");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/temperature_extractor.proto"], &["proto"])
+            .unwrap_or_else(|e| panic!("Couldn't compile the gRPC service: {}", e));
+    }
+}
@@ -0,0 +1,33 @@
+/* C bindings to temperature-extractor, built with
+ *   cargo rustc --release --lib --features ffi --crate-type cdylib
+ * which leaves libtemperature_extractor.so (or .dylib, or .dll) in target/release.
+ */
+#ifndef TEMPERATURE_EXTRACTOR_H
+#define TEMPERATURE_EXTRACTOR_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Dataset Dataset;
+
+/* Why the last call on this thread failed. Valid until the next failing call on this thread. */
+const char *temperature_extractor_last_error(void);
+
+/* Loads the dataset at path, which can stand for several files like on the command line.
+ * NULL on failure. */
+Dataset *temperature_dataset_open(const char *path);
+
+void temperature_dataset_free(Dataset *dataset);
+
+/* Writes the average temperature in celsius of the cell containing lon, lat to average,
+ * from start_year up to and including end_year, either 0 for no limit.
+ * Returns 0 on success, -1 on failure, including for lon outside -180..180 or lat outside -90..90. */
+int temperature_dataset_average(const Dataset *dataset, float lon, float lat, int start_year,
+                                int end_year, float *average);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,235 @@
+//! The Rust API, for analyses the command line doesn't do: load a dataset once, then work with
+//! each cell's monthly series directly.
+
+use crate::calendar::MonthStamp;
+use crate::coordinates::{Latitude, Longitude};
+use crate::resample::{self, Coverage};
+use crate::{BoundingBox, LonLatCell, Series, TemperatureDataset, TemperatureDatasetReadErr};
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// A loaded temperature dataset.
+pub struct Dataset(TemperatureDataset, Coverage);
+
+/// Why a dataset couldn't be loaded.
+#[derive(Debug)]
+pub struct OpenErr(TemperatureDatasetReadErr);
+
+impl Display for OpenErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.0.fmt(fmt)
+    }
+}
+
+impl Error for OpenErr {}
+
+impl Dataset {
+    /// `path` can stand for several files like on the command line, see `multifile::dataset_files`.
+    pub fn open(path: &Path) -> Result<Self, OpenErr> {
+        Self::open_with(path, Coverage::default())
+    }
+
+    /// Like `open`, with daily datasets' months needing only `coverage.days` of their days,
+    /// and `CellSeries::annual` years only `coverage.months` of their months.
+    pub fn open_with(path: &Path, coverage: Coverage) -> Result<Self, OpenErr> {
+        TemperatureDataset::open_with(path, false, coverage, Default::default(), None)
+            .map(|dataset| Dataset(dataset, coverage))
+            .map_err(OpenErr)
+    }
+
+    /// The series of the cell containing `lon`, `lat`. Empty where the dataset has no cell,
+    /// e.g. over the oceans for CRU TS.
+    pub fn series_at(&self, lon: Longitude, lat: Latitude) -> CellSeries {
+        let cell = LonLatCell::at(lon, lat);
+        CellSeries::new(cell, self.0.temperature_series_at(cell), self.1)
+    }
+
+    /// Every time step, in time order.
+    pub fn times(&self) -> impl Iterator<Item = MonthStamp> {
+        self.0.times().into_iter()
+    }
+
+    /// Goes through every cell, or those picked with `Traversal`'s combinators.
+    pub fn traverse(&self) -> Traversal<'_> {
+        Traversal {
+            dataset: self,
+            bounding_box: None,
+            years: None,
+            land_only: false,
+        }
+    }
+}
+
+/// Which cells, and which years of their series, to go through, e.g.
+/// `dataset.traverse().within(alps).years(1991..=2020).land_only().series()`.
+#[derive(Clone)]
+pub struct Traversal<'a> {
+    dataset: &'a Dataset,
+    bounding_box: Option<BoundingBox>,
+    years: Option<RangeInclusive<i32>>,
+    land_only: bool,
+}
+
+impl<'a> Traversal<'a> {
+    /// Only cells centered within `bounding_box`.
+    pub fn within(self, bounding_box: BoundingBox) -> Self {
+        Self {
+            bounding_box: Some(bounding_box),
+            ..self
+        }
+    }
+
+    /// Only these years of each series.
+    pub fn years(self, years: RangeInclusive<i32>) -> Self {
+        Self {
+            years: Some(years),
+            ..self
+        }
+    }
+
+    /// Only cells with at least one value in the years, which in CRU TS means land.
+    pub fn land_only(self) -> Self {
+        Self {
+            land_only: true,
+            ..self
+        }
+    }
+
+    /// The centers of the cells as (longitude, latitude), in the order they appear in the dataset.
+    /// Without `land_only`, no series are read.
+    pub fn cells(self) -> impl Iterator<Item = (f32, f32)> + 'a {
+        let cells: Box<dyn Iterator<Item = (f32, f32)>> = if self.land_only {
+            Box::new(self.series().map(|series| (series.lon, series.lat)))
+        } else {
+            Box::new(
+                self.dataset
+                    .0
+                    .cells()
+                    .into_iter()
+                    .map(|cell| cell.center())
+                    .filter(move |&(lon, lat)| {
+                        self.bounding_box
+                            .is_none_or(|bounding_box| bounding_box.contains(lon, lat))
+                    }),
+            )
+        };
+        cells
+    }
+
+    /// Each cell's series, in the order the cells appear in the dataset.
+    /// Only the cells within the bounding box are read, one at a time as they're asked for
+    /// when the dataset was left on disk.
+    pub fn series(self) -> impl Iterator<Item = CellSeries> + 'a {
+        let bounding_box = self.bounding_box;
+        let years = self.years;
+        let land_only = self.land_only;
+        let coverage = self.dataset.1;
+        self.dataset
+            .0
+            .series_of_cells_where(
+                move |cell| {
+                    let (lon, lat) = cell.center();
+                    bounding_box.is_none_or(|bounding_box| bounding_box.contains(lon, lat))
+                },
+                move |time| {
+                    years
+                        .as_ref()
+                        .is_none_or(|years| years.contains(&time.year()))
+                },
+            )
+            .filter(move |(_, series)| {
+                !land_only || series.iter().any(|(_, temperature)| temperature.is_some())
+            })
+            .map(move |(cell, series)| CellSeries::new(cell, series, coverage))
+    }
+}
+
+/// One month of a cell's series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Monthly {
+    pub time: MonthStamp,
+    /// None where the dataset has no value.
+    pub celsius: Option<f32>,
+}
+
+/// A grid cell's temperatures, month by month in time order.
+#[derive(Debug, Clone)]
+pub struct CellSeries {
+    /// Longitude of the middle of the cell.
+    pub lon: f32,
+    /// Latitude of the middle of the cell.
+    pub lat: f32,
+    months: Vec<Monthly>,
+    coverage: Coverage,
+}
+
+impl CellSeries {
+    fn new(cell: LonLatCell, series: Series, coverage: Coverage) -> Self {
+        let (lon, lat) = cell.center();
+        Self {
+            lon,
+            lat,
+            months: series
+                .into_iter()
+                .map(|(time, temperature)| Monthly {
+                    time,
+                    celsius: temperature.map(|t| t.celsius),
+                })
+                .collect(),
+            coverage,
+        }
+    }
+
+    pub fn monthly(&self) -> &[Monthly] {
+        &self.months
+    }
+
+    /// The mean of each year, None for years with fewer months than the dataset's `Coverage`
+    /// asks for, by default any month missing.
+    pub fn annual(&self) -> Vec<(i32, Option<f32>)> {
+        resample::monthly_to_annual(
+            self.months.iter().map(|month| (month.time, month.celsius)),
+            self.coverage,
+        )
+    }
+
+    /// The mean of each calendar month, January first, over every year with a value for it.
+    /// None for months never recorded.
+    pub fn climatology(&self) -> [Option<f32>; 12] {
+        self.climatology_over(|_| true)
+    }
+
+    /// Each month's difference from its calendar month's mean over the `baseline` years,
+    /// None where either is missing.
+    pub fn anomalies(&self, baseline: RangeInclusive<i32>) -> Vec<Monthly> {
+        let climatology = self.climatology_over(|year| baseline.contains(&year));
+        self.months
+            .iter()
+            .map(|month| Monthly {
+                celsius: month
+                    .celsius
+                    .zip(climatology[month.time.month() as usize - 1])
+                    .map(|(celsius, normal)| celsius - normal),
+                ..*month
+            })
+            .collect()
+    }
+
+    fn climatology_over(&self, include: impl Fn(i32) -> bool) -> [Option<f32>; 12] {
+        let mut sums = [(0.0, 0); 12];
+        for month in self
+            .months
+            .iter()
+            .filter(|month| include(month.time.year()))
+        {
+            if let Some(celsius) = month.celsius {
+                let (sum, count) = &mut sums[month.time.month() as usize - 1];
+                *sum += celsius;
+                *count += 1;
+            }
+        }
+        sums.map(|(sum, count)| (count > 0).then(|| sum / count as f32))
+    }
+}
@@ -0,0 +1,133 @@
+//! Datasets' time steps, as the month of the Gregorian calendar they fall in.
+
+use std::fmt::{self, Display};
+
+/// A month of a particular year. Orders chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MonthStamp {
+    // In this order for the derived ordering, and small, since datasets hold one per value.
+    year: i16,
+    /// 1 to 12.
+    month: u8,
+}
+
+/// Meteorological seasons, three whole months each, named as in the northern hemisphere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Season {
+    /// December to February. December belongs to the following winter.
+    Winter,
+    /// March to May.
+    Spring,
+    /// June to August.
+    Summer,
+    /// September to November.
+    Autumn,
+}
+
+/// The year, month and day of a number of days since 1970-1-1,
+/// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Shift the epoch to 0000-03-01 so leap days fall at the end of each year.
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let march_based_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * march_based_month + 2) / 5 + 1;
+    let year = year_of_era + era * 400 + if march_based_month >= 10 { 1 } else { 0 };
+    let month = if march_based_month < 10 {
+        march_based_month + 3
+    } else {
+        march_based_month - 9
+    };
+    (year, month as u32, day as u32)
+}
+
+impl MonthStamp {
+    /// None for months outside 1 to 12, or years outside what a time step can hold.
+    pub fn new(year: i32, month: u32) -> Option<Self> {
+        Some(Self {
+            year: year.try_into().ok()?,
+            month: (1..=12).contains(&month).then_some(month as u8)?,
+        })
+    }
+
+    /// The month a time coordinate in "days since 1900-1-1" falls in, as CRU TS has them.
+    pub fn from_days_since_1900(days: f32) -> Self {
+        let (year, month, _) = civil_from_days(days as i64 - 25567);
+        Self {
+            year: year as i16,
+            month: month as u8,
+        }
+    }
+
+    /// The month's first day in days since 1900-1-1, the inverse of `from_days_since_1900`,
+    /// see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    pub fn days_since_1900(self) -> f32 {
+        let month = i64::from(self.month);
+        let year = i64::from(self.year) - i64::from(month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let march_based_month = (month + 9) % 12;
+        let day_of_year = (153 * march_based_month + 2) / 5;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        (era * 146097 + day_of_era - 693901) as f32
+    }
+
+    pub fn year(self) -> i32 {
+        self.year.into()
+    }
+
+    /// 1 to 12.
+    pub fn month(self) -> u32 {
+        self.month.into()
+    }
+
+    /// How many days the month has, 29 for February in leap years.
+    pub fn days(self) -> u32 {
+        match self.month {
+            2 if self.is_leap_year() => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    fn is_leap_year(self) -> bool {
+        let year = self.year();
+        year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+    }
+
+    pub fn season(self) -> Season {
+        match self.month {
+            12 | 1 | 2 => Season::Winter,
+            3..=5 => Season::Spring,
+            6..=8 => Season::Summer,
+            _ => Season::Autumn,
+        }
+    }
+
+    /// The year whose season this is, the next one for December's winter.
+    pub fn season_year(self) -> i32 {
+        self.year() + i32::from(self.month == 12)
+    }
+}
+
+impl Display for MonthStamp {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(fmt, "{}-{:02}", self.year, self.month)
+    }
+}
+
+impl Display for Season {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        fmt.write_str(match self {
+            Season::Winter => "winter",
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+        })
+    }
+}
@@ -0,0 +1,24 @@
+//! Stopping a long operation from elsewhere, e.g. a GUI's cancel button or the command line's
+//! Ctrl-C: whatever runs checks the token between steps (dataset time steps, geocoder searches,
+//! cities), stops at the next one once it's cancelled and keeps what was done so far.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Clones share the same state, so one can be kept to cancel what another was given to.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,3667 @@
+//! The command line tool, its subcommands, and everything they need beyond the dataset itself.
+
+mod aliases;
+mod arrow;
+#[cfg(feature = "grpc")]
+mod auth;
+mod bands;
+mod bbox;
+mod bench;
+pub mod builder;
+#[cfg(feature = "grpc")]
+mod cache;
+mod coast;
+mod contouring;
+mod countries;
+mod crs;
+mod database;
+mod daylight;
+mod diff;
+mod downscale;
+mod elevation;
+mod encryption;
+mod find;
+#[cfg(feature = "grpc")]
+mod flight;
+mod global;
+pub mod hooks;
+mod init;
+mod input;
+mod interrupt;
+mod locate;
+mod metadata;
+#[cfg(feature = "grpc")]
+mod metrics;
+mod ncwrite;
+mod nominatim;
+pub(crate) mod opendap;
+mod package;
+mod points;
+mod presets;
+mod quality;
+mod reports;
+mod rpc;
+mod rules;
+mod schema;
+#[cfg(feature = "grpc")]
+mod serve;
+mod shards;
+mod sinks;
+mod site;
+pub mod sources;
+mod split;
+#[cfg(feature = "sql")]
+mod sql;
+mod stations;
+mod subset;
+mod update;
+mod validate;
+mod versions;
+mod wikidata;
+
+use crate::config::{Config, Geocoder, Units};
+use crate::coordinates::{self, Latitude, Longitude};
+use crate::resample::Coverage;
+use crate::stats::{self, Statistic};
+use crate::{
+    geo, hexagons, ondisk, AverageErr, CancellationToken, CitySettings, DatasetKind, LonLatCell,
+    MissingValues, Neighborhood, Period, Temperature, TemperatureDataset,
+    TemperatureDatasetReadErr, TemperatureDelta,
+};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use hooks::{Hooks, TerminalHooks};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Where a city's temperature came from, to help track down suspicious values.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct CellDiagnostics {
+    city: String,
+    country: String,
+    /// Indices of the city's cell along the dataset's lon and lat dimensions, empty when the
+    /// dataset doesn't have it, e.g. a subset elsewhere.
+    lon_index: Option<usize>,
+    lat_index: Option<usize>,
+    west: f32,
+    south: f32,
+    east: f32,
+    north: f32,
+    /// In km.
+    distance_from_cell_center: f32,
+    /// More than one when averaging over a neighborhood or hexagon. Cells of a neighborhood
+    /// without an average over the period, like the oceans, aren't counted.
+    cells_averaged: usize,
+    distance_weighted: bool,
+    hexagon: Option<String>,
+}
+
+impl CellDiagnostics {
+    fn new(
+        city: &City,
+        (longitude, latitude): (Longitude, Latitude),
+        settings: CitySettings,
+        dataset: &TemperatureDataset,
+    ) -> Self {
+        let (lon, lat) = (longitude.degrees(), latitude.degrees());
+        let cell = LonLatCell::containing(lon, lat);
+        let hexagon = settings
+            .h3_resolution
+            .map(|resolution| hexagons::hexagon_at(lon, lat, resolution));
+        let (west, south) = (
+            cell.half_degrees_lon_start as f32 * 0.5,
+            cell.half_degrees_lat_start as f32 * 0.5,
+        );
+        let (lon_index, lat_index) = dataset.grid_index(cell).unzip();
+        Self {
+            city: city.city.clone(),
+            country: city.country.clone(),
+            lon_index,
+            lat_index,
+            west,
+            south,
+            east: west + 0.5,
+            north: south + 0.5,
+            distance_from_cell_center: geo::distance::haversine_km((lon, lat), cell.center()),
+            cells_averaged: match hexagon {
+                Some(hexagon) => hexagons::cells_in(hexagon).len(),
+                None => dataset
+                    .averages_around(
+                        longitude,
+                        latitude,
+                        settings.neighborhood,
+                        settings.period,
+                        settings.statistic,
+                    )
+                    .map_or(0, |averages| averages.len()),
+            },
+            distance_weighted: settings.neighborhood.distance_weighted,
+            hexagon: hexagon.map(|hexagon| hexagon.to_string()),
+        }
+    }
+}
+
+/// Everything --strict refused to guess about, as printed on failure.
+#[derive(Debug, Serialize)]
+struct StrictReport {
+    problems: Vec<StrictProblem>,
+}
+
+#[derive(Debug, Serialize)]
+struct StrictProblem {
+    city: String,
+    country: String,
+    problem: String,
+}
+
+impl StrictProblem {
+    fn new(city: &City, problem: String) -> Self {
+        Self {
+            city: city.city.clone(),
+            country: city.country.clone(),
+            problem,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct City {
+    city: String,
+    /// State, province or other subdivision of the country, to tell same-named cities apart.
+    #[serde(alias = "State", alias = "Province")]
+    region: Option<String>,
+    country: String,
+    wikidata_entity_id: Option<String>,
+    wikidata_longitude: Option<f32>,
+    wikidata_latitude: Option<f32>,
+    average_temperature: Option<f32>,
+    /// One standard deviation of the average temperature, only computed with --uncertainty.
+    average_temperature_uncertainty: Option<f32>,
+    /// Mean number of stations behind each month of the city's cell over the period,
+    /// only read with --stations.
+    average_stations: Option<f32>,
+    /// Share of the cell's months over the period without a single station, filled in from
+    /// the climatology instead.
+    months_without_stations: Option<f32>,
+    /// Area-weighted mean of the city's country over the period, only computed with
+    /// --country-boundaries.
+    country_average_temperature: Option<f32>,
+    /// The city's average temperature minus its country's, positive for cities warmer than
+    /// their country.
+    difference_from_country: Option<f32>,
+    /// The average temperature and its uncertainty over each of --normals, in order, written
+    /// as column groups of their own after the others.
+    #[serde(skip)]
+    normals: Vec<(Option<f32>, Option<f32>)>,
+    /// IANA time zone name, only looked up with --timezones.
+    timezone: Option<String>,
+    /// In hours.
+    shortest_day: Option<f32>,
+    longest_day: Option<f32>,
+    /// In km, only computed with --coastline.
+    coast_distance: Option<f32>,
+    /// In meters, passed through when given and otherwise read from --elevation at the city.
+    elevation: Option<f32>,
+    /// The mean of --elevation over the city's cell, the elevation its temperature is for.
+    cell_elevation: Option<f32>,
+    /// How much warmer the city's elevation is than its cell's, at the standard atmosphere's
+    /// lapse rate, for judging whether the cell's temperature needs correcting.
+    elevation_correction: Option<f32>,
+    /// Not used for anything but the index command and --urban-heat-island, just passed through.
+    population: Option<u64>,
+    /// What --urban-heat-island added to the city's temperatures.
+    urban_heat_island: Option<f32>,
+    /// Curated coordinates, which win over everything else and are never looked up.
+    #[serde(alias = "override_longitude")]
+    override_longitude: Option<f32>,
+    #[serde(alias = "override_latitude")]
+    override_latitude: Option<f32>,
+    coordinate_source: Option<CoordinateSource>,
+}
+
+/// Where a city's coordinates came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CoordinateSource {
+    /// The override columns.
+    Override,
+    /// The input, as is.
+    Given,
+    /// An earlier run's geocoding, from --database.
+    Database,
+    /// Looked up by name in --gazetteer, with --offline.
+    Gazetteer,
+    Wikidata,
+    Nominatim,
+}
+
+impl City {
+    /// The output's columns, as serde names them.
+    fn header() -> csv::StringRecord {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer
+            .serialize(City::default())
+            .expect("Couldn't write city header");
+        let row = writer.into_inner().expect("Couldn't write city header");
+        csv::Reader::from_reader(&row[..])
+            .headers()
+            .expect("Couldn't read city header back")
+            .clone()
+    }
+
+    /// Geocoders search for `name` rather than the city's own, when given, e.g. from `--aliases`,
+    /// and ask `hooks` which result it is when several could be, until `cancel` is cancelled.
+    fn fill_or_update_geo_information(
+        &mut self,
+        geocoder: Geocoder,
+        name: Option<&str>,
+        hooks: &mut dyn Hooks,
+        cancel: &CancellationToken,
+    ) -> Result<(f32, f32), wikidata::GeocodeErr> {
+        let name = name.unwrap_or(&self.city).to_string();
+        if let Some(lon_lat) = self.override_lon_lat() {
+            return Ok(self.use_fixed_lon_lat(lon_lat));
+        }
+        let given_lon_lat = self.wikidata_longitude.zip(self.wikidata_latitude);
+        // Cached from an earlier run, no need to look them up again.
+        if let (Some(lon_lat), Some(CoordinateSource::Database)) =
+            (given_lon_lat, self.coordinate_source)
+        {
+            return Ok(self.use_fixed_lon_lat(lon_lat));
+        }
+        let (lon, lat) = match (&self.wikidata_entity_id, given_lon_lat, geocoder) {
+            (Some(entity_id), _, _) => {
+                self.coordinate_source = Some(CoordinateSource::Wikidata);
+                wikidata::acquire_lon_lat(entity_id)?
+            }
+            (None, Some(lon_lat), _) => return Ok(self.use_fixed_lon_lat(lon_lat)),
+            (None, None, Geocoder::Wikidata) => {
+                let id = wikidata::find_entity_id(
+                    &name,
+                    self.region.as_deref(),
+                    &self.country,
+                    hooks,
+                    cancel,
+                )?;
+                self.coordinate_source = Some(CoordinateSource::Wikidata);
+                wikidata::acquire_lon_lat(self.wikidata_entity_id.insert(id))?
+            }
+            (None, None, Geocoder::Nominatim) => {
+                let (id, lon, lat) = nominatim::find_place(
+                    &name,
+                    self.region.as_deref(),
+                    &self.country,
+                    hooks,
+                    cancel,
+                )?;
+                self.wikidata_entity_id = id;
+                self.coordinate_source = Some(CoordinateSource::Nominatim);
+                (lon, lat)
+            }
+        };
+        self.wikidata_longitude = Some(lon);
+        self.wikidata_latitude = Some(lat);
+
+        Ok((lon, lat))
+    }
+
+    fn override_lon_lat(&self) -> Option<(f32, f32)> {
+        self.override_longitude.zip(self.override_latitude)
+    }
+
+    /// Use the override or given coordinates, without looking anything up.
+    /// Given coordinates keep the source they were recorded with, if any.
+    fn use_fixed_lon_lat(&mut self, (lon, lat): (f32, f32)) -> (f32, f32) {
+        self.coordinate_source = if self.override_lon_lat().is_some() {
+            Some(CoordinateSource::Override)
+        } else {
+            self.coordinate_source.or(Some(CoordinateSource::Given))
+        };
+        self.wikidata_longitude = Some(lon);
+        self.wikidata_latitude = Some(lat);
+        (lon, lat)
+    }
+
+    /// Whether this is a finished row for the `input` city:
+    /// any pre-filled entity id must agree, and the temperature must have been computed.
+    fn completes(&self, input: &City) -> bool {
+        self.city == input.city
+            && self.region == input.region
+            && self.country == input.country
+            && (input.wikidata_entity_id.is_none()
+                || input.wikidata_entity_id == self.wikidata_entity_id)
+            && input.override_lon_lat().is_none_or(|lon_lat| {
+                Some(lon_lat) == self.wikidata_longitude.zip(self.wikidata_latitude)
+            })
+            && self.wikidata_longitude.is_some()
+            && self.wikidata_latitude.is_some()
+            && self.average_temperature.is_some()
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    override_usage = "temperature-extractor [OPTIONS] [TEMPERATURE_DATASET] <CITIES> <OUTPUT>\n       \
+                      temperature-extractor [OPTIONS] --cities-preset <PRESET> [TEMPERATURE_DATASET] <OUTPUT>\n       \
+                      temperature-extractor [OPTIONS] --continue-from-review [TEMPERATURE_DATASET] <OUTPUT>\n       \
+                      temperature-extractor [OPTIONS] --rpc\n       \
+                      temperature-extractor <COMMAND>"
+)]
+struct Args {
+    /// Where to read defaults from.
+    /// Defaults to config.toml in the platform's config directory, e.g.
+    /// ~/.config/temperature-extractor (or under $XDG_CONFIG_HOME) on Linux.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Instead of processing a cities file, answer JSON-RPC 2.0 requests, one per line on stdin,
+    /// with the dataset kept loaded in between. See src/cli/rpc.rs for the methods.
+    #[arg(long, conflicts_with = "paths")]
+    rpc: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compare two output files, reporting added/removed cities and changed values.
+    Diff(DiffArgs),
+    /// Check whether an output file can be reproduced from its recorded inputs.
+    Verify(VerifyArgs),
+    /// Combine the outputs of --shard runs into one, failing on cities they disagree about.
+    MergeShards(MergeShardsArgs),
+    /// Compute the population weighted mean temperature of each country from an output file.
+    Index(IndexArgs),
+    /// Compute the area weighted mean temperature over each country's grid cells.
+    CountryAverages(CountryAveragesArgs),
+    /// Print a shell completion script.
+    Completions(CompletionsArgs),
+    /// Print a man page in roff format.
+    Man,
+    /// Interactively set up the config file.
+    Init,
+    /// Write a cities file listing every city on Wikidata above a population threshold.
+    ListCities(ListCitiesArgs),
+    /// Manage the cities kept in a project database, see --database.
+    Db(DbArgs),
+    /// List every grid cell whose average temperature falls within a range.
+    Find(FindArgs),
+    /// Extract lines of equal average temperature as GeoJSON.
+    Isolines(IsolinesArgs),
+    /// Write the average temperature of every H3 hexagon at a resolution as CSV.
+    ExportH3(ExportH3Args),
+    /// Write the average temperature of every grid cell as CF-compliant NetCDF.
+    ExportGrid(ExportGridArgs),
+    /// Write a bounding box and/or period of the temperature dataset as a smaller classic NetCDF file.
+    Subset(SubsetArgs),
+    /// Add the months of an update, e.g. CRU's interim releases, after the last one of a dataset
+    /// directory read as `DIRECTORY/*.nc`.
+    UpdateDataset(UpdateDatasetArgs),
+    /// Write statistics of every grid cell within a rectangle as CSV or NetCDF.
+    Bbox(BboxArgs),
+    /// Time loading, indexing, city lookups and statistics on a synthetic workload, as JSON.
+    Bench(BenchArgs),
+    /// Compute the area weighted mean temperature of every cell with data and its trend, as JSON.
+    GlobalStats(GlobalStatsArgs),
+    /// Compute the mean temperature of each band of latitude, over every cell with data or an output's cities.
+    LatitudeBands(LatitudeBandsArgs),
+    /// Summarize how much of the dataset is missing by time step and by region, as JSON.
+    Quality(QualityArgs),
+    /// Compare computed temperatures of ~130 reference cities against their WMO climate normals, as JSON.
+    Validate(ValidateArgs),
+    /// Copy an output file into a directory with a datapackage.json or CSVW descriptor of its columns.
+    ExportPackage(ExportPackageArgs),
+    /// Render a browsable static HTML site of an output file's cities, with a world map.
+    Site(SiteArgs),
+    /// Answer point and city temperature queries over gRPC, see proto/temperature_extractor.proto.
+    #[cfg(feature = "grpc")]
+    Serve(ServeArgs),
+    /// Run a SQL query over an output file with an embedded DuckDB, writing the result as CSV.
+    #[cfg(feature = "sql")]
+    Sql(SqlArgs),
+}
+
+#[derive(clap::Args)]
+struct DiffArgs {
+    /// The output file from before the change.
+    old: PathBuf,
+    /// The output file from after the change.
+    new: PathBuf,
+    /// Numeric values that moved by less than this are not reported.
+    #[arg(long, default_value_t = 0.001)]
+    tolerance: f64,
+}
+
+#[derive(clap::Args)]
+struct MergeShardsArgs {
+    /// Where to write the combined output.
+    output: PathBuf,
+    /// The shards' output files, in the order to write their rows.
+    #[arg(required = true)]
+    shards: Vec<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct VerifyArgs {
+    /// The output file to check. Its `.meta.json` sidecar must be next to it.
+    output: PathBuf,
+    /// How far recomputed temperatures may stray from the recorded ones.
+    #[arg(long, default_value_t = 0.0)]
+    tolerance: f32,
+}
+
+#[derive(clap::Args)]
+struct IndexArgs {
+    /// An output file with a Population column, e.g. from running on a list-cities listing.
+    output: PathBuf,
+    /// One mean for the whole list, rather than one per country.
+    #[arg(long)]
+    overall: bool,
+}
+
+#[cfg(feature = "sql")]
+#[derive(clap::Args)]
+struct SqlArgs {
+    /// The output file, queried as the `cities` table.
+    output: PathBuf,
+    /// e.g. "SELECT Country, avg(AverageTemperature) FROM cities GROUP BY Country".
+    query: String,
+    /// Also load the monthly temperatures of every city's cell over the period as the `series`
+    /// table, with columns City, Region, Country, Year, Month and Temperature.
+    #[arg(long)]
+    series: bool,
+    /// Which path to read the temperature dataset from, for --series.
+    /// Can be left out if the config file names one.
+    #[arg(long)]
+    temperature_dataset: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+    /// Where to write the result, stdout if left out.
+    #[arg(long, short)]
+    result: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct CountryAveragesArgs {
+    /// A GeoJSON of country boundaries, e.g. Natural Earth's ne_10m_admin_0_countries.
+    /// Which cells fall in which country gets cached in the platform's cache directory.
+    boundaries: PathBuf,
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct CompletionsArgs {
+    /// Which shell to generate completions for.
+    shell: clap_complete::Shell,
+}
+
+#[derive(clap::Args)]
+struct ListCitiesArgs {
+    /// Where to write the cities file.
+    output: PathBuf,
+    /// Only list cities with at least this many inhabitants.
+    #[arg(long, default_value_t = 1_000_000)]
+    min_population: u64,
+    /// Only list cities in this country, by English name. Can be repeated.
+    #[arg(long)]
+    country: Vec<String>,
+}
+
+#[derive(clap::Args)]
+struct FindArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Lowest acceptable average temperature.
+    #[arg(long, allow_negative_numbers = true)]
+    min: f32,
+    /// Highest acceptable average temperature.
+    #[arg(long, allow_negative_numbers = true)]
+    max: f32,
+    /// Require every season's average to be within range too, not just the overall average.
+    #[arg(long)]
+    seasonal: bool,
+    /// A cities file with coordinates (e.g. from list-cities), used to name the matching cells.
+    #[arg(long)]
+    gazetteer: Option<PathBuf>,
+    /// Where to write the matching cells. Defaults to standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct IsolinesArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Average temperature to draw a line at. Can be repeated.
+    #[arg(long, required = true, allow_negative_numbers = true)]
+    level: Vec<f32>,
+    /// Where to write the GeoJSON. Defaults to standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct DbArgs {
+    /// The project database. Created if it doesn't exist.
+    database: PathBuf,
+    /// See the run option.
+    #[arg(long)]
+    database_key: Option<PathBuf>,
+    #[command(subcommand)]
+    command: DbCommand,
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Add a city, optionally with its wikidata entity id or coordinates.
+    Add {
+        city: String,
+        country: String,
+        #[arg(long)]
+        entity_id: Option<String>,
+        #[arg(long, requires = "lat", allow_negative_numbers = true)]
+        lon: Option<f32>,
+        #[arg(long, requires = "lon", allow_negative_numbers = true)]
+        lat: Option<f32>,
+    },
+    /// Remove a city, along with everything recorded about it.
+    Remove { city: String, country: String },
+    /// Write the cities as a cities file, with the coordinates found for them so far.
+    List {
+        /// Defaults to standard output.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write the history of runs recorded in the database as CSV.
+    Runs,
+}
+
+#[derive(clap::Args)]
+struct ExportH3Args {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// H3 resolution, from 0 (continent sized) to 15. Hexagons finer than 5 hold at most one cell.
+    #[arg(long, value_parser = hexagons::parse_resolution)]
+    resolution: u8,
+    /// Where to write the CSV. Defaults to standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct ExportGridArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Where to write the NetCDF file.
+    #[arg(long)]
+    output: PathBuf,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct SubsetArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    ///
+    /// Can also be an OPeNDAP URL (e.g. from a THREDDS server), like wherever a dataset is read,
+    /// in which case only the selected cells and months are transferred.
+    temperature_dataset: Option<PathBuf>,
+    /// Where to write the subset.
+    #[arg(long)]
+    output: PathBuf,
+    /// Only keep cells centered within WEST,SOUTH,EAST,NORTH, in degrees.
+    /// West can be greater than east to cross the antimeridian.
+    #[arg(long, allow_hyphen_values = true, value_parser = subset::parse_bounding_box)]
+    bbox: Option<subset::BoundingBox>,
+    /// Only keep this year on.
+    #[arg(long)]
+    start_year: Option<i32>,
+    /// Only keep up to and including this year.
+    #[arg(long)]
+    end_year: Option<i32>,
+    /// From an OPeNDAP URL, transfer this variable rather than the temperature variable found
+    /// the way runs find it. Subsets of files keep every variable.
+    #[arg(long = "variable", value_name = "NAME")]
+    variable: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct UpdateDatasetArgs {
+    /// The directory of the dataset's NetCDF files.
+    directory: PathBuf,
+    /// Where to get the update from: a URL or a path, to a NetCDF file, gzipped if it ends in .gz.
+    #[arg(long)]
+    source: String,
+}
+
+#[derive(clap::Args)]
+struct BboxArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// The rectangle, as WEST,SOUTH,EAST,NORTH in degrees.
+    /// West can be greater than east to cross the antimeridian.
+    #[arg(long, allow_hyphen_values = true, value_parser = subset::parse_bounding_box)]
+    bbox: subset::BoundingBox,
+    /// Where to write the statistics, as NetCDF if it ends in .nc and as CSV otherwise.
+    /// Defaults to CSV on standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct BenchArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// How many cities to look up, at random points on land.
+    #[arg(long, default_value_t = 1000)]
+    cities: usize,
+    /// Where the random points come from, the same seed gives the same points.
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+    /// Where to write the report. Defaults to standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct GlobalStatsArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Estimate from this many cells drawn with probability proportional to their area,
+    /// rather than going through every cell.
+    #[arg(long)]
+    sample: Option<usize>,
+    /// Which cells get drawn with --sample, the same seed gives the same cells.
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+    /// Where to write the report. Defaults to standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    coverage: CoverageArgs,
+    #[command(flatten)]
+    variable: VariableArgs,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct LatitudeBandsArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Bin the cities of this output file by their latitude, each counted equally, rather than
+    /// every cell of the dataset by its area. Reported in the units the output was run with.
+    #[arg(long, conflicts_with_all = ["temperature_dataset", "start_year", "end_year", "units"])]
+    cities: Option<PathBuf>,
+    /// How many degrees of latitude each band spans, from the south pole up.
+    #[arg(long, default_value_t = 10.0, value_parser = bands::parse_band_width)]
+    band_width: f32,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct QualityArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Time steps and regions missing more than this fraction of their values are flagged as poor.
+    #[arg(long, default_value_t = 0.1)]
+    threshold: f64,
+    /// Where to write the report. Defaults to standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Also write the fraction of values each cell with any is missing, as CSV.
+    #[arg(long)]
+    cells: Option<PathBuf>,
+    #[command(flatten)]
+    coverage: CoverageArgs,
+    #[command(flatten)]
+    variable: VariableArgs,
+}
+
+#[derive(clap::Args)]
+struct ValidateArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Where to write the report. Defaults to standard output.
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Average over the NxN block of cells centered on each city's cell, as in a run.
+    #[arg(long, default_value_t = 1, value_parser = parse_neighborhood_size)]
+    neighborhood: usize,
+    /// Weight the cells of the neighborhood by the inverse of their distance to the city.
+    #[arg(long)]
+    distance_weighted: bool,
+    /// How to sum up each cell's months, as in a run.
+    #[arg(long, default_value_t = Statistic::Mean, value_parser = stats::parse_statistic)]
+    statistic: Statistic,
+    #[command(flatten)]
+    coverage: CoverageArgs,
+    #[command(flatten)]
+    variable: VariableArgs,
+    /// The normals are for 1991 to 2020, use --start-year and --end-year to compare like with like.
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct ExportPackageArgs {
+    /// The output file of a run. Its `.meta.json` sidecar, if any, fills in the temperature's
+    /// period and units.
+    output: PathBuf,
+    /// The directory to write the package to. Created if it doesn't exist.
+    directory: PathBuf,
+    /// Which kind of descriptor to write.
+    #[arg(long, value_enum, default_value_t = package::DescriptorFormat::DataPackage)]
+    format: package::DescriptorFormat,
+    /// Title of the published dataset.
+    #[arg(long)]
+    title: Option<String>,
+    /// License of the published dataset, e.g. an SPDX identifier like CC-BY-4.0.
+    #[arg(long)]
+    license: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct SiteArgs {
+    /// The output file of a run, for its cities and their coordinates.
+    output: PathBuf,
+    /// The directory to write the site to. Created if it doesn't exist.
+    site: PathBuf,
+    /// Which path to read the temperature dataset from, for the map and climographs.
+    /// Can be left out if the config file names one.
+    #[arg(long)]
+    temperature_dataset: Option<PathBuf>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[cfg(feature = "grpc")]
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Which path to read the temperature dataset from.
+    /// Can be left out if the config file names one.
+    temperature_dataset: Option<PathBuf>,
+    /// Where to listen for requests.
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    address: std::net::SocketAddr,
+    /// Where to serve Prometheus metrics, at /metrics. Not served unless given.
+    #[arg(long)]
+    metrics_address: Option<std::net::SocketAddr>,
+    /// Use the time steps that are intact in a truncated temperature dataset, with a warning,
+    /// rather than refusing to read it.
+    #[arg(long)]
+    allow_truncated: bool,
+    /// Keep memory use under this many GB, see the same option without a subcommand.
+    #[arg(long, value_parser = ondisk::parse_gigabytes, value_name = "GB")]
+    max_memory: Option<u64>,
+    #[command(flatten)]
+    coverage: CoverageArgs,
+    #[command(flatten)]
+    variable: VariableArgs,
+    /// How many cell averages to keep, so popular places are answered straight away.
+    /// 0 turns the cache off.
+    #[arg(long, default_value_t = 100_000)]
+    cache_size: usize,
+    /// Forget cached averages after this many seconds. Kept until evicted unless given.
+    #[arg(long, value_name = "SECONDS")]
+    cache_ttl: Option<u64>,
+    /// Only answer requests carrying one of the API keys listed in this file, one per line,
+    /// as `authorization: Bearer <key>` or `x-api-key: <key>` metadata.
+    #[arg(long)]
+    api_keys: Option<PathBuf>,
+    /// Refuse requests beyond this many per minute from each API key,
+    /// or from each client address when there are no keys. Each query of a batch counts as one.
+    #[arg(long, value_name = "PER_MINUTE")]
+    rate_limit: Option<u32>,
+    /// A cities file with coordinates (e.g. from list-cities), to also answer point queries
+    /// with the nearest of its cities.
+    #[arg(long)]
+    gazetteer: Option<PathBuf>,
+    /// Also serve the Arrow IPC outputs (.arrow or .feather) in this directory over Arrow Flight,
+    /// on the same address, each as the flight named after its file.
+    #[arg(long, value_name = "DIR")]
+    flight_dir: Option<PathBuf>,
+    /// The period and units of queries that don't give their own.
+    #[command(flatten)]
+    compute: ComputeArgs,
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Which path to read the temperature dataset from, which path to read the list of cities from,
+    /// and where to write the output, in that order.
+    ///
+    /// We expect the temperature dataset to be a NetCDF file from https://crudata.uea.ac.uk/cru/data/hrg/
+    /// with a temperature variable. It can be left out if the config file names one.
+    /// A dataset split over several files, e.g. by decade, can be given as a glob
+    /// or as a list of files separated by ':'. It can also be an OPeNDAP URL, e.g. from a THREDDS
+    /// server.
+    ///
+    /// The cities are in CSV format (or the first sheet of an .xlsx or .ods spreadsheet),
+    /// with city and country name fields.
+    /// Will also allow pre-filling of the wikidata fields,
+    /// and will take those as a given.
+    /// Coordinates without an entity id are also taken as a given.
+    /// The cities can also be an http(s) URL, e.g. a published Google Sheet,
+    /// which gets downloaded next to the output as <OUTPUT>.cities.csv.
+    /// Leave the cities out when using --cities-preset or --continue-from-review.
+    ///
+    /// The output is CSV, or Arrow IPC, Parquet or GeoJSON when it ends in .arrow or .feather,
+    /// .parquet or .geojson. Leave it out when using --output.
+    #[arg(
+        required_unless_present_any = ["rpc", "outputs"],
+        num_args = 1..=3,
+        value_names = ["TEMPERATURE_DATASET", "CITIES", "OUTPUT"]
+    )]
+    paths: Vec<PathBuf>,
+    #[arg(skip)]
+    temperature_dataset: PathBuf,
+    #[arg(skip)]
+    cities: PathBuf,
+    #[arg(skip)]
+    output: PathBuf,
+    /// Write the output to each of these, in the format of its extension, from the one run,
+    /// e.g. --output out.csv --output out.geojson. The first is the one --merge, --watch and
+    /// the run metadata go by.
+    #[arg(long = "output", value_name = "OUTPUT")]
+    outputs: Vec<PathBuf>,
+    /// Also write the cities of each country to a file of their own, as <OUTPUT>/<ISO>/cities.csv
+    /// for an out.csv output (in the output's format). Directories are named after the country's
+    /// ISO code in --country-boundaries, or else after its name as the cities give it.
+    #[arg(long, value_enum)]
+    split_by: Option<split::SplitBy>,
+    /// Write the cities in this order rather than the order they were read in, so that runs over
+    /// cities that come in a different order (a country expanded to its cities, a sheet re-sorted)
+    /// diff cleanly.
+    #[arg(long, value_enum)]
+    sort_by: Option<SortBy>,
+    /// Also write the checks of the cities input (rows without a name or with impossible
+    /// coordinates, which are left out, and conflicting entity ids or text that isn't UTF-8,
+    /// which are flagged) to this file as JSON. With --strict, rejected rows fail the run.
+    #[arg(long)]
+    validation_report: Option<PathBuf>,
+    /// Read the cities as this format (csv, spreadsheet, geojson or sqlite) rather than the one
+    /// their extension says, e.g. for a URL without one.
+    #[arg(long, value_name = "FORMAT")]
+    input_format: Option<String>,
+    /// What reads the cities in each format, see `main_with_sources`.
+    #[arg(skip)]
+    sources: sources::Sources,
+    /// When the cities were given as a URL, `cities` is where they get downloaded to.
+    #[arg(skip)]
+    cities_url: Option<String>,
+    #[command(flatten)]
+    compute: ComputeArgs,
+    /// Average over the NxN block of cells centered on each city's cell, rather than just that cell.
+    #[arg(long, default_value_t = 1, value_parser = parse_neighborhood_size)]
+    neighborhood: usize,
+    /// Weight the cells of the neighborhood by the inverse of their distance to the city.
+    #[arg(long)]
+    distance_weighted: bool,
+    /// Average over the cells in the H3 hexagon of this resolution containing each city,
+    /// rather than the city's cell. Ocean cells are left out.
+    #[arg(long, value_parser = hexagons::parse_resolution, conflicts_with = "neighborhood")]
+    h3_resolution: Option<u8>,
+    /// How to sum up each cell's months: mean, median, or trimmed-mean, which leaves out the
+    /// coldest and warmest 10% first (or trimmed-mean:PERCENT), for cells with suspect values.
+    #[arg(long, default_value_t = Statistic::Mean, value_parser = stats::parse_statistic)]
+    statistic: Statistic,
+    /// Also write, for every city computed in this run, the months of its cell that are far from
+    /// the same calendar month in other years, as suspect values to look into.
+    #[arg(long)]
+    outliers: Option<PathBuf>,
+    /// How many standard deviations from the cell's own climatology make a month an outlier.
+    #[arg(long, default_value_t = 4.0, requires = "outliers")]
+    outlier_sigmas: f32,
+    /// Also write, for every city computed in this run, a Pettitt test for a shift in its cell's
+    /// annual means over the period, flagging cities whose trends are best distrusted.
+    #[arg(long)]
+    homogeneity: Option<PathBuf>,
+    /// Also write, for every city computed in this run, how hot and how cold each calendar month
+    /// gets once every --return-period years, from an extreme value distribution fitted to the
+    /// cell's values for that month over the period. For assessing heat risk rather than averages.
+    #[arg(long)]
+    return_levels: Option<PathBuf>,
+    /// In years, more than 1.
+    #[arg(
+        long,
+        default_value_t = 20.0,
+        requires = "return_levels",
+        value_parser = reports::parse_return_period
+    )]
+    return_period: f32,
+    /// Also write, for every city computed in this run, where the latest of each calendar month
+    /// sits among that month's values in the cell over the period, e.g. that the last July was
+    /// the 98th percentile July, and the month's mean. With a dataset kept up to date, the
+    /// recent extremes.
+    #[arg(long)]
+    percentiles: Option<PathBuf>,
+    /// Also write, for every city computed in this run, the mean of every run of this many
+    /// consecutive complete years of the period (e.g. 30 for moving climate normals), next to the
+    /// output as <OUTPUT>.rolling.csv.
+    #[arg(long, value_name = "YEARS", value_parser = clap::value_parser!(u32).range(1..))]
+    rolling: Option<u32>,
+    /// Also write, for every city computed in this run, its mean over each decade of the period
+    /// in a column of its own (2000s, 2010s, ...), next to the output as <OUTPUT>.decades.csv.
+    /// Decades cut short by the period or the dataset average the complete years they have, which
+    /// a column next to each decade's (2000sYears, ...) counts.
+    #[arg(long)]
+    decades: bool,
+    /// Also estimate how far off each city's average temperature is likely to be, as one standard
+    /// deviation in an AverageTemperatureUncertainty column, from the spread of its cell's
+    /// complete years, of the cells around it, and from years of the period missing.
+    #[arg(long)]
+    uncertainty: bool,
+    /// Also report how many stations each city's cell's months came from on average, and how
+    /// many months had none, from the dataset's stn variable (CRU TS files have one).
+    #[arg(long)]
+    stations: bool,
+    /// A GeoJSON of country boundaries (e.g. Natural Earth's ne_10m_admin_0_countries) to also
+    /// report each city's country's area-weighted mean, and how much warmer the city is.
+    /// Cities' countries are matched by the boundaries' names or ISO codes.
+    #[arg(long)]
+    country_boundaries: Option<PathBuf>,
+    /// Average over these WMO climate normal periods instead of --start-year to --end-year,
+    /// separated by commas. Each gets an AverageTemperature<PERIOD> column (and an
+    /// AverageTemperatureUncertainty<PERIOD> one with --uncertainty), and the first one also
+    /// fills the AverageTemperature column.
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with_all = ["start_year", "end_year"])]
+    normals: Vec<Normals>,
+    /// Add this many degrees celsius per tenfold Population over 1000 to each city's
+    /// temperatures, for the temperature people in the city live in rather than the cell's,
+    /// which is mostly countryside. 0.5 or so makes a city of a million 1.5 degrees warmer.
+    /// Cities without a Population are left as they are.
+    #[arg(long, value_name = "DEGREES")]
+    urban_heat_island: Option<f32>,
+    /// A fine climatology (e.g. WorldClim converted to NetCDF, one time step per calendar month)
+    /// to downscale the temperature dataset with, for small mountain cities the dataset's cells
+    /// average with the valleys or peaks around them. Each month of a city's temperature is the
+    /// climatology's at the city, plus how much warmer the dataset has that month over the years
+    /// asked for than over --fine-climatology-period. --statistic doesn't apply, and neither
+    /// --neighborhood nor --h3-resolution can be given with it.
+    #[arg(long, conflicts_with_all = ["neighborhood", "h3_resolution"])]
+    fine_climatology: Option<PathBuf>,
+    /// The years the fine climatology's months are the means of, e.g. 1970-2000 for WorldClim
+    /// 2.1 and 1961-1990 for CRU CL 2.0. The temperature dataset has to cover them.
+    #[arg(long, value_parser = downscale::parse_period, default_value = "1970-2000")]
+    fine_climatology_period: Period,
+    #[command(flatten)]
+    coverage: CoverageArgs,
+    #[command(flatten)]
+    variable: VariableArgs,
+    /// How to find cities that don't have a wikidata entity id yet. Defaults to wikidata.
+    #[arg(long, value_enum)]
+    geocoder: Option<Geocoder>,
+    /// Use a standard list of cities instead of reading them from a file.
+    /// The list is written next to the output as <OUTPUT>.cities.csv.
+    #[arg(long, value_enum)]
+    cities_preset: Option<presets::CitiesPreset>,
+    /// Stop once every city has coordinates, and write them next to the output as
+    /// <OUTPUT>.review.csv instead of computing anything, to check them (e.g. in a GIS)
+    /// and correct them before running again with --continue-from-review.
+    #[arg(long, conflicts_with = "watch")]
+    pause_after_geocode: bool,
+    /// Read the cities and their coordinates from the <OUTPUT>.review.csv written by
+    /// --pause-after-geocode, taking the coordinates as they are, rather than from a cities file.
+    #[arg(long, conflicts_with_all = ["cities_preset", "pause_after_geocode"])]
+    continue_from_review: bool,
+    /// Reuse finished rows from an existing output file instead of looking them up again. Their
+    /// temperatures and the columns computed with them are kept too, unless it was written with
+    /// other options, e.g. without --uncertainty or another --coastline.
+    #[arg(long)]
+    merge: bool,
+    /// Only process the i-th of N parts of the cities, e.g. 2/8, to split a large job across
+    /// machines. Every city falls in the same part whatever the order of the cities file.
+    /// Put the outputs back together with merge-shards.
+    #[arg(long, value_name = "i/N")]
+    shard: Option<shards::Shard>,
+    /// Keep running, and update the output whenever the cities file changes.
+    /// Implies --merge.
+    #[arg(long)]
+    watch: bool,
+    /// Also write, for every city computed in this run, which cells its temperature came from.
+    #[arg(long)]
+    diagnostics: Option<PathBuf>,
+    /// Never prompt, geocode or touch the network: every city must come with coordinates,
+    /// and every temperature must be computable as asked. Otherwise, print a JSON report
+    /// of every problem and exit with an error without writing the output.
+    #[arg(long, conflicts_with = "cities_preset")]
+    strict: bool,
+    /// Never touch the network: cities get the coordinates they come with, or were cached with
+    /// in --database, or else those of the only city of that name in --gazetteer. Cities that
+    /// would need geocoding are reported as problems, as with --strict, instead.
+    #[arg(long, conflicts_with = "cities_preset")]
+    offline: bool,
+    /// Check whether a newer CRU TS version than the dataset's is out, with a request to this
+    /// project's repository. Never checked with --offline or --strict.
+    #[arg(long)]
+    check_version: bool,
+    /// Also look up each city's IANA time zone on Wikidata. Needs an entity id.
+    #[arg(long, conflicts_with_all = ["strict", "offline"])]
+    timezones: bool,
+    /// A GeoJSON coastline (e.g. Natural Earth's ne_10m_coastline) to measure each city's
+    /// distance to the nearest coast with.
+    #[arg(long)]
+    coastline: Option<PathBuf>,
+    /// A classic NetCDF elevation grid (e.g. ETOPO1, or CRU's half degree one in km) to report
+    /// each city's cell's mean elevation next to the city's own with.
+    #[arg(long)]
+    elevation: Option<PathBuf>,
+    /// A project database (see the db command) to reuse earlier geocoding decisions from,
+    /// and to record the cities, temperatures and run in.
+    #[arg(long)]
+    database: Option<PathBuf>,
+    /// Keep the database encrypted with AES-256-GCM, with the 32 byte key in this file (raw, or
+    /// as 64 hexadecimal digits, e.g. from `openssl rand -hex 32`). Only ever decrypted in memory.
+    /// An existing plaintext database gets encrypted the first time it's written to.
+    #[arg(long, requires = "database")]
+    database_key: Option<PathBuf>,
+    /// A CSV file of other names cities go by (Alias and City columns, and optionally Country),
+    /// to geocode e.g. Bombay as Mumbai. Runs with --database also record the names Wikidata
+    /// knows each geocoded city by, to recognize renamed cities in later runs.
+    #[arg(long)]
+    aliases: Option<PathBuf>,
+    /// Look coordinates cached in the database more than this many days ago up again,
+    /// reporting cities that moved (usually an entity merged or corrected upstream).
+    #[arg(long, requires = "database", conflicts_with_all = ["strict", "offline"])]
+    max_cache_age: Option<u64>,
+    /// How far in km re-verified coordinates have to move to be reported.
+    #[arg(long, default_value_t = 1.0)]
+    stale_distance: f32,
+    /// A CSV file of points (City, Country, Longitude, Latitude and Weight columns) to average over
+    /// instead of the city's coordinates, for cities better represented by several places.
+    #[arg(long)]
+    points: Option<PathBuf>,
+    /// A cities file with coordinates and populations (e.g. from list-cities) to expand rows
+    /// standing for every city of a country, like `*,Iceland`, from, rather than asking Wikidata.
+    /// With --offline, cities without coordinates are also looked up in it by name.
+    #[arg(long)]
+    gazetteer: Option<PathBuf>,
+    /// Only expand `*` rows to cities with at least this many inhabitants.
+    #[arg(long, default_value_t = 100_000)]
+    min_population: u64,
+    /// Read one of the cities columns from a differently named one, e.g. `City=Town`. Can be repeated.
+    #[arg(long = "column", value_name = "FIELD=HEADER", value_parser = input::parse_column_mapping)]
+    columns: Vec<input::ColumnMapping>,
+    /// The coordinate reference system the cities' coordinates (given and override) are in,
+    /// e.g. `EPSG:27700` for British National Grid eastings and northings in meters, or a proj
+    /// string. They're reprojected to WGS84 longitude and latitude when read, and written as
+    /// such. Only for a cities file of your own, as preset cities and reviewed ones are already
+    /// in WGS84.
+    #[arg(
+        long,
+        value_name = "CRS",
+        value_parser = crs::parse_crs,
+        conflicts_with_all = ["continue_from_review", "cities_preset"]
+    )]
+    input_crs: Option<crs::Crs>,
+    /// Check the temperature dataset has this SHA-256 before doing anything with it,
+    /// to catch corrupted or truncated downloads. Defaults to the one in the config file,
+    /// if the dataset comes from there too.
+    #[arg(long, value_parser = parse_sha256)]
+    dataset_sha256: Option<String>,
+    /// Use the time steps that are intact in a truncated temperature dataset, with a warning,
+    /// rather than refusing to read it.
+    #[arg(long)]
+    allow_truncated: bool,
+    /// Keep memory use under this many GB, by reading the temperature dataset from its file
+    /// as needed (memory-mapped, or with plain reads if even the file is bigger)
+    /// instead of loading it whole, when that would take more.
+    #[arg(long, value_parser = ondisk::parse_gigabytes, value_name = "GB")]
+    max_memory: Option<u64>,
+}
+
+fn parse_sha256(s: &str) -> Result<String, String> {
+    if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(s.to_lowercase())
+    } else {
+        Err("expected 64 hexadecimal digits".to_string())
+    }
+}
+
+impl RunArgs {
+    /// Fill in the paths and anything left unspecified from the config file.
+    fn resolve(&mut self, config: &Config) {
+        let cities_derived = self.cities_preset.is_some() || self.continue_from_review;
+        let expected_paths = match (cities_derived, self.outputs.is_empty()) {
+            (true, false) => 1,
+            (true, true) | (false, false) => 2,
+            (false, true) => 3,
+        };
+        if self.paths.len() > expected_paths || self.paths.len() < expected_paths - 1 {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::WrongNumberOfValues,
+                    format!(
+                        "Expected {} or {} paths, got {}.",
+                        expected_paths - 1,
+                        expected_paths,
+                        self.paths.len()
+                    ),
+                )
+                .exit()
+        }
+        let mut paths = self.paths.iter().cloned();
+        let dataset_given = self.paths.len() == expected_paths;
+        self.temperature_dataset =
+            temperature_dataset_path(dataset_given.then(|| paths.next().unwrap()), config);
+        if !dataset_given && self.dataset_sha256.is_none() {
+            self.dataset_sha256 = config.temperature_dataset_sha256.clone();
+        }
+        if !cities_derived {
+            self.cities = paths.next().unwrap();
+        }
+        self.output = if self.outputs.is_empty() {
+            paths.next().unwrap()
+        } else {
+            self.outputs.remove(0)
+        };
+        if self.cities_preset.is_some() {
+            let mut cities = self.output.as_os_str().to_owned();
+            cities.push(".cities.csv");
+            self.cities = PathBuf::from(cities);
+        }
+        if self.continue_from_review {
+            self.cities = self.review_path();
+        }
+        if self.offline && self.temperature_dataset.to_string_lossy().contains("://") {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "Can't read a remote temperature dataset with --offline.",
+                )
+                .exit()
+        }
+        if let Some(url) = input::cities_url(&self.cities) {
+            if self.offline {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "Can't download cities with --offline.",
+                    )
+                    .exit()
+            }
+            if self.watch {
+                Args::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "Can't watch a cities URL for changes.",
+                    )
+                    .exit()
+            }
+            self.cities_url = Some(url.to_string());
+            self.cities = input::download_path(url, &self.output, &self.sources);
+        }
+
+        self.compute.resolve(config);
+        if let Some(normals) = self.normals.first() {
+            let period = normals.period();
+            self.compute.start_year = period.first_year;
+            self.compute.end_year = period.last_year;
+        }
+        self.geocoder = self.geocoder.or(config.geocoder);
+    }
+
+    /// Whether cities can be geocoded, and anything else looked up online.
+    fn network_allowed(&self) -> bool {
+        !self.strict && !self.offline
+    }
+
+    /// Where --pause-after-geocode writes the cities' coordinates.
+    fn review_path(&self) -> PathBuf {
+        let mut review = self.output.as_os_str().to_owned();
+        review.push(".review.csv");
+        PathBuf::from(review)
+    }
+
+    fn settings(&self) -> CitySettings {
+        CitySettings {
+            period: self.compute.period(),
+            units: self.compute.units(),
+            neighborhood: Neighborhood {
+                size: self.neighborhood,
+                distance_weighted: self.distance_weighted,
+            },
+            h3_resolution: self.h3_resolution,
+            statistic: self.statistic,
+        }
+    }
+
+    /// Everything recorded in the run metadata to reproduce the temperatures.
+    fn parameters(&self) -> BTreeMap<String, String> {
+        let mut parameters = self.settings().parameters();
+        if let Some(points) = &self.points {
+            parameters.insert("points".to_string(), points.display().to_string());
+        }
+        if !self.normals.is_empty() {
+            let normals: Vec<String> = self.normals.iter().map(Normals::to_string).collect();
+            parameters.insert("normals".to_string(), normals.join(","));
+        }
+        if let Some(shard) = self.shard {
+            parameters.insert("shard".to_string(), shard.to_string());
+        }
+        if let Some(degrees) = self.urban_heat_island {
+            parameters.insert("urban-heat-island".to_string(), degrees.to_string());
+        }
+        if let Some(path) = &self.fine_climatology {
+            let period = self.fine_climatology_period;
+            parameters.insert("fine-climatology".to_string(), path.display().to_string());
+            parameters.insert(
+                "fine-climatology-period".to_string(),
+                format!(
+                    "{}-{}",
+                    period
+                        .first_year
+                        .expect("The period is parsed with both years"),
+                    period
+                        .last_year
+                        .expect("The period is parsed with both years")
+                ),
+            );
+        }
+        // The columns these fill are only computed for rows being computed, so rows merged
+        // from an output without them are computed again.
+        let extras = [
+            ("uncertainty", self.uncertainty.then(String::new)),
+            ("stations", self.stations.then(String::new)),
+            ("timezones", self.timezones.then(String::new)),
+            (
+                "country-boundaries",
+                self.country_boundaries
+                    .as_ref()
+                    .map(|path| path.display().to_string()),
+            ),
+            (
+                "coastline",
+                self.coastline
+                    .as_ref()
+                    .map(|path| path.display().to_string()),
+            ),
+            (
+                "elevation",
+                self.elevation
+                    .as_ref()
+                    .map(|path| path.display().to_string()),
+            ),
+        ];
+        for (name, value) in extras {
+            if let Some(value) = value {
+                parameters.insert(name.to_string(), value);
+            }
+        }
+        self.coverage.parameters(&mut parameters);
+        self.variable.parameters(&mut parameters);
+        parameters
+    }
+
+    /// Whatever whoever uses the output should know, recorded in the run metadata.
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings: Vec<String> = self.variable.warning().into_iter().collect();
+        if let Some(degrees) = self.urban_heat_island {
+            warnings.push(format!(
+                "The temperatures were adjusted for the urban heat island by {} degrees celsius \
+                 per tenfold population over 1000, see the UrbanHeatIsland column.",
+                degrees
+            ));
+        }
+        warnings
+    }
+}
+
+/// The 30 year periods the WMO publishes climate normals for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Normals {
+    #[value(name = "1961-1990")]
+    From1961,
+    #[value(name = "1981-2010")]
+    From1981,
+    #[value(name = "1991-2020")]
+    From1991,
+}
+
+impl Normals {
+    fn period(self) -> Period {
+        let first_year = match self {
+            Normals::From1961 => 1961,
+            Normals::From1981 => 1981,
+            Normals::From1991 => 1991,
+        };
+        Period {
+            first_year: Some(first_year),
+            last_year: Some(first_year + 29),
+        }
+    }
+}
+
+/// The warming of a city of `population` over the countryside around it, in celsius:
+/// `degrees` per tenfold population over a village of 1000's.
+fn urban_warming(degrees: f32, population: u64) -> f32 {
+    degrees * (population as f32 / 1000.0).log10().max(0.0)
+}
+
+impl std::fmt::Display for Normals {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let value = self.to_possible_value().expect("Every normal has a name");
+        f.write_str(value.get_name())
+    }
+}
+
+/// The order to write an output's rows in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortBy {
+    /// City, then region and country, ignoring case.
+    Name,
+    /// Country, then the cities' names.
+    Country,
+    /// Coldest first, cities without a temperature last, then the cities' names.
+    Temperature,
+}
+
+impl SortBy {
+    /// A total order, so that the same cities come out the same way whatever order they were
+    /// read, geocoded or merged in.
+    fn sort(self, cities: &mut [City]) {
+        let name = |city: &City| {
+            (
+                city.city.to_lowercase(),
+                city.region.as_deref().map(str::to_lowercase),
+                city.country.to_lowercase(),
+                city.city.clone(),
+                city.region.clone(),
+                city.country.clone(),
+            )
+        };
+        cities.sort_by_cached_key(name);
+        match self {
+            SortBy::Name => {}
+            SortBy::Country => cities.sort_by_cached_key(|city| city.country.to_lowercase()),
+            SortBy::Temperature => {
+                cities.sort_by(
+                    |a, b| match (a.average_temperature, b.average_temperature) {
+                        (Some(a), Some(b)) => a.total_cmp(&b),
+                        (a, b) => a.is_none().cmp(&b.is_none()),
+                    },
+                )
+            }
+        }
+    }
+}
+
+fn database_key(path: Option<&Path>) -> Option<encryption::Key> {
+    path.map(|path| {
+        encryption::Key::load(path).unwrap_or_else(|e| panic!("Couldn't load database key: {}", e))
+    })
+}
+
+/// The dataset given on the command line, or else the one named in the config file.
+fn temperature_dataset_path(given: Option<PathBuf>, config: &Config) -> PathBuf {
+    given
+        .or_else(|| config.temperature_dataset.clone())
+        .unwrap_or_else(|| {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "No temperature dataset given, and the config file doesn't name one.",
+                )
+                .exit()
+        })
+}
+
+/// Exits with a usage error when `dataset` is a climatology, one of each month without years, and
+/// any of `options`, which pick years or need a series of them, were given.
+fn refuse_for_climatology(dataset: &TemperatureDataset, options: &[(&str, bool)]) {
+    if dataset.kind() != DatasetKind::Climatology {
+        return;
+    }
+    let given: Vec<&str> = options
+        .iter()
+        .filter(|(_, given)| *given)
+        .map(|(option, _)| *option)
+        .collect();
+    if !given.is_empty() {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "The temperature dataset is a climatology, with one of each month and no \
+                     years, so it can't be used with {}.",
+                    given.join(", ")
+                ),
+            )
+            .exit()
+    }
+}
+
+/// Options that influence how temperatures are computed, shared by everything that computes them.
+#[derive(clap::Args)]
+struct ComputeArgs {
+    /// Only average from this year on.
+    #[arg(long)]
+    start_year: Option<i32>,
+    /// Only average up to and including this year.
+    #[arg(long)]
+    end_year: Option<i32>,
+    /// Which units to report temperatures in. Defaults to celsius.
+    #[arg(long, value_enum)]
+    units: Option<Units>,
+}
+
+impl ComputeArgs {
+    /// Fill in anything left unspecified from the config file.
+    fn resolve(&mut self, config: &Config) {
+        self.start_year = self.start_year.or(config.start_year);
+        self.end_year = self.end_year.or(config.end_year);
+        self.units = self.units.or(config.units);
+    }
+
+    fn period(&self) -> Period {
+        Period {
+            first_year: self.start_year,
+            last_year: self.end_year,
+        }
+    }
+
+    /// The options picking years, and whether they were given, see `refuse_for_climatology`.
+    fn year_options(&self) -> [(&'static str, bool); 2] {
+        [
+            ("--start-year", self.start_year.is_some()),
+            ("--end-year", self.end_year.is_some()),
+        ]
+    }
+
+    fn units(&self) -> Units {
+        self.units.unwrap_or_default()
+    }
+}
+
+/// How much of each month and year needs values, for daily datasets and annual means.
+#[derive(clap::Args)]
+struct CoverageArgs {
+    /// The fraction of a month's days, from 0 to 1, a daily dataset needs values for
+    /// for the month to have a mean. Defaults to every day.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_day_coverage)]
+    min_day_coverage: f32,
+    /// How many months a year needs values for to have an annual mean. Defaults to all twelve.
+    #[arg(long, default_value_t = 12, value_parser = clap::value_parser!(u32).range(1..=12))]
+    min_month_coverage: u32,
+}
+
+impl CoverageArgs {
+    fn coverage(&self) -> Coverage {
+        Coverage {
+            days: self.min_day_coverage,
+            months: self.min_month_coverage,
+        }
+    }
+
+    /// For the run metadata, only what differs from the defaults.
+    fn parameters(&self, parameters: &mut BTreeMap<String, String>) {
+        let coverage = self.coverage();
+        let defaults = Coverage::default();
+        if coverage.days != defaults.days {
+            parameters.insert("min-day-coverage".to_string(), coverage.days.to_string());
+        }
+        if coverage.months != defaults.months {
+            parameters.insert(
+                "min-month-coverage".to_string(),
+                coverage.months.to_string(),
+            );
+        }
+    }
+
+    /// The inverse of `parameters`.
+    fn from_parameters(parameters: &BTreeMap<String, String>) -> Coverage {
+        let defaults = Coverage::default();
+        Coverage {
+            days: parameters
+                .get("min-day-coverage")
+                .map_or(defaults.days, |days| {
+                    days.parse().expect("Bad min-day-coverage parameter")
+                }),
+            months: parameters
+                .get("min-month-coverage")
+                .map_or(defaults.months, |months| {
+                    months.parse().expect("Bad min-month-coverage parameter")
+                }),
+        }
+    }
+}
+
+/// Which variable of the temperature dataset to read, and which of its values are missing,
+/// for datasets where that's not the way their metadata says.
+#[derive(clap::Args)]
+struct VariableArgs {
+    /// Read temperatures from this variable of the dataset. By default, the one with
+    /// standard_name air_temperature and units of temperature (celsius or kelvin), e.g. CRU TS's
+    /// tmp, asking which when several match.
+    #[arg(long = "variable", value_name = "NAME")]
+    name: Option<String>,
+    /// Treat this value of the temperature variable as missing, instead of the one its
+    /// missing_value attribute gives, for files where that's wrong or absent.
+    #[arg(long, allow_negative_numbers = true, value_name = "VALUE")]
+    missing_value: Option<f32>,
+    /// Also treat NaN values of the temperature variable as missing.
+    #[arg(long)]
+    treat_nan_as_missing: bool,
+}
+
+impl VariableArgs {
+    fn missing_values(&self) -> MissingValues {
+        MissingValues {
+            value: self.missing_value,
+            nan: self.treat_nan_as_missing,
+        }
+    }
+
+    /// For the run metadata, nothing unless overridden.
+    fn parameters(&self, parameters: &mut BTreeMap<String, String>) {
+        if let Some(name) = &self.name {
+            parameters.insert("variable".to_string(), name.clone());
+        }
+        if let Some(value) = self.missing_value {
+            parameters.insert("missing-value".to_string(), value.to_string());
+        }
+        if self.treat_nan_as_missing {
+            parameters.insert("treat-nan-as-missing".to_string(), "true".to_string());
+        }
+    }
+
+    /// The values no longer follow from the dataset alone, which whoever uses them should know.
+    fn warning(&self) -> Option<String> {
+        let overrides: Vec<String> = self
+            .missing_value
+            .map(|value| format!("--missing-value {}", value))
+            .into_iter()
+            .chain(
+                self.treat_nan_as_missing
+                    .then(|| "--treat-nan-as-missing".to_string()),
+            )
+            .collect();
+        (!overrides.is_empty()).then(|| {
+            format!(
+                "The temperature dataset's missing values were overridden with {}, \
+                 rather than taken from its metadata.",
+                overrides.join(" and ")
+            )
+        })
+    }
+
+    /// Opens the dataset with `open`, asking which variable to read when several could be the
+    /// temperature and remembering the answer, or failing when `strict`.
+    fn open(
+        &mut self,
+        strict: bool,
+        open: impl Fn(&Self) -> Result<TemperatureDataset, TemperatureDatasetReadErr>,
+    ) -> Result<TemperatureDataset, TemperatureDatasetReadErr> {
+        match open(self) {
+            Err(e) if !strict => match e.ambiguous_variables() {
+                Some(names) => {
+                    let choice = dialoguer::Select::new()
+                        .with_prompt("Several variables could be the temperature, which is it?")
+                        .items(names)
+                        .default(0)
+                        .interact()
+                        .expect("User didn't make a choice.");
+                    self.name = Some(names[choice].clone());
+                    open(self)
+                }
+                None => Err(e),
+            },
+            result => result,
+        }
+    }
+}
+
+fn parse_day_coverage(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        Ok(_) => Err("must be between 0 and 1".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_neighborhood_size(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(size) if size % 2 == 1 => Ok(size),
+        Ok(_) => Err("must be odd, so the block can be centered on the city".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+impl CitySettings {
+    /// The weighted combination of the temperatures at each point, when a city has several.
+    fn temperature_of(
+        &self,
+        dataset: &TemperatureDataset,
+        points: Option<&[points::WeightedPoint]>,
+        lon: Longitude,
+        lat: Latitude,
+    ) -> Result<f32, AverageErr> {
+        let Some(points) = points else {
+            return self.temperature_at(dataset, lon, lat);
+        };
+        let mut weighted_sum = 0.0;
+        let mut total_weight = 0.0;
+        for point in points {
+            weighted_sum += self.temperature_at(dataset, point.lon, point.lat)? * point.weight;
+            total_weight += point.weight;
+        }
+        Ok(weighted_sum / total_weight)
+    }
+
+    /// Like `temperature_of`, but downscaled with `fine` when given, see `downscale`.
+    fn downscaled_temperature_of(
+        &self,
+        dataset: &TemperatureDataset,
+        fine: Option<&downscale::FineClimatology>,
+        points: Option<&[points::WeightedPoint]>,
+        lon: Longitude,
+        lat: Latitude,
+    ) -> Result<f32, AverageErr> {
+        let Some(fine) = fine else {
+            return self.temperature_of(dataset, points, lon, lat);
+        };
+        let at = |lon, lat| {
+            fine.corrected(dataset, lon, lat, self.period)
+                .map(|t| t.in_units(self.units))
+        };
+        let Some(points) = points else {
+            return at(lon, lat);
+        };
+        let mut weighted_sum = 0.0;
+        let mut total_weight = 0.0;
+        for point in points {
+            weighted_sum += at(point.lon, point.lat)? * point.weight;
+            total_weight += point.weight;
+        }
+        Ok(weighted_sum / total_weight)
+    }
+
+    /// The settings as recorded in the run metadata, keyed by command line option.
+    fn parameters(&self) -> BTreeMap<String, String> {
+        let mut parameters = BTreeMap::new();
+        if let Some(year) = self.period.first_year {
+            parameters.insert("start-year".to_string(), year.to_string());
+        }
+        if let Some(year) = self.period.last_year {
+            parameters.insert("end-year".to_string(), year.to_string());
+        }
+        let units = self.units.to_possible_value().unwrap();
+        parameters.insert("units".to_string(), units.get_name().to_string());
+        if self.neighborhood.size != 1 {
+            parameters.insert(
+                "neighborhood".to_string(),
+                self.neighborhood.size.to_string(),
+            );
+        }
+        if self.neighborhood.distance_weighted {
+            parameters.insert("distance-weighted".to_string(), "true".to_string());
+        }
+        if let Some(resolution) = self.h3_resolution {
+            parameters.insert("h3-resolution".to_string(), resolution.to_string());
+        }
+        if self.statistic != Statistic::Mean {
+            parameters.insert("statistic".to_string(), self.statistic.to_string());
+        }
+        parameters
+    }
+
+    /// The inverse of `parameters`.
+    fn from_parameters(parameters: &BTreeMap<String, String>) -> Self {
+        let parameter = |name: &str| parameters.get(name);
+        Self {
+            period: Period {
+                first_year: parameter("start-year")
+                    .map(|y| y.parse().expect("Bad start-year parameter")),
+                last_year: parameter("end-year")
+                    .map(|y| y.parse().expect("Bad end-year parameter")),
+            },
+            units: parameter("units")
+                .map(|u| Units::from_str(u, true).expect("Bad units parameter"))
+                .unwrap_or_default(),
+            neighborhood: Neighborhood {
+                size: parameter("neighborhood")
+                    .map(|n| n.parse().expect("Bad neighborhood parameter"))
+                    .unwrap_or(1),
+                distance_weighted: parameter("distance-weighted").is_some(),
+            },
+            h3_resolution: parameter("h3-resolution")
+                .map(|r| r.parse().expect("Bad h3-resolution parameter")),
+            statistic: parameter("statistic")
+                .map(|s| stats::parse_statistic(s).expect("Bad statistic parameter"))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The command line tool, everything else is only public for the C bindings.
+pub fn main() {
+    main_with_sources(Default::default())
+}
+
+/// The command line tool, reading cities with `sources`, e.g. the built-in ones with another
+/// format registered.
+pub fn main_with_sources(sources: sources::Sources) {
+    let mut args = Args::parse();
+    args.run.sources = sources;
+
+    let config_path = args.config.clone().or_else(Config::default_path);
+    let config = match config_path {
+        Some(ref path) => {
+            Config::load(path).unwrap_or_else(|e| panic!("Couldn't load config: {}", e))
+        }
+        None => Config::default(),
+    };
+
+    match args.command {
+        Some(Command::Diff(diff_args)) => diff(diff_args),
+        Some(Command::Verify(verify_args)) => verify(verify_args),
+        Some(Command::MergeShards(merge_shards_args)) => merge_shards(merge_shards_args),
+        Some(Command::Index(index_args)) => index(index_args),
+        Some(Command::CountryAverages(country_averages_args)) => {
+            country_averages(country_averages_args, &config)
+        }
+        Some(Command::Completions(completions_args)) => clap_complete::generate(
+            completions_args.shell,
+            &mut Args::command(),
+            env!("CARGO_PKG_NAME"),
+            &mut std::io::stdout(),
+        ),
+        Some(Command::Man) => clap_mangen::Man::new(Args::command())
+            .render(&mut std::io::stdout())
+            .expect("Couldn't write man page"),
+        Some(Command::Init) => {
+            init::wizard(&config_path.expect("Nowhere to put the config file, use --config"))
+        }
+        Some(Command::ListCities(list_cities_args)) => list_cities(list_cities_args),
+        Some(Command::Db(db_args)) => db(db_args),
+        Some(Command::Find(find_args)) => find(find_args, &config),
+        Some(Command::Isolines(isolines_args)) => isolines(isolines_args, &config),
+        Some(Command::ExportH3(export_h3_args)) => export_h3(export_h3_args, &config),
+        Some(Command::ExportGrid(export_grid_args)) => export_grid(export_grid_args, &config),
+        Some(Command::Subset(subset_args)) => subset(subset_args, &config),
+        Some(Command::UpdateDataset(update_args)) => update_dataset(update_args),
+        Some(Command::Bbox(bbox_args)) => bbox(bbox_args, &config),
+        Some(Command::Bench(bench_args)) => bench(bench_args, &config),
+        Some(Command::GlobalStats(global_stats_args)) => global_stats(global_stats_args, &config),
+        Some(Command::Quality(quality_args)) => quality(quality_args, &config),
+        Some(Command::LatitudeBands(latitude_bands_args)) => {
+            latitude_bands(latitude_bands_args, &config)
+        }
+        Some(Command::Validate(validate_args)) => validate(validate_args, &config),
+        Some(Command::ExportPackage(export_package_args)) => export_package(export_package_args),
+        Some(Command::Site(site_args)) => site(site_args, &config),
+        #[cfg(feature = "grpc")]
+        Some(Command::Serve(serve_args)) => serve(serve_args, &config),
+        #[cfg(feature = "sql")]
+        Some(Command::Sql(sql_args)) => sql(sql_args, &config),
+        None if args.rpc => rpc::serve(&config),
+        None => run(args.run, &config),
+    }
+}
+
+fn diff(args: DiffArgs) {
+    let diff = diff::CsvDiff::between(&args.old, &args.new, args.tolerance)
+        .unwrap_or_else(|e| panic!("Couldn't compare output files: {}", e));
+    if diff.is_empty() {
+        println!("No differences.");
+    } else {
+        print!("{}", diff);
+    }
+}
+
+fn merge_shards(args: MergeShardsArgs) {
+    let merged = shards::merge(&args.shards, &args.output)
+        .unwrap_or_else(|e| panic!("Couldn't merge shards: {}", e));
+    for shard in &merged.missing {
+        eprintln!("Warning: none of the files is shard {}.", shard);
+    }
+    if !merged.conflicts.is_empty() {
+        for conflict in &merged.conflicts {
+            eprintln!(
+                "{} differs between {} and {}.",
+                conflict.city,
+                conflict.shards.0.display(),
+                conflict.shards.1.display()
+            );
+        }
+        eprintln!("Nothing written.");
+        std::process::exit(1);
+    }
+    println!(
+        "Wrote {} cities to {}.",
+        merged.cities,
+        args.output.display()
+    );
+}
+
+fn verify(args: VerifyArgs) {
+    let metadata = metadata::RunMetadata::read(&args.output).expect("Couldn't read run metadata");
+    let mut problems = vec![];
+
+    if metadata.tool_version != env!("CARGO_PKG_VERSION") {
+        problems.push(format!(
+            "Output was produced by version {}, this is version {}.",
+            metadata.tool_version,
+            env!("CARGO_PKG_VERSION")
+        ));
+    }
+    for (name, fingerprint) in [
+        ("Temperature dataset", &metadata.temperature_dataset),
+        ("Cities file", &metadata.cities),
+        ("Output file", &metadata.output),
+    ] {
+        match fingerprint.is_current() {
+            Ok(true) => {}
+            Ok(false) => problems.push(format!(
+                "{} {} has changed since the run.",
+                name,
+                fingerprint.path.display()
+            )),
+            Err(e) => problems.push(format!(
+                "{} {} can't be read: {}",
+                name,
+                fingerprint.path.display(),
+                e
+            )),
+        }
+    }
+
+    let settings = CitySettings::from_parameters(&metadata.parameters);
+    let points = metadata.parameters.get("points").map(|path| {
+        points::WeightedPoints::load(Path::new(path))
+            .unwrap_or_else(|e| panic!("Couldn't read points: {}", e))
+    });
+    let urban_heat_island: Option<f32> = metadata
+        .parameters
+        .get("urban-heat-island")
+        .map(|degrees| degrees.parse().expect("Bad urban-heat-island parameter"));
+
+    // The coordinates were looked up over the network, but given those,
+    // the temperatures should come out of the dataset exactly the same.
+    let missing = MissingValues {
+        value: metadata
+            .parameters
+            .get("missing-value")
+            .map(|value| value.parse().expect("Bad missing-value parameter")),
+        nan: metadata.parameters.contains_key("treat-nan-as-missing"),
+    };
+    let opened = TemperatureDataset::open_with(
+        &metadata.temperature_dataset.path,
+        false,
+        CoverageArgs::from_parameters(&metadata.parameters),
+        missing,
+        metadata.parameters.get("variable").map(String::as_str),
+    )
+    .map_err(|e| format!("Can't read temperature dataset: {}", e))
+    .and_then(|dataset| {
+        let fine_climatology = metadata
+            .parameters
+            .get("fine-climatology")
+            .map(|path| {
+                let period = metadata
+                    .parameters
+                    .get("fine-climatology-period")
+                    .expect("No fine-climatology-period parameter");
+                let period =
+                    downscale::parse_period(period).expect("Bad fine-climatology-period parameter");
+                downscale::FineClimatology::open(Path::new(path), period, &dataset)
+            })
+            .transpose()
+            .map_err(|e| format!("Can't read fine climatology: {}", e))?;
+        Ok((dataset, fine_climatology))
+    });
+    match opened {
+        Err(problem) => problems.push(problem),
+        Ok((dataset, fine_climatology)) => {
+            let cities = read_cities(&args.output).expect("Couldn't read city data from output.");
+            for city in cities {
+                let (Some(lon), Some(lat)) = (city.wikidata_longitude, city.wikidata_latitude)
+                else {
+                    problems.push(format!(
+                        "{}, {} has no coordinates.",
+                        city.city, city.country
+                    ));
+                    continue;
+                };
+                let (lon, lat) = match coordinates::lon_lat(lon, lat) {
+                    Ok(lon_lat) => lon_lat,
+                    Err(e) => {
+                        problems.push(format!("{}, {}: {}.", city.city, city.country, e));
+                        continue;
+                    }
+                };
+                let recomputed = settings
+                    .downscaled_temperature_of(
+                        &dataset,
+                        fine_climatology.as_ref(),
+                        points.as_ref().and_then(|points| points.of(&city)),
+                        lon,
+                        lat,
+                    )
+                    .map(|temperature| {
+                        let adjustment = urban_heat_island
+                            .zip(city.population)
+                            .map(|(degrees, population)| urban_warming(degrees, population))
+                            .unwrap_or_default();
+                        temperature
+                            + TemperatureDelta::from_celsius(adjustment).in_units(settings.units)
+                    });
+                match (city.average_temperature, recomputed) {
+                    (Some(recorded), Ok(recomputed))
+                        if (recorded - recomputed).abs() <= args.tolerance => {}
+                    (recorded, recomputed) => problems.push(format!(
+                        "{}, {}: recorded {:?}, recomputed {:?}.",
+                        city.city,
+                        city.country,
+                        recorded,
+                        recomputed.map_err(|e| e.to_string())
+                    )),
+                }
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!("{} is reproducible.", args.output.display());
+    } else {
+        for problem in problems {
+            println!("{}", problem);
+        }
+        std::process::exit(1);
+    }
+}
+
+fn index(args: IndexArgs) {
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct IndexRow {
+        country: String,
+        cities: usize,
+        population: u64,
+        average_temperature: f32,
+    }
+
+    let cities = read_cities(&args.output).expect("Couldn't read output file");
+    // City count, total population and sum of population weighted temperatures.
+    let mut countries: BTreeMap<String, (usize, u64, f64)> = BTreeMap::new();
+    let mut skipped = 0;
+    for city in cities {
+        let (Some(temperature), Some(population)) = (city.average_temperature, city.population)
+        else {
+            skipped += 1;
+            continue;
+        };
+        let country = if args.overall {
+            "All".to_string()
+        } else {
+            city.country
+        };
+        let (count, total_population, weighted_sum) = countries.entry(country).or_default();
+        *count += 1;
+        *total_population += population;
+        *weighted_sum += temperature as f64 * population as f64;
+    }
+    if skipped > 0 {
+        eprintln!(
+            "Left out {} cities without a temperature or population.",
+            skipped
+        );
+    }
+
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    for (country, (cities, population, weighted_sum)) in countries {
+        if population > 0 {
+            writer
+                .serialize(IndexRow {
+                    country,
+                    cities,
+                    population,
+                    average_temperature: (weighted_sum / population as f64) as f32,
+                })
+                .expect("Couldn't write index row");
+        }
+    }
+}
+
+fn country_averages(mut args: CountryAveragesArgs, config: &Config) {
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct CountryRow {
+        country: String,
+        iso: Option<String>,
+        cells: usize,
+        average_temperature: Option<f32>,
+    }
+
+    args.compute.resolve(config);
+    let period = args.compute.period();
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let boundaries = countries::Boundaries::load(&args.boundaries)
+        .unwrap_or_else(|e| panic!("Couldn't load country boundaries: {}", e));
+    let averages = boundaries.averages(&dataset, period);
+
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    for (country, average) in boundaries.countries.iter().zip(averages) {
+        writer
+            .serialize(CountryRow {
+                country: country.name.clone(),
+                iso: country.iso.clone(),
+                cells: average.cells,
+                average_temperature: average
+                    .celsius
+                    .map(|mean| Temperature::new(mean).in_units(args.compute.units())),
+            })
+            .expect("Couldn't write country row");
+    }
+}
+
+fn list_cities(args: ListCitiesArgs) {
+    // The same columns as the cities input with the wikidata fields pre-filled,
+    // so the listing can be used as-is without any interactive lookups.
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct ListedCityRow {
+        city: String,
+        country: String,
+        wikidata_entity_id: String,
+        wikidata_longitude: f32,
+        wikidata_latitude: f32,
+        population: Option<u64>,
+    }
+
+    let mut cities = wikidata::list_cities(args.min_population, &args.country)
+        .expect("Couldn't list cities from wikidata");
+    cities.sort_by(|a, b| (&a.country, &a.name).cmp(&(&b.country, &b.name)));
+
+    let mut output_writer =
+        csv::Writer::from_path(&args.output).expect("Couldn't open output file");
+    for city in cities.iter() {
+        output_writer
+            .serialize(ListedCityRow {
+                city: city.name.clone(),
+                country: city.country.clone(),
+                wikidata_entity_id: city.entity_id.clone(),
+                wikidata_longitude: city.lon,
+                wikidata_latitude: city.lat,
+                population: city.population,
+            })
+            .expect("Couldn't write city out to output file");
+    }
+    println!("Listed {} cities.", cities.len());
+}
+
+fn db(args: DbArgs) {
+    let mut database =
+        database::Database::open(&args.database, database_key(args.database_key.as_deref()))
+            .unwrap_or_else(|e| panic!("Couldn't open database: {}", e));
+    match args.command {
+        DbCommand::Add {
+            city,
+            country,
+            entity_id,
+            lon,
+            lat,
+        } => {
+            let added = database
+                .add_city(&City {
+                    city,
+                    country,
+                    wikidata_entity_id: entity_id,
+                    wikidata_longitude: lon,
+                    wikidata_latitude: lat,
+                    ..Default::default()
+                })
+                .expect("Couldn't add city");
+            if !added {
+                println!("Already there, updated its coordinates if any were given.");
+            }
+        }
+        DbCommand::Remove { city, country } => {
+            if !database
+                .remove_city(&city, &country)
+                .expect("Couldn't remove city")
+            {
+                eprintln!("No such city: {}, {}", city, country);
+                std::process::exit(1);
+            }
+        }
+        DbCommand::List { output } => {
+            let output: Box<dyn std::io::Write> = match output {
+                Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+                None => Box::new(std::io::stdout()),
+            };
+            let mut writer = csv::Writer::from_writer(output);
+            for city in database
+                .cities()
+                .expect("Couldn't read cities from database")
+            {
+                writer
+                    .serialize(city)
+                    .expect("Couldn't write city out to output file");
+            }
+        }
+        DbCommand::Runs => {
+            #[derive(Serialize)]
+            #[serde(rename_all = "PascalCase")]
+            struct RunRow {
+                /// Seconds since the epoch.
+                started_at: u64,
+                tool_version: String,
+                temperature_dataset: String,
+                temperature_dataset_sha256: String,
+                parameters: String,
+                output: String,
+                cities: usize,
+            }
+
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for run in database.runs().expect("Couldn't read runs from database") {
+                writer
+                    .serialize(RunRow {
+                        started_at: run.started_at,
+                        tool_version: run.tool_version,
+                        temperature_dataset: run.dataset_path,
+                        temperature_dataset_sha256: run.dataset_sha256,
+                        parameters: run
+                            .parameters
+                            .iter()
+                            .map(|(name, value)| format!("{}={}", name, value))
+                            .join(" "),
+                        output: run.output_path,
+                        cities: run.cities,
+                    })
+                    .expect("Couldn't write run");
+            }
+        }
+    }
+}
+
+fn find(mut args: FindArgs, config: &Config) {
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct CellRow {
+        longitude: f32,
+        latitude: f32,
+        average_temperature: f32,
+        #[serde(rename = "DJF")]
+        djf: Option<f32>,
+        #[serde(rename = "MAM")]
+        mam: Option<f32>,
+        #[serde(rename = "JJA")]
+        jja: Option<f32>,
+        #[serde(rename = "SON")]
+        son: Option<f32>,
+        nearby_cities: String,
+    }
+
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let gazetteer = find::Gazetteer::new(match args.gazetteer {
+        Some(ref path) => input::read_cities(path, &[]).expect("Couldn't read gazetteer"),
+        None => vec![],
+    });
+
+    let matches = find::cells_within(
+        &dataset,
+        args.compute.period(),
+        args.compute.units(),
+        args.min,
+        args.max,
+        args.seasonal,
+    );
+
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut output_writer = csv::Writer::from_writer(output);
+    for cell_match in matches.iter() {
+        let (longitude, latitude) = cell_match.cell.center();
+        let seasonal = cell_match.seasonal_averages;
+        output_writer
+            .serialize(CellRow {
+                longitude,
+                latitude,
+                average_temperature: cell_match.average,
+                djf: seasonal.map(|s| s[0]),
+                mam: seasonal.map(|s| s[1]),
+                jja: seasonal.map(|s| s[2]),
+                son: seasonal.map(|s| s[3]),
+                nearby_cities: gazetteer.places_in(cell_match.cell).join("; "),
+            })
+            .expect("Couldn't write cell out to output file");
+    }
+}
+
+fn isolines(mut args: IsolinesArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let grid = dataset.average_grid(args.compute.period(), args.compute.units());
+
+    let features: Vec<serde_json::Value> = args
+        .level
+        .iter()
+        .flat_map(|&level| {
+            contouring::isolines(&grid, level)
+                .into_iter()
+                .map(move |line| {
+                    serde_json::json!({
+                        "type": "Feature",
+                        "properties": { "temperature": level },
+                        "geometry": {
+                            "type": "LineString",
+                            "coordinates": line
+                                .into_iter()
+                                .map(|(lon, lat)| [lon, lat])
+                                .collect::<Vec<_>>(),
+                        },
+                    })
+                })
+        })
+        .collect();
+    let collection = serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    serde_json::to_writer(output, &collection).expect("Couldn't write GeoJSON");
+}
+
+fn export_h3(mut args: ExportH3Args, config: &Config) {
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct HexagonRow {
+        h3_index: String,
+        average_temperature: f32,
+        /// How many grid cells the average is over.
+        cells: usize,
+    }
+
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut writer = csv::Writer::from_writer(output);
+    for hexagon in hexagons::hexagon_averages(
+        &dataset,
+        args.compute.period(),
+        args.compute.units(),
+        args.resolution,
+    ) {
+        writer
+            .serialize(HexagonRow {
+                h3_index: hexagon.hexagon.to_string(),
+                average_temperature: hexagon.average,
+                cells: hexagon.cells,
+            })
+            .expect("Couldn't write hexagon row");
+    }
+}
+
+fn export_grid(mut args: ExportGridArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let grid = dataset.average_grid(args.compute.period(), args.compute.units());
+
+    let units = match args.compute.units() {
+        Units::Celsius => "degC",
+        Units::Fahrenheit => "degF",
+    };
+    let first_year = args
+        .compute
+        .start_year
+        .map_or("the start of the dataset".to_string(), |year| {
+            year.to_string()
+        });
+    let last_year = args
+        .compute
+        .end_year
+        .map_or("the end of the dataset".to_string(), |year| {
+            year.to_string()
+        });
+    ncwrite::write_grid(
+        &args.output,
+        &grid,
+        ncwrite::GridVariable {
+            name: "tmp",
+            long_name: "average near-surface temperature",
+            units,
+            cell_methods: Some("time: mean"),
+        },
+        &[
+            ("title", "Average temperature per grid cell".to_string()),
+            ("source", dataset_path.display().to_string()),
+            (
+                "comment",
+                format!("Averaged from {} to {}", first_year, last_year),
+            ),
+            (
+                "history",
+                format!("temperature-extractor {}", env!("CARGO_PKG_VERSION")),
+            ),
+        ],
+    )
+    .unwrap_or_else(|e| panic!("Couldn't write grid: {}", e));
+}
+
+fn subset(mut args: SubsetArgs, config: &Config) {
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let period = Period {
+        first_year: args.start_year,
+        last_year: args.end_year,
+    };
+    if opendap::is_url(&dataset_path) {
+        let url = dataset_path.to_str().expect("URLs are valid UTF-8");
+        opendap::subset(
+            url,
+            &args.output,
+            args.bbox,
+            period,
+            args.variable.as_deref(),
+        )
+        .unwrap_or_else(|e| panic!("Couldn't write subset: {}", e));
+    } else {
+        subset::subset(&dataset_path, &args.output, args.bbox, period)
+            .unwrap_or_else(|e| panic!("Couldn't write subset: {}", e));
+    }
+}
+
+fn update_dataset(args: UpdateDatasetArgs) {
+    match update::update(&args.directory, &args.source)
+        .unwrap_or_else(|e| panic!("Couldn't update dataset: {}", e))
+    {
+        update::Update::UpToDate(Some(last)) => println!("Already up to date through {}.", last),
+        update::Update::UpToDate(None) => println!("The update has no months."),
+        update::Update::Added {
+            path,
+            first,
+            last,
+            replaced,
+        } => {
+            for replaced in replaced {
+                println!(
+                    "Replaced {}, which this update revises.",
+                    replaced.display()
+                );
+            }
+            println!("Added {} through {} as {}.", first, last, path.display());
+        }
+    }
+}
+
+fn bbox(mut args: BboxArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let cells = bbox::cell_stats(
+        &dataset,
+        args.bbox,
+        args.compute.period(),
+        args.compute.units(),
+    );
+
+    let Some(path) = args
+        .output
+        .as_ref()
+        .filter(|path| path.extension().is_some_and(|extension| extension == "nc"))
+    else {
+        let output: Box<dyn std::io::Write> = match args.output {
+            Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+            None => Box::new(std::io::stdout()),
+        };
+        let mut writer = csv::Writer::from_writer(output);
+        for (_, stats) in &cells {
+            writer.serialize(stats).expect("Couldn't write cell row");
+        }
+        writer.flush().expect("Couldn't write output file");
+        return;
+    };
+
+    let grid_of = |value: fn(&bbox::CellStats) -> Option<f32>| {
+        crate::grid::Grid::from_cells(cells.iter().map(|(cell, stats)| (*cell, value(stats))))
+    };
+    let units = match args.compute.units() {
+        Units::Celsius => "degC",
+        Units::Fahrenheit => "degF",
+    };
+    let variable = |name, long_name, cell_methods| ncwrite::GridVariable {
+        name,
+        long_name,
+        units,
+        cell_methods,
+    };
+    ncwrite::write_grids(
+        path,
+        &[
+            (
+                &grid_of(|stats| stats.average_temperature),
+                variable(
+                    "tmp",
+                    "average near-surface temperature",
+                    Some("time: mean"),
+                ),
+            ),
+            (
+                &grid_of(|stats| stats.coldest_month),
+                variable(
+                    "tmp_min",
+                    "coldest month's temperature",
+                    Some("time: minimum"),
+                ),
+            ),
+            (
+                &grid_of(|stats| stats.warmest_month),
+                variable(
+                    "tmp_max",
+                    "warmest month's temperature",
+                    Some("time: maximum"),
+                ),
+            ),
+            (
+                &grid_of(|stats| stats.standard_deviation),
+                variable(
+                    "tmp_std",
+                    "standard deviation of monthly temperatures",
+                    Some("time: standard_deviation"),
+                ),
+            ),
+            (
+                &grid_of(|stats| stats.trend_per_decade),
+                variable("tmp_trend", "linear trend of yearly means per decade", None),
+            ),
+        ],
+        &[
+            (
+                "title",
+                "Temperature statistics per grid cell of a region".to_string(),
+            ),
+            ("source", dataset_path.display().to_string()),
+            (
+                "history",
+                format!("temperature-extractor {}", env!("CARGO_PKG_VERSION")),
+            ),
+        ],
+    )
+    .unwrap_or_else(|e| panic!("Couldn't write NetCDF file: {}", e));
+}
+
+fn read_cities(path: &Path) -> Result<Vec<City>, csv::Error> {
+    let csv = sinks::read(path).map_err(|e| csv::Error::from(std::io::Error::other(e)))?;
+    csv::Reader::from_reader(&csv[..]).deserialize().collect()
+}
+
+/// An output's cities as `read_cities` reads them, and the columns it doesn't have because an
+/// older version wrote it.
+fn read_previous_output(path: &Path) -> Result<(Vec<City>, Vec<String>), csv::Error> {
+    let csv = sinks::read(path).map_err(|e| csv::Error::from(std::io::Error::other(e)))?;
+    schema::read(&csv, &City::header())
+}
+
+/// Written aside first and then moved in place, so the file is whole even if the run is killed.
+fn write_cities(path: &Path, cities: &[City], normals: &[Normals], uncertainty: bool) {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut csv = vec![];
+    write_cities_to(&mut csv, cities, normals, uncertainty);
+    sinks::for_path(path)
+        .write(&csv, Path::new(&partial))
+        .unwrap_or_else(|e| panic!("Couldn't write output file {}: {}", path.display(), e));
+    fs::rename(&partial, path).expect("Couldn't move output file in place");
+}
+
+fn write_cities_to(
+    output_file: impl std::io::Write,
+    cities: &[City],
+    normals: &[Normals],
+    uncertainty: bool,
+) {
+    if normals.is_empty() {
+        let mut output_writer = csv::Writer::from_writer(output_file);
+        for city in cities.iter() {
+            output_writer
+                .serialize(city)
+                .expect("Couldn't write city out to output file");
+        }
+        output_writer.flush().expect("Couldn't write output file");
+        return;
+    }
+
+    // Serde can't name columns after the normals asked for, so the header is written by hand
+    // and every row as the city followed by its normals' columns.
+    let mut output_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(output_file);
+    let mut header = City::header();
+    for normals in normals {
+        header.push_field(&format!("AverageTemperature{}", normals));
+        if uncertainty {
+            header.push_field(&format!("AverageTemperatureUncertainty{}", normals));
+        }
+    }
+    output_writer
+        .write_record(&header)
+        .expect("Couldn't write header out to output file");
+    for city in cities.iter() {
+        let mut values = vec![];
+        for i in 0..normals.len() {
+            let (temperature, temperature_uncertainty) =
+                city.normals.get(i).copied().unwrap_or_default();
+            values.push(temperature);
+            if uncertainty {
+                values.push(temperature_uncertainty);
+            }
+        }
+        output_writer
+            .serialize((city, values))
+            .expect("Couldn't write city out to output file");
+    }
+    output_writer.flush().expect("Couldn't write output file");
+}
+
+/// Replace every row standing for all the cities of its country, e.g. `*,Iceland`, by the cities
+/// of the gazetteer or else Wikidata with at least --min-population inhabitants, leaving out
+/// those the input lists already.
+fn expand_countries(
+    cities: Vec<City>,
+    args: &RunArgs,
+    problems: &mut Vec<StrictProblem>,
+) -> Vec<City> {
+    if !cities.iter().any(|city| city.city == "*") {
+        return cities;
+    }
+    let gazetteer = args.gazetteer.as_ref().map(|path| {
+        input::read_cities(path, &[]).unwrap_or_else(|e| panic!("Couldn't read gazetteer: {}", e))
+    });
+    let key = |city: &City| (city.city.to_lowercase(), city.country.to_lowercase());
+    let mut listed: HashSet<(String, String)> = cities.iter().map(key).collect();
+    let mut expanded = vec![];
+    for row in cities {
+        if row.city != "*" {
+            expanded.push(row);
+            continue;
+        }
+        let country_cities: Vec<City> = match &gazetteer {
+            Some(gazetteer) => gazetteer
+                .iter()
+                .filter(|city| {
+                    city.country.eq_ignore_ascii_case(&row.country)
+                        && city
+                            .population
+                            .is_some_and(|population| population >= args.min_population)
+                })
+                .map(|city| City {
+                    city: city.city.clone(),
+                    region: city.region.clone(),
+                    wikidata_entity_id: city.wikidata_entity_id.clone(),
+                    wikidata_longitude: city.wikidata_longitude,
+                    wikidata_latitude: city.wikidata_latitude,
+                    population: city.population,
+                    ..Default::default()
+                })
+                .collect(),
+            None if !args.network_allowed() => {
+                problems.push(StrictProblem::new(
+                    &row,
+                    "listing every city of a country offline needs --gazetteer".to_string(),
+                ));
+                continue;
+            }
+            None => wikidata::list_cities(args.min_population, std::slice::from_ref(&row.country))
+                .expect("Couldn't list cities from wikidata")
+                .into_iter()
+                .map(|city| City {
+                    city: city.name,
+                    wikidata_entity_id: Some(city.entity_id),
+                    wikidata_longitude: Some(city.lon),
+                    wikidata_latitude: Some(city.lat),
+                    population: city.population,
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        if country_cities.is_empty() {
+            eprintln!(
+                "No city of {} has at least {} inhabitants.",
+                row.country, args.min_population
+            );
+        }
+        for city in country_cities {
+            // Spelled as in the input, which is what output rows are matched by.
+            let city = City {
+                country: row.country.clone(),
+                ..city
+            };
+            if listed.insert(key(&city)) {
+                expanded.push(city);
+            }
+        }
+    }
+    expanded
+}
+
+/// Replace input rows by their finished counterparts from a previous output, if any.
+/// Their temperatures are only kept if `keep_temperatures`,
+/// e.g. because the previous output was computed with the same parameters.
+/// Returns which cities still need geo information.
+fn merge_previous_output(cities: &mut [City], output: &Path, keep_temperatures: bool) -> Vec<bool> {
+    let key = |city: &City| (city.city.clone(), city.region.clone(), city.country.clone());
+    let (previous, missing) = match read_previous_output(output) {
+        Ok(previous) => previous,
+        Err(e) => {
+            if output.exists() {
+                eprintln!(
+                    "Couldn't read {} to merge with, so every city is looked up again: {}",
+                    output.display(),
+                    e
+                );
+            }
+            (vec![], vec![])
+        }
+    };
+    if !previous.is_empty() && !missing.is_empty() {
+        eprintln!(
+            "{} is from an older version without the {} columns. They're filled in from the \
+             cities and computed again, and written this time.",
+            output.display(),
+            missing.join(", ")
+        );
+    }
+    // Temperatures can't be kept when the columns computed with them aren't there.
+    let keep_temperatures = keep_temperatures && missing.is_empty();
+    let mut previous: HashMap<_, City> = previous
+        .into_iter()
+        .map(|city| (key(&city), city))
+        .collect();
+    cities
+        .iter_mut()
+        .map(|city| {
+            let key = key(city);
+            match previous.remove(&key) {
+                Some(done) if done.completes(city) => {
+                    *city = if missing.is_empty() {
+                        done
+                    } else {
+                        schema::fill(&done, city, &missing).unwrap_or_else(|e| {
+                            panic!("Couldn't upgrade {}: {}", output.display(), e)
+                        })
+                    };
+                    if !keep_temperatures {
+                        city.average_temperature = None;
+                    }
+                    false
+                }
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+/// The cities input, with its coordinates in WGS84, less the rows rejected by its checks.
+fn read_input(args: &RunArgs) -> Result<Vec<City>, input::CitiesReadErr> {
+    let (mut cities, non_utf8) = input::read_cities_from(
+        &args.sources,
+        args.input_format.as_deref(),
+        &args.cities,
+        &args.columns,
+    )?;
+    if let Some(crs) = &args.input_crs {
+        for city in &mut cities {
+            let to_wgs84 = |x: Option<f32>, y: Option<f32>| {
+                x.zip(y).map(|(x, y)| {
+                    crs.to_wgs84(x, y).unwrap_or_else(|e| {
+                        panic!(
+                            "Couldn't reproject {} {} of {}, {} from {}: {}",
+                            x, y, city.city, city.country, crs, e
+                        )
+                    })
+                })
+            };
+            if let Some((lon, lat)) = to_wgs84(city.wikidata_longitude, city.wikidata_latitude) {
+                city.wikidata_longitude = Some(lon);
+                city.wikidata_latitude = Some(lat);
+            }
+            if let Some((lon, lat)) = to_wgs84(city.override_longitude, city.override_latitude) {
+                city.override_longitude = Some(lon);
+                city.override_latitude = Some(lat);
+            }
+        }
+    }
+
+    let report = rules::check(&cities, &non_utf8);
+    for finding in &report.findings {
+        eprintln!("{}", finding);
+    }
+    if let Some(path) = &args.validation_report {
+        let file = File::create(path).expect("Couldn't open validation report");
+        serde_json::to_writer_pretty(file, &report).expect("Couldn't write validation report");
+    }
+    if args.strict && report.rejected > 0 {
+        serde_json::to_writer_pretty(std::io::stdout(), &report)
+            .expect("Couldn't write validation report");
+        println!();
+        std::process::exit(1);
+    }
+    let mut index = 0;
+    cities.retain(|_| {
+        index += 1;
+        !report.is_rejected(index - 1)
+    });
+    Ok(cities)
+}
+
+fn run(mut args: RunArgs, config: &Config) {
+    args.resolve(config);
+    if let Some(preset) = args.cities_preset {
+        let cities = preset
+            .cities()
+            .unwrap_or_else(|e| panic!("Couldn't get preset cities: {}", e));
+        write_cities(&args.cities, &cities, &[], false);
+    }
+    if let Some(url) = &args.cities_url {
+        input::download(url, &args.cities)
+            .unwrap_or_else(|e| panic!("Couldn't download cities: {}", e));
+    }
+    let cities = read_input(&args).expect("Couldn't read city data from input.");
+
+    if let Some(expected) = &args.dataset_sha256 {
+        let actual = metadata::sha256_of(&args.temperature_dataset)
+            .unwrap_or_else(|e| panic!("Couldn't hash temperature data: {}", e));
+        if !actual.eq_ignore_ascii_case(expected) {
+            panic!(
+                "Temperature data doesn't have the expected SHA-256 {}, but {}. Corrupted or truncated download?",
+                expected, actual
+            );
+        }
+    }
+    let dataset = args
+        .variable
+        .open(args.strict, |variable| match args.max_memory {
+            Some(max_bytes) => TemperatureDataset::open_within(
+                &args.temperature_dataset,
+                args.allow_truncated,
+                max_bytes,
+                args.coverage.coverage(),
+                variable.missing_values(),
+                variable.name.as_deref(),
+            ),
+            None => TemperatureDataset::open_with(
+                &args.temperature_dataset,
+                args.allow_truncated,
+                args.coverage.coverage(),
+                variable.missing_values(),
+                variable.name.as_deref(),
+            ),
+        })
+        .unwrap_or_else(|e| panic!("Couldn't read temperature data: {}", e));
+    if let Some(warning) = args.variable.warning() {
+        eprintln!("Warning: {}", warning);
+    }
+    refuse_for_climatology(
+        &dataset,
+        &[
+            args.compute.year_options().as_slice(),
+            &[
+                ("--normals", !args.normals.is_empty()),
+                ("--rolling", args.rolling.is_some()),
+                ("--decades", args.decades),
+                ("--uncertainty", args.uncertainty),
+                ("--outliers", args.outliers.is_some()),
+                ("--homogeneity", args.homogeneity.is_some()),
+                ("--return-levels", args.return_levels.is_some()),
+                ("--percentiles", args.percentiles.is_some()),
+                ("--fine-climatology", args.fine_climatology.is_some()),
+            ],
+        ]
+        .concat(),
+    );
+    if let Some(version) = versions::detect(&args.temperature_dataset)
+        .filter(|_| args.network_allowed() && args.check_version)
+    {
+        if let Some(hint) = versions::upgrade_hint(version) {
+            eprintln!("{}", hint);
+        }
+    }
+
+    let coastline = args.coastline.as_ref().map(|path| {
+        coast::Coastline::load(path).unwrap_or_else(|e| panic!("Couldn't load coastline: {}", e))
+    });
+
+    let elevations = args.elevation.as_ref().map(|path| {
+        elevation::ElevationGrid::load(path)
+            .unwrap_or_else(|e| panic!("Couldn't load elevation grid: {}", e))
+    });
+
+    let stations = args.stations.then(|| {
+        stations::StationCounts::open(&args.temperature_dataset)
+            .unwrap_or_else(|e| panic!("Couldn't read station counts: {}", e))
+    });
+
+    let country_averages = args.country_boundaries.as_ref().map(|path| {
+        let boundaries = countries::Boundaries::load(path)
+            .unwrap_or_else(|e| panic!("Couldn't load country boundaries: {}", e));
+        countries::CountryAverages::new(boundaries, &dataset, args.compute.period())
+    });
+
+    let fine_climatology = args.fine_climatology.as_ref().map(|path| {
+        downscale::FineClimatology::open(path, args.fine_climatology_period, &dataset)
+            .unwrap_or_else(|e| panic!("Couldn't read fine climatology: {}", e))
+    });
+
+    // Until now there was nothing to lose.
+    interrupt::install();
+    let extras = Extras {
+        coastline: coastline.as_ref(),
+        elevations: elevations.as_ref(),
+        stations: stations.as_ref(),
+        country_averages: country_averages.as_ref(),
+        fine_climatology: fine_climatology.as_ref(),
+    };
+    process_cities(&args, &dataset, extras, cities);
+
+    if args.watch {
+        watch(&args, &dataset, extras);
+    }
+}
+
+/// What a run loads once besides the dataset, for every city.
+#[derive(Clone, Copy)]
+struct Extras<'a> {
+    coastline: Option<&'a coast::Coastline>,
+    elevations: Option<&'a elevation::ElevationGrid>,
+    stations: Option<&'a stations::StationCounts>,
+    country_averages: Option<&'a countries::CountryAverages>,
+    fine_climatology: Option<&'a downscale::FineClimatology>,
+}
+
+/// Poll the cities file, and bring the output up to date whenever it changes.
+fn watch(args: &RunArgs, dataset: &TemperatureDataset, extras: Extras) -> ! {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_seen = modified(&args.cities);
+    println!("Watching {} for changes.", args.cities.display());
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        if interrupt::requested() {
+            std::process::exit(interrupt::EXIT_STATUS);
+        }
+        let current = modified(&args.cities);
+        if current == last_seen {
+            continue;
+        }
+        last_seen = current;
+        // Spreadsheet programs don't always write the file in one go,
+        // so a bad read just means waiting for the next change.
+        match read_input(args) {
+            Ok(cities) => process_cities(args, dataset, extras, cities),
+            Err(e) => eprintln!(
+                "Couldn't read {}, waiting for the next change: {}",
+                args.cities.display(),
+                e
+            ),
+        }
+    }
+}
+
+fn process_cities(args: &RunArgs, dataset: &TemperatureDataset, extras: Extras, cities: Vec<City>) {
+    let Extras {
+        coastline,
+        elevations,
+        stations,
+        country_averages,
+        fine_climatology,
+    } = extras;
+    let started_at = database::now();
+    let mut problems = vec![];
+    let mut cities = expand_countries(cities, args, &mut problems);
+    if let Some(shard) = args.shard {
+        cities.retain(|city| shard.contains(&city.city, city.region.as_deref(), &city.country));
+    }
+    let mut database = args.database.as_ref().map(|path| {
+        database::Database::open(path, database_key(args.database_key.as_deref()))
+            .unwrap_or_else(|e| panic!("Couldn't open database: {}", e))
+    });
+    let mut expired_lon_lats = HashMap::new();
+    if let Some(database) = &database {
+        let max_age = args.max_cache_age.map(|days| days * 24 * 60 * 60);
+        for (city_index, city) in cities.iter_mut().enumerate() {
+            if let Some(lon_lat) = database
+                .recall_geocode(city, max_age)
+                .expect("Couldn't read geocodes from database")
+            {
+                expired_lon_lats.insert(city_index, lon_lat);
+            }
+        }
+    }
+
+    let pending = if args.merge || args.watch {
+        // By contents, since a dataset can be updated in place or gain files matching its glob.
+        let same_parameters = metadata::RunMetadata::read(&args.output).is_ok_and(|previous| {
+            previous.parameters == args.parameters()
+                && metadata::sha256_of(&args.temperature_dataset)
+                    .is_ok_and(|sha256| sha256 == previous.temperature_dataset.sha256)
+        });
+        merge_previous_output(&mut cities, &args.output, same_parameters)
+    } else {
+        vec![true; cities.len()]
+    };
+
+    let points = args.points.as_ref().map(|path| {
+        points::WeightedPoints::load(path).unwrap_or_else(|e| panic!("Couldn't read points: {}", e))
+    });
+    let aliases = args.aliases.as_ref().map(|path| {
+        aliases::Aliases::load(path).unwrap_or_else(|e| panic!("Couldn't read aliases: {}", e))
+    });
+    let gazetteer = args
+        .gazetteer
+        .as_ref()
+        .filter(|_| args.offline)
+        .map(|path| {
+            find::Gazetteer::new(
+                input::read_cities(path, &[])
+                    .unwrap_or_else(|e| panic!("Couldn't read gazetteer: {}", e)),
+            )
+        });
+    let mut diagnostics = vec![];
+    let mut reports = reports::SeriesReports::new(args);
+    let mut interrupted_at = None;
+    for city_index in 0..(cities.len()) {
+        if interrupt::requested() {
+            interrupted_at = Some(city_index);
+            break;
+        }
+        {
+            let city = &mut cities[city_index];
+            let given_lon_lat = city
+                .override_lon_lat()
+                .or(city.wikidata_longitude.zip(city.wikidata_latitude));
+            // Normals aren't read back from a previous output, so those rows are computed again.
+            let (lon, lat) = if !pending[city_index]
+                && city.average_temperature.is_some()
+                && args.normals.is_empty()
+            {
+                continue;
+            } else if pending[city_index] && args.network_allowed() && !args.continue_from_review {
+                let name = aliases
+                    .as_ref()
+                    .and_then(|aliases| aliases.name_of(&city.city, &city.country));
+                let lon_lat = match city.fill_or_update_geo_information(
+                    args.geocoder.unwrap_or_default(),
+                    name,
+                    &mut TerminalHooks,
+                    interrupt::token(),
+                ) {
+                    Ok(lon_lat) => lon_lat,
+                    Err(wikidata::GeocodeErr::Interrupted) => {
+                        interrupted_at = Some(city_index);
+                        break;
+                    }
+                    Err(e) => panic!("Couldn't fill in geo information: {}", e),
+                };
+                if let (
+                    Some(database),
+                    Some(entity_id),
+                    Some(CoordinateSource::Wikidata | CoordinateSource::Nominatim),
+                ) = (
+                    &mut database,
+                    &city.wikidata_entity_id,
+                    city.coordinate_source,
+                ) {
+                    let known_as =
+                        wikidata::acquire_aliases(entity_id).expect("Couldn't look up aliases");
+                    database
+                        .record_aliases(&city.country, entity_id, &known_as)
+                        .expect("Couldn't record aliases in database");
+                }
+                if let Some(&cached) = expired_lon_lats.get(&city_index) {
+                    let moved = geo::distance::haversine_km(cached, lon_lat);
+                    if moved > args.stale_distance {
+                        eprintln!(
+                            "{}, {} moved {:.1} km on Wikidata since its coordinates were cached.",
+                            city.city, city.country, moved
+                        );
+                    }
+                }
+                lon_lat
+            } else if let Some(lon_lat) = given_lon_lat {
+                // Merged from a previous output but with different parameters, reviewed,
+                // or in strict or offline mode, where coordinates are never looked up.
+                city.use_fixed_lon_lat(lon_lat)
+            } else if let Some(gazetteer) = &gazetteer {
+                let name = aliases
+                    .as_ref()
+                    .and_then(|aliases| aliases.name_of(&city.city, &city.country))
+                    .unwrap_or(&city.city);
+                match gazetteer.named(name, city.region.as_deref(), &city.country)[..] {
+                    [place] => {
+                        city.coordinate_source = Some(CoordinateSource::Gazetteer);
+                        city.wikidata_longitude = Some(place.lon);
+                        city.wikidata_latitude = Some(place.lat);
+                        (place.lon, place.lat)
+                    }
+                    [] => {
+                        problems.push(StrictProblem::new(
+                            city,
+                            "no coordinates given, cached or in the gazetteer".to_string(),
+                        ));
+                        continue;
+                    }
+                    ref places => {
+                        problems.push(StrictProblem::new(
+                            city,
+                            format!(
+                                "{} places of that name in the gazetteer, give a region",
+                                places.len()
+                            ),
+                        ));
+                        continue;
+                    }
+                }
+            } else {
+                problems.push(StrictProblem::new(city, "no coordinates given".to_string()));
+                continue;
+            };
+            let (longitude, latitude) = match coordinates::lon_lat(lon, lat) {
+                Ok(lon_lat) => lon_lat,
+                Err(e) if args.strict => {
+                    problems.push(StrictProblem::new(city, e.to_string()));
+                    continue;
+                }
+                Err(e) => panic!(
+                    "Couldn't use coordinates of {}, {}: {}",
+                    city.city, city.country, e
+                ),
+            };
+            if args.pause_after_geocode {
+                if pending[city_index] && !args.strict {
+                    write_cities(&args.review_path(), &cities, &[], false);
+                }
+                continue;
+            }
+            let city_points = points.as_ref().and_then(|points| points.of(city));
+            match args.settings().downscaled_temperature_of(
+                dataset,
+                fine_climatology,
+                city_points,
+                longitude,
+                latitude,
+            ) {
+                Ok(temperature) => {
+                    city.average_temperature = Some(temperature);
+                    if args.uncertainty {
+                        city.average_temperature_uncertainty = args.settings().uncertainty_at(
+                            dataset,
+                            longitude,
+                            latitude,
+                            args.coverage.coverage(),
+                        );
+                    }
+                }
+                Err(e) if args.strict => {
+                    problems.push(StrictProblem::new(city, e.to_string()));
+                    continue;
+                }
+                Err(e) => panic!("Couldn't find average temperature: {}", e),
+            }
+            city.normals = args
+                .normals
+                .iter()
+                .map(|normals| {
+                    let settings = CitySettings {
+                        period: normals.period(),
+                        ..args.settings()
+                    };
+                    let temperature = settings
+                        .downscaled_temperature_of(
+                            dataset,
+                            fine_climatology,
+                            city_points,
+                            longitude,
+                            latitude,
+                        )
+                        .ok();
+                    let uncertainty = temperature.filter(|_| args.uncertainty).and_then(|_| {
+                        settings.uncertainty_at(
+                            dataset,
+                            longitude,
+                            latitude,
+                            args.coverage.coverage(),
+                        )
+                    });
+                    (temperature, uncertainty)
+                })
+                .collect();
+            city.urban_heat_island =
+                args.urban_heat_island
+                    .zip(city.population)
+                    .map(|(degrees, population)| {
+                        TemperatureDelta::from_celsius(urban_warming(degrees, population))
+                            .in_units(args.compute.units())
+                    });
+            if let Some(adjustment) = city.urban_heat_island {
+                city.average_temperature = city.average_temperature.map(|t| t + adjustment);
+                for (temperature, _) in &mut city.normals {
+                    *temperature = temperature.map(|t| t + adjustment);
+                }
+            }
+            if let Some(coastline) = coastline {
+                city.coast_distance = coastline.distance_km(lon, lat);
+            }
+            if let Some(elevations) = elevations {
+                city.elevation = city
+                    .elevation
+                    .or_else(|| elevations.at(longitude, latitude));
+                city.cell_elevation = elevations.cell_mean(longitude, latitude);
+                city.elevation_correction =
+                    city.elevation.zip(city.cell_elevation).map(|(city, cell)| {
+                        TemperatureDelta::from_celsius((cell - city) * elevation::LAPSE_RATE)
+                            .in_units(args.compute.units())
+                    });
+            }
+            if let Some(stations) = stations {
+                let summary = stations
+                    .summary(LonLatCell::at(longitude, latitude), args.compute.period())
+                    .unwrap_or_else(|e| panic!("Couldn't read station counts: {}", e));
+                city.average_stations = summary.map(|summary| summary.mean);
+                city.months_without_stations = summary.map(|summary| summary.zero_fraction);
+            }
+            if let Some(country_averages) = country_averages {
+                city.country_average_temperature = country_averages
+                    .of(&city.country)
+                    .map(|celsius| Temperature::new(celsius).in_units(args.compute.units()));
+                city.difference_from_country = city
+                    .average_temperature
+                    .zip(city.country_average_temperature)
+                    .map(|(city, country)| city - country);
+            }
+            let (shortest_day, longest_day) = daylight::shortest_and_longest_day(lat);
+            city.shortest_day = Some(shortest_day);
+            city.longest_day = Some(longest_day);
+            if args.timezones && (pending[city_index] || city.timezone.is_none()) {
+                if let Some(entity_id) = &city.wikidata_entity_id {
+                    city.timezone =
+                        wikidata::acquire_timezone(entity_id).expect("Couldn't look up time zone");
+                }
+            }
+            if args.diagnostics.is_some() {
+                diagnostics.push(CellDiagnostics::new(
+                    city,
+                    (longitude, latitude),
+                    args.settings(),
+                    dataset,
+                ));
+            }
+            if reports.wanted() {
+                let series = dataset.temperature_series_at(LonLatCell::at(longitude, latitude));
+                reports.add(city, &series);
+            }
+        }
+
+        // Nothing is slow enough to be worth saving progress for in strict mode.
+        if pending[city_index] && !args.strict {
+            write_cities(&args.output, &cities, &args.normals, args.uncertainty);
+        }
+    }
+    if let Some(done) = interrupted_at {
+        // Every row is written, those not reached yet without coordinates or temperature.
+        let saved_to = if args.pause_after_geocode {
+            write_cities(&args.review_path(), &cities, &[], false);
+            args.review_path()
+        } else {
+            write_cities(&args.output, &cities, &args.normals, args.uncertainty);
+            args.output.clone()
+        };
+        if let Some(database) = &mut database {
+            database
+                .record_geocodes(&cities[..done], started_at)
+                .expect("Couldn't record geocodes in database");
+        }
+        eprintln!(
+            "Interrupted after {} of {} cities, saved to {}.",
+            done,
+            cities.len(),
+            saved_to.display()
+        );
+        if args.pause_after_geocode {
+            if args.database.is_some() {
+                eprintln!("Run the same command again, the coordinates found so far are kept in the database.");
+            }
+        } else {
+            eprintln!("Run the same command again with --merge to pick up where this one stopped.");
+        }
+        std::process::exit(interrupt::EXIT_STATUS);
+    }
+    if !problems.is_empty() {
+        serde_json::to_writer_pretty(std::io::stdout(), &StrictReport { problems })
+            .expect("Couldn't write strict mode report");
+        println!();
+        std::process::exit(1);
+    }
+    if args.pause_after_geocode {
+        write_cities(&args.review_path(), &cities, &[], false);
+        println!(
+            "Wrote the coordinates of {} cities to {}. Check them, then run again with \
+             --continue-from-review to compute their temperatures.",
+            cities.len(),
+            args.review_path().display()
+        );
+        return;
+    }
+    if let Some(sort_by) = args.sort_by {
+        sort_by.sort(&mut cities);
+    }
+    write_cities(&args.output, &cities, &args.normals, args.uncertainty);
+    for output in &args.outputs {
+        write_cities(output, &cities, &args.normals, args.uncertainty);
+    }
+    if let Some(split::SplitBy::Country) = args.split_by {
+        let directory = split::directory(&args.output);
+        let groups = split::by_country(
+            cities.iter().map(|city| city.country.as_str()),
+            country_averages,
+        );
+        for (group, indices) in &groups {
+            let path = split::path(&directory, group, &args.output);
+            fs::create_dir_all(path.parent().expect("Inside the directory"))
+                .unwrap_or_else(|e| panic!("Couldn't create {}: {}", directory.display(), e));
+            let group_cities: Vec<City> = indices.iter().map(|&i| cities[i].clone()).collect();
+            write_cities(&path, &group_cities, &args.normals, args.uncertainty);
+        }
+        eprintln!(
+            "Wrote the cities of {} countries under {}.",
+            groups.len(),
+            directory.display()
+        );
+    }
+    if let Some(path) = &args.diagnostics {
+        let mut writer = csv::Writer::from_path(path).expect("Couldn't open diagnostics file");
+        for row in diagnostics {
+            writer
+                .serialize(row)
+                .expect("Couldn't write diagnostics row");
+        }
+    }
+    reports.write();
+
+    let metadata = metadata::RunMetadata {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        temperature_dataset: metadata::FileFingerprint::of(&args.temperature_dataset)
+            .expect("Couldn't fingerprint temperature dataset"),
+        cities: metadata::FileFingerprint::of(&args.cities)
+            .expect("Couldn't fingerprint cities file"),
+        output: metadata::FileFingerprint::of(&args.output)
+            .expect("Couldn't fingerprint output file"),
+        dataset_version: versions::detect(&args.temperature_dataset)
+            .map(|version| version.to_string()),
+        parameters: args.parameters(),
+        warnings: args.warnings(),
+    };
+    metadata.write().expect("Couldn't write run metadata");
+
+    if let Some(database) = &mut database {
+        database
+            .record_run(
+                &cities,
+                &database::Run {
+                    started_at,
+                    tool_version: metadata.tool_version,
+                    dataset_path: metadata.temperature_dataset.path.display().to_string(),
+                    dataset_sha256: metadata.temperature_dataset.sha256,
+                    parameters: metadata.parameters,
+                    output_path: metadata.output.path.display().to_string(),
+                    cities: cities.len(),
+                },
+            )
+            .expect("Couldn't record run in database");
+    }
+}
+
+fn bench(mut args: BenchArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let report = bench::bench(&dataset_path, &args.compute, args.cities, args.seed)
+        .unwrap_or_else(|e| panic!("Couldn't read temperature data: {}", e));
+
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    serde_json::to_writer_pretty(output, &report).expect("Couldn't write report");
+}
+
+fn global_stats(mut args: GlobalStatsArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = args
+        .variable
+        .open(false, |variable| {
+            TemperatureDataset::open_with(
+                &dataset_path,
+                false,
+                args.coverage.coverage(),
+                variable.missing_values(),
+                variable.name.as_deref(),
+            )
+        })
+        .expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let stats = global::global_stats(
+        &dataset,
+        args.compute.period(),
+        args.compute.units(),
+        args.sample.map(|cells| (cells, args.seed)),
+        args.coverage.coverage(),
+    );
+
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    serde_json::to_writer_pretty(output, &stats).expect("Couldn't write report");
+}
+
+fn latitude_bands(mut args: LatitudeBandsArgs, config: &Config) {
+    args.compute.resolve(config);
+    let bands = match args.cities {
+        Some(ref path) => {
+            let cities = read_cities(path).expect("Couldn't read output file");
+            let total = cities.len();
+            let located: Vec<(f32, f32)> = cities
+                .into_iter()
+                .filter_map(|city| Some((city.wikidata_latitude?, city.average_temperature?)))
+                .collect();
+            if located.len() < total {
+                eprintln!(
+                    "Left out {} cities without coordinates or a temperature.",
+                    total - located.len()
+                );
+            }
+            bands::of_cities(located, args.band_width)
+        }
+        None => {
+            let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+            let dataset =
+                TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+            bands::of_cells(
+                &dataset,
+                args.compute.period(),
+                args.band_width,
+                args.compute.units(),
+            )
+        }
+    };
+
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    for band in bands {
+        writer
+            .serialize(band)
+            .expect("Couldn't write latitude band row");
+    }
+}
+
+fn validate(mut args: ValidateArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = args
+        .variable
+        .open(false, |variable| {
+            TemperatureDataset::open_with(
+                &dataset_path,
+                false,
+                args.coverage.coverage(),
+                variable.missing_values(),
+                variable.name.as_deref(),
+            )
+        })
+        .expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let settings = CitySettings {
+        period: args.compute.period(),
+        units: args.compute.units(),
+        neighborhood: Neighborhood {
+            size: args.neighborhood,
+            distance_weighted: args.distance_weighted,
+        },
+        h3_resolution: None,
+        statistic: args.statistic,
+    };
+    let report = validate::validate(&dataset, settings);
+    match (report.bias, report.rmse) {
+        (Some(bias), Some(rmse)) => eprintln!(
+            "Bias {:+.2}, RMSE {:.2} over {} reference cities ({} skipped).",
+            bias, rmse, report.cities, report.skipped
+        ),
+        _ => eprintln!("None of the reference cities could be computed from this dataset."),
+    }
+
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    serde_json::to_writer_pretty(output, &report).expect("Couldn't write report");
+}
+
+fn quality(mut args: QualityArgs, config: &Config) {
+    #[derive(Serialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct CellRow {
+        longitude: f32,
+        latitude: f32,
+        missing_fraction: f64,
+    }
+
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = args
+        .variable
+        .open(false, |variable| {
+            TemperatureDataset::open_with(
+                &dataset_path,
+                false,
+                args.coverage.coverage(),
+                variable.missing_values(),
+                variable.name.as_deref(),
+            )
+        })
+        .expect("Couldn't read temperature data");
+    let (report, cells) = quality::quality(&dataset, args.threshold);
+    eprintln!(
+        "{:.1}% of values missing. {} of {} time steps and {} of {} regions are missing more than {}%.",
+        report.missing_fraction * 100.0,
+        report.by_time_step.iter().filter(|time| time.poor).count(),
+        report.time_steps,
+        report.by_region.iter().filter(|region| region.poor).count(),
+        report.by_region.len(),
+        args.threshold * 100.0
+    );
+
+    if let Some(path) = &args.cells {
+        let mut writer = csv::Writer::from_path(path).expect("Couldn't open cells file");
+        for ((longitude, latitude), missing_fraction) in cells {
+            writer
+                .serialize(CellRow {
+                    longitude,
+                    latitude,
+                    missing_fraction,
+                })
+                .expect("Couldn't write cells row");
+        }
+    }
+    let output: Box<dyn std::io::Write> = match args.output {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open output file")),
+        None => Box::new(std::io::stdout()),
+    };
+    serde_json::to_writer_pretty(output, &report).expect("Couldn't write report");
+}
+
+fn export_package(args: ExportPackageArgs) {
+    let descriptor = package::export(
+        &args.output,
+        &args.directory,
+        args.format,
+        args.title.as_deref(),
+        args.license.as_deref(),
+    )
+    .unwrap_or_else(|e| panic!("Couldn't export package: {}", e));
+    println!("Wrote {}", descriptor.display());
+}
+
+fn site(mut args: SiteArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data");
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let cities = read_cities(&args.output).expect("Couldn't read output file");
+    let pages = site::render(
+        &args.site,
+        &cities,
+        &dataset,
+        args.compute.period(),
+        args.compute.units(),
+    )
+    .unwrap_or_else(|e| panic!("Couldn't write site: {}", e));
+    if pages < cities.len() {
+        eprintln!(
+            "Left out {} cities without coordinates or temperature data.",
+            cities.len() - pages
+        );
+    }
+    println!(
+        "Wrote {} city pages to {}",
+        pages,
+        args.site.join("index.html").display()
+    );
+}
+
+#[cfg(feature = "sql")]
+fn sql(mut args: SqlArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset = args.series.then(|| {
+        let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+        TemperatureDataset::new(&dataset_path).expect("Couldn't read temperature data")
+    });
+    let series = dataset.as_ref().map(|dataset| sql::SeriesTable {
+        dataset,
+        period: args.compute.period(),
+        units: args.compute.units(),
+    });
+    let result: Box<dyn std::io::Write> = match args.result {
+        Some(ref path) => Box::new(File::create(path).expect("Couldn't open result file")),
+        None => Box::new(std::io::stdout()),
+    };
+    sql::query(&args.output, series, &args.query, result)
+        .unwrap_or_else(|e| panic!("Couldn't run query: {}", e));
+}
+
+#[cfg(feature = "grpc")]
+fn serve(mut args: ServeArgs, config: &Config) {
+    args.compute.resolve(config);
+    let dataset_path = temperature_dataset_path(args.temperature_dataset.take(), config);
+    let dataset = args
+        .variable
+        .open(false, |variable| match args.max_memory {
+            Some(max_bytes) => TemperatureDataset::open_within(
+                &dataset_path,
+                args.allow_truncated,
+                max_bytes,
+                args.coverage.coverage(),
+                variable.missing_values(),
+                variable.name.as_deref(),
+            ),
+            None => TemperatureDataset::open_with(
+                &dataset_path,
+                args.allow_truncated,
+                args.coverage.coverage(),
+                variable.missing_values(),
+                variable.name.as_deref(),
+            ),
+        })
+        .unwrap_or_else(|e| panic!("Couldn't read temperature data: {}", e));
+    refuse_for_climatology(&dataset, &args.compute.year_options());
+    let defaults = CitySettings {
+        period: args.compute.period(),
+        units: args.compute.units(),
+        ..Default::default()
+    };
+    let api_keys = args.api_keys.as_ref().map(|path| {
+        auth::read_api_keys(path).unwrap_or_else(|e| panic!("Couldn't read API keys: {}", e))
+    });
+    let gazetteer = args.gazetteer.as_ref().map(|path| {
+        let gazetteer =
+            find::Gazetteer::new(input::read_cities(path, &[]).expect("Couldn't read gazetteer"));
+        if gazetteer.is_empty() {
+            eprintln!("Warning: the gazetteer has no city with coordinates.");
+        }
+        gazetteer
+    });
+    serve::serve(
+        dataset,
+        defaults,
+        config.geocoder.unwrap_or_default(),
+        gazetteer,
+        serve::Options {
+            address: args.address,
+            metrics_address: args.metrics_address,
+            cache: cache::AverageCache::new(
+                args.cache_size,
+                args.cache_ttl.map(Duration::from_secs),
+            ),
+            api_keys,
+            rate_limit: args.rate_limit,
+            flight_dir: args.flight_dir,
+        },
+    );
+}
@@ -0,0 +1,45 @@
+//! Other names cities go by, e.g. Bombay for Mumbai or Saigon for Ho Chi Minh City, so a cities
+//! file using an old or local name is geocoded as the name the geocoders know.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AliasRow {
+    alias: String,
+    city: String,
+    /// Only for cities of this country, when an alias is ambiguous.
+    country: Option<String>,
+}
+
+pub struct Aliases {
+    /// By lowercased alias and country, the country being None for aliases of every country.
+    by_alias: HashMap<(String, Option<String>), String>,
+}
+
+impl Aliases {
+    /// A CSV file with Alias and City columns, and optionally Country, with one row per alias.
+    pub fn load(path: &Path) -> Result<Self, csv::Error> {
+        let mut by_alias = HashMap::new();
+        for row in csv::Reader::from_path(path)?.deserialize() {
+            let row: AliasRow = row?;
+            let country = row.country.filter(|country| !country.is_empty());
+            by_alias.insert(
+                (row.alias.to_lowercase(), country.map(|c| c.to_lowercase())),
+                row.city,
+            );
+        }
+        Ok(Self { by_alias })
+    }
+
+    /// The name to geocode a city by, if it's an alias. Ignores case.
+    pub fn name_of(&self, city: &str, country: &str) -> Option<&str> {
+        let alias = city.to_lowercase();
+        self.by_alias
+            .get(&(alias.clone(), Some(country.to_lowercase())))
+            .or_else(|| self.by_alias.get(&(alias, None)))
+            .map(String::as_str)
+    }
+}
@@ -0,0 +1,126 @@
+//! Keeping `serve` usable when it's exposed publicly: requests can be required to carry one of
+//! a list of API keys, and each key (or each client address, without keys) can be limited to a
+//! number of requests per minute.
+
+use crate::cli::metrics::Metrics;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// One key per line, ignoring blank lines and lines starting with '#'.
+pub fn read_api_keys(path: &Path) -> io::Result<HashSet<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// How long a bucket takes to refill completely, after which it's no different from a new one.
+const REFILL: Duration = Duration::from_secs(60);
+
+/// A token bucket per client, refilled continuously, so a client can burst up to a minute's worth.
+pub struct RateLimiter {
+    per_minute: f64,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    /// Tokens left as of when they were last taken.
+    by_client: HashMap<String, (f64, Instant)>,
+    /// When full buckets were last dropped, so clients that went away don't take up memory.
+    pruned: Instant,
+}
+
+/// Who a request counts against, as the gate worked it out, for what charges more than the
+/// request itself.
+#[derive(Clone)]
+pub struct Client(String);
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute: per_minute.into(),
+            buckets: Mutex::new(Buckets {
+                by_client: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Whether `client` still has `requests` left, taking them all if so and none otherwise.
+    pub fn allow(&self, client: &Client, requests: usize) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("No user panicked");
+        if now.duration_since(buckets.pruned) >= REFILL {
+            buckets
+                .by_client
+                .retain(|_, (_, last)| now.duration_since(*last) < REFILL);
+            buckets.pruned = now;
+        }
+        let (tokens, last) = buckets
+            .by_client
+            .entry(client.0.clone())
+            .or_insert((self.per_minute, now));
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.per_minute / 60.0)
+            .min(self.per_minute);
+        *last = now;
+        let requests = requests as f64;
+        if *tokens >= requests {
+            *tokens -= requests;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Checks every request before it reaches the service.
+#[derive(Clone)]
+pub struct Gate {
+    /// None lets every request through.
+    pub api_keys: Option<Arc<HashSet<String>>>,
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub metrics: Arc<Metrics>,
+}
+
+/// From `authorization: Bearer <key>`, or `x-api-key: <key>`.
+fn api_key<T>(request: &Request<T>) -> Option<&str> {
+    let metadata = request.metadata();
+    metadata
+        .get("authorization")
+        .and_then(|value| value.to_str().ok()?.strip_prefix("Bearer "))
+        .or_else(|| metadata.get("x-api-key")?.to_str().ok())
+}
+
+impl Interceptor for Gate {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let key = api_key(&request);
+        if let Some(api_keys) = &self.api_keys {
+            if !key.is_some_and(|key| api_keys.contains(key)) {
+                self.metrics.rejected("unauthenticated");
+                return Err(Status::unauthenticated("missing or unknown API key"));
+            }
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let client = Client(match (key, request.remote_addr()) {
+                (Some(key), _) if self.api_keys.is_some() => key.to_string(),
+                (_, Some(address)) => address.ip().to_string(),
+                (_, None) => String::new(),
+            });
+            if !rate_limiter.allow(&client, 1) {
+                self.metrics.rejected("rate_limited");
+                return Err(Status::resource_exhausted("rate limit exceeded, slow down"));
+            }
+            // For batches to be charged the rest of their queries.
+            request.extensions_mut().insert(client);
+        }
+        Ok(request)
+    }
+}
@@ -0,0 +1,100 @@
+//! `latitude-bands`: the mean temperature of each band of latitude, over every cell with data or
+//! over the cities of an output. Temperatures should fall off from the tropics towards the poles,
+//! so a band out of line points at a wrong variable, units or grid; and it's the textbook picture
+//! of climate by latitude.
+
+use crate::config::Units;
+use crate::{AreaWeightedMean, Period, Temperature, TemperatureDataset};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LatitudeBand {
+    /// Inclusive, in degrees.
+    pub south: f32,
+    /// Exclusive but for the northernmost band, which includes the pole.
+    pub north: f32,
+    /// Cells or cities in the band with a temperature.
+    pub count: usize,
+    /// None for bands without any.
+    pub average_temperature: Option<f32>,
+}
+
+/// A band width in degrees, dividing the globe into at least one band.
+pub fn parse_band_width(s: &str) -> Result<f32, String> {
+    let width: f32 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(width > 0.0 && width <= 180.0) {
+        return Err("must be more than 0 and at most 180 degrees".to_string());
+    }
+    Ok(width)
+}
+
+/// From the south pole up, the last one narrower when `width` doesn't divide 180.
+fn bands(width: f32) -> Vec<(f32, f32)> {
+    let count = (180.0 / width).ceil() as usize;
+    (0..count)
+        .map(|i| {
+            let south = -90.0 + i as f32 * width;
+            (south, (south + width).min(90.0))
+        })
+        .collect()
+}
+
+fn band_of(lat: f32, width: f32, count: usize) -> usize {
+    (((lat + 90.0) / width) as usize).min(count - 1)
+}
+
+/// Every cell with data averaged over `period`, then weighted by its area within its band,
+/// see `AreaWeightedMean`.
+pub fn of_cells(
+    dataset: &TemperatureDataset,
+    period: Period,
+    width: f32,
+    units: Units,
+) -> Vec<LatitudeBand> {
+    let bands = bands(width);
+    let mut means = vec![AreaWeightedMean::default(); bands.len()];
+    for (cell, series) in dataset.series_by_cell() {
+        let series = series
+            .into_iter()
+            .filter(|&(time, _)| period.contains(time));
+        let Ok(average) = Temperature::average(series) else {
+            continue;
+        };
+        means[band_of(cell.center().1, width, bands.len())].add(cell, average.celsius as f64);
+    }
+    bands
+        .into_iter()
+        .zip(means)
+        .map(|((south, north), mean)| LatitudeBand {
+            south,
+            north,
+            count: mean.cells,
+            average_temperature: mean
+                .mean()
+                .map(|mean| Temperature::new(mean as f32).in_units(units)),
+        })
+        .collect()
+}
+
+/// Cities, as their latitude and average temperature in `units`, counted equally: a band's mean
+/// is that of where the cities are, not of the band's land.
+pub fn of_cities(cities: impl IntoIterator<Item = (f32, f32)>, width: f32) -> Vec<LatitudeBand> {
+    let bands = bands(width);
+    let mut sums = vec![(0, 0.0); bands.len()];
+    for (lat, temperature) in cities {
+        let (count, sum) = &mut sums[band_of(lat, width, bands.len())];
+        *count += 1;
+        *sum += temperature as f64;
+    }
+    bands
+        .into_iter()
+        .zip(sums)
+        .map(|((south, north), (count, sum))| LatitudeBand {
+            south,
+            north,
+            count,
+            average_temperature: (count > 0).then(|| (sum / count as f64) as f32),
+        })
+        .collect()
+}
@@ -0,0 +1,101 @@
+//! `bbox`: statistics of every cell of the dataset within a rectangle, for regional extracts
+//! that open straight in a spreadsheet or GIS.
+
+use crate::config::Units;
+use crate::resample::Coverage;
+use crate::stats;
+use crate::{BoundingBox, LonLatCell, Period, Temperature, TemperatureDataset, TemperatureDelta};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CellStats {
+    /// Of the cell's center.
+    pub longitude: f32,
+    pub latitude: f32,
+    /// The rest are None for cells missing any month of the period.
+    pub average_temperature: Option<f32>,
+    pub coldest_month: Option<f32>,
+    pub warmest_month: Option<f32>,
+    /// Of the monthly temperatures, around the average.
+    pub standard_deviation: Option<f32>,
+    /// Least squares slope of the yearly means, per decade. None with fewer than two
+    /// complete years.
+    pub trend_per_decade: Option<f32>,
+    /// In the period.
+    pub months: usize,
+}
+
+/// Every cell with data over `period` whose center is within `bounding_box`, row by row from the south west.
+pub fn cell_stats(
+    dataset: &TemperatureDataset,
+    bounding_box: BoundingBox,
+    period: Period,
+    units: Units,
+) -> Vec<(LonLatCell, CellStats)> {
+    let mut cells: Vec<(LonLatCell, CellStats)> = dataset
+        .series_of_cells_where(
+            |cell| {
+                let (lon, lat) = cell.center();
+                bounding_box.contains(lon, lat)
+            },
+            |_| true,
+        )
+        .filter_map(|(cell, series)| {
+            let in_period: Vec<_> = series
+                .iter()
+                .copied()
+                .filter(|&(time, _)| period.contains(time))
+                .collect();
+            // Ocean, for CRU TS.
+            if in_period
+                .iter()
+                .all(|(_, temperature)| temperature.is_none())
+            {
+                return None;
+            }
+            let celsius: Option<Vec<f32>> = in_period
+                .iter()
+                .map(|(_, temperature)| temperature.map(|t| t.celsius))
+                .collect();
+            let celsius = celsius.filter(|values| !values.is_empty());
+            let mean = celsius
+                .as_ref()
+                .map(|values| values.iter().sum::<f32>() / values.len() as f32);
+            let in_units = |celsius: f32| Temperature::new(celsius).in_units(units);
+            let delta_in_units =
+                |celsius: f32| TemperatureDelta::from_celsius(celsius).in_units(units);
+            let years: Vec<(f64, f64)> = stats::annual_means(&series, period, Coverage::default())
+                .into_iter()
+                .map(|(year, mean)| (year as f64, mean))
+                .collect();
+            let (lon, lat) = cell.center();
+            let stats = CellStats {
+                longitude: lon,
+                latitude: lat,
+                average_temperature: mean.map(in_units),
+                coldest_month: celsius
+                    .as_ref()
+                    .and_then(|values| values.iter().copied().reduce(f32::min))
+                    .map(in_units),
+                warmest_month: celsius
+                    .as_ref()
+                    .and_then(|values| values.iter().copied().reduce(f32::max))
+                    .map(in_units),
+                standard_deviation: celsius.as_ref().zip(mean).map(|(values, mean)| {
+                    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>()
+                        / values.len() as f32;
+                    delta_in_units(variance.sqrt())
+                }),
+                trend_per_decade: celsius
+                    .as_ref()
+                    .and_then(|_| stats::trend(&years))
+                    .map(|trend| delta_in_units((trend.slope * 10.0) as f32)),
+                months: in_period.len(),
+            };
+            Some((cell, stats))
+        })
+        .collect();
+    cells.sort_by_key(|(cell, _)| (cell.half_degrees_lat_start, cell.half_degrees_lon_start));
+    cells
+}
@@ -0,0 +1,135 @@
+//! Timing the main stages of a run on a synthetic workload, so performance can be compared
+//! across releases and datasets without needing a cities file or the network.
+
+use crate::cli::ComputeArgs;
+use crate::coordinates::{self, Latitude, Longitude};
+use crate::{CitySettings, TemperatureDataset, TemperatureDatasetReadErr};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub tool_version: &'static str,
+    pub temperature_dataset: String,
+    pub observations: usize,
+    pub cells: usize,
+    pub cities: usize,
+    pub stages: Vec<Stage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stage {
+    pub name: &'static str,
+    pub seconds: f64,
+    /// How many items the stage went through, e.g. cities for lookups.
+    pub items: usize,
+    pub items_per_second: f64,
+}
+
+impl Stage {
+    fn timed<T>(
+        name: &'static str,
+        work: impl FnOnce() -> T,
+        items: impl Fn(&T) -> usize,
+    ) -> (Self, T) {
+        let start = Instant::now();
+        let result = work();
+        let seconds = start.elapsed().as_secs_f64();
+        let items = items(&result);
+        (
+            Self {
+                name,
+                seconds,
+                items,
+                items_per_second: items as f64 / seconds.max(f64::EPSILON),
+            },
+            result,
+        )
+    }
+}
+
+/// A small xorshift generator, so the same seed always gives the same workload.
+pub struct Xorshift(pub u64);
+
+impl Xorshift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in `0.0..1.0`.
+    pub fn fraction(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Loads the dataset, indexes it by cell, looks up `cities` random points within cells
+/// that have data, and averages every cell, timing each.
+pub fn bench(
+    path: &Path,
+    compute: &ComputeArgs,
+    cities: usize,
+    seed: u64,
+) -> Result<BenchReport, TemperatureDatasetReadErr> {
+    let (load, dataset) = Stage::timed(
+        "load",
+        || TemperatureDataset::new(path),
+        |dataset| dataset.as_ref().map_or(0, |d| d.len()),
+    );
+    let dataset = dataset?;
+
+    let (index, cells) = Stage::timed("index", || dataset.series_by_cell(), Vec::len);
+    let land: Vec<_> = cells
+        .iter()
+        .filter(|(_, series)| series.iter().any(|(_, temp)| temp.is_some()))
+        .map(|(cell, _)| cell.center())
+        .collect();
+
+    // Xorshift never leaves zero.
+    let mut random = Xorshift(seed.max(1));
+    let points: Vec<(Longitude, Latitude)> = (0..cities)
+        .filter(|_| !land.is_empty())
+        .map(|_| {
+            let (lon, lat) = land[random.next() as usize % land.len()];
+            // Anywhere within the half degree cell.
+            coordinates::lon_lat(
+                lon + random.fraction() * 0.5 - 0.25,
+                lat + random.fraction() * 0.5 - 0.25,
+            )
+            .expect("Cells are on the globe")
+        })
+        .collect();
+    let settings = CitySettings {
+        period: compute.period(),
+        units: compute.units(),
+        ..Default::default()
+    };
+    let (lookup, _) = Stage::timed(
+        "lookup",
+        || {
+            points
+                .iter()
+                .map(|&(lon, lat)| settings.temperature_at(&dataset, lon, lat))
+                .collect::<Vec<_>>()
+        },
+        Vec::len,
+    );
+
+    let (statistics, _) = Stage::timed(
+        "statistics",
+        || dataset.average_grid(compute.period(), compute.units()),
+        |_| cells.len(),
+    );
+
+    Ok(BenchReport {
+        tool_version: env!("CARGO_PKG_VERSION"),
+        temperature_dataset: path.display().to_string(),
+        observations: dataset.len(),
+        cells: cells.len(),
+        cities: points.len(),
+        stages: vec![load, index, lookup, statistics],
+    })
+}
@@ -0,0 +1,369 @@
+//! `RunBuilder`, a run of the command line configured in code rather than with arguments: which
+//! dataset, which cities, how to find the ones without coordinates and how to compute their
+//! temperatures, and where to write them, with the cities' results returned as well, e.g.
+//! `RunBuilder::new().dataset(path).cities("cities.csv").years(1991..=2020).output("out.parquet").execute()`.
+//! Progress and the questions geocoding has go to its `Hooks`, in the terminal by default, and a
+//! `CancellationToken` stops it between cities with the ones done so far.
+
+use super::hooks::{CityName, Hooks, TerminalHooks};
+use super::sources::Sources;
+use super::{input, sinks, write_cities_to, City};
+use crate::config::{Geocoder, Units};
+use crate::resample::Coverage;
+use crate::stats::Statistic;
+use crate::{
+    coordinates, hexagons, CancellationToken, CitySettings, DatasetKind, MissingValues,
+    Neighborhood, Period, TemperatureDataset, TemperatureDatasetReadErr,
+};
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Which cells a city's temperature comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Interpolation {
+    /// The cell the city is in.
+    #[default]
+    Cell,
+    /// The `size` by `size` block of cells centered on the city's, `size` being odd, weighted by
+    /// the inverse of their distance to the city if `distance_weighted` and equally otherwise.
+    Neighborhood {
+        size: usize,
+        distance_weighted: bool,
+    },
+    /// The cells in the H3 hexagon of this resolution containing the city, less ocean cells.
+    Hexagon(u8),
+}
+
+/// A city to run on, when they're not read from a file.
+#[derive(Debug, Clone, Default)]
+pub struct CityInput {
+    pub city: String,
+    pub region: Option<String>,
+    pub country: String,
+    /// Looked up with the geocoder when left out.
+    pub longitude: Option<f32>,
+    pub latitude: Option<f32>,
+}
+
+/// A city's result, in the order the cities were given.
+#[derive(Debug, Clone)]
+pub struct CityResult {
+    pub city: String,
+    pub region: Option<String>,
+    pub country: String,
+    pub wikidata_entity_id: Option<String>,
+    pub longitude: Option<f32>,
+    pub latitude: Option<f32>,
+    /// In the run's units. None when there's a problem.
+    pub average_temperature: Option<f32>,
+    /// Why the city has no temperature: no coordinates, a failed lookup, or no data there.
+    pub problem: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RunResult {
+    /// Only the cities run before it was cancelled, if it was.
+    pub cities: Vec<CityResult>,
+    pub cancelled: bool,
+}
+
+#[derive(Debug)]
+pub enum RunErr {
+    NoDataset,
+    NoCities,
+    /// A setting that can't be run with, e.g. an even neighborhood.
+    Setting(String),
+    Dataset(String),
+    Cities(String),
+    Output(PathBuf, String),
+    /// Before the dataset was loaded, so there's nothing to show for the run.
+    Cancelled,
+}
+
+impl Display for RunErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            RunErr::NoDataset => write!(fmt, "no temperature dataset given"),
+            RunErr::NoCities => write!(fmt, "no cities given"),
+            RunErr::Setting(e) => write!(fmt, "{}", e),
+            RunErr::Dataset(e) => write!(fmt, "can't read temperature dataset: {}", e),
+            RunErr::Cities(e) => write!(fmt, "can't read cities: {}", e),
+            RunErr::Output(path, e) => write!(fmt, "can't write {}: {}", path.display(), e),
+            RunErr::Cancelled => write!(fmt, "cancelled"),
+        }
+    }
+}
+
+impl Error for RunErr {}
+
+enum Cities {
+    Path(PathBuf),
+    Given(Vec<CityInput>),
+}
+
+#[derive(Default)]
+pub struct RunBuilder {
+    /// Several for a dataset split over files, joined like on the command line.
+    datasets: Vec<PathBuf>,
+    cities: Option<Cities>,
+    sources: Sources,
+    geocoder: Option<Geocoder>,
+    statistic: Statistic,
+    interpolation: Interpolation,
+    years: Option<RangeInclusive<i32>>,
+    units: Units,
+    outputs: Vec<PathBuf>,
+    hooks: Option<Box<dyn Hooks>>,
+    cancel: CancellationToken,
+}
+
+impl RunBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A dataset file, or a glob or list like on the command line. Called several times, the files
+    /// together make up the dataset, e.g. one per decade.
+    pub fn dataset(mut self, path: impl Into<PathBuf>) -> Self {
+        self.datasets.push(path.into());
+        self
+    }
+
+    /// Read the cities from a file, in any format `sources` reads.
+    pub fn cities(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cities = Some(Cities::Path(path.into()));
+        self
+    }
+
+    pub fn city_list(mut self, cities: Vec<CityInput>) -> Self {
+        self.cities = Some(Cities::Given(cities));
+        self
+    }
+
+    /// What reads the cities file, the built-in formats by default.
+    pub fn sources(mut self, sources: Sources) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// Look up the coordinates of the cities without any. Without a geocoder, nothing touches the
+    /// network and those cities are problems.
+    pub fn geocoder(mut self, geocoder: Geocoder) -> Self {
+        self.geocoder = Some(geocoder);
+        self
+    }
+
+    /// How each cell's months are summed up, the mean by default.
+    pub fn statistic(mut self, statistic: Statistic) -> Self {
+        self.statistic = statistic;
+        self
+    }
+
+    pub fn interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// Only average over these years, rather than the whole dataset.
+    pub fn years(mut self, years: RangeInclusive<i32>) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    pub fn units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Also write the cities to this file, in the format of its extension like the command
+    /// line's `--output`. Can be called several times.
+    pub fn output(mut self, path: impl Into<PathBuf>) -> Self {
+        self.outputs.push(path.into());
+        self
+    }
+
+    /// What's told of each city's progress and asked which search result is the city, instead of
+    /// the terminal.
+    pub fn hooks(mut self, hooks: impl Hooks + 'static) -> Self {
+        self.hooks = Some(Box::new(hooks));
+        self
+    }
+
+    /// Keep a clone of `cancel` to stop the run from elsewhere, e.g. another thread. Loading the
+    /// dataset stops between its time steps, geocoding between searches, and the cities between
+    /// cities, whose results so far are still returned and written to the outputs.
+    pub fn cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn execute(mut self) -> Result<RunResult, RunErr> {
+        let settings = self.settings()?;
+        if self.datasets.is_empty() {
+            return Err(RunErr::NoDataset);
+        }
+        // Separated the way `multifile::dataset_files` splits them on this platform, which a path
+        // given once can already be.
+        let dataset_path = match &self.datasets[..] {
+            [path] => path.clone().into_os_string(),
+            paths => env::join_paths(paths).map_err(|e| RunErr::Dataset(e.to_string()))?,
+        };
+        let dataset = TemperatureDataset::open_cancellable(
+            Path::new(&dataset_path),
+            false,
+            Coverage::default(),
+            MissingValues::default(),
+            None,
+            &self.cancel,
+        )
+        .map_err(|e| match e {
+            TemperatureDatasetReadErr::Cancelled => RunErr::Cancelled,
+            e => RunErr::Dataset(e.to_string()),
+        })?;
+        if self.years.is_some() && dataset.kind() == DatasetKind::Climatology {
+            return Err(RunErr::Setting(
+                "the dataset is a climatology, with one of each month and no years to pick from"
+                    .to_string(),
+            ));
+        }
+
+        let mut cities: Vec<City> = match self.cities {
+            None => return Err(RunErr::NoCities),
+            Some(Cities::Path(path)) => {
+                input::read_cities_from(&self.sources, None, &path, &[])
+                    .map_err(|e| RunErr::Cities(e.to_string()))?
+                    .0
+            }
+            Some(Cities::Given(given)) => given
+                .into_iter()
+                .map(|given| City {
+                    city: given.city,
+                    region: given.region,
+                    country: given.country,
+                    wikidata_longitude: given.longitude,
+                    wikidata_latitude: given.latitude,
+                    ..Default::default()
+                })
+                .collect(),
+        };
+
+        let hooks: &mut dyn Hooks = match &mut self.hooks {
+            Some(hooks) => hooks.as_mut(),
+            None => &mut TerminalHooks,
+        };
+        let total = cities.len();
+        let mut results = vec![];
+        for (index, city) in cities.iter_mut().enumerate() {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let name = CityName {
+                city: &city.city,
+                region: city.region.as_deref(),
+                country: &city.country,
+            };
+            hooks.on_city_started(index, total, name);
+            let problem =
+                temperature_of(city, &dataset, settings, self.geocoder, hooks, &self.cancel).err();
+            let result = result_of(city, problem);
+            hooks.on_city_completed(index, total, &result);
+            results.push(result);
+        }
+        if !self.outputs.is_empty() {
+            let mut csv = vec![];
+            write_cities_to(&mut csv, &cities, &[], false);
+            for output in &self.outputs {
+                sinks::for_path(output)
+                    .write(&csv, output)
+                    .map_err(|e| RunErr::Output(output.clone(), e.to_string()))?;
+            }
+        }
+        Ok(RunResult {
+            cancelled: results.len() < total,
+            cities: results,
+        })
+    }
+
+    fn settings(&self) -> Result<CitySettings, RunErr> {
+        let (neighborhood, h3_resolution) = match self.interpolation {
+            Interpolation::Cell => (Neighborhood::default(), None),
+            Interpolation::Neighborhood {
+                size,
+                distance_weighted,
+            } => {
+                if size % 2 == 0 {
+                    return Err(RunErr::Setting(format!(
+                        "the neighborhood must be an odd number of cells wide, not {}",
+                        size
+                    )));
+                }
+                (
+                    Neighborhood {
+                        size,
+                        distance_weighted,
+                    },
+                    None,
+                )
+            }
+            Interpolation::Hexagon(resolution) => {
+                hexagons::parse_resolution(&resolution.to_string()).map_err(RunErr::Setting)?;
+                (Neighborhood::default(), Some(resolution))
+            }
+        };
+        Ok(CitySettings {
+            period: Period {
+                first_year: self.years.as_ref().map(|years| *years.start()),
+                last_year: self.years.as_ref().map(|years| *years.end()),
+            },
+            units: self.units,
+            neighborhood,
+            h3_resolution,
+            statistic: self.statistic,
+        })
+    }
+}
+
+fn result_of(city: &City, problem: Option<String>) -> CityResult {
+    CityResult {
+        city: city.city.clone(),
+        region: city.region.clone(),
+        country: city.country.clone(),
+        wikidata_entity_id: city.wikidata_entity_id.clone(),
+        longitude: city.override_longitude.or(city.wikidata_longitude),
+        latitude: city.override_latitude.or(city.wikidata_latitude),
+        average_temperature: city.average_temperature,
+        problem,
+    }
+}
+
+/// Fills in `city`'s coordinates, looking them up with `geocoder` if need be, and temperature.
+fn temperature_of(
+    city: &mut City,
+    dataset: &TemperatureDataset,
+    settings: CitySettings,
+    geocoder: Option<Geocoder>,
+    hooks: &mut dyn Hooks,
+    cancel: &CancellationToken,
+) -> Result<(), String> {
+    let (lon, lat) = match (
+        city.override_lon_lat()
+            .or(city.wikidata_longitude.zip(city.wikidata_latitude)),
+        geocoder,
+    ) {
+        (Some(lon_lat), _) => city.use_fixed_lon_lat(lon_lat),
+        (None, Some(geocoder)) => city
+            .fill_or_update_geo_information(geocoder, None, hooks, cancel)
+            .map_err(|e| e.to_string())?,
+        (None, None) => return Err("no coordinates given, and no geocoder".to_string()),
+    };
+    let (lon, lat) = coordinates::lon_lat(lon, lat).map_err(|e| e.to_string())?;
+    city.average_temperature = Some(
+        settings
+            .temperature_at(dataset, lon, lat)
+            .map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
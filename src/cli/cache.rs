@@ -0,0 +1,49 @@
+//! `serve`'s cache of cell averages, so popular cities are answered without going through the
+//! dataset again. Least recently used entries make room for new ones, and entries can also be
+//! given a time to live.
+
+use crate::{LonLatCell, Period};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A cell and the first and last years of a period.
+type Key = (LonLatCell, Option<i32>, Option<i32>);
+
+pub struct AverageCache {
+    /// None when caching is turned off.
+    entries: Option<Mutex<LruCache<Key, (f32, Instant)>>>,
+    ttl: Option<Duration>,
+}
+
+impl AverageCache {
+    /// A `size` of 0 caches nothing.
+    pub fn new(size: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            entries: NonZeroUsize::new(size).map(|size| Mutex::new(LruCache::new(size))),
+            ttl,
+        }
+    }
+
+    /// The cached average in celsius, if there is one that hasn't expired.
+    pub fn get(&self, cell: LonLatCell, period: Period) -> Option<f32> {
+        let mut entries = self.entries.as_ref()?.lock().expect("No user panicked");
+        let key = (cell, period.first_year, period.last_year);
+        let &(celsius, cached_at) = entries.get(&key)?;
+        if self.ttl.is_some_and(|ttl| cached_at.elapsed() > ttl) {
+            entries.pop(&key);
+            return None;
+        }
+        Some(celsius)
+    }
+
+    pub fn put(&self, cell: LonLatCell, period: Period, celsius: f32) {
+        if let Some(entries) = &self.entries {
+            entries.lock().expect("No user panicked").put(
+                (cell, period.first_year, period.last_year),
+                (celsius, Instant::now()),
+            );
+        }
+    }
+}
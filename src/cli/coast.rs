@@ -0,0 +1,94 @@
+//! Distance to the nearest coast, from a GeoJSON coastline such as Natural Earth's
+//! https://www.naturalearthdata.com/downloads/10m-physical-vectors/10m-coastline/
+
+use crate::geo::distance;
+use serde::Deserialize;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    geometry: Geometry,
+}
+
+/// Polygon rings (e.g. from a land dataset) are as good as lines.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    LineString {
+        coordinates: Vec<[f32; 2]>,
+    },
+    MultiLineString {
+        coordinates: Vec<Vec<[f32; 2]>>,
+    },
+    Polygon {
+        coordinates: Vec<Vec<[f32; 2]>>,
+    },
+    MultiPolygon {
+        coordinates: Vec<Vec<Vec<[f32; 2]>>>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug)]
+pub enum CoastlineErr {
+    Read(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl Display for CoastlineErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            CoastlineErr::Read(e) => write!(fmt, "can't read coastline: {}", e),
+            CoastlineErr::Parse(e) => write!(fmt, "can't parse coastline GeoJSON: {}", e),
+        }
+    }
+}
+
+pub struct Coastline {
+    /// Lines of (longitude, latitude) points.
+    lines: Vec<Vec<[f32; 2]>>,
+}
+
+impl Coastline {
+    pub fn load(path: &Path) -> Result<Self, CoastlineErr> {
+        let file = File::open(path).map_err(CoastlineErr::Read)?;
+        let collection: FeatureCollection =
+            serde_json::from_reader(BufReader::new(file)).map_err(CoastlineErr::Parse)?;
+        let lines = collection
+            .features
+            .into_iter()
+            .flat_map(|feature| match feature.geometry {
+                Geometry::LineString { coordinates } => vec![coordinates],
+                Geometry::MultiLineString { coordinates } | Geometry::Polygon { coordinates } => {
+                    coordinates
+                }
+                Geometry::MultiPolygon { coordinates } => {
+                    coordinates.into_iter().flatten().collect()
+                }
+                Geometry::Other => vec![],
+            })
+            .collect();
+        Ok(Self { lines })
+    }
+
+    /// Great circle distance in km to the nearest coast.
+    pub fn distance_km(&self, lon: f32, lat: f32) -> Option<f32> {
+        self.lines
+            .iter()
+            .flat_map(|line| line.windows(2))
+            .map(|segment| {
+                let [start, end] = [segment[0], segment[1]].map(|[lon, lat]| (lon, lat));
+                distance::segment_km((lon, lat), start, end)
+            })
+            .min_by(f32::total_cmp)
+    }
+}
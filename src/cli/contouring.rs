@@ -0,0 +1,140 @@
+//! Marching squares over a grid of cell centers.
+//! See https://en.wikipedia.org/wiki/Marching_squares
+
+use crate::grid::Grid;
+use std::collections::HashMap;
+
+/// An edge between two neighbouring cell centers, identified by its lower-left end
+/// and whether it goes east (horizontal) or north (vertical).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Edge {
+    x: usize,
+    y: usize,
+    horizontal: bool,
+}
+
+impl Edge {
+    fn east(x: usize, y: usize) -> Self {
+        Self {
+            x,
+            y,
+            horizontal: true,
+        }
+    }
+
+    fn north(x: usize, y: usize) -> Self {
+        Self {
+            x,
+            y,
+            horizontal: false,
+        }
+    }
+
+    fn ends(&self) -> ((usize, usize), (usize, usize)) {
+        if self.horizontal {
+            ((self.x, self.y), (self.x + 1, self.y))
+        } else {
+            ((self.x, self.y), (self.x, self.y + 1))
+        }
+    }
+}
+
+/// Lines along which the grid crosses `level`, as (longitude, latitude) points.
+/// Lines stop where the grid has no data; closed lines start and end on the same point.
+pub fn isolines(grid: &Grid, level: f32) -> Vec<Vec<(f32, f32)>> {
+    let segments = segments(grid, level);
+
+    let mut segments_at: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, &(a, b)) in segments.iter().enumerate() {
+        segments_at.entry(a).or_default().push(i);
+        segments_at.entry(b).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    // Follow the chain of unused segments from `edge`, returning the edges passed through.
+    let walk = |mut edge: Edge, used: &mut Vec<bool>| {
+        let mut line = vec![];
+        while let Some(&next) = segments_at[&edge].iter().find(|&&s| !used[s]) {
+            used[next] = true;
+            let (a, b) = segments[next];
+            edge = if a == edge { b } else { a };
+            line.push(edge);
+        }
+        line
+    };
+
+    let mut lines = vec![];
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let (a, b) = segments[start];
+        let forward = walk(b, &mut used);
+        let backward = walk(a, &mut used);
+        let line: Vec<Edge> = backward
+            .into_iter()
+            .rev()
+            .chain([a, b])
+            .chain(forward)
+            .collect();
+        lines.push(
+            line.into_iter()
+                .map(|edge| crossing(grid, edge, level))
+                .collect(),
+        );
+    }
+    lines
+}
+
+/// Where along `edge` the grid crosses `level`, interpolating linearly between the cell centers.
+fn crossing(grid: &Grid, edge: Edge, level: f32) -> (f32, f32) {
+    let ((x0, y0), (x1, y1)) = edge.ends();
+    let (v0, v1) = (grid.get(x0, y0).unwrap(), grid.get(x1, y1).unwrap());
+    let t = (level - v0) / (v1 - v0);
+    let (lon0, lat0) = grid.cell(x0, y0).center();
+    let (lon1, lat1) = grid.cell(x1, y1).center();
+    (lon0 + t * (lon1 - lon0), lat0 + t * (lat1 - lat0))
+}
+
+/// Each square of four neighbouring cell centers contributes one or two segments
+/// joining the edges the level crosses.
+fn segments(grid: &Grid, level: f32) -> Vec<(Edge, Edge)> {
+    let mut segments = vec![];
+    for y in 0..grid.height.saturating_sub(1) {
+        for x in 0..grid.width.saturating_sub(1) {
+            // Counter-clockwise from the lower left.
+            let corners = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)];
+            let Some(values) = corners
+                .iter()
+                .map(|&(cx, cy)| grid.get(cx, cy))
+                .collect::<Option<Vec<f32>>>()
+            else {
+                continue;
+            };
+            let above: Vec<bool> = values.iter().map(|&v| v >= level).collect();
+            // Edge i joins corner i to corner i + 1.
+            let edges = [
+                Edge::east(x, y),
+                Edge::north(x + 1, y),
+                Edge::east(x, y + 1),
+                Edge::north(x, y),
+            ];
+            let crossed: Vec<usize> = (0..4).filter(|&i| above[i] != above[(i + 1) % 4]).collect();
+            match crossed.len() {
+                2 => segments.push((edges[crossed[0]], edges[crossed[1]])),
+                4 => {
+                    // A saddle: the level crosses all four edges.
+                    // The corners on the other side from the middle of the square
+                    // get cut off by a segment each.
+                    let middle_above = values.iter().sum::<f32>() / 4.0 >= level;
+                    for corner in (0..4).filter(|&i| above[i] != middle_above) {
+                        segments.push((edges[(corner + 3) % 4], edges[corner]));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    segments
+}
@@ -0,0 +1,336 @@
+//! Which grid cells fall in each country, from a GeoJSON of country boundaries such as Natural Earth's
+//! https://www.naturalearthdata.com/downloads/10m-cultural-vectors/10m-admin-0-countries/
+//!
+//! Testing every cell against every country is slow, so the result is cached in the platform's
+//! cache directory (or next to the boundaries without one), keyed by the cells the dataset has
+//! data for and the boundaries' checksum.
+
+use crate::cli::metadata;
+use crate::config;
+use crate::{AreaWeightedMean, LonLatCell, Period, Temperature, TemperatureDataset};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    #[serde(default)]
+    properties: Map<String, Value>,
+    geometry: Option<Geometry>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    Polygon {
+        coordinates: Vec<Vec<[f32; 2]>>,
+    },
+    MultiPolygon {
+        coordinates: Vec<Vec<Vec<[f32; 2]>>>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug)]
+pub enum CountriesErr {
+    Read(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl Display for CountriesErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            CountriesErr::Read(e) => write!(fmt, "can't read country boundaries: {}", e),
+            CountriesErr::Parse(e) => write!(fmt, "can't parse country boundaries GeoJSON: {}", e),
+        }
+    }
+}
+
+pub struct Country {
+    pub name: String,
+    /// ISO 3166-1 alpha-3 code, when the boundaries have one.
+    pub iso: Option<String>,
+    /// Polygons as rings of (longitude, latitude) points, the first ring being the outside.
+    polygons: Vec<Vec<Vec<[f32; 2]>>>,
+    /// (west, south, east, north), to skip most cells without looking at the rings.
+    bounds: [f32; 4],
+}
+
+/// The first of `keys` with a usable string value. Natural Earth uses "-99" for missing codes.
+fn property(properties: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| properties.get(*key)?.as_str())
+        .find(|value| !value.is_empty() && *value != "-99")
+        .map(str::to_string)
+}
+
+impl Country {
+    /// Even-odd rule over every ring, so holes (e.g. Lesotho in South Africa) are left out.
+    fn contains(&self, lon: f32, lat: f32) -> bool {
+        let [west, south, east, north] = self.bounds;
+        if lon < west || lon > east || lat < south || lat > north {
+            return false;
+        }
+        let mut inside = false;
+        for ring in self.polygons.iter().flatten() {
+            for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+                if (a[1] > lat) != (b[1] > lat)
+                    && lon < a[0] + (lat - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+                {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+/// A country's area-weighted mean temperature over a period.
+pub struct CountryAverage {
+    /// How many cells with data have their center in the country.
+    pub cells: usize,
+    /// In celsius. None for countries too small to hold a cell's center, e.g. most islands.
+    pub celsius: Option<f32>,
+}
+
+pub struct Boundaries {
+    pub countries: Vec<Country>,
+    path: PathBuf,
+    sha256: String,
+}
+
+impl Boundaries {
+    pub fn load(path: &Path) -> Result<Self, CountriesErr> {
+        let sha256 = metadata::sha256_of(path).map_err(CountriesErr::Read)?;
+        let file = File::open(path).map_err(CountriesErr::Read)?;
+        let collection: FeatureCollection =
+            serde_json::from_reader(BufReader::new(file)).map_err(CountriesErr::Parse)?;
+        let countries = collection
+            .features
+            .into_iter()
+            .filter_map(|feature| {
+                let polygons = match feature.geometry? {
+                    Geometry::Polygon { coordinates } => vec![coordinates],
+                    Geometry::MultiPolygon { coordinates } => coordinates,
+                    Geometry::Other => return None,
+                };
+                let name = property(
+                    &feature.properties,
+                    &["ADMIN", "NAME", "admin", "name", "NAME_EN", "name_en"],
+                )?;
+                let iso = property(
+                    &feature.properties,
+                    &["ISO_A3", "iso_a3", "ADM0_A3", "adm0_a3"],
+                );
+                let mut bounds = [f32::MAX, f32::MAX, f32::MIN, f32::MIN];
+                for &[lon, lat] in polygons.iter().flatten().flatten() {
+                    bounds = [
+                        bounds[0].min(lon),
+                        bounds[1].min(lat),
+                        bounds[2].max(lon),
+                        bounds[3].max(lat),
+                    ];
+                }
+                Some(Country {
+                    name,
+                    iso,
+                    polygons,
+                    bounds,
+                })
+            })
+            .collect();
+        Ok(Self {
+            countries,
+            path: path.to_path_buf(),
+            sha256,
+        })
+    }
+
+    /// The cells whose center is in each country, in the same order as `countries`.
+    /// Read from the cache when it was made from the same boundaries and the same cells.
+    pub fn cells_by_country(&self, cells: &[LonLatCell]) -> Vec<Vec<LonLatCell>> {
+        let cache_path = self.cache_path(cells);
+        if let Some(cached) = self.read_cache(&cache_path) {
+            return cached;
+        }
+        let by_country: Vec<Vec<LonLatCell>> = self
+            .countries
+            .iter()
+            .map(|country| {
+                cells
+                    .iter()
+                    .copied()
+                    .filter(|cell| {
+                        let (lon, lat) = cell.center();
+                        country.contains(lon, lat)
+                    })
+                    .collect()
+            })
+            .collect();
+        // Only a cache, the cells are right either way.
+        if let Err(e) = self.write_cache(&cache_path, &by_country) {
+            eprintln!("Couldn't cache cells by country: {}", e);
+        }
+        by_country
+    }
+
+    /// Every country's average over `period`, in the same order as `countries`.
+    pub fn averages(&self, dataset: &TemperatureDataset, period: Period) -> Vec<CountryAverage> {
+        // Every cell with data averaged over the period once, countries sharing none of them.
+        let averages: HashMap<LonLatCell, Temperature> = dataset
+            .series_by_cell()
+            .into_iter()
+            .filter_map(|(cell, series)| {
+                let series = series
+                    .into_iter()
+                    .filter(|&(time, _)| period.contains(time));
+                Some((cell, Temperature::average(series).ok()?))
+            })
+            .collect();
+        let cells: Vec<LonLatCell> = averages.keys().copied().collect();
+        self.cells_by_country(&cells)
+            .into_iter()
+            .map(|cells| {
+                let mean: AreaWeightedMean = cells
+                    .iter()
+                    .map(|&cell| (cell, averages[&cell].celsius as f64))
+                    .collect();
+                CountryAverage {
+                    cells: cells.len(),
+                    celsius: mean.mean().map(|mean| mean as f32),
+                }
+            })
+            .collect()
+    }
+
+    /// The country a city's Country column names, by name or ISO code, ignoring case.
+    fn find(&self, name: &str) -> Option<usize> {
+        self.countries.iter().position(|country| {
+            country.name.eq_ignore_ascii_case(name)
+                || country
+                    .iso
+                    .as_deref()
+                    .is_some_and(|iso| iso.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// e.g. `countries.geojson.cells-1a2b3c4d5e6f7a8b.json`, the suffix telling grids apart,
+    /// under `countries/<boundaries checksum>/` in the cache directory.
+    fn cache_path(&self, cells: &[LonLatCell]) -> PathBuf {
+        let mut sorted: Vec<(i16, i16)> = cells
+            .iter()
+            .map(|cell| (cell.half_degrees_lon_start, cell.half_degrees_lat_start))
+            .collect();
+        sorted.sort_unstable();
+        let mut hasher = Sha256::new();
+        for (lon, lat) in sorted {
+            hasher.update(lon.to_le_bytes());
+            hasher.update(lat.to_le_bytes());
+        }
+        let grid: String = hasher.finalize()[..8]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".cells-{}.json", grid));
+        match config::cache_dir() {
+            Some(dir) => dir.join("countries").join(&self.sha256[..16]).join(name),
+            None => self.path.with_file_name(name),
+        }
+    }
+
+    fn read_cache(&self, path: &Path) -> Option<Vec<Vec<LonLatCell>>> {
+        let file = File::open(path).ok()?;
+        let cache: CellCache = serde_json::from_reader(BufReader::new(file)).ok()?;
+        // Boundaries edited in place keep their name.
+        if cache.boundaries_sha256 != self.sha256 || cache.countries.len() != self.countries.len() {
+            return None;
+        }
+        Some(
+            cache
+                .countries
+                .into_iter()
+                .map(|cells| {
+                    cells
+                        .into_iter()
+                        .map(|[lon, lat]| LonLatCell {
+                            half_degrees_lon_start: lon,
+                            half_degrees_lat_start: lat,
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    fn write_cache(&self, path: &Path, by_country: &[Vec<LonLatCell>]) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let cache = CellCache {
+            boundaries_sha256: self.sha256.clone(),
+            countries: by_country
+                .iter()
+                .map(|cells| {
+                    cells
+                        .iter()
+                        .map(|cell| [cell.half_degrees_lon_start, cell.half_degrees_lat_start])
+                        .collect()
+                })
+                .collect(),
+        };
+        // Written aside and moved into place, so an interrupted run doesn't leave half a cache,
+        // under a name of its own so runs working it out at the same time don't mix theirs.
+        let partial = path.with_extension(format!("json.partial-{}", std::process::id()));
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut writer, &cache)?;
+        writer.flush()?;
+        fs::rename(partial, path)
+    }
+}
+
+/// Every country's average, to compare cities with their country's.
+pub struct CountryAverages {
+    boundaries: Boundaries,
+    averages: Vec<CountryAverage>,
+}
+
+impl CountryAverages {
+    pub fn new(boundaries: Boundaries, dataset: &TemperatureDataset, period: Period) -> Self {
+        let averages = boundaries.averages(dataset, period);
+        Self {
+            boundaries,
+            averages,
+        }
+    }
+
+    /// In celsius. None when the boundaries have no such country, or it has no cell.
+    pub fn of(&self, country: &str) -> Option<f32> {
+        self.averages[self.boundaries.find(country)?].celsius
+    }
+
+    /// The ISO code of the country a city's Country column names, when the boundaries have one.
+    pub fn iso(&self, country: &str) -> Option<&str> {
+        self.boundaries.countries[self.boundaries.find(country)?]
+            .iso
+            .as_deref()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CellCache {
+    boundaries_sha256: String,
+    /// Each country's cells as [half degrees longitude, half degrees latitude] of their corner.
+    countries: Vec<Vec<[i16; 2]>>,
+}
@@ -0,0 +1,49 @@
+//! Coordinates given in another coordinate reference system than WGS84's longitude and latitude,
+//! e.g. a national grid's eastings and northings, reprojected with proj4rs.
+
+use proj4rs::Proj;
+use std::fmt::{self, Display};
+
+/// What `--input-crs` names, e.g. `EPSG:27700` or a proj string.
+#[derive(Clone)]
+pub struct Crs {
+    name: String,
+    proj: Proj,
+}
+
+impl fmt::Debug for Crs {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(fmt, "Crs({})", self.name)
+    }
+}
+
+impl Display for Crs {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        fmt.write_str(&self.name)
+    }
+}
+
+pub fn parse_crs(s: &str) -> Result<Crs, String> {
+    let proj = Proj::from_user_string(s).map_err(|e| format!("{}: {}", s, e))?;
+    Ok(Crs {
+        name: s.to_string(),
+        proj,
+    })
+}
+
+impl Crs {
+    /// `x` and `y` in the CRS's own units, e.g. meters east and north, or degrees for
+    /// geographic ones, to WGS84 longitude and latitude in degrees.
+    pub fn to_wgs84(&self, x: f32, y: f32) -> Result<(f32, f32), proj4rs::errors::Error> {
+        let wgs84 = Proj::from_proj_string("+proj=longlat +datum=WGS84 +no_defs")
+            .expect("WGS84 is a valid projection");
+        let (x, y) = if self.proj.is_latlong() {
+            ((x as f64).to_radians(), (y as f64).to_radians())
+        } else {
+            (x as f64, y as f64)
+        };
+        let mut point = (x, y, 0.0);
+        proj4rs::transform::transform(&self.proj, &wgs84, &mut point)?;
+        Ok((point.0.to_degrees() as f32, point.1.to_degrees() as f32))
+    }
+}
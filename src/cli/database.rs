@@ -0,0 +1,443 @@
+//! A project database keeping the list of cities, the geocoding decisions made for them,
+//! every temperature computed for them and the history of runs, across runs.
+//!
+//! With a key, the file is encrypted (see `encryption`) and the database only ever decrypted in
+//! memory, being written back whole after every change.
+//!
+//! Several runs can share a database, e.g. over shards of a city list. SQLite does the locking
+//! for a plaintext one, waiting on the others' writes. An encrypted one is changed by reading it
+//! again, changing it and writing it back while holding a lock on `<DATABASE>.lock`, so no run
+//! overwrites what another has just written.
+
+use crate::cli::encryption::{EncryptionErr, Key};
+use crate::cli::{City, CoordinateSource};
+use rusqlite::{params, Connection, OptionalExtension, MAIN_DB};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS cities (
+        id INTEGER PRIMARY KEY,
+        city TEXT NOT NULL,
+        country TEXT NOT NULL,
+        UNIQUE (city, country)
+    );
+    CREATE TABLE IF NOT EXISTS geocodes (
+        city_id INTEGER PRIMARY KEY REFERENCES cities (id) ON DELETE CASCADE,
+        wikidata_entity_id TEXT,
+        longitude REAL NOT NULL,
+        latitude REAL NOT NULL,
+        -- Seconds since the epoch.
+        geocoded_at INTEGER NOT NULL DEFAULT 0
+    );
+    -- Other names Wikidata knows geocoded entities by, to recognize renamed cities.
+    CREATE TABLE IF NOT EXISTS aliases (
+        alias TEXT NOT NULL COLLATE NOCASE,
+        country TEXT NOT NULL,
+        wikidata_entity_id TEXT NOT NULL,
+        PRIMARY KEY (alias, country)
+    );
+    CREATE TABLE IF NOT EXISTS temperatures (
+        city_id INTEGER NOT NULL REFERENCES cities (id) ON DELETE CASCADE,
+        dataset_sha256 TEXT NOT NULL,
+        parameters TEXT NOT NULL,
+        average_temperature REAL NOT NULL,
+        PRIMARY KEY (city_id, dataset_sha256, parameters)
+    );
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        started_at INTEGER NOT NULL,
+        tool_version TEXT NOT NULL,
+        dataset_path TEXT NOT NULL,
+        dataset_sha256 TEXT NOT NULL,
+        parameters TEXT NOT NULL,
+        output_path TEXT NOT NULL,
+        cities INTEGER NOT NULL
+    );
+";
+
+/// Starts every plaintext SQLite database file.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// How long to wait for another run's write to a plaintext database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub enum DatabaseErr {
+    Sqlite(rusqlite::Error),
+    Io(io::Error),
+    Encryption(EncryptionErr),
+}
+
+impl Display for DatabaseErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            DatabaseErr::Sqlite(e) => write!(fmt, "{}", e),
+            DatabaseErr::Io(e) => write!(fmt, "{}", e),
+            DatabaseErr::Encryption(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DatabaseErr {
+    fn from(e: rusqlite::Error) -> Self {
+        DatabaseErr::Sqlite(e)
+    }
+}
+
+impl From<io::Error> for DatabaseErr {
+    fn from(e: io::Error) -> Self {
+        DatabaseErr::Io(e)
+    }
+}
+
+impl From<EncryptionErr> for DatabaseErr {
+    fn from(e: EncryptionErr) -> Self {
+        DatabaseErr::Encryption(e)
+    }
+}
+
+pub struct Database {
+    connection: Connection,
+    /// The file and key to write an encrypted database back with, the connection then being
+    /// to a copy in memory.
+    encrypted: Option<(PathBuf, Key)>,
+}
+
+pub struct Run {
+    pub started_at: u64,
+    pub tool_version: String,
+    pub dataset_path: String,
+    pub dataset_sha256: String,
+    pub parameters: BTreeMap<String, String>,
+    pub output_path: String,
+    pub cities: usize,
+}
+
+impl Database {
+    /// Creates the database if it doesn't exist yet. With a `key`, a plaintext database is
+    /// read as is, and encrypted the next time it's written.
+    pub fn open(path: &Path, key: Option<Key>) -> Result<Self, DatabaseErr> {
+        let mut connection = match &key {
+            None => {
+                let connection = Connection::open(path)?;
+                connection.busy_timeout(BUSY_TIMEOUT)?;
+                connection
+            }
+            Some(key) => {
+                let mut connection = Connection::open_in_memory()?;
+                load(&mut connection, path, key)?;
+                connection
+            }
+        };
+        prepare(&mut connection)?;
+        Ok(Self {
+            connection,
+            encrypted: key.map(|key| (path.to_path_buf(), key)),
+        })
+    }
+
+    /// Make a change, for an encrypted database to what's in the file at the time, which is
+    /// then written back, aside first so an interrupted write doesn't lose it. Plaintext
+    /// databases are written by SQLite as they change.
+    fn change<T>(
+        &mut self,
+        change: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, DatabaseErr> {
+        let Some((path, key)) = &self.encrypted else {
+            return Ok(change(&mut self.connection)?);
+        };
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        // Released when dropped.
+        let lock = File::create(lock_path)?;
+        lock.lock()?;
+        load(&mut self.connection, path, key)?;
+        prepare(&mut self.connection)?;
+        let changed = change(&mut self.connection)?;
+        let plaintext = self.connection.serialize(MAIN_DB)?;
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, key.encrypt(&plaintext))?;
+        fs::rename(&partial, path)?;
+        Ok(changed)
+    }
+
+    /// Returns whether the city wasn't there already.
+    pub fn add_city(&mut self, city: &City) -> Result<bool, DatabaseErr> {
+        self.change(|connection| {
+            let added = connection.execute(
+                "INSERT OR IGNORE INTO cities (city, country) VALUES (?1, ?2)",
+                params![city.city, city.country],
+            )? > 0;
+            record_city(connection, city, now())?;
+            Ok(added)
+        })
+    }
+
+    /// Returns whether the city was there.
+    pub fn remove_city(&mut self, city: &str, country: &str) -> Result<bool, DatabaseErr> {
+        self.change(|connection| {
+            Ok(connection.execute(
+                "DELETE FROM cities WHERE city = ?1 AND country = ?2",
+                params![city, country],
+            )? > 0)
+        })
+    }
+
+    /// Every city, with its coordinates when they're known, sorted by country then name.
+    pub fn cities(&self) -> rusqlite::Result<Vec<City>> {
+        let mut statement = self.connection.prepare(
+            "SELECT city, country, wikidata_entity_id, longitude, latitude
+             FROM cities LEFT JOIN geocodes ON geocodes.city_id = cities.id
+             ORDER BY country, city",
+        )?;
+        let cities = statement.query_map([], |row| {
+            Ok(City {
+                city: row.get(0)?,
+                country: row.get(1)?,
+                wikidata_entity_id: row.get(2)?,
+                wikidata_longitude: row.get(3)?,
+                wikidata_latitude: row.get(4)?,
+                ..Default::default()
+            })
+        })?;
+        cities.collect()
+    }
+
+    fn city_id(&self, city: &City) -> rusqlite::Result<Option<i64>> {
+        self.connection
+            .query_row(
+                "SELECT id FROM cities WHERE city = ?1 AND country = ?2",
+                params![city.city, city.country],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Fill in the entity id and coordinates chosen in an earlier run, if the city has none,
+    /// for this city or one it's an alias of.
+    /// Coordinates older than `max_age` seconds are left out if they can be looked up again
+    /// from the entity id, and returned instead so they can be compared with the new ones.
+    pub fn recall_geocode(
+        &self,
+        city: &mut City,
+        max_age: Option<u64>,
+    ) -> rusqlite::Result<Option<(f32, f32)>> {
+        if city.wikidata_entity_id.is_some()
+            || city.wikidata_longitude.is_some()
+            || city.override_longitude.is_some()
+        {
+            return Ok(None);
+        }
+        let row = |row: &rusqlite::Row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, i64>(3)?))
+        };
+        let geocode = match self.city_id(city)? {
+            Some(id) => self
+                .connection
+                .query_row(
+                    "SELECT wikidata_entity_id, longitude, latitude, geocoded_at
+                     FROM geocodes WHERE city_id = ?1",
+                    params![id],
+                    row,
+                )
+                .optional()?,
+            None => None,
+        };
+        let geocode = match geocode {
+            Some(geocode) => Some(geocode),
+            None => self
+                .connection
+                .query_row(
+                    "SELECT geocodes.wikidata_entity_id, longitude, latitude, geocoded_at
+                     FROM aliases JOIN geocodes
+                     ON geocodes.wikidata_entity_id = aliases.wikidata_entity_id
+                     WHERE alias = ?1 AND country = ?2
+                     LIMIT 1",
+                    params![city.city, city.country],
+                    row,
+                )
+                .optional()?,
+        };
+        let Some((entity_id, lon, lat, geocoded_at)) = geocode else {
+            return Ok(None);
+        };
+        let stale =
+            max_age.is_some_and(|max_age| now().saturating_sub(geocoded_at as u64) > max_age);
+        city.wikidata_entity_id = entity_id;
+        if stale && city.wikidata_entity_id.is_some() {
+            return Ok(Some((lon, lat)));
+        }
+        city.wikidata_longitude = Some(lon);
+        city.wikidata_latitude = Some(lat);
+        city.coordinate_source = Some(CoordinateSource::Database);
+        Ok(None)
+    }
+
+    /// Replaces whatever entity the aliases were recorded for before in the country.
+    pub fn record_aliases(
+        &mut self,
+        country: &str,
+        entity_id: &str,
+        aliases: &[String],
+    ) -> Result<(), DatabaseErr> {
+        self.change(|connection| {
+            for alias in aliases {
+                connection.execute(
+                    "INSERT OR REPLACE INTO aliases (alias, country, wikidata_entity_id)
+                     VALUES (?1, ?2, ?3)",
+                    params![alias, country, entity_id],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Store the coordinates of the cities geocoded so far by an interrupted run,
+    /// so the questions answered for them aren't asked again.
+    pub fn record_geocodes(
+        &mut self,
+        cities: &[City],
+        geocoded_at: u64,
+    ) -> Result<(), DatabaseErr> {
+        self.change(|connection| {
+            let transaction = connection.transaction()?;
+            for city in cities {
+                record_city(&transaction, city, geocoded_at)?;
+            }
+            transaction.commit()
+        })
+    }
+
+    /// Store everything a run found out about its cities, and the run itself.
+    pub fn record_run(&mut self, cities: &[City], run: &Run) -> Result<(), DatabaseErr> {
+        let parameters = serde_json::to_string(&run.parameters).expect("Parameters serialize");
+        self.change(|connection| {
+            let transaction = connection.transaction()?;
+            for city in cities {
+                let id = record_city(&transaction, city, run.started_at)?;
+                if let Some(temperature) = city.average_temperature {
+                    transaction.execute(
+                        "INSERT OR REPLACE INTO temperatures
+                         (city_id, dataset_sha256, parameters, average_temperature)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        params![id, run.dataset_sha256, parameters, temperature],
+                    )?;
+                }
+            }
+            transaction.execute(
+                "INSERT INTO runs
+                 (started_at, tool_version, dataset_path, dataset_sha256, parameters, output_path, cities)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    run.started_at as i64,
+                    run.tool_version,
+                    run.dataset_path,
+                    run.dataset_sha256,
+                    parameters,
+                    run.output_path,
+                    run.cities as i64
+                ],
+            )?;
+            transaction.commit()
+        })
+    }
+
+    /// Oldest first.
+    pub fn runs(&self) -> rusqlite::Result<Vec<Run>> {
+        let mut statement = self.connection.prepare(
+            "SELECT started_at, tool_version, dataset_path, dataset_sha256, parameters, output_path, cities
+             FROM runs ORDER BY id",
+        )?;
+        let runs = statement.query_map([], |row| {
+            let parameters: String = row.get(4)?;
+            Ok(Run {
+                started_at: row.get::<_, i64>(0)? as u64,
+                tool_version: row.get(1)?,
+                dataset_path: row.get(2)?,
+                dataset_sha256: row.get(3)?,
+                parameters: serde_json::from_str(&parameters).unwrap_or_default(),
+                output_path: row.get(5)?,
+                cities: row.get::<_, i64>(6)? as usize,
+            })
+        })?;
+        runs.collect()
+    }
+}
+
+/// The database in the file, if there's one yet, into the in-memory `connection`.
+fn load(connection: &mut Connection, path: &Path, key: &Key) -> Result<(), DatabaseErr> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(DatabaseErr::Io(e)),
+    };
+    let plaintext = if contents.starts_with(SQLITE_HEADER) {
+        contents
+    } else {
+        key.decrypt(&contents)?
+    };
+    connection.deserialize_read_exact(MAIN_DB, &plaintext[..], plaintext.len(), false)?;
+    Ok(())
+}
+
+/// Bring the schema up to date.
+fn prepare(connection: &mut Connection) -> rusqlite::Result<()> {
+    connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+    connection.execute_batch(SCHEMA)?;
+    // Databases from before geocodes were timestamped count as infinitely old.
+    let has_geocoded_at: bool = connection.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('geocodes') WHERE name = 'geocoded_at'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_geocoded_at {
+        connection.execute_batch(
+            "ALTER TABLE geocodes ADD COLUMN geocoded_at INTEGER NOT NULL DEFAULT 0;",
+        )?;
+    }
+    Ok(())
+}
+
+/// Adds the city if it's new, and its coordinates if they were geocoded. Returns its id.
+fn record_city(connection: &Connection, city: &City, geocoded_at: u64) -> rusqlite::Result<i64> {
+    connection.execute(
+        "INSERT OR IGNORE INTO cities (city, country) VALUES (?1, ?2)",
+        params![city.city, city.country],
+    )?;
+    let id: i64 = connection.query_row(
+        "SELECT id FROM cities WHERE city = ?1 AND country = ?2",
+        params![city.city, city.country],
+        |row| row.get(0),
+    )?;
+    // Overrides stay in the input, they aren't a geocoding decision,
+    // and coordinates from the database keep the time they were looked up.
+    let geocoded = !matches!(
+        city.coordinate_source,
+        Some(CoordinateSource::Override | CoordinateSource::Database)
+    );
+    if let (Some(lon), Some(lat), true) =
+        (city.wikidata_longitude, city.wikidata_latitude, geocoded)
+    {
+        connection.execute(
+            "INSERT OR REPLACE INTO geocodes
+             (city_id, wikidata_entity_id, longitude, latitude, geocoded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, city.wikidata_entity_id, lon, lat, geocoded_at as i64],
+        )?;
+    }
+    Ok(id)
+}
+
+/// Seconds since the epoch, for `Run::started_at`.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,21 @@
+//! Day lengths from latitude alone, see https://en.wikipedia.org/wiki/Sunrise_equation
+//! Geometric, so ignoring refraction and the size of the sun's disc, which add a few minutes.
+
+/// Axial tilt of the earth, which is the sun's declination at the solstices.
+const OBLIQUITY_DEGREES: f32 = 23.44;
+
+/// Hours between sunrise and sunset when the sun is at `declination` degrees.
+fn day_length_hours(lat: f32, declination: f32) -> f32 {
+    let cos_hour_angle = -lat.to_radians().tan() * declination.to_radians().tan();
+    // Beyond the polar circles the sun doesn't set (or rise) at all.
+    2.0 * cos_hour_angle.clamp(-1.0, 1.0).acos().to_degrees() / 15.0
+}
+
+/// The lengths in hours of the shortest and longest days of the year, at the solstices.
+pub fn shortest_and_longest_day(lat: f32) -> (f32, f32) {
+    let (june, december) = (
+        day_length_hours(lat, OBLIQUITY_DEGREES),
+        day_length_hours(lat, -OBLIQUITY_DEGREES),
+    );
+    (june.min(december), june.max(december))
+}
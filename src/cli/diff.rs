@@ -0,0 +1,231 @@
+use crate::cli::sinks::{self, SinkErr};
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// Cities are identified across files by their city, region and country,
+/// since that's the only thing guaranteed to be present in every row.
+/// The region is empty for files without one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CityKey {
+    city: String,
+    region: String,
+    country: String,
+}
+
+impl Display for CityKey {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.region.is_empty() {
+            write!(fmt, "{}, {}", self.city, self.country)
+        } else {
+            write!(fmt, "{}, {}, {}", self.city, self.region, self.country)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DiffErr {
+    CantReadFile(SinkErr),
+    MissingKeyColumn(&'static str),
+}
+
+impl Display for DiffErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            DiffErr::CantReadFile(e) => write!(fmt, "can't read file: {}", e),
+            DiffErr::MissingKeyColumn(column) => write!(fmt, "no {} column", column),
+        }
+    }
+}
+
+impl From<csv::Error> for DiffErr {
+    fn from(e: csv::Error) -> Self {
+        DiffErr::CantReadFile(SinkErr::Csv(e))
+    }
+}
+
+/// An output file's rows, keyed by city.
+pub struct Table {
+    pub headers: StringRecord,
+    pub rows: Vec<(CityKey, StringRecord)>,
+}
+
+impl Table {
+    /// From an output in any of the formats runs write that can be read back.
+    pub fn read(path: &Path) -> Result<Self, DiffErr> {
+        let csv = sinks::read(path).map_err(DiffErr::CantReadFile)?;
+        let mut reader = csv::Reader::from_reader(&csv[..]);
+        let headers = reader.headers()?.clone();
+        let column = |name| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or(DiffErr::MissingKeyColumn(name))
+        };
+        let (city, country) = (column("City")?, column("Country")?);
+        let region = column("Region").ok();
+        let rows = reader
+            .records()
+            .map(|record| {
+                let record = record?;
+                let key = CityKey {
+                    city: record[city].to_string(),
+                    region: region.map(|i| record[i].to_string()).unwrap_or_default(),
+                    country: record[country].to_string(),
+                };
+                Ok((key, record))
+            })
+            .collect::<Result<_, DiffErr>>()?;
+        Ok(Self { headers, rows })
+    }
+
+    fn value<'a>(&self, record: &'a StringRecord, column: &str) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .position(|h| h == column)
+            .and_then(|i| record.get(i))
+    }
+}
+
+#[derive(Debug)]
+pub struct ColumnChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Default)]
+pub struct CsvDiff {
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    pub added_cities: Vec<CityKey>,
+    pub removed_cities: Vec<CityKey>,
+    pub changed_cities: Vec<(CityKey, Vec<ColumnChange>)>,
+}
+
+impl CsvDiff {
+    /// Compare two output files.
+    /// A value appearing or disappearing is always a change, NaN counting as missing. Numeric
+    /// columns are otherwise only reported as changed if they moved by more than `tolerance`,
+    /// everything else is compared as text.
+    pub fn between(old: &Path, new: &Path, tolerance: f64) -> Result<Self, DiffErr> {
+        let old = Table::read(old)?;
+        let new = Table::read(new)?;
+        let mut diff = CsvDiff::default();
+
+        let shared_columns: Vec<&str> = new
+            .headers
+            .iter()
+            .filter(|h| old.headers.iter().any(|o| o == *h))
+            .collect();
+        diff.added_columns = new
+            .headers
+            .iter()
+            .filter(|h| !shared_columns.contains(h))
+            .map(str::to_string)
+            .collect();
+        diff.removed_columns = old
+            .headers
+            .iter()
+            .filter(|h| !shared_columns.contains(h))
+            .map(str::to_string)
+            .collect();
+
+        let old_rows: HashMap<&CityKey, &StringRecord> =
+            old.rows.iter().map(|(key, record)| (key, record)).collect();
+        let new_rows: HashMap<&CityKey, &StringRecord> =
+            new.rows.iter().map(|(key, record)| (key, record)).collect();
+
+        for (key, new_record) in new.rows.iter() {
+            let Some(old_record) = old_rows.get(key) else {
+                diff.added_cities.push(key.clone());
+                continue;
+            };
+            let changes: Vec<ColumnChange> = shared_columns
+                .iter()
+                .filter_map(|&column| {
+                    let old_value = old.value(old_record, column).unwrap_or("");
+                    let new_value = new.value(new_record, column).unwrap_or("");
+                    let changed = match (is_missing(old_value), is_missing(new_value)) {
+                        (true, true) => false,
+                        (true, false) | (false, true) => true,
+                        (false, false) => {
+                            match (old_value.parse::<f64>(), new_value.parse::<f64>()) {
+                                (Ok(o), Ok(n)) => (n - o).abs() > tolerance,
+                                _ => old_value != new_value,
+                            }
+                        }
+                    };
+                    changed.then(|| ColumnChange {
+                        column: column.to_string(),
+                        old: old_value.to_string(),
+                        new: new_value.to_string(),
+                    })
+                })
+                .collect();
+            if !changes.is_empty() {
+                diff.changed_cities.push((key.clone(), changes));
+            }
+        }
+        diff.removed_cities = old
+            .rows
+            .iter()
+            .filter(|(key, _)| !new_rows.contains_key(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        Ok(diff)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_columns.is_empty()
+            && self.removed_columns.is_empty()
+            && self.added_cities.is_empty()
+            && self.removed_cities.is_empty()
+            && self.changed_cities.is_empty()
+    }
+}
+
+/// An empty cell, or a NaN, which some writers use for a value they don't have.
+fn is_missing(value: &str) -> bool {
+    value.is_empty() || value.parse::<f64>().is_ok_and(f64::is_nan)
+}
+
+impl Display for CsvDiff {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        for column in self.added_columns.iter() {
+            writeln!(fmt, "+ column {}", column)?;
+        }
+        for column in self.removed_columns.iter() {
+            writeln!(fmt, "- column {}", column)?;
+        }
+        for city in self.added_cities.iter() {
+            writeln!(fmt, "+ {}", city)?;
+        }
+        for city in self.removed_cities.iter() {
+            writeln!(fmt, "- {}", city)?;
+        }
+        for (city, changes) in self.changed_cities.iter() {
+            writeln!(fmt, "~ {}", city)?;
+            for change in changes {
+                match (change.old.parse::<f64>(), change.new.parse::<f64>()) {
+                    (Ok(o), Ok(n)) if !o.is_nan() && !n.is_nan() => writeln!(
+                        fmt,
+                        "    {}: {} -> {} ({:+})",
+                        change.column,
+                        change.old,
+                        change.new,
+                        (n - o) as f32
+                    )?,
+                    _ => writeln!(
+                        fmt,
+                        "    {}: {:?} -> {:?}",
+                        change.column, change.old, change.new
+                    )?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
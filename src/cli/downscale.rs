@@ -0,0 +1,138 @@
+//! Delta-method downscaling, for `--fine-climatology`: a coarse time series like CRU TS averages
+//! a mountain town with the valleys and peaks around it, while a fine climatology like WorldClim
+//! resolves the town but only for its own period. Each month of the city's temperature is then
+//! the fine climatology's, plus how much warmer or colder the coarse series has that month over
+//! the period asked for than over the climatology's. The fine grid gives where, the coarse series
+//! gives when.
+
+use crate::climatology::{self, DatasetKind};
+use crate::coordinates::{Latitude, Longitude};
+use crate::{
+    AverageErr, CancellationToken, DatasetFile, LonLatCell, MissingValues, Period, Temperature,
+    TemperatureDataset, TemperatureDatasetReadErr,
+};
+use std::fmt::{self, Display};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum FineClimatologyErr {
+    Read(TemperatureDatasetReadErr),
+    /// A time series rather than one of each month.
+    NotClimatology,
+    /// The temperature dataset has none of the climatology's years to correct it with.
+    PeriodNotCovered,
+}
+
+impl Display for FineClimatologyErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            FineClimatologyErr::Read(e) => write!(fmt, "{}", e),
+            FineClimatologyErr::NotClimatology => write!(
+                fmt,
+                "not a climatology, it should have one time step per calendar month"
+            ),
+            FineClimatologyErr::PeriodNotCovered => write!(
+                fmt,
+                "the temperature dataset doesn't cover the climatology's period \
+                 (set it with --fine-climatology-period)"
+            ),
+        }
+    }
+}
+
+/// A climatology read at its own resolution, rather than in the half degree cells datasets are.
+pub struct FineClimatology {
+    file: DatasetFile,
+    /// The years its months are the means of.
+    period: Period,
+}
+
+impl FineClimatology {
+    /// `period` is the climatology's, which `dataset` has to cover to correct it with.
+    pub fn open(
+        path: &Path,
+        period: Period,
+        dataset: &TemperatureDataset,
+    ) -> Result<Self, FineClimatologyErr> {
+        if !dataset
+            .times()
+            .into_iter()
+            .any(|time| period.contains(time))
+        {
+            return Err(FineClimatologyErr::PeriodNotCovered);
+        }
+        let file = TemperatureDataset::read_file(
+            path,
+            false,
+            MissingValues::default(),
+            None,
+            &CancellationToken::new(),
+        )
+        .map_err(FineClimatologyErr::Read)?;
+        if climatology::kind_of(file.times.first().copied()) != DatasetKind::Climatology {
+            return Err(FineClimatologyErr::NotClimatology);
+        }
+        Ok(Self { file, period })
+    }
+
+    /// The value of the grid point nearest to the point, for `month` from 1 to 12.
+    fn at(&self, month: u32, lon: Longitude, lat: Latitude) -> Option<Temperature> {
+        let nearest = |values: &[f32], distance: &dyn Fn(f32) -> f32| {
+            (0..values.len()).min_by(|&a, &b| distance(values[a]).total_cmp(&distance(values[b])))
+        };
+        let lat_index = nearest(&self.file.lats, &|grid_lat| {
+            (grid_lat - lat.degrees()).abs()
+        })?;
+        let lon_index = nearest(&self.file.lons, &|grid_lon| {
+            let east = (grid_lon - lon.degrees()).rem_euclid(360.0);
+            east.min(360.0 - east)
+        })?;
+        let points = self.file.lats.len() * self.file.lons.len();
+        let index = (month as usize - 1) * points + lat_index * self.file.lons.len() + lon_index;
+        self.file.observations.temperature(index)
+    }
+
+    /// The temperature at the point over `period`, in celsius: the mean of its months, each the
+    /// fine climatology's corrected by the difference between `dataset`'s means of that month
+    /// over `period` and over the climatology's period, in the point's cell.
+    pub fn corrected(
+        &self,
+        dataset: &TemperatureDataset,
+        lon: Longitude,
+        lat: Latitude,
+        period: Period,
+    ) -> Result<Temperature, AverageErr> {
+        let series = dataset.temperature_series_at(LonLatCell::at(lon, lat));
+        let month_mean = |month: u32, period: Period| {
+            Temperature::average(
+                series
+                    .iter()
+                    .copied()
+                    .filter(|&(time, _)| time.month() == month && period.contains(time)),
+            )
+        };
+        let mut months = vec![];
+        for month in 1..=12 {
+            let fine = self.at(month, lon, lat).ok_or(AverageErr::NoDatapoints)?;
+            let delta = month_mean(month, period)? - month_mean(month, self.period)?;
+            months.push(fine + delta);
+        }
+        Ok(months.into_iter().sum::<Temperature>() / 12)
+    }
+}
+
+/// A period of years like 1970-2000, both ends included.
+pub fn parse_period(s: &str) -> Result<Period, String> {
+    let (first, last) = s
+        .split_once('-')
+        .ok_or_else(|| "must be FIRST-LAST, e.g. 1970-2000".to_string())?;
+    let year = |year: &str| year.trim().parse::<i32>().map_err(|e| e.to_string());
+    let (first, last) = (year(first)?, year(last)?);
+    if first > last {
+        return Err(format!("{} is after {}", first, last));
+    }
+    Ok(Period {
+        first_year: Some(first),
+        last_year: Some(last),
+    })
+}
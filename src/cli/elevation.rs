@@ -0,0 +1,193 @@
+//! Surface elevation from a grid in a classic NetCDF file, such as CRU's half degree one or ETOPO1:
+//! each city's cell's mean elevation, which its temperature is really the temperature at, and the
+//! elevation at the city itself. Air cools by about 6.5 degrees per km going up, so a city in a
+//! valley under mountains reads colder than it is, and one on a plateau over the plains warmer.
+
+use crate::coordinates::{Latitude, Longitude};
+use crate::LonLatCell;
+use netcdf3::{Attribute, DataType, DataVector, FileReader};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// The standard atmosphere's, in celsius per meter.
+pub const LAPSE_RATE: f32 = 6.5 / 1000.0;
+
+const LATITUDE_NAMES: [&str; 3] = ["lat", "latitude", "y"];
+const LONGITUDE_NAMES: [&str; 3] = ["lon", "longitude", "x"];
+
+#[derive(Debug)]
+pub enum ElevationErr {
+    Read(String),
+    /// No variable over latitude and longitude.
+    NoGrid,
+    /// Which of these is the elevation can't be told.
+    SeveralGrids(Vec<String>),
+    /// The coordinate variables aren't on the globe.
+    Coordinates(String),
+}
+
+impl Display for ElevationErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ElevationErr::Read(e) => write!(fmt, "can't read elevation grid: {}", e),
+            ElevationErr::NoGrid => write!(fmt, "no variable over latitude and longitude"),
+            ElevationErr::SeveralGrids(names) => write!(
+                fmt,
+                "several variables over latitude and longitude: {}",
+                names.join(", ")
+            ),
+            ElevationErr::Coordinates(e) => write!(fmt, "bad coordinates: {}", e),
+        }
+    }
+}
+
+pub struct ElevationGrid {
+    lats: Vec<f32>,
+    /// From -180 to 180.
+    lons: Vec<f32>,
+    /// In meters, row by row, NaN where missing.
+    meters: Vec<f32>,
+    /// The mean of the grid's points in every half degree cell with any.
+    cell_means: HashMap<LonLatCell, f32>,
+}
+
+impl ElevationGrid {
+    /// The file's only variable over latitude and longitude (`lat`/`latitude`/`y` then
+    /// `lon`/`longitude`/`x`), in meters unless its units say km.
+    pub fn load(path: &Path) -> Result<Self, ElevationErr> {
+        let mut reader = FileReader::open(path).map_err(|e| ElevationErr::Read(e.to_string()))?;
+        let data_set = reader.data_set();
+        let grids: Vec<(String, String, String)> = data_set
+            .get_vars()
+            .into_iter()
+            .filter_map(|var| {
+                let [lat, lon] = &var.dim_names()[..] else {
+                    return None;
+                };
+                (LATITUDE_NAMES.contains(&lat.as_str())
+                    && LONGITUDE_NAMES.contains(&lon.as_str())
+                    && data_set.get_var(lat).is_some()
+                    && data_set.get_var(lon).is_some())
+                .then(|| (var.name().to_string(), lat.clone(), lon.clone()))
+            })
+            .collect();
+        let (name, lat_name, lon_name) = match &grids[..] {
+            [] => return Err(ElevationErr::NoGrid),
+            [grid] => grid.clone(),
+            several => {
+                return Err(ElevationErr::SeveralGrids(
+                    several.iter().map(|(name, _, _)| name.clone()).collect(),
+                ))
+            }
+        };
+        let var = data_set.get_var(&name).expect("Found among the variables");
+        let missing: Vec<f32> = ["_FillValue", "missing_value"]
+            .into_iter()
+            .filter_map(|attr| var.get_attr(attr).and_then(first_number))
+            .collect();
+        let scale = match var.get_attr_as_string("units").as_deref() {
+            Some("km") => 1000.0,
+            _ => 1.0,
+        };
+
+        let mut read = |name: &str| {
+            reader
+                .read_var(name)
+                .map(to_f32)
+                .map_err(|e| ElevationErr::Read(format!("{}: {}", name, e)))
+        };
+        let lats = read(&lat_name)?
+            .into_iter()
+            .map(|lat| Latitude::new(lat).map(Latitude::degrees))
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| ElevationErr::Coordinates(e.to_string()))?;
+        let lons = read(&lon_name)?
+            .into_iter()
+            .map(|lon| Longitude::normalized(lon).map(Longitude::degrees))
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| ElevationErr::Coordinates(e.to_string()))?;
+        let meters: Vec<f32> = read(&name)?
+            .into_iter()
+            .map(|value| {
+                if missing.contains(&value) {
+                    f32::NAN
+                } else {
+                    value * scale
+                }
+            })
+            .collect();
+
+        // Sum and count of every cell's points, a row at a time.
+        let mut sums: HashMap<LonLatCell, (f64, u32)> = HashMap::new();
+        for (&lat, row) in lats.iter().zip(meters.chunks_exact(lons.len())) {
+            for (&lon, &value) in lons.iter().zip(row) {
+                if value.is_nan() {
+                    continue;
+                }
+                let cell = LonLatCell::at(
+                    Longitude::new(lon).expect("Normalized"),
+                    Latitude::new(lat).expect("Checked"),
+                );
+                let (sum, count) = sums.entry(cell).or_default();
+                *sum += value as f64;
+                *count += 1;
+            }
+        }
+        let cell_means = sums
+            .into_iter()
+            .map(|(cell, (sum, count))| (cell, (sum / count as f64) as f32))
+            .collect();
+        Ok(Self {
+            lats,
+            lons,
+            meters,
+            cell_means,
+        })
+    }
+
+    /// The mean over the half degree cell of the temperature dataset containing the point.
+    pub fn cell_mean(&self, lon: Longitude, lat: Latitude) -> Option<f32> {
+        self.cell_means.get(&LonLatCell::at(lon, lat)).copied()
+    }
+
+    /// At the grid's nearest point, which is only the city's own for grids much finer than
+    /// the city.
+    pub fn at(&self, lon: Longitude, lat: Latitude) -> Option<f32> {
+        let nearest = |values: &[f32], distance: &dyn Fn(f32) -> f32| {
+            (0..values.len()).min_by(|&a, &b| distance(values[a]).total_cmp(&distance(values[b])))
+        };
+        let row = nearest(&self.lats, &|grid_lat| (grid_lat - lat.degrees()).abs())?;
+        let column = nearest(&self.lons, &|grid_lon| {
+            let difference = (grid_lon - lon.degrees()).abs();
+            difference.min(360.0 - difference)
+        })?;
+        let value = self.meters[row * self.lons.len() + column];
+        (!value.is_nan()).then_some(value)
+    }
+}
+
+/// Of a numeric attribute.
+fn first_number(attr: &Attribute) -> Option<f32> {
+    let values = match attr.data_type() {
+        DataType::I8 => DataVector::I8(attr.get_i8()?.to_vec()),
+        DataType::I16 => DataVector::I16(attr.get_i16()?.to_vec()),
+        DataType::I32 => DataVector::I32(attr.get_i32()?.to_vec()),
+        DataType::F32 => DataVector::F32(attr.get_f32()?.to_vec()),
+        DataType::F64 => DataVector::F64(attr.get_f64()?.to_vec()),
+        // Text.
+        DataType::U8 => return None,
+    };
+    to_f32(values).first().copied()
+}
+
+fn to_f32(values: DataVector) -> Vec<f32> {
+    match values {
+        DataVector::I8(values) => values.into_iter().map(f32::from).collect(),
+        DataVector::U8(values) => values.into_iter().map(f32::from).collect(),
+        DataVector::I16(values) => values.into_iter().map(f32::from).collect(),
+        DataVector::I32(values) => values.into_iter().map(|value| value as f32).collect(),
+        DataVector::F32(values) => values,
+        DataVector::F64(values) => values.into_iter().map(|value| value as f32).collect(),
+    }
+}
@@ -0,0 +1,86 @@
+//! Encryption at rest for the project database, for deployments that can't keep the places
+//! they looked up in plaintext: AES-256-GCM with a key read from a file, the whole file
+//! encrypted at once with a fresh nonce on every write.
+
+use aes_gcm::aead::{Aead, Generate, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Starts every encrypted file, followed by the nonce and the ciphertext.
+const MAGIC: &[u8] = b"temperature-extractor aes-256-gcm\n";
+const NONCE_BYTES: usize = 12;
+
+#[derive(Debug)]
+pub enum EncryptionErr {
+    Read(io::Error),
+    /// The key file doesn't hold 32 bytes, raw or as 64 hexadecimal digits.
+    InvalidKey,
+    /// Not a file encrypted by us.
+    NotEncrypted,
+    /// Encrypted with another key, or corrupted.
+    Decrypt,
+}
+
+impl Display for EncryptionErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            EncryptionErr::Read(e) => write!(fmt, "can't read key: {}", e),
+            EncryptionErr::InvalidKey => write!(
+                fmt,
+                "the key file must hold 32 bytes, raw or as 64 hexadecimal digits"
+            ),
+            EncryptionErr::NotEncrypted => write!(fmt, "the file isn't encrypted"),
+            EncryptionErr::Decrypt => {
+                write!(fmt, "can't decrypt, wrong key or corrupted file")
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Key(Aes256Gcm);
+
+impl Key {
+    /// e.g. made with `openssl rand -hex 32 > key`.
+    pub fn load(path: &Path) -> Result<Self, EncryptionErr> {
+        let contents = fs::read(path).map_err(EncryptionErr::Read)?;
+        let hex = std::str::from_utf8(&contents)
+            .ok()
+            .map(str::trim)
+            .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+        let bytes = match hex {
+            Some(hex) => (0..32)
+                .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| EncryptionErr::InvalidKey)?,
+            None => contents,
+        };
+        Aes256Gcm::new_from_slice(&bytes)
+            .map(Key)
+            .map_err(|_| EncryptionErr::InvalidKey)
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Nonce::generate();
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .expect("Encryption only fails on absurdly large plaintexts");
+        [MAGIC, nonce.as_slice(), &ciphertext].concat()
+    }
+
+    pub fn decrypt(&self, encrypted: &[u8]) -> Result<Vec<u8>, EncryptionErr> {
+        let rest = encrypted
+            .strip_prefix(MAGIC)
+            .filter(|rest| rest.len() >= NONCE_BYTES)
+            .ok_or(EncryptionErr::NotEncrypted)?;
+        let (nonce, ciphertext) = rest.split_at(NONCE_BYTES);
+        let nonce = Nonce::try_from(nonce).map_err(|_| EncryptionErr::NotEncrypted)?;
+        self.0
+            .decrypt(&nonce, ciphertext)
+            .map_err(|_| EncryptionErr::Decrypt)
+    }
+}
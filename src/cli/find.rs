@@ -0,0 +1,168 @@
+use crate::calendar::Season;
+use crate::cli::City;
+use crate::config::Units;
+use crate::geo::distance::haversine_km;
+use crate::{LonLatCell, Period, Series, Temperature, TemperatureDataset};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The order of `CellMatch::seasonal_averages`: DJF, MAM, JJA and SON.
+const SEASONS: [Season; 4] = [
+    Season::Winter,
+    Season::Spring,
+    Season::Summer,
+    Season::Autumn,
+];
+
+pub struct CellMatch {
+    pub cell: LonLatCell,
+    pub average: f32,
+    /// In the order of `SEASONS`, only when seasons were asked for.
+    pub seasonal_averages: Option<[f32; 4]>,
+}
+
+/// Every cell whose average temperature (and every seasonal average, if `seasonal`)
+/// lies within `min..=max`, in `units`.
+/// Cells with any missing data in the period (e.g. the oceans) never match.
+pub fn cells_within(
+    dataset: &TemperatureDataset,
+    period: Period,
+    units: Units,
+    min: f32,
+    max: f32,
+    seasonal: bool,
+) -> Vec<CellMatch> {
+    let (min, max) = (
+        Temperature::from_units(min, units),
+        Temperature::from_units(max, units),
+    );
+    let within = |t: Temperature| (min..=max).contains(&t);
+    dataset
+        .series_by_cell()
+        .into_iter()
+        .filter_map(|(cell, series)| {
+            let series: Series = series
+                .into_iter()
+                .filter(|&(time, _)| period.contains(time))
+                .collect();
+            let average = Temperature::average(series.iter().copied()).ok()?;
+            if !within(average) {
+                return None;
+            }
+            let seasonal_averages = if seasonal {
+                let mut averages = [0.0; 4];
+                for (average, in_season) in averages.iter_mut().zip(SEASONS) {
+                    let season = Temperature::average(
+                        series
+                            .iter()
+                            .copied()
+                            .filter(|(time, _)| time.season() == in_season),
+                    )
+                    .ok()?;
+                    if !within(season) {
+                        return None;
+                    }
+                    *average = season.in_units(units);
+                }
+                Some(averages)
+            } else {
+                None
+            };
+            Some(CellMatch {
+                cell,
+                average: average.in_units(units),
+                seasonal_averages,
+            })
+        })
+        .collect()
+}
+
+/// A place of a `Gazetteer`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Place {
+    pub city: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    pub country: String,
+    pub lon: f32,
+    pub lat: f32,
+}
+
+/// Known places, indexed by the cell they fall in.
+pub struct Gazetteer {
+    places: Vec<Place>,
+    /// Indices into `places`.
+    cells: HashMap<LonLatCell, Vec<usize>>,
+}
+
+impl Gazetteer {
+    /// Cities without coordinates are ignored.
+    pub fn new(cities: Vec<City>) -> Self {
+        let mut places = vec![];
+        let mut cells: HashMap<LonLatCell, Vec<usize>> = HashMap::new();
+        for city in cities {
+            if let (Some(lon), Some(lat)) = (city.wikidata_longitude, city.wikidata_latitude) {
+                cells
+                    .entry(LonLatCell::containing(lon, lat))
+                    .or_default()
+                    .push(places.len());
+                places.push(Place {
+                    city: city.city,
+                    region: city.region,
+                    country: city.country,
+                    lon,
+                    lat,
+                });
+            }
+        }
+        Self { places, cells }
+    }
+
+    pub fn len(&self) -> usize {
+        self.places.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.places.is_empty()
+    }
+
+    /// As "City, Country".
+    pub fn places_in(&self, cell: LonLatCell) -> Vec<String> {
+        self.cells
+            .get(&cell)
+            .map(|places| {
+                places
+                    .iter()
+                    .map(|&i| format!("{}, {}", self.places[i].city, self.places[i].country))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The places named `city` in `country`, ignoring case, and in `region` if given and the
+    /// gazetteer has regions.
+    pub fn named(&self, city: &str, region: Option<&str>, country: &str) -> Vec<&Place> {
+        self.places
+            .iter()
+            .filter(|place| {
+                place.city.eq_ignore_ascii_case(city)
+                    && place.country.eq_ignore_ascii_case(country)
+                    && region
+                        .zip(place.region.as_deref())
+                        .is_none_or(|(region, place_region)| {
+                            region.eq_ignore_ascii_case(place_region)
+                        })
+            })
+            .collect()
+    }
+
+    /// The place closest to a point and its distance in km, None when there are no places.
+    /// Looks at every place, which is quick enough for the few hundred thousand cities
+    /// list-cities gives.
+    pub fn nearest(&self, lon: f32, lat: f32) -> Option<(&Place, f32)> {
+        self.places
+            .iter()
+            .map(|place| (place, haversine_km((lon, lat), (place.lon, place.lat))))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
@@ -0,0 +1,171 @@
+//! `serve --flight-dir`: the Arrow IPC outputs in a directory served over Arrow Flight, on the
+//! same address as the gRPC service, each as the flight named after its file. Clients list them
+//! with ListFlights and fetch one with DoGet, its file name as the ticket.
+
+use crate::cli::arrow;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::FlightService;
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use arrow_ipc::writer::IpcWriteOptions;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::fs;
+use std::path::PathBuf;
+use tonic::{Request, Response, Status, Streaming};
+
+pub struct ResultFiles {
+    pub dir: PathBuf,
+}
+
+impl ResultFiles {
+    /// The path of the flight, refusing names that would reach outside the directory.
+    fn path(&self, name: &[u8]) -> Result<PathBuf, Status> {
+        let name = std::str::from_utf8(name)
+            .map_err(|_| Status::invalid_argument("flight names are UTF-8"))?;
+        let path = self.dir.join(name);
+        if path.file_name().and_then(|file| file.to_str()) != Some(name) || !arrow::is_arrow(&path)
+        {
+            return Err(Status::not_found(format!("no flight {}", name)));
+        }
+        Ok(path)
+    }
+
+    fn info(&self, name: &str) -> Result<FlightInfo, Status> {
+        let batches = arrow::read(&self.path(name.as_bytes())?)
+            .map_err(|e| Status::not_found(format!("{}: {}", name, e)))?;
+        let Some(schema) = batches.first().map(|batch| batch.schema()) else {
+            return Err(Status::not_found(format!("{} is empty", name)));
+        };
+        let records = batches.iter().map(|batch| batch.num_rows() as i64).sum();
+        Ok(FlightInfo::new()
+            .try_with_schema(&schema)
+            .map_err(|e| Status::internal(e.to_string()))?
+            .with_descriptor(FlightDescriptor::new_path(vec![name.to_string()]))
+            .with_endpoint(FlightEndpoint::new().with_ticket(Ticket::new(name.to_string())))
+            .with_total_records(records))
+    }
+}
+
+/// The one path element naming the flight.
+fn flight_name(descriptor: &FlightDescriptor) -> Result<&str, Status> {
+    match &descriptor.path[..] {
+        [name] => Ok(name),
+        _ => Err(Status::invalid_argument(
+            "flights are named by a single path element",
+        )),
+    }
+}
+
+#[tonic::async_trait]
+impl FlightService for ResultFiles {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+
+    async fn list_flights(
+        &self,
+        _: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        let mut names: Vec<String> = fs::read_dir(&self.dir)
+            .map_err(|e| Status::internal(e.to_string()))?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| arrow::is_arrow(name.as_ref()))
+            .collect();
+        names.sort();
+        // Outputs are written aside and moved in place, so files that can't be read (or are
+        // empty) aren't outputs, and are left out.
+        let infos: Vec<Result<FlightInfo, Status>> = names
+            .iter()
+            .filter_map(|name| self.info(name).ok())
+            .map(Ok)
+            .collect();
+        Ok(Response::new(stream::iter(infos).boxed()))
+    }
+
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        self.info(flight_name(request.get_ref())?)
+            .map(Response::new)
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        let info = self.info(flight_name(request.get_ref())?)?;
+        let schema = info
+            .try_decode_schema()
+            .map_err(|e| Status::internal(e.to_string()))?;
+        SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map(Response::new)
+            .map_err(|e: arrow_schema::ArrowError| Status::internal(e.to_string()))
+    }
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let path = self.path(&request.get_ref().ticket)?;
+        let batches = arrow::read(&path).map_err(|e| Status::not_found(e.to_string()))?;
+        let data = FlightDataEncoderBuilder::new()
+            .build(stream::iter(batches.into_iter().map(Ok::<_, FlightError>)))
+            .map(|data| data.map_err(Status::from));
+        Ok(Response::new(data.boxed()))
+    }
+
+    async fn handshake(
+        &self,
+        _: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented(
+            "authenticate with API keys, see --api-keys",
+        ))
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _: Request<FlightDescriptor>,
+    ) -> Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented(
+            "flights are files, ready straight away",
+        ))
+    }
+
+    async fn do_put(
+        &self,
+        _: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("flights are read only"))
+    }
+
+    async fn do_exchange(
+        &self,
+        _: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("flights are read only"))
+    }
+
+    async fn do_action(
+        &self,
+        _: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("there are no actions"))
+    }
+
+    async fn list_actions(
+        &self,
+        _: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(stream::empty().boxed()))
+    }
+}
@@ -0,0 +1,147 @@
+//! `global-stats`: the area weighted mean temperature over every cell with data (for CRU TS,
+//! land outside Antarctica) and its trend, computed straight from the dataset. A sanity check
+//! against CRU's own published global land series.
+
+use crate::cli::bench::Xorshift;
+use crate::config::Units;
+use crate::resample::Coverage;
+use crate::stats;
+use crate::{AreaWeightedMean, LonLatCell, Period, TemperatureDataset, TemperatureDelta};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize)]
+pub struct GlobalStats {
+    /// Cells with at least one complete year in the period.
+    pub cells: usize,
+    /// How many cells were drawn, when estimating from a sample.
+    pub sampled: Option<usize>,
+    /// The average of the yearly means.
+    pub mean: f32,
+    /// Least squares slope of the yearly means, per decade. None with fewer than two years.
+    pub trend_per_decade: Option<f32>,
+    /// The 95% confidence interval of the trend, allowing for consecutive years being correlated.
+    /// None with too few years for one.
+    pub trend_95: Option<[f32; 2]>,
+    /// Lag-1 autocorrelation of the yearly means around the trend.
+    pub autocorrelation: Option<f32>,
+    /// How many independent years the correlated ones are worth.
+    pub effective_years: Option<f32>,
+    pub years: Vec<YearMean>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct YearMean {
+    pub year: i32,
+    pub mean: f32,
+    /// Cells with enough months that year, all twelve by default.
+    pub cells: usize,
+}
+
+/// Each year's mean weights every cell with `coverage.months` months that year by its area,
+/// see `AreaWeightedMean`.
+/// With `sample`, that many cells are drawn instead, with probability proportional to their area
+/// and seeded for repeatability, and given equal weight.
+pub fn global_stats(
+    dataset: &TemperatureDataset,
+    period: Period,
+    units: Units,
+    sample: Option<(usize, u64)>,
+    coverage: Coverage,
+) -> GlobalStats {
+    // Each cell's complete years' means, in celsius.
+    let cells: Vec<(LonLatCell, BTreeMap<i32, f64>)> = dataset
+        .series_by_cell()
+        .into_iter()
+        .filter_map(|(cell, series)| {
+            let years = stats::annual_means(&series, period, coverage);
+            (!years.is_empty()).then_some((cell, years))
+        })
+        .collect();
+
+    // Cells with a weight on top of their area.
+    let weighted: Vec<(LonLatCell, f64, &BTreeMap<i32, f64>)> = match sample {
+        None => cells
+            .iter()
+            .map(|(cell, years)| (*cell, 1.0, years))
+            .collect(),
+        Some((draws, seed)) => {
+            let mut cumulative = Vec::with_capacity(cells.len());
+            let mut total = 0.0;
+            for (cell, _) in &cells {
+                total += cell.area_weight();
+                cumulative.push(total);
+            }
+            // Xorshift never leaves zero.
+            let mut random = Xorshift(seed.max(1));
+            (0..draws)
+                .filter(|_| !cells.is_empty())
+                .map(|_| {
+                    let target = random.fraction() as f64 * total;
+                    let i = cumulative
+                        .partition_point(|&c| c <= target)
+                        .min(cells.len() - 1);
+                    let (cell, years) = &cells[i];
+                    // Already drawn in proportion to its area, so counted equally.
+                    (*cell, 1.0 / cell.area_weight(), years)
+                })
+                .collect()
+        }
+    };
+
+    let mut by_year: BTreeMap<i32, AreaWeightedMean> = BTreeMap::new();
+    for &(cell, weight, years) in &weighted {
+        for (&year, &mean) in years {
+            by_year
+                .entry(year)
+                .or_default()
+                .add_weighted(cell, mean, weight);
+        }
+    }
+    let yearly: Vec<(i32, f64, usize)> = by_year
+        .into_iter()
+        .map(|(year, mean)| {
+            let cells = mean.cells;
+            (
+                year,
+                mean.mean().expect("Years are only added with a cell"),
+                cells,
+            )
+        })
+        .collect();
+
+    let mean = yearly.iter().map(|&(_, mean, _)| mean).sum::<f64>() / yearly.len().max(1) as f64;
+    let trend = stats::trend(
+        &yearly
+            .iter()
+            .map(|&(year, mean, _)| (year as f64, mean))
+            .collect::<Vec<_>>(),
+    );
+    let per_decade =
+        |per_year: f64| TemperatureDelta::from_celsius((per_year * 10.0) as f32).in_units(units);
+    let in_units = |celsius: f64| crate::Temperature::new(celsius as f32).in_units(units);
+
+    GlobalStats {
+        cells: cells.len(),
+        sampled: sample.map(|(draws, _)| draws),
+        mean: in_units(mean),
+        trend_per_decade: trend.map(|trend| per_decade(trend.slope)),
+        trend_95: trend.and_then(|trend| {
+            let margin = trend.margin_95?;
+            Some([
+                per_decade(trend.slope - margin),
+                per_decade(trend.slope + margin),
+            ])
+        }),
+        autocorrelation: trend.map(|trend| trend.autocorrelation as f32),
+        effective_years: trend.map(|trend| trend.effective_sample_size as f32),
+        years: yearly
+            .into_iter()
+            .map(|(year, mean, cells)| YearMean {
+                year,
+                mean: in_units(mean),
+                cells,
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,86 @@
+//! What a run tells whoever drives it, and asks them: each city as it's started and completed,
+//! and which of a geocoder's results is the city when none stands out. The command line asks in
+//! the terminal; a GUI front-end implements `Hooks` to ask through its own UI and show progress,
+//! and gives them to `RunBuilder::hooks`.
+
+use super::builder::CityResult;
+use super::interrupt;
+
+/// The city a hook is about, as the input names it.
+#[derive(Debug, Clone, Copy)]
+pub struct CityName<'a> {
+    pub city: &'a str,
+    pub region: Option<&'a str>,
+    pub country: &'a str,
+}
+
+/// The answer to `on_geocode_ambiguous`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Choice {
+    /// The index of the right candidate.
+    Candidate(usize),
+    /// None of them is right, search for this instead.
+    Search(String),
+}
+
+pub trait Hooks {
+    /// Before the `index`th city of `total` is geocoded and computed.
+    fn on_city_started(&mut self, _index: usize, _total: usize, _city: CityName) {}
+
+    /// A search for the city found several `candidates` (described for people, best first) and
+    /// nothing tells which it is. `search` is what to suggest searching for instead if none is.
+    /// None gives up on the city, like Ctrl-C.
+    ///
+    /// By default, asks in the terminal.
+    fn on_geocode_ambiguous(
+        &mut self,
+        city: CityName,
+        search: &str,
+        candidates: &[String],
+    ) -> Option<Choice> {
+        ask_in_terminal(city, search, candidates)
+    }
+
+    /// Once the `index`th city of `total` has its coordinates and temperature, or a problem.
+    fn on_city_completed(&mut self, _index: usize, _total: usize, _result: &CityResult) {}
+}
+
+/// The command line's: asks in the terminal and says nothing about progress.
+pub struct TerminalHooks;
+
+impl Hooks for TerminalHooks {}
+
+fn ask_in_terminal(city: CityName, search: &str, candidates: &[String]) -> Option<Choice> {
+    let mut options = candidates.to_vec();
+    options.push("None of these are right, change the search string".to_string());
+    let choice = answer(
+        dialoguer::Select::new()
+            .with_prompt(format!("Select match for {}, {}", city.city, city.country))
+            .items(&options)
+            .interact(),
+        "User didn't make a choice.",
+    )?;
+    if choice < candidates.len() {
+        return Some(Choice::Candidate(choice));
+    }
+    answer(
+        dialoguer::Input::new()
+            .with_prompt(format!(
+                "Edit search string for {}, {}",
+                city.city, city.country
+            ))
+            .with_initial_text(search)
+            .interact_text(),
+        "User didn't enter a new search string.",
+    )
+    .map(Choice::Search)
+}
+
+/// The user's answer to a prompt, or None if they pressed Ctrl-C instead.
+fn answer<T>(prompted: dialoguer::Result<T>, unanswered: &str) -> Option<T> {
+    match prompted {
+        Ok(answer) => Some(answer),
+        Err(_) if interrupt::requested() => None,
+        Err(e) => panic!("{}: {}", unanswered, e),
+    }
+}
@@ -0,0 +1,152 @@
+use crate::cli::metadata;
+use crate::config::{Config, Geocoder, Units};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// The dataset the published output was computed from.
+pub const CRU_DATASET_URL: &str = "https://crudata.uea.ac.uk/cru/data/hrg/cru_ts_4.06/cruts.2205201912.v4.06/tmp/cru_ts4.06.2011.2020.tmp.dat.nc.gz";
+/// The SHA-256 of the file CRU publishes at `CRU_DATASET_URL`, decompressed, which a download
+/// has to match.
+pub const CRU_DATASET_SHA256: &str =
+    "3c05fa7da4c12951eb1567a150efa15d32173452487cc33b78cd23a1c6ed93ff";
+
+#[derive(Debug)]
+pub enum DownloadErr {
+    Http(reqwest::Error),
+    Io(io::Error),
+}
+
+impl Display for DownloadErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            DownloadErr::Http(e) => write!(fmt, "download failed: {}", e),
+            DownloadErr::Io(e) => write!(fmt, "can't write dataset: {}", e),
+        }
+    }
+}
+
+/// Download a NetCDF file, decompressing it on the way to `destination` if it's gzipped, as
+/// CRU's are.
+pub fn download_dataset(url: &str, destination: &Path) -> Result<(), DownloadErr> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .build()
+        .map_err(DownloadErr::Http)?;
+    let mut resp = client
+        .get(url)
+        .header("User-Agent", "Christophe's geolocator helper script.")
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .map_err(DownloadErr::Http)?;
+    let mut output = BufWriter::new(File::create(destination).map_err(DownloadErr::Io)?);
+    if url.ends_with(".gz") {
+        io::copy(&mut GzDecoder::new(resp), &mut output).map_err(DownloadErr::Io)?;
+    } else {
+        io::copy(&mut resp, &mut output).map_err(DownloadErr::Io)?;
+    }
+    Ok(())
+}
+
+fn select<T: ValueEnum + PartialEq>(prompt: &str, current: Option<T>) -> T {
+    let variants = T::value_variants();
+    let names: Vec<String> = variants
+        .iter()
+        .map(|v| {
+            let value = v.to_possible_value().expect("No skipped variants");
+            match value.get_help() {
+                Some(help) => format!("{}: {}", value.get_name(), help),
+                None => value.get_name().to_string(),
+            }
+        })
+        .collect();
+    let choice = dialoguer::Select::new()
+        .with_prompt(prompt)
+        .items(&names)
+        .default(
+            current
+                .and_then(|c| variants.iter().position(|v| *v == c))
+                .unwrap_or(0),
+        )
+        .interact()
+        .expect("User didn't make a choice.");
+    variants[choice].clone()
+}
+
+fn optional_year(prompt: &str, current: Option<i32>) -> Option<i32> {
+    let answer: String = dialoguer::Input::new()
+        .with_prompt(prompt)
+        .with_initial_text(current.map(|y| y.to_string()).unwrap_or_default())
+        .allow_empty(true)
+        .validate_with(|input: &String| -> Result<(), &str> {
+            if input.is_empty() || input.parse::<i32>().is_ok() {
+                Ok(())
+            } else {
+                Err("Not a year")
+            }
+        })
+        .interact_text()
+        .expect("User didn't enter a year.");
+    answer.parse().ok()
+}
+
+/// Ask the user for everything the config file can hold, and write it out.
+pub fn wizard(config_path: &Path) {
+    let mut config = Config::load(config_path).unwrap_or_default();
+
+    let download = dialoguer::Select::new()
+        .with_prompt("Temperature dataset")
+        .items(&[
+            "I already have a CRU TS NetCDF file",
+            "Download the CRU TS 4.06 2011-2020 file",
+        ])
+        .default(0)
+        .interact()
+        .expect("User didn't make a choice.")
+        == 1;
+    let dataset: String = dialoguer::Input::new()
+        .with_prompt(if download {
+            "Where should it be saved?"
+        } else {
+            "Where is it?"
+        })
+        .with_initial_text(
+            config
+                .temperature_dataset
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "cru_ts4.06.2011.2020.tmp.dat.nc".to_string()),
+        )
+        .interact_text()
+        .expect("User didn't enter a dataset path.");
+    let dataset = PathBuf::from(dataset);
+    if download {
+        println!("Downloading {}", CRU_DATASET_URL);
+        download_dataset(CRU_DATASET_URL, &dataset)
+            .unwrap_or_else(|e| panic!("Couldn't download dataset: {}", e));
+        let sha256 = metadata::sha256_of(&dataset)
+            .unwrap_or_else(|e| panic!("Couldn't hash dataset: {}", e));
+        if sha256 != CRU_DATASET_SHA256 {
+            panic!(
+                "The download of {} is corrupted: its SHA-256 is {} rather than {}",
+                CRU_DATASET_URL, sha256, CRU_DATASET_SHA256
+            );
+        }
+    }
+    // Pinned so later runs notice if the download gets corrupted or replaced.
+    config.temperature_dataset_sha256 = download.then(|| CRU_DATASET_SHA256.to_string());
+    config.temperature_dataset = Some(dataset);
+
+    config.start_year = optional_year("First year to average (empty for all)", config.start_year);
+    config.end_year = optional_year("Last year to average (empty for all)", config.end_year);
+    config.units = Some(select::<Units>("Units", config.units));
+    config.geocoder = Some(select::<Geocoder>("Geocoder", config.geocoder));
+
+    config
+        .save(config_path)
+        .unwrap_or_else(|e| panic!("Couldn't save config: {}", e));
+    println!("Wrote {}", config_path.display());
+}
@@ -0,0 +1,137 @@
+//! Reading the cities input, from any of the formats of `sources`,
+//! with columns optionally renamed to the ones we expect.
+
+use crate::cli::init::DownloadErr;
+use crate::cli::sources::{Rows, SourceErr, Sources};
+use crate::cli::City;
+use csv::StringRecord;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+
+#[derive(Debug)]
+pub enum CitiesReadErr {
+    Source(SourceErr),
+    Csv(csv::Error),
+}
+
+impl Display for CitiesReadErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            CitiesReadErr::Source(e) => write!(fmt, "{}", e),
+            CitiesReadErr::Csv(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl From<SourceErr> for CitiesReadErr {
+    fn from(e: SourceErr) -> Self {
+        CitiesReadErr::Source(e)
+    }
+}
+
+impl From<csv::Error> for CitiesReadErr {
+    fn from(e: csv::Error) -> Self {
+        CitiesReadErr::Csv(e)
+    }
+}
+
+/// A `--column` option: which input column to read one of ours from.
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub field: String,
+    pub header: String,
+}
+
+pub fn parse_column_mapping(s: &str) -> Result<ColumnMapping, String> {
+    let (field, header) = s
+        .split_once('=')
+        .ok_or_else(|| "expected FIELD=HEADER, e.g. City=Town".to_string())?;
+    Ok(ColumnMapping {
+        field: field.to_string(),
+        header: header.to_string(),
+    })
+}
+
+/// The URL, if the cities argument is one rather than a path.
+pub fn cities_url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|s| s.starts_with("https://") || s.starts_with("http://"))
+}
+
+/// Where to keep the download of `url` next to `output`, keeping its extension if one of
+/// `sources` reads it.
+pub fn download_path(url: &str, output: &Path, sources: &Sources) -> PathBuf {
+    let url_path = Path::new(url.split(['?', '#']).next().unwrap_or(url));
+    let extension = match url_path.extension().and_then(|e| e.to_str()) {
+        Some(extension) if sources.for_extension(url_path).is_some() => extension,
+        _ => "csv",
+    };
+    let mut path = output.as_os_str().to_owned();
+    path.push(format!(".cities.{}", extension));
+    PathBuf::from(path)
+}
+
+/// Published spreadsheets are served by busy servers, so give it a few tries.
+pub fn download(url: &str, destination: &Path) -> Result<(), DownloadErr> {
+    let client = reqwest::blocking::Client::new();
+    let mut attempt = 1;
+    let mut resp = loop {
+        match client
+            .get(url)
+            .header("User-Agent", "Christophe's geolocator helper script.")
+            .send()
+            .and_then(|resp| resp.error_for_status())
+        {
+            Ok(resp) => break resp,
+            Err(e) if attempt == DOWNLOAD_ATTEMPTS => return Err(DownloadErr::Http(e)),
+            Err(e) => {
+                eprintln!("Couldn't download {}, retrying: {}", url, e);
+                thread::sleep(Duration::from_secs(2u64.pow(attempt)));
+                attempt += 1;
+            }
+        }
+    };
+    let mut output = File::create(destination).map_err(DownloadErr::Io)?;
+    io::copy(&mut resp, &mut output).map_err(DownloadErr::Io)?;
+    Ok(())
+}
+
+pub fn read_cities(path: &Path, columns: &[ColumnMapping]) -> Result<Vec<City>, CitiesReadErr> {
+    read_cities_from(&Sources::default(), None, path, columns).map(|(cities, _)| cities)
+}
+
+/// The cities, read by the source named `format` or else the one for the path's extension, and
+/// the indices of those whose row wasn't valid UTF-8, whose text has the replacement character in
+/// place of the invalid bytes.
+pub fn read_cities_from(
+    sources: &Sources,
+    format: Option<&str>,
+    path: &Path,
+    columns: &[ColumnMapping],
+) -> Result<(Vec<City>, Vec<usize>), CitiesReadErr> {
+    let Rows {
+        header,
+        rows,
+        non_utf8,
+    } = sources.read(path, format)?;
+    let headers: StringRecord = header
+        .iter()
+        .map(|header| {
+            columns
+                .iter()
+                .find(|mapping| &mapping.header == header)
+                .map_or(header, |mapping| &mapping.field)
+        })
+        .collect();
+    let cities = rows
+        .iter()
+        .map(|row| StringRecord::from(row.clone()).deserialize(Some(&headers)))
+        .collect::<Result<_, _>>()?;
+    Ok((cities, non_utf8))
+}
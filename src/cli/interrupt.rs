@@ -0,0 +1,33 @@
+//! Ctrl-C during a run: the first one lets the city being worked on finish (or the question
+//! being asked about it go unanswered), then saves what was done so far and explains how to
+//! pick up from there. A second one quits right away, the output as of the last city.
+
+use crate::CancellationToken;
+use std::process;
+use std::sync::LazyLock;
+
+/// Cancelled by the first Ctrl-C.
+static TOKEN: LazyLock<CancellationToken> = LazyLock::new(CancellationToken::new);
+
+/// The exit status of a process killed by SIGINT, as shells report it.
+pub const EXIT_STATUS: i32 = 130;
+
+pub fn install() {
+    ctrlc::set_handler(|| {
+        if TOKEN.is_cancelled() {
+            process::exit(EXIT_STATUS);
+        }
+        TOKEN.cancel();
+        eprintln!("\nStopping after the current city, Ctrl-C again to quit right away.");
+    })
+    .unwrap_or_else(|e| panic!("Couldn't handle Ctrl-C: {}", e));
+}
+
+pub fn requested() -> bool {
+    TOKEN.is_cancelled()
+}
+
+/// The token Ctrl-C cancels, for what takes one.
+pub fn token() -> &'static CancellationToken {
+    &TOKEN
+}
@@ -0,0 +1,130 @@
+//! Finding a city's coordinates without prompting, for the servers: either the geocoder has a
+//! single match (or a single one in the region), or the caller gets the candidates to pick from.
+
+use crate::cli::{nominatim, wikidata};
+use crate::config::Geocoder;
+use crate::coordinates::{self, CoordinateErr, Latitude, Longitude};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Serialize)]
+pub struct Candidate {
+    pub entity_id: Option<String>,
+    pub label: String,
+    pub description: Option<String>,
+    pub lon: Option<f32>,
+    pub lat: Option<f32>,
+}
+
+#[derive(Debug)]
+pub enum LocateErr {
+    Geocoder(reqwest::Error),
+    NotFound,
+    Ambiguous(Vec<Candidate>),
+    NoCoordinates,
+    /// The geocoder's coordinates aren't on the globe.
+    InvalidCoordinates(CoordinateErr),
+}
+
+impl Display for LocateErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            LocateErr::Geocoder(e) => write!(fmt, "can't geocode: {}", e),
+            LocateErr::NotFound => write!(fmt, "no match"),
+            LocateErr::Ambiguous(candidates) => {
+                write!(fmt, "{} candidates, pick one's entity id", candidates.len())
+            }
+            LocateErr::NoCoordinates => write!(fmt, "the match has no coordinates"),
+            LocateErr::InvalidCoordinates(e) => write!(fmt, "the match's {}", e),
+        }
+    }
+}
+
+/// What the geocoder found, best first. Only Nominatim gives coordinates straight away.
+pub fn candidates(
+    geocoder: Geocoder,
+    city: &str,
+    region: Option<&str>,
+    country: &str,
+) -> Result<Vec<Candidate>, reqwest::Error> {
+    Ok(match geocoder {
+        Geocoder::Wikidata => wikidata::search(city)?
+            .into_iter()
+            .map(|result| Candidate {
+                entity_id: Some(result.id),
+                label: result.label,
+                description: result.description,
+                lon: None,
+                lat: None,
+            })
+            .collect(),
+        Geocoder::Nominatim => nominatim::search(&nominatim::search_string(city, region, country))?
+            .into_iter()
+            .map(|mut result| Candidate {
+                entity_id: result.wikidata_entity_id(),
+                label: result.display_name,
+                description: Some(result.kind),
+                lon: Some(result.lon),
+                lat: Some(result.lat),
+            })
+            .collect(),
+    })
+}
+
+/// The entity id (when known), longitude and latitude of a city, taking `entity_id` as a given.
+pub fn locate(
+    geocoder: Geocoder,
+    city: &str,
+    region: Option<&str>,
+    country: &str,
+    entity_id: Option<String>,
+) -> Result<(Option<String>, Longitude, Latitude), LocateErr> {
+    let (entity_id, lon, lat) = locate_unchecked(geocoder, city, region, country, entity_id)?;
+    let (lon, lat) = coordinates::lon_lat(lon, lat).map_err(LocateErr::InvalidCoordinates)?;
+    Ok((entity_id, lon, lat))
+}
+
+fn locate_unchecked(
+    geocoder: Geocoder,
+    city: &str,
+    region: Option<&str>,
+    country: &str,
+    entity_id: Option<String>,
+) -> Result<(Option<String>, f32, f32), LocateErr> {
+    if let Some(entity_id) = entity_id {
+        let (lon, lat) = wikidata::acquire_lon_lat(&entity_id).map_err(LocateErr::Geocoder)?;
+        return Ok((Some(entity_id), lon, lat));
+    }
+    let mut candidates =
+        candidates(geocoder, city, region, country).map_err(LocateErr::Geocoder)?;
+    let choice = match candidates.len() {
+        0 => return Err(LocateErr::NotFound),
+        1 => Some(0),
+        _ => wikidata::only_match_in_region(
+            region,
+            candidates.iter().map(|candidate| match geocoder {
+                Geocoder::Nominatim => &candidate.label[..],
+                Geocoder::Wikidata => candidate.description.as_deref().unwrap_or_default(),
+            }),
+        ),
+    };
+    let Some(choice) = choice else {
+        return Err(LocateErr::Ambiguous(candidates));
+    };
+    match candidates.swap_remove(choice) {
+        Candidate {
+            lon: Some(lon),
+            lat: Some(lat),
+            entity_id,
+            ..
+        } => Ok((entity_id, lon, lat)),
+        Candidate {
+            entity_id: Some(entity_id),
+            ..
+        } => {
+            let (lon, lat) = wikidata::acquire_lon_lat(&entity_id).map_err(LocateErr::Geocoder)?;
+            Ok((Some(entity_id), lon, lat))
+        }
+        _ => Err(LocateErr::NoCoordinates),
+    }
+}
@@ -0,0 +1,147 @@
+use crate::cli::opendap;
+use crate::multifile;
+#[cfg(feature = "object-store")]
+use crate::objectstore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// A file as it was when the run happened, so we can tell if it has changed since.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileFingerprint {
+    #[serde(with = "os_path")]
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+impl FileFingerprint {
+    pub fn of(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_owned(),
+            sha256: sha256_of(path)?,
+        })
+    }
+
+    /// Whether the file at the recorded path still has the recorded contents.
+    pub fn is_current(&self) -> io::Result<bool> {
+        Ok(sha256_of(&self.path)? == self.sha256)
+    }
+}
+
+/// Paths as strings when they're valid UTF-8, as they nearly always are, and otherwise in their
+/// platform's own encoding, bytes on Unix and UTF-16 on Windows, which JSON strings can't hold.
+mod os_path {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Utf8(String),
+        #[cfg(unix)]
+        Unix {
+            unix_bytes: Vec<u8>,
+        },
+        #[cfg(windows)]
+        Windows {
+            windows_wide: Vec<u16>,
+        },
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = match path.to_str() {
+            Some(path) => Encoded::Utf8(path.to_string()),
+            #[cfg(unix)]
+            None => Encoded::Unix {
+                unix_bytes: std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec(),
+            },
+            #[cfg(windows)]
+            None => Encoded::Windows {
+                windows_wide: std::os::windows::ffi::OsStrExt::encode_wide(path.as_os_str())
+                    .collect(),
+            },
+            #[cfg(not(any(unix, windows)))]
+            None => Encoded::Utf8(path.to_string_lossy().into_owned()),
+        };
+        encoded.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(match Encoded::deserialize(deserializer)? {
+            Encoded::Utf8(path) => PathBuf::from(path),
+            #[cfg(unix)]
+            Encoded::Unix { unix_bytes } => PathBuf::from(
+                <std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(unix_bytes),
+            ),
+            #[cfg(windows)]
+            Encoded::Windows { windows_wide } => PathBuf::from(
+                <std::ffi::OsString as std::os::windows::ffi::OsStringExt>::from_wide(
+                    &windows_wide,
+                ),
+            ),
+        })
+    }
+}
+
+/// Of all the files together, if `path` stands for several, see `multifile::dataset_files`.
+/// Objects in buckets and datasets on OPeNDAP servers are fingerprinted without downloading them,
+/// see `objectstore::fingerprint` and `opendap::fingerprint`.
+pub fn sha256_of(path: &Path) -> io::Result<String> {
+    if let Some(url) = path.to_str().filter(|_| opendap::is_url(path)) {
+        return opendap::fingerprint(url).map_err(|e| io::Error::other(e.to_string()));
+    }
+    #[cfg(feature = "object-store")]
+    if let Some(url) = path.to_str().filter(|_| objectstore::is_s3_url(path)) {
+        return objectstore::fingerprint(url).map_err(|e| io::Error::other(e.to_string()));
+    }
+    let mut hasher = Sha256::new();
+    for file in multifile::dataset_files(path)? {
+        io::copy(&mut BufReader::new(File::open(file)?), &mut hasher)?;
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Everything needed to decide whether an output file can be reproduced,
+/// written next to the output as `<output>.meta.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub tool_version: String,
+    pub temperature_dataset: FileFingerprint,
+    pub cities: FileFingerprint,
+    pub output: FileFingerprint,
+    /// The CRU TS version of the temperature dataset, e.g. 4.06, when it is one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset_version: Option<String>,
+    /// Any option that influences the computed values, keyed by its command line name.
+    pub parameters: BTreeMap<String, String>,
+    /// Anything about the run that whoever uses its output should know, e.g. overridden
+    /// missing values.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl RunMetadata {
+    pub fn sidecar_path(output: &Path) -> PathBuf {
+        let mut path = output.as_os_str().to_owned();
+        path.push(".meta.json");
+        PathBuf::from(path)
+    }
+
+    pub fn read(output: &Path) -> io::Result<Self> {
+        let file = File::open(Self::sidecar_path(output))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn write(&self) -> io::Result<()> {
+        let file = File::create(Self::sidecar_path(&self.output.path))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! Prometheus metrics for `serve`: requests and queries answered, how long they took, and how
+//! much memory the dataset holds, in the text exposition format on `--metrics-address`.
+
+use crate::TemperatureDataset;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram's buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// How long a scraper gets to send its request and take the response, so one that stalls doesn't
+/// hold up the others, which are answered one at a time.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct Method {
+    requests: u64,
+    errors: u64,
+    /// Requests that took at most each bucket's bound, and more than the one before.
+    buckets: [u64; BUCKETS.len()],
+    seconds: f64,
+}
+
+pub struct Metrics {
+    dataset_bytes: usize,
+    observations: usize,
+    methods: Mutex<BTreeMap<&'static str, Method>>,
+    /// Points or cities looked up, counting each one of a batch.
+    queries: Mutex<BTreeMap<&'static str, u64>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    /// Requests turned away before reaching the service, by reason.
+    rejected: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn new(dataset: &TemperatureDataset) -> Self {
+        Self {
+            dataset_bytes: dataset.memory_bytes(),
+            observations: dataset.len(),
+            methods: Mutex::default(),
+            queries: Mutex::default(),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            rejected: Mutex::default(),
+        }
+    }
+
+    pub fn request(&self, method: &'static str, elapsed: Duration, succeeded: bool) {
+        let mut methods = self.methods.lock().expect("No recorder panicked");
+        let method = methods.entry(method).or_default();
+        let seconds = elapsed.as_secs_f64();
+        method.requests += 1;
+        method.errors += u64::from(!succeeded);
+        method.seconds += seconds;
+        if let Some(bucket) = BUCKETS.iter().position(|&bound| seconds <= bound) {
+            method.buckets[bucket] += 1;
+        }
+    }
+
+    /// `kind` is "point" or "city".
+    pub fn query(&self, kind: &'static str) {
+        *self
+            .queries
+            .lock()
+            .expect("No recorder panicked")
+            .entry(kind)
+            .or_default() += 1;
+    }
+
+    /// `reason` is "unauthenticated" or "rate_limited".
+    pub fn rejected(&self, reason: &'static str) {
+        *self
+            .rejected
+            .lock()
+            .expect("No recorder panicked")
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self) -> String {
+        let methods = self.methods.lock().expect("No recorder panicked");
+        let queries = self.queries.lock().expect("No recorder panicked");
+        let mut out = String::new();
+
+        let requests = "temperature_extractor_requests_total";
+        family(&mut out, requests, "counter", "gRPC requests answered.");
+        for (name, method) in methods.iter() {
+            let _ = writeln!(
+                out,
+                "{}{{method=\"{}\"}} {}",
+                requests, name, method.requests
+            );
+        }
+        let errors = "temperature_extractor_request_errors_total";
+        family(&mut out, errors, "counter", "gRPC requests that failed.");
+        for (name, method) in methods.iter() {
+            let _ = writeln!(out, "{}{{method=\"{}\"}} {}", errors, name, method.errors);
+        }
+        let duration = "temperature_extractor_request_duration_seconds";
+        family(
+            &mut out,
+            duration,
+            "histogram",
+            "How long gRPC requests took to answer.",
+        );
+        for (name, method) in methods.iter() {
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(method.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                    duration, name, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{method=\"{}\",le=\"+Inf\"}} {}",
+                duration, name, method.requests
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{method=\"{}\"}} {}",
+                duration, name, method.seconds
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{method=\"{}\"}} {}",
+                duration, name, method.requests
+            );
+        }
+        let looked_up = "temperature_extractor_queries_total";
+        family(
+            &mut out,
+            looked_up,
+            "counter",
+            "Points and cities looked up, counting each one of a batch.",
+        );
+        for (kind, count) in queries.iter() {
+            let _ = writeln!(out, "{}{{kind=\"{}\"}} {}", looked_up, kind, count);
+        }
+        let rejected = "temperature_extractor_rejected_total";
+        family(
+            &mut out,
+            rejected,
+            "counter",
+            "Requests turned away for lacking an API key or going over the rate limit.",
+        );
+        for (reason, count) in self.rejected.lock().expect("No recorder panicked").iter() {
+            let _ = writeln!(out, "{}{{reason=\"{}\"}} {}", rejected, reason, count);
+        }
+        let hits = "temperature_extractor_cache_hits_total";
+        family(&mut out, hits, "counter", "Averages found in the cache.");
+        let _ = writeln!(out, "{} {}", hits, self.cache_hits.load(Ordering::Relaxed));
+        let misses = "temperature_extractor_cache_misses_total";
+        family(
+            &mut out,
+            misses,
+            "counter",
+            "Averages computed from the dataset.",
+        );
+        let _ = writeln!(
+            out,
+            "{} {}",
+            misses,
+            self.cache_misses.load(Ordering::Relaxed)
+        );
+        let bytes = "temperature_extractor_dataset_bytes";
+        family(
+            &mut out,
+            bytes,
+            "gauge",
+            "Memory held by the temperature dataset, not counting memory-mapped pages.",
+        );
+        let _ = writeln!(out, "{} {}", bytes, self.dataset_bytes);
+        let observations = "temperature_extractor_dataset_observations";
+        family(
+            &mut out,
+            observations,
+            "gauge",
+            "Values in the temperature dataset, cells times time steps.",
+        );
+        let _ = writeln!(out, "{} {}", observations, self.observations);
+        out
+    }
+}
+
+/// The `# HELP` and `# TYPE` lines that start each metric.
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+}
+
+/// Answers `GET /metrics` on `address` from a thread of its own.
+pub fn expose(metrics: Arc<Metrics>, address: SocketAddr) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A scraper hanging up halfway isn't worth stopping for.
+            let _ = respond(&metrics, stream);
+        }
+    });
+    Ok(())
+}
+
+fn respond(metrics: &Metrics, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+    stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Headers aren't needed, but closing with them unread would reset the connection.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, body) = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", "/metrics", _] => ("200 OK", metrics.render()),
+        _ => (
+            "404 Not Found",
+            "Only GET /metrics is served here.\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
@@ -0,0 +1,119 @@
+//! Writing derived grids back out as CF-compliant NetCDF, so they open in standard climate tools.
+
+use crate::grid::Grid;
+use netcdf3::{DataSet, DataType, FileWriter, InvalidDataSet, Version, WriteError};
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// The NetCDF default fill value for floats, which is also what the CRU datasets use.
+const FILL_VALUE: f32 = 9.969_21e36;
+
+#[derive(Debug)]
+pub enum NcWriteErr {
+    Define(InvalidDataSet),
+    Write(WriteError),
+}
+
+impl Display for NcWriteErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            NcWriteErr::Define(e) => write!(fmt, "can't define dataset: {}", e),
+            NcWriteErr::Write(e) => write!(fmt, "can't write dataset: {:?}", e),
+        }
+    }
+}
+
+impl From<InvalidDataSet> for NcWriteErr {
+    fn from(e: InvalidDataSet) -> Self {
+        NcWriteErr::Define(e)
+    }
+}
+
+impl From<WriteError> for NcWriteErr {
+    fn from(e: WriteError) -> Self {
+        NcWriteErr::Write(e)
+    }
+}
+
+/// What the values of a grid are, for the data variable's attributes.
+pub struct GridVariable<'a> {
+    pub name: &'a str,
+    pub long_name: &'a str,
+    /// UDUNITS, e.g. "degC".
+    pub units: &'a str,
+    /// CF cell methods, e.g. "time: mean".
+    pub cell_methods: Option<&'a str>,
+}
+
+/// Write `grid` as a (lat, lon) variable with its coordinate variables,
+/// and `attributes` as global attributes on top of the CF conventions one.
+pub fn write_grid(
+    path: &Path,
+    grid: &Grid,
+    variable: GridVariable,
+    attributes: &[(&str, String)],
+) -> Result<(), NcWriteErr> {
+    write_grids(path, &[(grid, variable)], attributes)
+}
+
+/// Like `write_grid`, with a variable for each of `grids`, which all cover the same cells.
+pub fn write_grids(
+    path: &Path,
+    grids: &[(&Grid, GridVariable)],
+    attributes: &[(&str, String)],
+) -> Result<(), NcWriteErr> {
+    let (grid, _) = grids.first().expect("At least one grid to write");
+    let lons: Vec<f32> = (0..grid.width)
+        .map(|x| grid.cell(x, 0).center().0)
+        .collect();
+    let lats: Vec<f32> = (0..grid.height)
+        .map(|y| grid.cell(0, y).center().1)
+        .collect();
+
+    let mut data_set = DataSet::new();
+    data_set.add_fixed_dim("lat", grid.height)?;
+    data_set.add_fixed_dim("lon", grid.width)?;
+
+    data_set.add_var("lat", &["lat"], DataType::F32)?;
+    data_set.add_var_attr_string("lat", "standard_name", "latitude")?;
+    data_set.add_var_attr_string("lat", "long_name", "latitude")?;
+    data_set.add_var_attr_string("lat", "units", "degrees_north")?;
+    data_set.add_var_attr_string("lat", "axis", "Y")?;
+
+    data_set.add_var("lon", &["lon"], DataType::F32)?;
+    data_set.add_var_attr_string("lon", "standard_name", "longitude")?;
+    data_set.add_var_attr_string("lon", "long_name", "longitude")?;
+    data_set.add_var_attr_string("lon", "units", "degrees_east")?;
+    data_set.add_var_attr_string("lon", "axis", "X")?;
+
+    for (_, variable) in grids {
+        data_set.add_var(variable.name, &["lat", "lon"], DataType::F32)?;
+        data_set.add_var_attr_string(variable.name, "long_name", variable.long_name)?;
+        data_set.add_var_attr_string(variable.name, "units", variable.units)?;
+        if let Some(cell_methods) = variable.cell_methods {
+            data_set.add_var_attr_string(variable.name, "cell_methods", cell_methods)?;
+        }
+        data_set.add_var_attr_f32(variable.name, "_FillValue", vec![FILL_VALUE])?;
+        data_set.add_var_attr_f32(variable.name, "missing_value", vec![FILL_VALUE])?;
+    }
+
+    data_set.add_global_attr_string("Conventions", "CF-1.8")?;
+    for (name, value) in attributes {
+        data_set.add_global_attr_string(name, value)?;
+    }
+
+    let mut writer = FileWriter::open(path)?;
+    writer.set_def(&data_set, Version::Classic, 0)?;
+    writer.write_var_f32("lat", &lats)?;
+    writer.write_var_f32("lon", &lons)?;
+    for (grid, variable) in grids {
+        let values: Vec<f32> = grid
+            .values
+            .iter()
+            .map(|value| value.unwrap_or(FILL_VALUE))
+            .collect();
+        writer.write_var_f32(variable.name, &values)?;
+    }
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::cli::hooks::{Choice, CityName, Hooks};
+use crate::cli::wikidata::{self, GeocodeErr};
+use crate::CancellationToken;
+use serde::{self, Deserialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Deserialize, Debug)]
+pub struct SearchResult {
+    pub display_name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(deserialize_with = "parse_float")]
+    pub lon: f32,
+    #[serde(deserialize_with = "parse_float")]
+    pub lat: f32,
+    extratags: Option<HashMap<String, String>>,
+}
+
+impl SearchResult {
+    /// When OpenStreetMap knows it.
+    pub fn wikidata_entity_id(&mut self) -> Option<String> {
+        self.extratags.as_mut()?.remove("wikidata")
+    }
+}
+
+fn parse_float<'de, D>(deserializer: D) -> Result<f32, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    f32::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+/// The places matching `search_string`, best first.
+pub fn search(search_string: &str) -> Result<Vec<SearchResult>, reqwest::Error> {
+    reqwest::blocking::Client::new()
+        .get("https://nominatim.openstreetmap.org/search")
+        .header("Accept", "application/json")
+        .header("User-Agent", "Christophe's geolocator helper script.")
+        .query(&[
+            ("q", search_string),
+            ("format", "jsonv2"),
+            ("extratags", "1"),
+            ("limit", "10"),
+        ])
+        .send()?
+        .json()
+}
+
+pub fn search_string(city: &str, region: Option<&str>, country: &str) -> String {
+    match region {
+        Some(region) => format!("{}, {}, {}", city, region, country),
+        None => format!("{}, {}", city, country),
+    }
+}
+
+/// Returns the place's wikidata entity id (when OpenStreetMap knows it), longitude and latitude.
+/// Asks `hooks` which search result is the right one, unless `region` makes it obvious. Gives up
+/// before searching again once `cancel` is cancelled.
+pub fn find_place(
+    city: &str,
+    region: Option<&str>,
+    country: &str,
+    hooks: &mut dyn Hooks,
+    cancel: &CancellationToken,
+) -> Result<(Option<String>, f32, f32), GeocodeErr> {
+    let mut search_string = search_string(city, region, country);
+
+    loop {
+        if cancel.is_cancelled() {
+            return Err(GeocodeErr::Interrupted);
+        }
+        let mut resp = search(&search_string)?;
+
+        let choice = match wikidata::only_match_in_region(
+            region,
+            resp.iter().map(|result| &result.display_name[..]),
+        ) {
+            Some(choice) => Choice::Candidate(choice),
+            None => {
+                let options: Vec<String> = resp
+                    .iter()
+                    .map(|result| format!("{} ({})", result.display_name, result.kind))
+                    .collect();
+                let name = CityName {
+                    city,
+                    region,
+                    country,
+                };
+                hooks
+                    .on_geocode_ambiguous(name, &search_string, &options)
+                    .ok_or(GeocodeErr::Interrupted)?
+            }
+        };
+
+        match choice {
+            Choice::Candidate(choice) if choice < resp.len() => {
+                let mut place = resp.remove(choice);
+                return Ok((place.wikidata_entity_id(), place.lon, place.lat));
+            }
+            Choice::Candidate(_) => {}
+            Choice::Search(search) => search_string = search,
+        }
+    }
+}
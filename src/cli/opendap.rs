@@ -0,0 +1,496 @@
+//! Reading from OPeNDAP (DAP2) servers such as THREDDS or Hyrax: a dataset URL can be given
+//! wherever a dataset file can, and `subset` asks the server for just the cells and months
+//! needed rather than downloading the whole file.
+
+use crate::calendar::MonthStamp;
+use crate::cli::subset::BoundingBox;
+use crate::{Period, TemperatureDataset};
+use netcdf3::{DataSet, DataType, DataVector, FileWriter, InvalidDataSet, Version, WriteError};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum OpendapErr {
+    Http(reqwest::Error),
+    /// The server's answer didn't make sense to us.
+    Response(String),
+    Empty(&'static str),
+    /// Which variable to subset can't be told, see `TemperatureDataset::temperature_variable`.
+    Variable(String),
+    Define(InvalidDataSet),
+    Write(WriteError),
+}
+
+impl Display for OpendapErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            OpendapErr::Http(e) => write!(fmt, "request failed: {}", e),
+            OpendapErr::Response(e) => write!(fmt, "unexpected response: {}", e),
+            OpendapErr::Empty(dim) => write!(fmt, "nothing left along {}", dim),
+            OpendapErr::Variable(e) => write!(fmt, "{}", e),
+            OpendapErr::Define(e) => write!(fmt, "can't define subset: {}", e),
+            OpendapErr::Write(e) => write!(fmt, "can't write subset: {:?}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for OpendapErr {
+    fn from(e: reqwest::Error) -> Self {
+        OpendapErr::Http(e)
+    }
+}
+
+impl From<InvalidDataSet> for OpendapErr {
+    fn from(e: InvalidDataSet) -> Self {
+        OpendapErr::Define(e)
+    }
+}
+
+impl From<WriteError> for OpendapErr {
+    fn from(e: WriteError) -> Self {
+        OpendapErr::Write(e)
+    }
+}
+
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|s| s.starts_with("https://") || s.starts_with("http://"))
+}
+
+/// A variable as declared in a DDS, e.g. `Float32 tmp[time = 1464][lat = 360][lon = 720];`.
+#[derive(Debug)]
+struct Declaration {
+    data_type: DataType,
+    name: String,
+    dims: Vec<(String, usize)>,
+}
+
+fn parse_data_type(name: &str) -> Option<DataType> {
+    match name {
+        "Byte" => Some(DataType::U8),
+        "Int16" | "UInt16" => Some(DataType::I16),
+        "Int32" | "UInt32" => Some(DataType::I32),
+        "Float32" => Some(DataType::F32),
+        "Float64" => Some(DataType::F64),
+        _ => None,
+    }
+}
+
+/// The variables of a DDS in the order their data comes in, leaving out the
+/// Grid and Structure wrappers. Strings and URLs aren't supported.
+fn parse_dds(dds: &str) -> Result<Vec<Declaration>, OpendapErr> {
+    let mut declarations = vec![];
+    for line in dds.lines().map(str::trim) {
+        let Some(line) = line.strip_suffix(';') else {
+            continue;
+        };
+        let Some((data_type, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let Some(data_type) = parse_data_type(data_type) else {
+            if ["String", "Url"].contains(&data_type) {
+                return Err(OpendapErr::Response(format!(
+                    "unsupported {} variable",
+                    data_type
+                )));
+            }
+            continue;
+        };
+        let rest = rest.trim();
+        let (name, dims) = rest.split_once('[').unwrap_or((rest, ""));
+        let dims = dims
+            .split('[')
+            .filter(|dim| !dim.is_empty())
+            .map(|dim| {
+                let dim = dim.trim_end_matches(']');
+                let (dim_name, size) = dim.split_once('=').unwrap_or(("", dim));
+                let size = size
+                    .trim()
+                    .parse()
+                    .map_err(|_| OpendapErr::Response(format!("bad dimension {}", dim)))?;
+                Ok((dim_name.trim().to_string(), size))
+            })
+            .collect::<Result<_, OpendapErr>>()?;
+        declarations.push(Declaration {
+            data_type,
+            name: name.trim().to_string(),
+            dims,
+        });
+    }
+    Ok(declarations)
+}
+
+/// Attributes of a variable, or of `NC_GLOBAL`, as declared in a DAS.
+#[derive(Debug, Clone)]
+enum AttributeValue {
+    Text(String),
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+
+fn parse_attribute(data_type: &str, values: &str) -> Option<AttributeValue> {
+    if data_type == "String" || data_type == "Url" {
+        let text = values.trim();
+        let text = text.strip_prefix('"').unwrap_or(text);
+        let text = text.strip_suffix('"').unwrap_or(text);
+        return Some(AttributeValue::Text(text.replace("\\\"", "\"")));
+    }
+    let values = values.split(',').map(str::trim);
+    Some(match data_type {
+        "Byte" => AttributeValue::I8(values.map(|v| v.parse().ok()).collect::<Option<_>>()?),
+        "Int16" | "UInt16" => {
+            AttributeValue::I16(values.map(|v| v.parse().ok()).collect::<Option<_>>()?)
+        }
+        "Int32" | "UInt32" => {
+            AttributeValue::I32(values.map(|v| v.parse().ok()).collect::<Option<_>>()?)
+        }
+        "Float32" => AttributeValue::F32(values.map(|v| v.parse().ok()).collect::<Option<_>>()?),
+        "Float64" => AttributeValue::F64(values.map(|v| v.parse().ok()).collect::<Option<_>>()?),
+        _ => return None,
+    })
+}
+
+/// Attribute name and value by container, only one level deep, which is all NetCDF has.
+/// Anything that doesn't parse is left out, attributes being informational.
+fn parse_das(das: &str) -> HashMap<String, Vec<(String, AttributeValue)>> {
+    let mut attributes: HashMap<String, Vec<_>> = HashMap::new();
+    let mut containers: Vec<String> = vec![];
+    for line in das.lines().map(str::trim) {
+        if let Some(container) = line.strip_suffix('{') {
+            containers.push(container.trim().to_string());
+        } else if line == "}" {
+            containers.pop();
+        } else if let (Some(line), [_, container]) = (line.strip_suffix(';'), &containers[..]) {
+            let mut parts = line.splitn(3, char::is_whitespace);
+            let (Some(data_type), Some(name), Some(values)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            if let Some(value) = parse_attribute(data_type, values) {
+                attributes
+                    .entry(container.clone())
+                    .or_default()
+                    .push((name.to_string(), value));
+            }
+        }
+    }
+    attributes
+}
+
+/// Decode the XDR encoded data following a DataDDS's `Data:` marker.
+fn parse_data(
+    declarations: &[Declaration],
+    mut data: &[u8],
+) -> Result<HashMap<String, DataVector>, OpendapErr> {
+    let mut take = |n: usize| -> Result<&[u8], OpendapErr> {
+        if data.len() < n {
+            return Err(OpendapErr::Response("truncated data".to_string()));
+        }
+        let (taken, rest) = data.split_at(n);
+        data = rest;
+        Ok(taken)
+    };
+    let mut vectors = HashMap::new();
+    for declaration in declarations {
+        let length = if declaration.dims.is_empty() {
+            1
+        } else {
+            // Arrays are prefixed with their length, twice.
+            let length = u32::from_be_bytes(take(8)?[..4].try_into().expect("4 bytes"));
+            length as usize
+        };
+        let vector = match declaration.data_type {
+            DataType::U8 => {
+                let bytes = take(length.div_ceil(4) * 4)?;
+                DataVector::U8(bytes[..length].to_vec())
+            }
+            DataType::I16 => DataVector::I16(
+                take(length * 4)?
+                    .chunks(4)
+                    .map(|c| i32::from_be_bytes(c.try_into().expect("4 bytes")) as i16)
+                    .collect(),
+            ),
+            DataType::I32 => DataVector::I32(
+                take(length * 4)?
+                    .chunks(4)
+                    .map(|c| i32::from_be_bytes(c.try_into().expect("4 bytes")))
+                    .collect(),
+            ),
+            DataType::F32 => DataVector::F32(
+                take(length * 4)?
+                    .chunks(4)
+                    .map(|c| f32::from_be_bytes(c.try_into().expect("4 bytes")))
+                    .collect(),
+            ),
+            DataType::F64 => DataVector::F64(
+                take(length * 8)?
+                    .chunks(8)
+                    .map(|c| f64::from_be_bytes(c.try_into().expect("8 bytes")))
+                    .collect(),
+            ),
+            DataType::I8 => unreachable!("DAP2 bytes are unsigned"),
+        };
+        vectors.entry(declaration.name.clone()).or_insert(vector);
+    }
+    Ok(vectors)
+}
+
+struct Server {
+    client: reqwest::blocking::Client,
+    url: String,
+}
+
+impl Server {
+    fn new(url: &str) -> Result<Self, OpendapErr> {
+        Ok(Self {
+            client: reqwest::blocking::Client::builder().timeout(None).build()?,
+            url: url.trim_end_matches(".html").to_string(),
+        })
+    }
+
+    fn get(&self, suffix: &str, constraint: &str) -> Result<Vec<u8>, OpendapErr> {
+        let url = if constraint.is_empty() {
+            format!("{}.{}", self.url, suffix)
+        } else {
+            // Servers behind Tomcat, such as THREDDS, refuse unescaped brackets.
+            let constraint = constraint.replace('[', "%5B").replace(']', "%5D");
+            format!("{}.{}?{}", self.url, suffix, constraint)
+        };
+        Ok(self
+            .client
+            .get(url)
+            .header("User-Agent", "Christophe's geolocator helper script.")
+            .send()?
+            .error_for_status()?
+            .bytes()?
+            .to_vec())
+    }
+
+    fn data(&self, constraint: &str) -> Result<HashMap<String, DataVector>, OpendapErr> {
+        let response = self.get("dods", constraint)?;
+        const MARKER: &[u8] = b"\nData:\n";
+        let split = response
+            .windows(MARKER.len())
+            .position(|window| window == MARKER)
+            .ok_or_else(|| OpendapErr::Response(String::from_utf8_lossy(&response).into_owned()))?;
+        let declarations = parse_dds(&String::from_utf8_lossy(&response[..split]))?;
+        parse_data(&declarations, &response[split + MARKER.len()..])
+    }
+}
+
+/// A dataset on an OPeNDAP server, described like a classic NetCDF file from its DDS and DAS,
+/// with each variable fetched whole when it's read.
+pub struct RemoteDataset {
+    server: Server,
+    data_set: DataSet,
+    attributes: HashMap<String, Vec<(String, AttributeValue)>>,
+}
+
+impl RemoteDataset {
+    pub fn open(url: &str) -> Result<Self, OpendapErr> {
+        let server = Server::new(url)?;
+        let declarations = parse_dds(&String::from_utf8_lossy(&server.get("dds", "")?))?;
+        let attributes = parse_das(&String::from_utf8_lossy(&server.get("das", "")?));
+        let mut data_set = DataSet::new();
+        for declaration in declarations {
+            // Grids repeat their coordinate variables as maps.
+            if data_set.get_var(&declaration.name).is_some() {
+                continue;
+            }
+            let mut dims = vec![];
+            for (i, (dim, size)) in declaration.dims.iter().enumerate() {
+                let dim = match dim.as_str() {
+                    "" => format!("{}_{}", declaration.name, i),
+                    dim => dim.to_string(),
+                };
+                if data_set.get_dim(&dim).is_none() {
+                    data_set.add_fixed_dim(&dim, *size)?;
+                }
+                dims.push(dim);
+            }
+            data_set.add_var(&declaration.name, &dims, declaration.data_type)?;
+            add_attributes(
+                &mut data_set,
+                Some(&declaration.name),
+                attributes.get(&declaration.name).map_or(&[], |a| &a[..]),
+            )?;
+        }
+        add_attributes(
+            &mut data_set,
+            None,
+            attributes.get("NC_GLOBAL").map_or(&[], |a| &a[..]),
+        )?;
+        Ok(Self {
+            server,
+            data_set,
+            attributes,
+        })
+    }
+
+    pub fn data_set(&self) -> &DataSet {
+        &self.data_set
+    }
+
+    /// Like a NetCDF file's variable of floats, taking doubles too, which servers often give
+    /// coordinates as.
+    pub fn read_var_f32(&self, name: &str) -> Result<Vec<f32>, OpendapErr> {
+        match self.server.data(name)?.remove(name) {
+            Some(DataVector::F32(values)) => Ok(values),
+            Some(DataVector::F64(values)) => Ok(values.into_iter().map(|v| v as f32).collect()),
+            Some(values) => Err(OpendapErr::Response(format!(
+                "{} is {:?}, not F32",
+                name,
+                values.data_type()
+            ))),
+            None => Err(OpendapErr::Response(format!("no {} variable", name))),
+        }
+    }
+}
+
+/// Identifies the dataset without downloading it: the SHA-256 of its URL, DDS and DAS, whose
+/// attributes change with each version.
+pub fn fingerprint(url: &str) -> Result<String, OpendapErr> {
+    let server = Server::new(url)?;
+    let mut hasher = Sha256::new();
+    hasher.update(url.as_bytes());
+    hasher.update(server.get("dds", "")?);
+    hasher.update(server.get("das", "")?);
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// The first and last of the indices of `values` to keep.
+fn index_range(
+    values: &[f32],
+    keep: impl Fn(f32) -> bool,
+    dim: &'static str,
+) -> Result<(usize, usize), OpendapErr> {
+    let first = values.iter().position(|&v| keep(v));
+    let last = values.iter().rposition(|&v| keep(v));
+    first.zip(last).ok_or(OpendapErr::Empty(dim))
+}
+
+fn add_attributes(
+    data_set: &mut DataSet,
+    var: Option<&str>,
+    attributes: &[(String, AttributeValue)],
+) -> Result<(), InvalidDataSet> {
+    for (name, value) in attributes {
+        match (var, value.clone()) {
+            (Some(var), AttributeValue::Text(v)) => data_set.add_var_attr_string(var, name, v),
+            (Some(var), AttributeValue::I8(v)) => data_set.add_var_attr_i8(var, name, v),
+            (Some(var), AttributeValue::I16(v)) => data_set.add_var_attr_i16(var, name, v),
+            (Some(var), AttributeValue::I32(v)) => data_set.add_var_attr_i32(var, name, v),
+            (Some(var), AttributeValue::F32(v)) => data_set.add_var_attr_f32(var, name, v),
+            (Some(var), AttributeValue::F64(v)) => data_set.add_var_attr_f64(var, name, v),
+            (None, AttributeValue::Text(v)) => data_set.add_global_attr_string(name, v),
+            (None, AttributeValue::I8(v)) => data_set.add_global_attr_i8(name, v),
+            (None, AttributeValue::I16(v)) => data_set.add_global_attr_i16(name, v),
+            (None, AttributeValue::I32(v)) => data_set.add_global_attr_i32(name, v),
+            (None, AttributeValue::F32(v)) => data_set.add_global_attr_f32(name, v),
+            (None, AttributeValue::F64(v)) => data_set.add_global_attr_f64(name, v),
+        }?;
+    }
+    Ok(())
+}
+
+/// Like `subset::subset`, but the selection happens on the OPeNDAP server at `url`, so only the
+/// selected cells and months of `variable` (by default the temperature, found the way runs find
+/// it) are transferred. Boxes across the antimeridian transfer every longitude in between.
+pub fn subset(
+    url: &str,
+    output: &Path,
+    bounding_box: Option<BoundingBox>,
+    period: Period,
+    variable: Option<&str>,
+) -> Result<(), OpendapErr> {
+    let remote = RemoteDataset::open(url)?;
+    let variable = match variable {
+        Some(variable) if remote.data_set().get_var(variable).is_none() => {
+            return Err(OpendapErr::Variable(format!("no {} variable", variable)));
+        }
+        Some(variable) => variable.to_string(),
+        None => TemperatureDataset::detect_temperature_variable(remote.data_set())
+            .map_err(|e| OpendapErr::Variable(e.to_string()))?
+            .ok_or_else(|| OpendapErr::Variable("no temperature variable".to_string()))?
+            .name()
+            .to_string(),
+    };
+    let variable = variable.as_str();
+    let das = &remote.attributes;
+
+    let lons = remote.read_var_f32("lon")?;
+    let lats = remote.read_var_f32("lat")?;
+    let times = remote.read_var_f32("time")?;
+    let (lon_first, lon_last) = index_range(
+        &lons,
+        |lon| bounding_box.is_none_or(|b| b.contains_lon(lon)),
+        "lon",
+    )?;
+    let (lat_first, lat_last) = index_range(
+        &lats,
+        |lat| bounding_box.is_none_or(|b| b.contains_lat(lat)),
+        "lat",
+    )?;
+    let (time_first, time_last) = index_range(
+        &times,
+        |time| period.contains(MonthStamp::from_days_since_1900(time)),
+        "time",
+    )?;
+
+    let mut data = remote.server.data(&format!(
+        "{}[{}:1:{}][{}:1:{}][{}:1:{}]",
+        variable, time_first, time_last, lat_first, lat_last, lon_first, lon_last
+    ))?;
+    let values = data
+        .remove(variable)
+        .ok_or_else(|| OpendapErr::Response(format!("no {} variable", variable)))?;
+
+    let mut data_set = DataSet::new();
+    data_set.set_unlimited_dim("time", time_last - time_first + 1)?;
+    data_set.add_fixed_dim("lat", lat_last - lat_first + 1)?;
+    data_set.add_fixed_dim("lon", lon_last - lon_first + 1)?;
+    for (name, data_type, dims) in [
+        ("lon", DataType::F32, &["lon"][..]),
+        ("lat", DataType::F32, &["lat"]),
+        ("time", DataType::F32, &["time"]),
+        (variable, values.data_type(), &["time", "lat", "lon"]),
+    ] {
+        data_set.add_var(name, dims, data_type)?;
+        add_attributes(
+            &mut data_set,
+            Some(name),
+            das.get(name).map_or(&[], |a| &a[..]),
+        )?;
+    }
+    add_attributes(
+        &mut data_set,
+        None,
+        das.get("NC_GLOBAL").map_or(&[], |a| &a[..]),
+    )?;
+
+    let mut writer = FileWriter::open(output)?;
+    writer.set_def(&data_set, Version::Classic, 0)?;
+    writer.write_var_f32("lon", &lons[lon_first..=lon_last])?;
+    writer.write_var_f32("lat", &lats[lat_first..=lat_last])?;
+    writer.write_var_f32("time", &times[time_first..=time_last])?;
+    match values {
+        DataVector::I8(v) => writer.write_var_i8(variable, &v),
+        DataVector::U8(v) => writer.write_var_u8(variable, &v),
+        DataVector::I16(v) => writer.write_var_i16(variable, &v),
+        DataVector::I32(v) => writer.write_var_i32(variable, &v),
+        DataVector::F32(v) => writer.write_var_f32(variable, &v),
+        DataVector::F64(v) => writer.write_var_f64(variable, &v),
+    }?;
+    writer.close()?;
+    Ok(())
+}
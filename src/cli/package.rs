@@ -0,0 +1,217 @@
+//! `export-package`: an output file copied next to a descriptor of its columns, so open-data
+//! portals can show it without anyone explaining the columns by hand. Either a Frictionless
+//! `datapackage.json`, or a CSV on the Web `<file>-metadata.json`.
+
+use crate::cli::metadata::{self, RunMetadata};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DescriptorFormat {
+    /// A Frictionless Data tabular data package, https://specs.frictionlessdata.io/.
+    #[value(name = "datapackage")]
+    DataPackage,
+    /// CSV on the Web metadata, https://www.w3.org/TR/tabular-metadata/.
+    Csvw,
+}
+
+/// What's known about a column, with types named as in Frictionless table schemas.
+struct Column {
+    name: String,
+    kind: &'static str,
+    description: Option<String>,
+}
+
+/// The descriptions of the columns `City` is written with. Others are left as plain strings.
+fn column(name: &str, temperature: &str) -> Column {
+    let (kind, description) = match name {
+        "City" => ("string", Some("City name, as given in the cities file.")),
+        "Region" => (
+            "string",
+            Some("State, province or other subdivision of the country."),
+        ),
+        "Country" => ("string", Some("Country name, as given in the cities file.")),
+        "WikidataEntityId" => ("string", Some("Wikidata item of the city, e.g. Q90.")),
+        "WikidataLongitude" => ("number", Some("Longitude of the city, in degrees east.")),
+        "WikidataLatitude" => ("number", Some("Latitude of the city, in degrees north.")),
+        "AverageTemperature" => ("number", None),
+        "Timezone" => ("string", Some("IANA time zone name.")),
+        "ShortestDay" => (
+            "number",
+            Some("Length of the shortest day of the year, in hours."),
+        ),
+        "LongestDay" => (
+            "number",
+            Some("Length of the longest day of the year, in hours."),
+        ),
+        "CoastDistance" => ("number", Some("Distance to the nearest coast, in km.")),
+        "Population" => ("integer", Some("Population, as given in the cities file.")),
+        "OverrideLongitude" => ("number", Some("Curated longitude, in degrees east.")),
+        "OverrideLatitude" => ("number", Some("Curated latitude, in degrees north.")),
+        "CoordinateSource" => ("string", Some("Where the coordinates came from.")),
+        _ => ("string", None),
+    };
+    Column {
+        name: name.to_string(),
+        kind,
+        description: match name {
+            "AverageTemperature" => Some(temperature.to_string()),
+            _ => description.map(str::to_string),
+        },
+    }
+}
+
+/// e.g. "Average near-surface air temperature from 2011 to 2020, in degrees Celsius.",
+/// from the parameters the run recorded.
+fn temperature_description(metadata: Option<&RunMetadata>) -> String {
+    let parameter = |name: &str| metadata.and_then(|m| m.parameters.get(name));
+    let period = match (parameter("start-year"), parameter("end-year")) {
+        (Some(start), Some(end)) => format!(" from {} to {}", start, end),
+        (Some(start), None) => format!(" from {} on", start),
+        (None, Some(end)) => format!(" up to {}", end),
+        (None, None) => String::new(),
+    };
+    let units = match parameter("units").map(String::as_str) {
+        Some("fahrenheit") => "degrees Fahrenheit",
+        _ => "degrees Celsius",
+    };
+    format!(
+        "Average near-surface air temperature{}, in {}.",
+        period, units
+    )
+}
+
+/// Copies `output` into `dir` and writes the descriptor next to it, returning the descriptor's path.
+pub fn export(
+    output: &Path,
+    dir: &Path,
+    format: DescriptorFormat,
+    title: Option<&str>,
+    license: Option<&str>,
+) -> io::Result<PathBuf> {
+    let file_name = output
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no usable file name"))?;
+    let run = RunMetadata::read(output).ok();
+    let temperature = temperature_description(run.as_ref());
+    let columns: Vec<Column> = csv::Reader::from_path(output)?
+        .headers()?
+        .iter()
+        .map(|name| column(name, &temperature))
+        .collect();
+
+    fs::create_dir_all(dir)?;
+    let copy = dir.join(file_name);
+    // Copying a file onto itself would truncate it.
+    if fs::canonicalize(output)? != fs::canonicalize(dir)?.join(file_name) {
+        fs::copy(output, &copy)?;
+    }
+    let bytes = fs::metadata(&copy)?.len();
+    let sha256 = metadata::sha256_of(&copy)?;
+    let name = Path::new(file_name)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(file_name)
+        .to_lowercase()
+        .replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '_',
+            "-",
+        );
+    let title = title.unwrap_or("Average temperatures of cities");
+    let source = json!({
+        "title": "CRU TS, Climatic Research Unit, University of East Anglia",
+        "path": "https://crudata.uea.ac.uk/cru/data/hrg/",
+    });
+    // How the file was produced, for whoever wants to reproduce it.
+    let provenance = run.map(|run| {
+        json!({
+            "tool": env!("CARGO_PKG_NAME"),
+            "version": run.tool_version,
+            "temperature_dataset_sha256": run.temperature_dataset.sha256,
+            "parameters": run.parameters,
+        })
+    });
+
+    let (descriptor_path, mut descriptor) = match format {
+        DescriptorFormat::DataPackage => {
+            let fields: Vec<Value> = columns
+                .iter()
+                .map(|column| {
+                    let mut field = json!({ "name": column.name, "type": column.kind });
+                    if let Some(description) = &column.description {
+                        field["description"] = json!(description);
+                    }
+                    field
+                })
+                .collect();
+            let mut package = json!({
+                "profile": "tabular-data-package",
+                "name": name,
+                "title": title,
+                "sources": [source],
+                "resources": [{
+                    "profile": "tabular-data-resource",
+                    "name": name,
+                    "path": file_name,
+                    "format": "csv",
+                    "mediatype": "text/csv",
+                    "encoding": "utf-8",
+                    "bytes": bytes,
+                    "hash": format!("sha256:{}", sha256),
+                    "schema": { "fields": fields },
+                }],
+            });
+            if let Some(license) = license {
+                package["licenses"] = json!([{ "name": license }]);
+            }
+            (dir.join("datapackage.json"), package)
+        }
+        DescriptorFormat::Csvw => {
+            let columns: Vec<Value> = columns
+                .iter()
+                .map(|column| {
+                    let datatype = match column.kind {
+                        "number" => "double",
+                        kind => kind,
+                    };
+                    let mut value = json!({
+                        "name": column.name,
+                        "titles": column.name,
+                        "datatype": datatype,
+                    });
+                    if let Some(description) = &column.description {
+                        value["dc:description"] = json!(description);
+                    }
+                    value
+                })
+                .collect();
+            let mut table = json!({
+                "@context": "http://www.w3.org/ns/csvw",
+                "url": file_name,
+                "dc:title": title,
+                "dc:source": source["path"],
+                "tableSchema": { "columns": columns },
+            });
+            if let Some(license) = license {
+                table["dc:license"] = json!(license);
+            }
+            (dir.join(format!("{}-metadata.json", file_name)), table)
+        }
+    };
+    if let Some(provenance) = provenance {
+        let key = match format {
+            DescriptorFormat::DataPackage => "temperature_extractor",
+            DescriptorFormat::Csvw => "prov:wasGeneratedBy",
+        };
+        descriptor[key] = provenance;
+    }
+    fs::write(
+        &descriptor_path,
+        serde_json::to_string_pretty(&descriptor)? + "\n",
+    )?;
+    Ok(descriptor_path)
+}
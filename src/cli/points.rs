@@ -0,0 +1,96 @@
+//! Cities represented by several weighted points rather than one, e.g. the centroids
+//! of a sprawling metro's districts weighted by population.
+
+use crate::cli::City;
+use crate::coordinates::{Latitude, Longitude};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum PointsErr {
+    Csv(csv::Error),
+    /// Not a positive number, which a weighted mean can't use. Lines count from 1, the header.
+    BadWeight {
+        line: u64,
+        city: String,
+        weight: f32,
+    },
+}
+
+impl Display for PointsErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            PointsErr::Csv(e) => write!(fmt, "{}", e),
+            PointsErr::BadWeight { line, city, weight } => write!(
+                fmt,
+                "line {}: {}'s point has weight {}, weights have to be positive",
+                line, city, weight
+            ),
+        }
+    }
+}
+
+impl From<csv::Error> for PointsErr {
+    fn from(e: csv::Error) -> Self {
+        PointsErr::Csv(e)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PointRow {
+    city: String,
+    region: Option<String>,
+    country: String,
+    longitude: Longitude,
+    latitude: Latitude,
+    weight: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WeightedPoint {
+    pub lon: Longitude,
+    pub lat: Latitude,
+    pub weight: f32,
+}
+
+pub struct WeightedPoints {
+    by_city: HashMap<(String, Option<String>, String), Vec<WeightedPoint>>,
+}
+
+impl WeightedPoints {
+    /// A CSV file with City, Country, Longitude, Latitude and Weight columns,
+    /// and optionally Region, with one row per point. Weights have to be positive.
+    pub fn load(path: &Path) -> Result<Self, PointsErr> {
+        let mut by_city: HashMap<_, Vec<WeightedPoint>> = HashMap::new();
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut record = csv::StringRecord::new();
+        while reader.read_record(&mut record)? {
+            let row: PointRow = record.deserialize(Some(reader.headers()?))?;
+            if !(row.weight.is_finite() && row.weight > 0.0) {
+                return Err(PointsErr::BadWeight {
+                    line: record.position().map_or(0, |position| position.line()),
+                    city: row.city,
+                    weight: row.weight,
+                });
+            }
+            by_city
+                .entry((row.city, row.region, row.country))
+                .or_default()
+                .push(WeightedPoint {
+                    lon: row.longitude,
+                    lat: row.latitude,
+                    weight: row.weight,
+                });
+        }
+        Ok(Self { by_city })
+    }
+
+    pub fn of(&self, city: &City) -> Option<&[WeightedPoint]> {
+        self.by_city
+            .get(&(city.city.clone(), city.region.clone(), city.country.clone()))
+            .map(|points| &points[..])
+    }
+}
@@ -0,0 +1,142 @@
+use crate::cli::{wikidata, City};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CitiesPreset {
+    /// The capital of every sovereign state, from Wikidata.
+    Capitals,
+    /// Every UN/LOCODE location with an airport.
+    Unlocode,
+}
+
+const UNLOCODE_CODE_LIST_URL: &str =
+    "https://raw.githubusercontent.com/datasets/un-locode/main/data/code-list.csv";
+const UNLOCODE_COUNTRY_CODES_URL: &str =
+    "https://raw.githubusercontent.com/datasets/un-locode/main/data/country-codes.csv";
+
+#[derive(Debug)]
+pub enum PresetErr {
+    Http(reqwest::Error),
+    Csv(csv::Error),
+}
+
+impl Display for PresetErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            PresetErr::Http(e) => write!(fmt, "can't download reference data: {}", e),
+            PresetErr::Csv(e) => write!(fmt, "can't read reference data: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for PresetErr {
+    fn from(e: reqwest::Error) -> Self {
+        PresetErr::Http(e)
+    }
+}
+
+impl From<csv::Error> for PresetErr {
+    fn from(e: csv::Error) -> Self {
+        PresetErr::Csv(e)
+    }
+}
+
+impl CitiesPreset {
+    pub fn cities(self) -> Result<Vec<City>, PresetErr> {
+        let mut cities = match self {
+            CitiesPreset::Capitals => wikidata::list_capitals()?
+                .into_iter()
+                .map(|capital| City {
+                    city: capital.name,
+                    country: capital.country,
+                    wikidata_entity_id: Some(capital.entity_id),
+                    wikidata_longitude: Some(capital.lon),
+                    wikidata_latitude: Some(capital.lat),
+                    ..Default::default()
+                })
+                .collect(),
+            CitiesPreset::Unlocode => unlocode_airports()?,
+        };
+        cities.sort_by(|a, b| (&a.country, &a.city).cmp(&(&b.country, &b.city)));
+        Ok(cities)
+    }
+}
+
+fn download_csv<T: for<'de> Deserialize<'de>>(url: &str) -> Result<Vec<T>, PresetErr> {
+    let resp = reqwest::blocking::Client::new()
+        .get(url)
+        .header("User-Agent", "Christophe's geolocator helper script.")
+        .send()?
+        .error_for_status()?;
+    Ok(csv::Reader::from_reader(resp)
+        .deserialize()
+        .collect::<Result<_, _>>()?)
+}
+
+fn unlocode_airports() -> Result<Vec<City>, PresetErr> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Country {
+        country_code: String,
+        country_name: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Location {
+        country: String,
+        name_wo_diacritics: String,
+        function: String,
+        coordinates: String,
+    }
+
+    let country_names: HashMap<String, String> =
+        download_csv::<Country>(UNLOCODE_COUNTRY_CODES_URL)?
+            .into_iter()
+            .map(|c| (c.country_code, c.country_name))
+            .collect();
+
+    Ok(download_csv::<Location>(UNLOCODE_CODE_LIST_URL)?
+        .into_iter()
+        // The function classifier is 8 positions, the 4th being set for airports.
+        .filter(|location| location.function.chars().nth(3) == Some('4'))
+        .filter_map(|location| {
+            let (lon, lat) = parse_unlocode_coordinates(&location.coordinates)?;
+            Some(City {
+                country: country_names
+                    .get(&location.country)
+                    .cloned()
+                    .unwrap_or(location.country),
+                city: location.name_wo_diacritics,
+                wikidata_entity_id: None,
+                wikidata_longitude: Some(lon),
+                wikidata_latitude: Some(lat),
+                ..Default::default()
+            })
+        })
+        .collect())
+}
+
+/// UN/LOCODE writes coordinates as degrees and minutes, e.g. `4230N 00131E`.
+fn parse_unlocode_coordinates(coordinates: &str) -> Option<(f32, f32)> {
+    fn parse(part: &str, degree_digits: usize, positive: char, negative: char) -> Option<f32> {
+        let (digits, hemisphere) = part.split_at(part.len().checked_sub(1)?);
+        if digits.len() != degree_digits + 2 {
+            return None;
+        }
+        let degrees: f32 = digits[..degree_digits].parse().ok()?;
+        let minutes: f32 = digits[degree_digits..].parse().ok()?;
+        let value = degrees + minutes / 60.0;
+        match hemisphere.chars().next()? {
+            c if c == positive => Some(value),
+            c if c == negative => Some(-value),
+            _ => None,
+        }
+    }
+
+    let (lat, lon) = coordinates.trim().split_once(' ')?;
+    Some((parse(lon, 3, 'E', 'W')?, parse(lat, 2, 'N', 'S')?))
+}
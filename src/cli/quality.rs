@@ -0,0 +1,126 @@
+//! `quality`: how much of the dataset is missing, by time step and by region, to pick a period
+//! and an area with good coverage before trusting results from them.
+//!
+//! Cells without a single value (the oceans, for CRU TS) aren't counted as missing anything,
+//! they're just not part of the dataset's coverage.
+
+use crate::calendar::MonthStamp;
+use crate::TemperatureDataset;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Regions are squares of this many degrees, aligned on multiples of it.
+const REGION_DEGREES: f32 = 10.0;
+
+#[derive(Debug, Serialize)]
+pub struct QualityReport {
+    /// Cells with at least one value.
+    pub cells: usize,
+    pub time_steps: usize,
+    /// Of every value of the cells with at least one.
+    pub missing_fraction: f64,
+    /// Time steps or regions missing more than this fraction are flagged as poor.
+    pub threshold: f64,
+    pub by_time_step: Vec<TimeStepQuality>,
+    /// Worst first.
+    pub by_region: Vec<RegionQuality>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeStepQuality {
+    pub time: String,
+    pub missing_fraction: f64,
+    pub poor: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegionQuality {
+    pub west: f32,
+    pub south: f32,
+    pub east: f32,
+    pub north: f32,
+    /// Cells with at least one value.
+    pub cells: usize,
+    pub missing_fraction: f64,
+    pub poor: bool,
+}
+
+/// A cell's center and the fraction of its time steps without a value, for cells with any.
+pub type CellQuality = ((f32, f32), f64);
+
+pub fn quality(dataset: &TemperatureDataset, threshold: f64) -> (QualityReport, Vec<CellQuality>) {
+    let times = dataset.times();
+    let index: BTreeMap<MonthStamp, usize> = times
+        .iter()
+        .enumerate()
+        .map(|(i, &time)| (time, i))
+        .collect();
+    let mut missing_by_time = vec![0usize; times.len()];
+    // Cells and missing values of each region, by its south west corner.
+    let mut regions: BTreeMap<(i32, i32), (usize, usize)> = BTreeMap::new();
+    let mut cells = vec![];
+    let mut missing = 0;
+    for (cell, series) in dataset.series_by_cell() {
+        if series.iter().all(|(_, temperature)| temperature.is_none()) {
+            continue;
+        }
+        let mut cell_missing = 0;
+        for (time, temperature) in &series {
+            if temperature.is_none() {
+                missing_by_time[index[time]] += 1;
+                cell_missing += 1;
+            }
+        }
+        missing += cell_missing;
+        let (lon, lat) = cell.center();
+        let region = (
+            (lon / REGION_DEGREES).floor() as i32,
+            (lat / REGION_DEGREES).floor() as i32,
+        );
+        let (region_cells, region_missing) = regions.entry(region).or_default();
+        *region_cells += 1;
+        *region_missing += cell_missing;
+        cells.push(((lon, lat), cell_missing as f64 / series.len() as f64));
+    }
+
+    let fraction = |missing: usize, of: usize| missing as f64 / of.max(1) as f64;
+    let by_time_step = times
+        .iter()
+        .zip(missing_by_time)
+        .map(|(time, missing)| {
+            let missing_fraction = fraction(missing, cells.len());
+            TimeStepQuality {
+                time: time.to_string(),
+                missing_fraction,
+                poor: missing_fraction > threshold,
+            }
+        })
+        .collect();
+    let mut by_region: Vec<RegionQuality> = regions
+        .into_iter()
+        .map(|((x, y), (region_cells, missing))| {
+            let missing_fraction = fraction(missing, region_cells * times.len());
+            let (west, south) = (x as f32 * REGION_DEGREES, y as f32 * REGION_DEGREES);
+            RegionQuality {
+                west,
+                south,
+                east: west + REGION_DEGREES,
+                north: south + REGION_DEGREES,
+                cells: region_cells,
+                missing_fraction,
+                poor: missing_fraction > threshold,
+            }
+        })
+        .collect();
+    by_region.sort_by(|a, b| b.missing_fraction.total_cmp(&a.missing_fraction));
+
+    let report = QualityReport {
+        cells: cells.len(),
+        time_steps: times.len(),
+        missing_fraction: fraction(missing, cells.len() * times.len()),
+        threshold,
+        by_time_step,
+        by_region,
+    };
+    (report, cells)
+}
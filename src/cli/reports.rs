@@ -0,0 +1,445 @@
+//! Reports on each city's cell's monthly series beyond its average, for users who need to know
+//! how far to trust it, how extreme it gets or how extreme it's been lately. Each is a CSV written after the run's output.
+
+use crate::calendar::MonthStamp;
+use crate::cli::{City, RunArgs};
+use crate::config::Units;
+use crate::stats;
+use crate::{Period, Series};
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// A suspect month in a city's cell, see `stats::outliers`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct OutlierRow {
+    city: String,
+    region: Option<String>,
+    country: String,
+    time: String,
+    temperature: f32,
+    /// The mean of the same calendar month over the whole dataset.
+    normal: f32,
+    sigmas: f32,
+}
+
+impl OutlierRow {
+    fn new(city: &City, outlier: stats::Outlier, units: Units) -> Self {
+        Self {
+            city: city.city.clone(),
+            region: city.region.clone(),
+            country: city.country.clone(),
+            time: outlier.time.to_string(),
+            temperature: outlier.temperature.in_units(units),
+            normal: outlier.normal.in_units(units),
+            sigmas: outlier.sigmas,
+        }
+    }
+}
+
+/// Whether a city's cell's annual means look homogeneous, see `stats::pettitt`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct HomogeneityRow {
+    city: String,
+    region: Option<String>,
+    country: String,
+    /// How many complete years were tested.
+    years: usize,
+    /// The first year after the most likely shift.
+    break_year: Option<i32>,
+    pettitt_k: Option<f64>,
+    p_value: Option<f64>,
+    /// A shift significant at the 5% level, so trends of this city are best distrusted.
+    inhomogeneous: bool,
+}
+
+impl HomogeneityRow {
+    fn new(city: &City, years: &BTreeMap<i32, f64>) -> Self {
+        let values: Vec<f64> = years.values().copied().collect();
+        let change_point = stats::pettitt(&values);
+        Self {
+            city: city.city.clone(),
+            region: city.region.clone(),
+            country: city.country.clone(),
+            years: values.len(),
+            break_year: change_point.map(|change_point| {
+                *years
+                    .keys()
+                    .nth(change_point.index)
+                    .expect("The break is within the series")
+            }),
+            pettitt_k: change_point.map(|change_point| change_point.statistic),
+            p_value: change_point.map(|change_point| change_point.p_value),
+            inhomogeneous: change_point.is_some_and(|change_point| change_point.p_value < 0.05),
+        }
+    }
+}
+
+/// A return period in years, which has to be more than one for anything to be rarer than every
+/// year.
+pub fn parse_return_period(s: &str) -> Result<f32, String> {
+    let years: f32 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(years.is_finite() && years > 1.0) {
+        return Err("must be more than 1 year".to_string());
+    }
+    Ok(years)
+}
+
+/// How hot and how cold one calendar month gets once in so many years in a city's cell,
+/// see `stats::Gev`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ReturnLevelRow {
+    city: String,
+    region: Option<String>,
+    country: String,
+    /// 1 to 12.
+    month: u32,
+    /// How many years had a value for the month.
+    years: usize,
+    return_period: f32,
+    /// The monthly mean exceeded once per return period, None with fewer than 3 years.
+    hottest: Option<f32>,
+    /// The monthly mean undercut once per return period.
+    coldest: Option<f32>,
+}
+
+impl ReturnLevelRow {
+    /// One per calendar month.
+    fn of(
+        city: &City,
+        series: &Series,
+        period: Period,
+        return_period: f32,
+        units: Units,
+    ) -> Vec<Self> {
+        let mut by_month: [Vec<f64>; 12] = Default::default();
+        for &(time, temperature) in series {
+            if let (true, Some(temperature)) = (period.contains(time), temperature) {
+                by_month[time.month() as usize - 1].push(temperature.celsius as f64);
+            }
+        }
+        let in_units = |celsius: f64| crate::Temperature::new(celsius as f32).in_units(units);
+        by_month
+            .iter()
+            .zip(1..)
+            .map(|(values, month)| {
+                let negated: Vec<f64> = values.iter().map(|value| -value).collect();
+                Self {
+                    city: city.city.clone(),
+                    region: city.region.clone(),
+                    country: city.country.clone(),
+                    month,
+                    years: values.len(),
+                    return_period,
+                    hottest: stats::Gev::fit(values)
+                        .map(|gev| in_units(gev.return_level(return_period as f64))),
+                    // The coldest are the hottest of the negated values.
+                    coldest: stats::Gev::fit(&negated)
+                        .map(|gev| in_units(-gev.return_level(return_period as f64))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Where the latest of one calendar month sits among all of them in a city's cell, e.g. the
+/// last July was the 98th percentile July, for spotting recent extremes in a dataset that's
+/// kept up to date.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PercentileRow {
+    city: String,
+    region: Option<String>,
+    country: String,
+    /// 1 to 12.
+    month: u32,
+    /// The latest time step of the month with a value.
+    time: String,
+    temperature: f32,
+    /// The mean of the month over every year.
+    normal: f32,
+    /// The share of the years whose month was colder, counting ties as half, from 0 to 100.
+    percentile: f32,
+    years: usize,
+}
+
+impl PercentileRow {
+    /// One per calendar month with a value.
+    fn of(city: &City, series: &Series, period: Period, units: Units) -> Vec<Self> {
+        let mut by_month: [Vec<(MonthStamp, f32)>; 12] = Default::default();
+        for &(time, temperature) in series {
+            if let (true, Some(temperature)) = (period.contains(time), temperature) {
+                by_month[time.month() as usize - 1].push((time, temperature.celsius));
+            }
+        }
+        by_month
+            .iter()
+            .zip(1..)
+            .filter_map(|(values, month)| {
+                let &(time, latest) = values.iter().max_by_key(|(time, _)| *time)?;
+                let colder = values.iter().filter(|(_, value)| *value < latest).count();
+                let ties = values.iter().filter(|(_, value)| *value == latest).count();
+                let normal =
+                    values.iter().map(|(_, value)| value).sum::<f32>() / values.len() as f32;
+                Some(Self {
+                    city: city.city.clone(),
+                    region: city.region.clone(),
+                    country: city.country.clone(),
+                    month,
+                    time: time.to_string(),
+                    temperature: crate::Temperature::new(latest).in_units(units),
+                    normal: crate::Temperature::new(normal).in_units(units),
+                    percentile: 100.0 * (colder as f32 + 0.5 * ties as f32) / values.len() as f32,
+                    years: values.len(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The mean of a city's cell over a window of consecutive years.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RollingRow {
+    city: String,
+    region: Option<String>,
+    country: String,
+    first_year: i32,
+    last_year: i32,
+    average_temperature: f32,
+}
+
+impl RollingRow {
+    /// One per window with every year complete, in time order.
+    fn of(city: &City, years: &BTreeMap<i32, f64>, window: u32, units: Units) -> Vec<Self> {
+        let (Some(&first), Some(&last)) = (years.keys().next(), years.keys().next_back()) else {
+            return vec![];
+        };
+        let window = window as i32;
+        (first..=last - window + 1)
+            .filter_map(|start| {
+                let means: Vec<f64> = (start..start + window)
+                    .map(|year| years.get(&year).copied())
+                    .collect::<Option<_>>()?;
+                let mean = means.iter().sum::<f64>() / means.len() as f64;
+                Some(Self {
+                    city: city.city.clone(),
+                    region: city.region.clone(),
+                    country: city.country.clone(),
+                    first_year: start,
+                    last_year: start + window - 1,
+                    average_temperature: crate::Temperature::new(mean as f32).in_units(units),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Each decade's mean of a city's cell and how many complete years it's of, keyed by the
+/// decade's first year.
+struct DecadesRow {
+    city: String,
+    region: Option<String>,
+    country: String,
+    decades: BTreeMap<i32, (usize, f32)>,
+}
+
+impl DecadesRow {
+    fn new(city: &City, years: &BTreeMap<i32, f64>, units: Units) -> Self {
+        let mut sums: BTreeMap<i32, (usize, f64)> = BTreeMap::new();
+        for (&year, &mean) in years {
+            let (count, sum) = sums.entry(year.div_euclid(10) * 10).or_default();
+            *count += 1;
+            *sum += mean;
+        }
+        Self {
+            city: city.city.clone(),
+            region: city.region.clone(),
+            country: city.country.clone(),
+            decades: sums
+                .into_iter()
+                .map(|(decade, (count, sum))| {
+                    let mean = sum / count as f64;
+                    (
+                        decade,
+                        (count, crate::Temperature::new(mean as f32).in_units(units)),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Two columns per decade any city has, its mean and its number of years, which serde can't do
+/// with a struct.
+fn write_decades(path: &Path, rows: Vec<DecadesRow>) -> csv::Result<()> {
+    let decades: BTreeSet<i32> = rows
+        .iter()
+        .flat_map(|row| row.decades.keys().copied())
+        .collect();
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(
+        ["City", "Region", "Country"]
+            .map(str::to_string)
+            .into_iter()
+            .chain(
+                decades
+                    .iter()
+                    .flat_map(|decade| [format!("{}s", decade), format!("{}sYears", decade)]),
+            ),
+    )?;
+    for row in rows {
+        writer.write_record(
+            [row.city, row.region.unwrap_or_default(), row.country]
+                .into_iter()
+                .chain(
+                    decades
+                        .iter()
+                        .flat_map(|decade| match row.decades.get(decade) {
+                            Some((years, mean)) => [mean.to_string(), years.to_string()],
+                            None => [String::new(), "0".to_string()],
+                        }),
+                ),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Every report asked for, gathered city by city.
+pub struct SeriesReports<'a> {
+    args: &'a RunArgs,
+    outliers: Vec<OutlierRow>,
+    homogeneity: Vec<HomogeneityRow>,
+    return_levels: Vec<ReturnLevelRow>,
+    percentiles: Vec<PercentileRow>,
+    rolling: Vec<RollingRow>,
+    decades: Vec<DecadesRow>,
+}
+
+impl<'a> SeriesReports<'a> {
+    pub fn new(args: &'a RunArgs) -> Self {
+        Self {
+            args,
+            outliers: vec![],
+            homogeneity: vec![],
+            return_levels: vec![],
+            percentiles: vec![],
+            rolling: vec![],
+            decades: vec![],
+        }
+    }
+
+    /// Whether any report needs the cities' series.
+    pub fn wanted(&self) -> bool {
+        self.args.outliers.is_some()
+            || self.args.homogeneity.is_some()
+            || self.args.return_levels.is_some()
+            || self.args.percentiles.is_some()
+            || self.args.rolling.is_some()
+            || self.args.decades
+    }
+
+    /// `series` is the whole series of the city's cell, the reports pick the period from it.
+    pub fn add(&mut self, city: &City, series: &Series) {
+        let period = self.args.compute.period();
+        let units = self.args.compute.units();
+        if self.args.outliers.is_some() {
+            self.outliers.extend(
+                stats::outliers(series, self.args.outlier_sigmas)
+                    .into_iter()
+                    .filter(|outlier| period.contains(outlier.time))
+                    .map(|outlier| OutlierRow::new(city, outlier, units)),
+            );
+        }
+        let years = stats::annual_means(series, period, self.args.coverage.coverage());
+        if self.args.homogeneity.is_some() {
+            self.homogeneity.push(HomogeneityRow::new(city, &years));
+        }
+        if let Some(window) = self.args.rolling {
+            self.rolling
+                .extend(RollingRow::of(city, &years, window, units));
+        }
+        if self.args.decades {
+            self.decades.push(DecadesRow::new(city, &years, units));
+        }
+        if self.args.return_levels.is_some() {
+            self.return_levels.extend(ReturnLevelRow::of(
+                city,
+                series,
+                period,
+                self.args.return_period,
+                units,
+            ));
+        }
+        if self.args.percentiles.is_some() {
+            self.percentiles
+                .extend(PercentileRow::of(city, series, period, units));
+        }
+    }
+
+    pub fn write(self) {
+        if let Some(path) = &self.args.outliers {
+            let cities_flagged = self
+                .outliers
+                .iter()
+                .map(|row| (&row.city, &row.region, &row.country))
+                .unique()
+                .count();
+            eprintln!(
+                "Flagged {} suspect months in {} cities.",
+                self.outliers.len(),
+                cities_flagged
+            );
+            write_rows(path, "outliers", self.outliers);
+        }
+        if let Some(path) = &self.args.homogeneity {
+            eprintln!(
+                "{} of {} cities have a likely break in their annual means.",
+                self.homogeneity
+                    .iter()
+                    .filter(|row| row.inhomogeneous)
+                    .count(),
+                self.homogeneity.len()
+            );
+            write_rows(path, "homogeneity", self.homogeneity);
+        }
+        if let Some(path) = &self.args.return_levels {
+            write_rows(path, "return levels", self.return_levels);
+        }
+        if let Some(path) = &self.args.percentiles {
+            let extreme = |row: &&PercentileRow| row.percentile <= 5.0 || row.percentile >= 95.0;
+            eprintln!(
+                "{} of {} latest city months were at the 5th percentile or below, or the 95th or above.",
+                self.percentiles.iter().filter(extreme).count(),
+                self.percentiles.len()
+            );
+            write_rows(path, "percentiles", self.percentiles);
+        }
+        if self.args.rolling.is_some() {
+            let mut path = self.args.output.as_os_str().to_owned();
+            path.push(".rolling.csv");
+            write_rows(Path::new(&path), "rolling means", self.rolling);
+        }
+        if self.args.decades {
+            let mut path = self.args.output.as_os_str().to_owned();
+            path.push(".decades.csv");
+            write_decades(Path::new(&path), self.decades)
+                .unwrap_or_else(|e| panic!("Couldn't write decades file: {}", e));
+        }
+    }
+}
+
+fn write_rows(path: &Path, report: &str, rows: Vec<impl Serialize>) {
+    let mut writer = csv::Writer::from_path(path)
+        .unwrap_or_else(|e| panic!("Couldn't open {} file: {}", report, e));
+    for row in rows {
+        writer
+            .serialize(row)
+            .unwrap_or_else(|e| panic!("Couldn't write {} row: {}", report, e));
+    }
+}
@@ -0,0 +1,281 @@
+//! `--rpc`: JSON-RPC 2.0 over standard input and output, one message per line, so another
+//! program can keep the tool running as a subprocess and only pay for loading the dataset once.
+//!
+//! Methods, with their params and results:
+//! - `load_dataset {path, allow_truncated?}` → `{observations}`. Without it, the first query
+//!   loads the config file's dataset.
+//! - `load_gazetteer {path}` → `{places}`: a cities file with coordinates (e.g. from
+//!   list-cities), after which `query_point` also answers the nearest of its cities.
+//! - `query_point {lon, lat, start_year?, end_year?, units?}`
+//!   → `{average_temperature, nearest_city?: {city, country, lon, lat, distance_km}}`
+//! - `geocode {city, region?, country}` → `{candidates: [{entity_id?, label, description?, lon?, lat?}]}`
+//! - `query_city {city, region?, country, entity_id?, start_year?, end_year?, units?}`
+//!   → `{entity_id?, lon, lat, average_temperature}`. Never prompts: a city with several
+//!   candidates is an error carrying them, to be retried with one's `entity_id`.
+
+use crate::cli::find::Gazetteer;
+use crate::cli::input;
+use crate::cli::locate::{self, LocateErr};
+use crate::config::{Config, Units};
+use crate::coordinates::{Latitude, Longitude};
+use crate::{CitySettings, Period, TemperatureDataset};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Couldn't load the dataset, compute a temperature, or reach the geocoder.
+const FAILED: i64 = -32000;
+/// The city matched several places, listed in the error's data.
+const AMBIGUOUS: i64 = -32001;
+
+#[derive(Serialize)]
+struct RpcErr {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcErr {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct LoadDatasetParams {
+    path: PathBuf,
+    #[serde(default)]
+    allow_truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct LoadGazetteerParams {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ComputeParams {
+    start_year: Option<i32>,
+    end_year: Option<i32>,
+    units: Option<Units>,
+}
+
+#[derive(Deserialize)]
+struct QueryPointParams {
+    lon: Longitude,
+    lat: Latitude,
+    #[serde(flatten)]
+    compute: ComputeParams,
+}
+
+#[derive(Deserialize)]
+struct GeocodeParams {
+    city: String,
+    region: Option<String>,
+    country: String,
+}
+
+#[derive(Deserialize)]
+struct QueryCityParams {
+    #[serde(flatten)]
+    place: GeocodeParams,
+    entity_id: Option<String>,
+    #[serde(flatten)]
+    compute: ComputeParams,
+}
+
+struct Server<'a> {
+    config: &'a Config,
+    dataset: Option<TemperatureDataset>,
+    gazetteer: Option<Gazetteer>,
+}
+
+/// Answers requests until standard input closes.
+pub fn serve(config: &Config) {
+    let mut server = Server {
+        config,
+        dataset: None,
+        gazetteer: None,
+    };
+    let mut stdout = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("Couldn't read request");
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, result) = match serde_json::from_str::<Request>(&line) {
+            Err(e) if e.is_data() => (Value::Null, Err(RpcErr::new(INVALID_REQUEST, e))),
+            Err(e) => (Value::Null, Err(RpcErr::new(PARSE_ERROR, e))),
+            Ok(request) if request.jsonrpc != "2.0" => (
+                request.id.unwrap_or_default(),
+                Err(RpcErr::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")),
+            ),
+            Ok(request) => {
+                let result = server.handle(&request.method, request.params);
+                match request.id {
+                    Some(id) => (id, result),
+                    None => continue,
+                }
+            }
+        };
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => json!({"jsonrpc": "2.0", "id": id, "error": e}),
+        };
+        writeln!(stdout, "{}", response)
+            .and_then(|()| stdout.flush())
+            .expect("Couldn't write response");
+    }
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcErr> {
+    serde_json::from_value(params).map_err(|e| RpcErr::new(INVALID_PARAMS, e))
+}
+
+impl Server<'_> {
+    fn handle(&mut self, method: &str, params_value: Value) -> Result<Value, RpcErr> {
+        match method {
+            "load_dataset" => self.load_dataset(params(params_value)?),
+            "load_gazetteer" => self.load_gazetteer(params(params_value)?),
+            "query_point" => self.query_point(params(params_value)?),
+            "geocode" => self.geocode(params(params_value)?),
+            "query_city" => self.query_city(params(params_value)?),
+            _ => Err(RpcErr::new(
+                METHOD_NOT_FOUND,
+                format!("no method {}", method),
+            )),
+        }
+    }
+
+    fn load_dataset(&mut self, params: LoadDatasetParams) -> Result<Value, RpcErr> {
+        let dataset = TemperatureDataset::open(&params.path, params.allow_truncated)
+            .map_err(|e| RpcErr::new(FAILED, format!("can't read temperature data: {}", e)))?;
+        let observations = dataset.len();
+        self.dataset = Some(dataset);
+        Ok(json!({ "observations": observations }))
+    }
+
+    fn load_gazetteer(&mut self, params: LoadGazetteerParams) -> Result<Value, RpcErr> {
+        let cities = input::read_cities(&params.path, &[])
+            .map_err(|e| RpcErr::new(FAILED, format!("can't read gazetteer: {}", e)))?;
+        let gazetteer = Gazetteer::new(cities);
+        if gazetteer.is_empty() {
+            return Err(RpcErr::new(
+                FAILED,
+                "the gazetteer has no city with coordinates",
+            ));
+        }
+        let places = gazetteer.len();
+        self.gazetteer = Some(gazetteer);
+        Ok(json!({ "places": places }))
+    }
+
+    fn dataset(&mut self) -> Result<&TemperatureDataset, RpcErr> {
+        if self.dataset.is_none() {
+            let path = self.config.temperature_dataset.clone().ok_or_else(|| {
+                RpcErr::new(
+                    FAILED,
+                    "no dataset loaded, and the config file doesn't name one",
+                )
+            })?;
+            self.load_dataset(LoadDatasetParams {
+                path,
+                allow_truncated: false,
+            })?;
+        }
+        Ok(self.dataset.as_ref().expect("Just loaded"))
+    }
+
+    fn average(
+        &mut self,
+        compute: ComputeParams,
+        lon: Longitude,
+        lat: Latitude,
+    ) -> Result<f32, RpcErr> {
+        let settings = CitySettings {
+            period: Period {
+                first_year: compute.start_year.or(self.config.start_year),
+                last_year: compute.end_year.or(self.config.end_year),
+            },
+            units: compute.units.or(self.config.units).unwrap_or_default(),
+            ..Default::default()
+        };
+        settings
+            .temperature_at(self.dataset()?, lon, lat)
+            .map_err(|e| RpcErr::new(FAILED, e))
+    }
+
+    fn query_point(&mut self, params: QueryPointParams) -> Result<Value, RpcErr> {
+        let average_temperature = self.average(params.compute, params.lon, params.lat)?;
+        let mut answer = json!({ "average_temperature": average_temperature });
+        let nearest = self
+            .gazetteer
+            .as_ref()
+            .and_then(|gazetteer| gazetteer.nearest(params.lon.degrees(), params.lat.degrees()));
+        if let Some((place, distance_km)) = nearest {
+            let mut nearest_city = json!(place);
+            nearest_city["distance_km"] = json!(distance_km);
+            answer["nearest_city"] = nearest_city;
+        }
+        Ok(answer)
+    }
+
+    fn geocode(&mut self, params: GeocodeParams) -> Result<Value, RpcErr> {
+        let candidates = locate::candidates(
+            self.config.geocoder.unwrap_or_default(),
+            &params.city,
+            params.region.as_deref(),
+            &params.country,
+        )
+        .map_err(|e| RpcErr::new(FAILED, LocateErr::Geocoder(e)))?;
+        Ok(json!({ "candidates": candidates }))
+    }
+
+    fn query_city(&mut self, params: QueryCityParams) -> Result<Value, RpcErr> {
+        let place = &params.place;
+        let (entity_id, lon, lat) = locate::locate(
+            self.config.geocoder.unwrap_or_default(),
+            &place.city,
+            place.region.as_deref(),
+            &place.country,
+            params.entity_id,
+        )
+        .map_err(|e| {
+            let message = format!("{}, {}: {}", place.city, place.country, e);
+            match e {
+                LocateErr::Ambiguous(candidates) => RpcErr {
+                    data: Some(json!({ "candidates": candidates })),
+                    ..RpcErr::new(AMBIGUOUS, message)
+                },
+                _ => RpcErr::new(FAILED, message),
+            }
+        })?;
+        let average_temperature = self.average(params.compute, lon, lat)?;
+        Ok(json!({
+            "entity_id": entity_id,
+            "lon": lon,
+            "lat": lat,
+            "average_temperature": average_temperature,
+        }))
+    }
+}
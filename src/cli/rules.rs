@@ -0,0 +1,168 @@
+//! Checks of the cities input before anything is looked up: rows that can't be processed are
+//! rejected and left out of the run, and rows that can but look wrong are flagged, all in one
+//! report rather than a panic halfway through.
+
+use crate::cli::City;
+use crate::coordinates;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+/// The header is the first line, so the first city is on the second.
+const FIRST_LINE: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// Rejected: there's nothing to look up or to name the row by in the output.
+    EmptyName,
+    /// Rejected: a longitude or latitude off the globe, or not a number.
+    ImpossibleCoordinates,
+    /// Flagged: the same Wikidata entity given for different cities, all but one of which are
+    /// getting some other place's coordinates.
+    ConflictingEntityId,
+    /// Flagged: text that wasn't valid UTF-8, read with the invalid bytes replaced, usually a
+    /// file saved in a legacy encoding.
+    NonUtf8,
+}
+
+impl Rule {
+    pub fn rejects(self) -> bool {
+        matches!(self, Rule::EmptyName | Rule::ImpossibleCoordinates)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    /// In the input, counting the header.
+    pub line: usize,
+    pub city: String,
+    pub country: String,
+    pub rule: Rule,
+    /// Whether the row is left out of the run.
+    pub rejected: bool,
+    pub message: String,
+}
+
+impl Display for Finding {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            fmt,
+            "Line {} ({}, {}): {}{}",
+            self.line,
+            self.city,
+            self.country,
+            self.message,
+            if self.rejected { ", left out" } else { "" }
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InputReport {
+    pub rows: usize,
+    /// Rows left out.
+    pub rejected: usize,
+    /// Rows kept despite a finding.
+    pub flagged: usize,
+    /// In the order of the input.
+    pub findings: Vec<Finding>,
+}
+
+impl InputReport {
+    /// Whether the row at `index` of the cities is to be left out.
+    pub fn is_rejected(&self, index: usize) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.rejected && finding.line == index + FIRST_LINE)
+    }
+}
+
+/// Check `cities`, as read from the input, `non_utf8` being the indices of the rows that weren't
+/// valid UTF-8.
+pub fn check(cities: &[City], non_utf8: &[usize]) -> InputReport {
+    let mut findings = vec![];
+    let mut first_with_entity: HashMap<&str, usize> = HashMap::new();
+    for (index, city) in cities.iter().enumerate() {
+        let mut find = |rule: Rule, message: String| {
+            findings.push(Finding {
+                line: index + FIRST_LINE,
+                city: city.city.clone(),
+                country: city.country.clone(),
+                rule,
+                rejected: rule.rejects(),
+                message,
+            })
+        };
+        if non_utf8.contains(&index) {
+            find(Rule::NonUtf8, "not valid UTF-8".to_string());
+        }
+        if city.city.trim().is_empty() {
+            find(Rule::EmptyName, "no city name".to_string());
+        }
+        for (columns, lon, lat) in [
+            (
+                "WikidataLongitude/WikidataLatitude",
+                city.wikidata_longitude,
+                city.wikidata_latitude,
+            ),
+            (
+                "OverrideLongitude/OverrideLatitude",
+                city.override_longitude,
+                city.override_latitude,
+            ),
+        ] {
+            if let Some((lon, lat)) = lon.zip(lat) {
+                if let Err(e) = coordinates::lon_lat(lon, lat) {
+                    find(
+                        Rule::ImpossibleCoordinates,
+                        format!("{} {}, {}: {}", columns, lon, lat, e),
+                    );
+                }
+            }
+        }
+        if let Some(entity_id) = city.wikidata_entity_id.as_deref().map(str::trim) {
+            if entity_id.is_empty() {
+                continue;
+            }
+            match first_with_entity.get(entity_id) {
+                Some(&first) => {
+                    let other = &cities[first];
+                    if (&other.city, &other.region, &other.country)
+                        != (&city.city, &city.region, &city.country)
+                    {
+                        find(
+                            Rule::ConflictingEntityId,
+                            format!(
+                                "{} is also given for {}, {} on line {}",
+                                entity_id,
+                                other.city,
+                                other.country,
+                                first + FIRST_LINE
+                            ),
+                        );
+                    }
+                }
+                None => {
+                    first_with_entity.insert(entity_id, index);
+                }
+            }
+        }
+    }
+    let rejected: HashSet<usize> = findings
+        .iter()
+        .filter(|finding| finding.rejected)
+        .map(|finding| finding.line)
+        .collect();
+    let flagged: HashSet<usize> = findings
+        .iter()
+        .map(|finding| finding.line)
+        .filter(|line| !rejected.contains(line))
+        .collect();
+    InputReport {
+        rows: cities.len(),
+        rejected: rejected.len(),
+        flagged: flagged.len(),
+        findings,
+    }
+}
@@ -0,0 +1,62 @@
+//! Output files written by older versions, which lack the columns added since. Their rows read
+//! with those columns empty, and `fill` takes what those columns should hold from elsewhere, e.g.
+//! the cities input for a curated column like Population, so a long-lived output merged into isn't
+//! stuck without them. Writing the rows back out then writes every current column.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// `columns` that aren't in `header`, in `columns`' order.
+pub fn missing(header: &csv::StringRecord, columns: &csv::StringRecord) -> Vec<String> {
+    columns
+        .iter()
+        .filter(|column| !header.iter().any(|name| name == *column))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The rows of `csv`, which has a header, and the current `columns` it doesn't have.
+pub fn read<T: DeserializeOwned>(
+    csv: &[u8],
+    columns: &csv::StringRecord,
+) -> Result<(Vec<T>, Vec<String>), csv::Error> {
+    let mut reader = csv::Reader::from_reader(csv);
+    let missing = missing(reader.headers()?, columns);
+    let rows = reader.deserialize().collect::<Result<_, _>>()?;
+    Ok((rows, missing))
+}
+
+/// `row` with the values of its `missing` columns taken from `from`'s.
+pub fn fill<T: Serialize + DeserializeOwned>(
+    row: &T,
+    from: &T,
+    missing: &[String],
+) -> Result<T, csv::Error> {
+    let (header, record) = to_records(row)?;
+    let (_, from) = to_records(from)?;
+    let filled: csv::StringRecord = header
+        .iter()
+        .zip(record.iter().zip(from.iter()))
+        .map(|(column, (value, from))| {
+            if missing.iter().any(|name| name == column) {
+                from
+            } else {
+                value
+            }
+        })
+        .collect();
+    filled.deserialize(Some(&header))
+}
+
+/// `row`'s header and values as serde writes them to CSV.
+fn to_records<T: Serialize>(row: &T) -> Result<(csv::StringRecord, csv::StringRecord), csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.serialize(row)?;
+    let csv = writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(std::io::Error::other(e.to_string())))?;
+    let mut reader = csv::Reader::from_reader(&csv[..]);
+    let header = reader.headers()?.clone();
+    let record = reader.records().next().expect("A row was just written")?;
+    Ok((header, record))
+}
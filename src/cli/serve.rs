@@ -0,0 +1,310 @@
+//! `serve`: a gRPC server for temperature queries, so a deployment can load the dataset once and
+//! share it across every request. The service is defined in proto/temperature_extractor.proto.
+
+use crate::cli::auth::{Client, Gate, RateLimiter};
+use crate::cli::cache::AverageCache;
+use crate::cli::find::Gazetteer;
+use crate::cli::flight::ResultFiles;
+use crate::cli::locate::{self, LocateErr};
+use crate::cli::metrics::{self, Metrics};
+use crate::config::{Geocoder, Units};
+use crate::coordinates::{self, Latitude, Longitude};
+use crate::{CitySettings, LonLatCell, Period, Temperature, TemperatureDataset};
+use arrow_flight::flight_service_server::FlightServiceServer;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tonic::{Request, Response, Status};
+
+mod proto {
+    tonic::include_proto!("temperature_extractor");
+}
+
+use proto::temperatures_server::{Temperatures, TemperaturesServer};
+use proto::{
+    city_answers, point_answers, CityAnswer, CityAnswers, CityQueries, CityQuery, Compute,
+    NearestCity, PointAnswer, PointAnswers, PointQueries, PointQuery,
+};
+
+#[derive(Clone)]
+struct Server {
+    dataset: Arc<TemperatureDataset>,
+    /// For whatever a query leaves out.
+    defaults: CitySettings,
+    geocoder: Geocoder,
+    metrics: Arc<Metrics>,
+    cache: Arc<AverageCache>,
+    /// To name the city nearest to each point, if any.
+    gazetteer: Option<Arc<Gazetteer>>,
+    /// The gate's, to charge batches for each of their queries.
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+/// How `serve` is reached and protected.
+pub struct Options {
+    pub address: SocketAddr,
+    /// Where to serve metrics, if anywhere.
+    pub metrics_address: Option<SocketAddr>,
+    pub cache: AverageCache,
+    /// Requests without one of these are refused, unless there are none.
+    pub api_keys: Option<HashSet<String>>,
+    /// Requests per minute for each key, or each client address without keys.
+    pub rate_limit: Option<u32>,
+    /// Arrow IPC outputs to serve over Arrow Flight, if any.
+    pub flight_dir: Option<PathBuf>,
+}
+
+/// Answers queries until killed.
+pub fn serve(
+    dataset: TemperatureDataset,
+    defaults: CitySettings,
+    geocoder: Geocoder,
+    gazetteer: Option<Gazetteer>,
+    options: Options,
+) {
+    let rate_limiter = options
+        .rate_limit
+        .map(|per_minute| Arc::new(RateLimiter::new(per_minute)));
+    let server = Server {
+        cache: Arc::new(options.cache),
+        metrics: Arc::new(Metrics::new(&dataset)),
+        dataset: Arc::new(dataset),
+        defaults,
+        geocoder,
+        gazetteer: gazetteer.map(Arc::new),
+        rate_limiter: rate_limiter.clone(),
+    };
+    let gate = Gate {
+        api_keys: options.api_keys.map(Arc::new),
+        rate_limiter,
+        metrics: server.metrics.clone(),
+    };
+    let address = options.address;
+    if let Some(metrics_address) = options.metrics_address {
+        metrics::expose(server.metrics.clone(), metrics_address)
+            .unwrap_or_else(|e| panic!("Couldn't serve metrics: {}", e));
+        eprintln!("Serving metrics on http://{}/metrics", metrics_address);
+    }
+    // Behind the same API keys and rate limits.
+    let flight = options.flight_dir.map(|dir| {
+        eprintln!("Serving {} over Arrow Flight", dir.display());
+        FlightServiceServer::with_interceptor(ResultFiles { dir }, gate.clone())
+    });
+    let runtime = tokio::runtime::Runtime::new().expect("Couldn't start the async runtime");
+    eprintln!("Serving gRPC on {}", address);
+    runtime
+        .block_on(
+            tonic::transport::Server::builder()
+                .add_service(TemperaturesServer::with_interceptor(server, gate))
+                .add_optional_service(flight)
+                .serve(address),
+        )
+        .unwrap_or_else(|e| panic!("Couldn't serve: {}", e));
+}
+
+impl Server {
+    /// Lookups and geocoding block, so they're kept off the async workers.
+    async fn answer<T: Send + 'static>(
+        &self,
+        method: &'static str,
+        answer: impl FnOnce(Server) -> Result<T, Status> + Send + 'static,
+    ) -> Result<Response<T>, Status> {
+        let start = Instant::now();
+        let server = self.clone();
+        let result = tokio::task::spawn_blocking(move || answer(server))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))
+            .and_then(|result| result);
+        self.metrics
+            .request(method, start.elapsed(), result.is_ok());
+        result.map(Response::new)
+    }
+
+    /// Takes the rate limit's requests for a batch's queries past the first, which the gate
+    /// already took as the request itself, so a batch is no way around the limit.
+    fn charge<T>(&self, request: &Request<T>, queries: usize) -> Result<(), Status> {
+        let (Some(rate_limiter), Some(client)) =
+            (&self.rate_limiter, request.extensions().get::<Client>())
+        else {
+            return Ok(());
+        };
+        if rate_limiter.allow(client, queries.saturating_sub(1)) {
+            Ok(())
+        } else {
+            self.metrics.rejected("rate_limited");
+            Err(Status::resource_exhausted(format!(
+                "rate limit exceeded by a batch of {} queries, send fewer at a time",
+                queries
+            )))
+        }
+    }
+
+    fn settings(&self, compute: Option<Compute>) -> CitySettings {
+        let compute = compute.unwrap_or_default();
+        CitySettings {
+            period: Period {
+                first_year: compute.start_year.or(self.defaults.period.first_year),
+                last_year: compute.end_year.or(self.defaults.period.last_year),
+            },
+            units: match compute.units() {
+                proto::Units::Default => self.defaults.units,
+                proto::Units::Celsius => Units::Celsius,
+                proto::Units::Fahrenheit => Units::Fahrenheit,
+            },
+            ..self.defaults
+        }
+    }
+
+    /// Cached by cell, which is all that matters with the default neighborhood of a single cell.
+    fn average(
+        &self,
+        compute: Option<Compute>,
+        lon: Longitude,
+        lat: Latitude,
+    ) -> Result<f32, Status> {
+        let settings = self.settings(compute);
+        let cell = LonLatCell::at(lon, lat);
+        let cached = self.cache.get(cell, settings.period);
+        self.metrics.cache(cached.is_some());
+        let celsius = match cached {
+            Some(celsius) => celsius,
+            None => {
+                let celsius = CitySettings {
+                    units: Units::Celsius,
+                    ..settings
+                }
+                .temperature_at(&self.dataset, lon, lat)
+                .map_err(|e| Status::not_found(e.to_string()))?;
+                self.cache.put(cell, settings.period, celsius);
+                celsius
+            }
+        };
+        Ok(Temperature::new(celsius).in_units(settings.units))
+    }
+
+    fn point(&self, query: PointQuery) -> Result<PointAnswer, Status> {
+        self.metrics.query("point");
+        let (lon, lat) = coordinates::lon_lat(query.lon, query.lat)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let nearest_city = self.gazetteer.as_ref().and_then(|gazetteer| {
+            let (place, distance_km) = gazetteer.nearest(lon.degrees(), lat.degrees())?;
+            Some(NearestCity {
+                city: place.city.clone(),
+                country: place.country.clone(),
+                lon: place.lon,
+                lat: place.lat,
+                distance_km,
+            })
+        });
+        Ok(PointAnswer {
+            average_temperature: self.average(query.compute, lon, lat)?,
+            nearest_city,
+        })
+    }
+
+    fn city(&self, query: CityQuery) -> Result<CityAnswer, Status> {
+        self.metrics.query("city");
+        let (entity_id, lon, lat) = locate::locate(
+            self.geocoder,
+            &query.city,
+            query.region.as_deref(),
+            &query.country,
+            query.entity_id,
+        )
+        .map_err(|e| {
+            let message = format!("{}, {}: {}", query.city, query.country, e);
+            match e {
+                LocateErr::Geocoder(_) => Status::unavailable(message),
+                LocateErr::NotFound
+                | LocateErr::NoCoordinates
+                | LocateErr::InvalidCoordinates(_) => Status::not_found(message),
+                LocateErr::Ambiguous(candidates) => {
+                    let candidates = candidates.iter().map(|candidate| {
+                        format!(
+                            "{} {} ({})",
+                            candidate.entity_id.as_deref().unwrap_or("?"),
+                            candidate.label,
+                            candidate.description.as_deref().unwrap_or_default()
+                        )
+                    });
+                    Status::failed_precondition(format!(
+                        "{}: {}",
+                        message,
+                        candidates.collect::<Vec<_>>().join(", ")
+                    ))
+                }
+            }
+        })?;
+        Ok(CityAnswer {
+            entity_id,
+            lon: lon.degrees(),
+            lat: lat.degrees(),
+            average_temperature: self.average(query.compute, lon, lat)?,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Temperatures for Server {
+    async fn query_point(
+        &self,
+        request: Request<PointQuery>,
+    ) -> Result<Response<PointAnswer>, Status> {
+        self.answer("query_point", |server| server.point(request.into_inner()))
+            .await
+    }
+
+    async fn query_city(
+        &self,
+        request: Request<CityQuery>,
+    ) -> Result<Response<CityAnswer>, Status> {
+        self.answer("query_city", |server| server.city(request.into_inner()))
+            .await
+    }
+
+    async fn query_points(
+        &self,
+        request: Request<PointQueries>,
+    ) -> Result<Response<PointAnswers>, Status> {
+        self.charge(&request, request.get_ref().queries.len())?;
+        self.answer("query_points", |server| {
+            let results = request.into_inner().queries.into_iter().map(|query| {
+                let result = match server.point(query) {
+                    Ok(answer) => point_answers::result::Result::Answer(answer),
+                    Err(status) => point_answers::result::Result::Error(status.message().into()),
+                };
+                point_answers::Result {
+                    result: Some(result),
+                }
+            });
+            Ok(PointAnswers {
+                results: results.collect(),
+            })
+        })
+        .await
+    }
+
+    async fn query_cities(
+        &self,
+        request: Request<CityQueries>,
+    ) -> Result<Response<CityAnswers>, Status> {
+        self.charge(&request, request.get_ref().queries.len())?;
+        self.answer("query_cities", |server| {
+            let results = request.into_inner().queries.into_iter().map(|query| {
+                let result = match server.city(query) {
+                    Ok(answer) => city_answers::result::Result::Answer(answer),
+                    Err(status) => city_answers::result::Result::Error(status.message().into()),
+                };
+                city_answers::Result {
+                    result: Some(result),
+                }
+            });
+            Ok(CityAnswers {
+                results: results.collect(),
+            })
+        })
+        .await
+    }
+}
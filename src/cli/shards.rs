@@ -0,0 +1,201 @@
+//! Splitting a run across machines: `--shard i/N` keeps the cities whose hash falls in the
+//! `i`th of `N` parts, the same ones whatever order the cities file lists them in, and
+//! `merge-shards` puts the parts' outputs back together into one.
+
+use crate::cli::diff::{CityKey, DiffErr, Table};
+use crate::cli::metadata::{FileFingerprint, RunMetadata};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The `index`th of `count` parts of a city list, from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    /// Cities differing only by case, as people type them, fall in the same shard.
+    pub fn contains(&self, city: &str, region: Option<&str>, country: &str) -> bool {
+        let mut hasher = Sha256::new();
+        for part in [city, region.unwrap_or_default(), country] {
+            hasher.update(part.to_lowercase().as_bytes());
+            hasher.update([0]);
+        }
+        let hash = u64::from_be_bytes(hasher.finalize()[..8].try_into().expect("8 bytes"));
+        hash % self.count == self.index - 1
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("{} isn't of the form i/N", s))?;
+        let index: u64 = index.parse().map_err(|e| format!("{}: {}", index, e))?;
+        let count: u64 = count.parse().map_err(|e| format!("{}: {}", count, e))?;
+        if !(1..=count).contains(&index) {
+            return Err(format!("the shard must be from 1 to {}", count));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(fmt, "{}/{}", self.index, self.count)
+    }
+}
+
+#[derive(Debug)]
+pub enum MergeErr {
+    CantReadShard(PathBuf, DiffErr),
+    /// Its columns aren't those of the first shard's, e.g. from other --normals.
+    DifferentColumns(PathBuf),
+    /// Its run had other parameters or another dataset than the first shard's.
+    DifferentRun(PathBuf),
+    CantWrite(io::Error),
+}
+
+impl Display for MergeErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            MergeErr::CantReadShard(path, e) => write!(fmt, "{}: {}", path.display(), e),
+            MergeErr::DifferentColumns(path) => write!(
+                fmt,
+                "{} doesn't have the same columns as the first shard",
+                path.display()
+            ),
+            MergeErr::DifferentRun(path) => write!(
+                fmt,
+                "{} was computed from another dataset or with other parameters than the first shard",
+                path.display()
+            ),
+            MergeErr::CantWrite(e) => write!(fmt, "can't write output: {}", e),
+        }
+    }
+}
+
+impl From<csv::Error> for MergeErr {
+    fn from(e: csv::Error) -> Self {
+        MergeErr::CantWrite(e.into())
+    }
+}
+
+impl From<io::Error> for MergeErr {
+    fn from(e: io::Error) -> Self {
+        MergeErr::CantWrite(e)
+    }
+}
+
+/// A city in several shards with different values, e.g. because they ran with different
+/// versions of the cities file.
+#[derive(Debug)]
+pub struct Conflict {
+    pub city: CityKey,
+    pub shards: (PathBuf, PathBuf),
+}
+
+#[derive(Debug, Default)]
+pub struct Merged {
+    pub cities: usize,
+    pub conflicts: Vec<Conflict>,
+    /// Shards of the runs that are in none of the files, when the files have their run metadata.
+    pub missing: Vec<Shard>,
+}
+
+/// Write the rows of every shard to `output`, unless some conflict, in which case nothing is
+/// written. Rows that appear identically in several shards are written once.
+pub fn merge(shards: &[PathBuf], output: &Path) -> Result<Merged, MergeErr> {
+    let mut merged = Merged::default();
+    let mut headers = None;
+    let mut rows = vec![];
+    let mut seen: HashMap<CityKey, (usize, usize)> = HashMap::new();
+    for (i, path) in shards.iter().enumerate() {
+        let table = Table::read(path).map_err(|e| MergeErr::CantReadShard(path.clone(), e))?;
+        if *headers.get_or_insert_with(|| table.headers.clone()) != table.headers {
+            return Err(MergeErr::DifferentColumns(path.clone()));
+        }
+        for (key, record) in table.rows {
+            match seen.get(&key) {
+                Some(&(_, row)) if rows[row] == record => {}
+                Some(&(shard, _)) => merged.conflicts.push(Conflict {
+                    city: key,
+                    shards: (shards[shard].clone(), path.clone()),
+                }),
+                None => {
+                    seen.insert(key, (i, rows.len()));
+                    rows.push(record);
+                }
+            }
+        }
+    }
+    let metadata = run_metadata(shards, &mut merged)?;
+    if !merged.conflicts.is_empty() {
+        return Ok(merged);
+    }
+
+    let mut writer = csv::Writer::from_path(output)?;
+    if let Some(headers) = headers {
+        writer.write_record(&headers)?;
+    }
+    for row in &rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+    merged.cities = rows.len();
+    // The merged output can be verified like any other, when the shards could be.
+    if let Some(mut metadata) = metadata {
+        metadata.output = FileFingerprint::of(output)?;
+        metadata.write()?;
+    }
+    Ok(merged)
+}
+
+/// The run metadata of the merged output when every shard has it, checking they're all from
+/// the same run and noting which shards of it are missing.
+fn run_metadata(shards: &[PathBuf], merged: &mut Merged) -> Result<Option<RunMetadata>, MergeErr> {
+    let mut first: Option<RunMetadata> = None;
+    let mut present = vec![];
+    for path in shards {
+        let Ok(mut metadata) = RunMetadata::read(path) else {
+            return Ok(None);
+        };
+        if let Some(shard) = metadata
+            .parameters
+            .remove("shard")
+            .and_then(|shard| shard.parse::<Shard>().ok())
+        {
+            if present
+                .first()
+                .is_some_and(|first: &Shard| first.count != shard.count)
+            {
+                return Err(MergeErr::DifferentRun(path.clone()));
+            }
+            present.push(shard);
+        }
+        match &first {
+            None => first = Some(metadata),
+            Some(first)
+                if first.parameters != metadata.parameters
+                    || first.temperature_dataset.sha256 != metadata.temperature_dataset.sha256 =>
+            {
+                return Err(MergeErr::DifferentRun(path.clone()));
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(count) = present.first().map(|shard| shard.count) {
+        merged.missing = (1..=count)
+            .map(|index| Shard { index, count })
+            .filter(|shard| !present.contains(shard))
+            .collect();
+    }
+    Ok(first)
+}
@@ -0,0 +1,277 @@
+//! The formats a run's cities can be written in, picked by the output's extension: CSV, Arrow IPC,
+//! Parquet or GeoJSON. Every one is given the CSV rendering of the cities, so the columns (including
+//! those of `--normals`) are the same whichever is asked for, and one run can write several.
+
+use super::arrow;
+use arrow_schema::ArrowError;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use serde::de::{Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum SinkErr {
+    Io(std::io::Error),
+    Csv(csv::Error),
+    Arrow(ArrowError),
+    Parquet(ParquetError),
+    GeoJson(serde_json::Error),
+}
+
+impl Display for SinkErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SinkErr::Io(e) => write!(fmt, "{}", e),
+            SinkErr::Csv(e) => write!(fmt, "{}", e),
+            SinkErr::Arrow(e) => write!(fmt, "{}", e),
+            SinkErr::Parquet(e) => write!(fmt, "{}", e),
+            SinkErr::GeoJson(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SinkErr {}
+
+impl From<std::io::Error> for SinkErr {
+    fn from(e: std::io::Error) -> Self {
+        SinkErr::Io(e)
+    }
+}
+
+impl From<csv::Error> for SinkErr {
+    fn from(e: csv::Error) -> Self {
+        SinkErr::Csv(e)
+    }
+}
+
+impl From<ArrowError> for SinkErr {
+    fn from(e: ArrowError) -> Self {
+        SinkErr::Arrow(e)
+    }
+}
+
+impl From<ParquetError> for SinkErr {
+    fn from(e: ParquetError) -> Self {
+        SinkErr::Parquet(e)
+    }
+}
+
+pub trait Sink {
+    /// Write `csv`, which has a header, to `path` in this sink's format.
+    fn write(&self, csv: &[u8], path: &Path) -> Result<(), SinkErr>;
+
+    /// Read what this sink wrote at `path` back as CSV with a header, as `write` was given it.
+    fn read(&self, path: &Path) -> Result<Vec<u8>, SinkErr>;
+}
+
+/// The CSV rendering of the output at `path`, in whichever format its extension says.
+pub fn read(path: &Path) -> Result<Vec<u8>, SinkErr> {
+    for_path(path).read(path)
+}
+
+/// What `path`'s extension asks for, CSV for any other.
+pub fn for_path(path: &Path) -> Box<dyn Sink> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        _ if arrow::is_arrow(path) => Box::new(ArrowSink),
+        Some("parquet") => Box::new(ParquetSink),
+        Some("geojson") => Box::new(GeoJsonSink),
+        _ => Box::new(CsvSink),
+    }
+}
+
+pub struct CsvSink;
+
+impl Sink for CsvSink {
+    fn write(&self, csv: &[u8], path: &Path) -> Result<(), SinkErr> {
+        Ok(fs::write(path, csv)?)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, SinkErr> {
+        Ok(fs::read(path)?)
+    }
+}
+
+/// See `arrow`.
+pub struct ArrowSink;
+
+impl Sink for ArrowSink {
+    fn write(&self, csv: &[u8], path: &Path) -> Result<(), SinkErr> {
+        Ok(arrow::write_csv(csv, path)?)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, SinkErr> {
+        Ok(arrow::read_csv(path)?)
+    }
+}
+
+/// Typed like the Arrow IPC output, uncompressed.
+pub struct ParquetSink;
+
+impl Sink for ParquetSink {
+    fn write(&self, csv: &[u8], path: &Path) -> Result<(), SinkErr> {
+        let (schema, batches) = arrow::of_csv(csv)?;
+        let mut writer = ArrowWriter::try_new(BufWriter::new(File::create(path)?), schema, None)?;
+        for batch in batches {
+            writer.write(&batch)?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>, SinkErr> {
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(arrow::to_csv(batches)?)
+    }
+}
+
+/// A point feature per city, at its override coordinates if it has any and its Wikidata ones
+/// otherwise, without a geometry when it has neither. Every column is a property, in order,
+/// numbers as numbers and empty values as null.
+pub struct GeoJsonSink;
+
+#[derive(Serialize, Deserialize)]
+struct FeatureCollection {
+    #[serde(rename = "type")]
+    kind: String,
+    features: Vec<Feature>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Feature {
+    #[serde(rename = "type")]
+    kind: String,
+    geometry: Option<Value>,
+    properties: Properties,
+}
+
+/// In the order of the columns, which `serde_json`'s maps don't keep.
+struct Properties(Vec<(String, Value)>);
+
+impl Serialize for Properties {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Properties {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct InOrder;
+
+        impl<'de> Visitor<'de> for InOrder {
+            type Value = Properties;
+
+            fn expecting(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                fmt.write_str("an object of properties")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Properties, A::Error> {
+                let mut properties = vec![];
+                while let Some(property) = map.next_entry()? {
+                    properties.push(property);
+                }
+                Ok(Properties(properties))
+            }
+        }
+
+        deserializer.deserialize_map(InOrder)
+    }
+}
+
+impl Sink for GeoJsonSink {
+    fn write(&self, csv: &[u8], path: &Path) -> Result<(), SinkErr> {
+        let mut reader = csv::Reader::from_reader(csv);
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|header| header == name);
+        let coordinates = [
+            (column("OverrideLongitude"), column("OverrideLatitude")),
+            (column("WikidataLongitude"), column("WikidataLatitude")),
+        ];
+        let mut features = vec![];
+        for record in reader.records() {
+            let record = record?;
+            let number = |index: Option<usize>| {
+                index
+                    .and_then(|index| record.get(index))
+                    .and_then(|value| value.parse::<f64>().ok())
+            };
+            let geometry = coordinates
+                .iter()
+                .find_map(|&(lon, lat)| number(lon).zip(number(lat)))
+                .map(|(lon, lat)| json!({"type": "Point", "coordinates": [lon, lat]}));
+            let properties = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(header, value)| (header.to_string(), property(value)))
+                .collect();
+            features.push(Feature {
+                kind: "Feature".to_string(),
+                geometry,
+                properties: Properties(properties),
+            });
+        }
+        let collection = FeatureCollection {
+            kind: "FeatureCollection".to_string(),
+            features,
+        };
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &collection)
+            .map_err(std::io::Error::from)?;
+        Ok(())
+    }
+
+    /// The geometry is left out, since it's a copy of the coordinate columns.
+    fn read(&self, path: &Path) -> Result<Vec<u8>, SinkErr> {
+        let collection: FeatureCollection =
+            serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(SinkErr::GeoJson)?;
+        let mut writer = csv::Writer::from_writer(vec![]);
+        if let Some(first) = collection.features.first() {
+            writer.write_record(first.properties.0.iter().map(|(name, _)| name))?;
+        }
+        for feature in &collection.features {
+            writer.write_record(feature.properties.0.iter().map(|(_, value)| text(value)))?;
+        }
+        writer.into_inner().map_err(|e| SinkErr::Io(e.into_error()))
+    }
+}
+
+/// The inverse of `property`.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn property(value: &str) -> Value {
+    if value.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(boolean) = value.parse::<bool>() {
+        return Value::Bool(boolean);
+    }
+    if let Ok(integer) = value.parse::<i64>() {
+        return Value::from(integer);
+    }
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(serde_json::Number::from_f64)
+        .map_or_else(|| Value::String(value.to_string()), Value::Number)
+}
@@ -0,0 +1,378 @@
+//! `site`: a browsable static HTML site of a run's cities. An index with a sortable table and a
+//! world map of average temperatures, and a page per city with its climograph and statistics.
+//! Everything is plain HTML and inline SVG, so the directory can be put on any web server.
+
+use crate::cli::City;
+use crate::config::Units;
+use crate::{grid, LonLatCell, Period, Series, Temperature, TemperatureDataset};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+table { border-collapse: collapse; width: 100%; }
+th, td { padding: 0.3em 0.6em; border-bottom: 1px solid #ddd; text-align: left; }
+th { cursor: pointer; user-select: none; }
+td.number { text-align: right; }
+svg { display: block; width: 100%; height: auto; }
+svg.map { background: #dde8f0; }
+";
+
+/// Sorts the index table by whichever header was clicked, numerically where cells have a value.
+const SORT_SCRIPT: &str = "\
+document.querySelectorAll('th').forEach((th, column) => th.addEventListener('click', () => {
+  const body = th.closest('table').tBodies[0];
+  const ascending = th.dataset.order !== 'ascending';
+  th.dataset.order = ascending ? 'ascending' : 'descending';
+  const key = row => {
+    const cell = row.cells[column];
+    return cell.dataset.value !== undefined ? parseFloat(cell.dataset.value) : cell.textContent;
+  };
+  [...body.rows]
+    .sort((a, b) => (key(a) < key(b) ? -1 : key(a) > key(b) ? 1 : 0) * (ascending ? 1 : -1))
+    .forEach(row => body.appendChild(row));
+}));
+";
+
+struct CityPage<'a> {
+    city: &'a City,
+    file: String,
+    lon: f32,
+    lat: f32,
+    average: Temperature,
+    /// None for months without data in the period.
+    monthly: [Option<Temperature>; 12],
+    years: usize,
+}
+
+/// Writes the site into `dir`, returning how many cities got a page. Temperatures are computed
+/// afresh from the cell each city falls in, so the table and climographs agree.
+pub fn render(
+    dir: &Path,
+    cities: &[City],
+    dataset: &TemperatureDataset,
+    period: Period,
+    units: Units,
+) -> io::Result<usize> {
+    let series: HashMap<LonLatCell, Series> = dataset
+        .series_by_cell()
+        .into_iter()
+        .map(|(cell, series)| {
+            let series = series
+                .into_iter()
+                .filter(|&(time, _)| period.contains(time))
+                .collect();
+            (cell, series)
+        })
+        .collect();
+    let grid = grid::Grid::from_cells(series.iter().map(|(&cell, series)| {
+        let average = Temperature::average(series.iter().copied()).ok();
+        (cell, average.map(|t| t.celsius))
+    }));
+
+    let pages: Vec<CityPage> = cities
+        .iter()
+        .enumerate()
+        .filter_map(|(i, city)| {
+            let (lon, lat) = city
+                .override_lon_lat()
+                .or(city.wikidata_longitude.zip(city.wikidata_latitude))?;
+            let series = series.get(&LonLatCell::containing(lon, lat))?;
+            let average = Temperature::average(series.iter().copied()).ok()?;
+            let monthly = std::array::from_fn(|month| {
+                let in_month = series
+                    .iter()
+                    .copied()
+                    .filter(|(time, _)| time.month() == month as u32 + 1);
+                Temperature::average(in_month).ok()
+            });
+            let mut years: Vec<i32> = series.iter().map(|(time, _)| time.year()).collect();
+            years.dedup();
+            Some(CityPage {
+                city,
+                file: format!("cities/{}-{}.html", i + 1, slug(&city.city)),
+                lon,
+                lat,
+                average,
+                monthly,
+                years: years.len(),
+            })
+        })
+        .collect();
+
+    fs::create_dir_all(dir.join("cities"))?;
+    fs::write(dir.join("style.css"), STYLE)?;
+    fs::write(dir.join("index.html"), index(&pages, &grid, units))?;
+    for page in &pages {
+        fs::write(dir.join(&page.file), city_page(page, units))?;
+    }
+    Ok(pages.len())
+}
+
+fn index(pages: &[CityPage], grid: &grid::Grid, units: Units) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Average temperatures</title>\n<link rel=\"stylesheet\" href=\"style.css\">\n\
+         </head>\n<body>\n<h1>Average temperatures</h1>\n"
+    );
+    html.push_str(&world_map(pages, grid, units));
+    let _ = write!(
+        html,
+        "<table>\n<thead><tr><th>City</th><th>Region</th><th>Country</th>\
+         <th>Average temperature ({})</th></tr></thead>\n<tbody>\n",
+        unit_symbol(units)
+    );
+    for page in pages {
+        let average = page.average.in_units(units);
+        let _ = writeln!(
+            html,
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td>\
+             <td class=\"number\" data-value=\"{}\">{:.1}</td></tr>",
+            page.file,
+            escape(&page.city.city),
+            escape(page.city.region.as_deref().unwrap_or_default()),
+            escape(&page.city.country),
+            average,
+            average
+        );
+    }
+    let _ = write!(
+        html,
+        "</tbody>\n</table>\n<script>\n{}</script>\n</body>\n</html>\n",
+        SORT_SCRIPT
+    );
+    html
+}
+
+/// Every cell with data, coloured by its average, and a dot per city linking to its page.
+/// Runs of cells of the same colour along a row are drawn as one rectangle, to keep it small.
+fn world_map(pages: &[CityPage], grid: &grid::Grid, units: Units) -> String {
+    let mut svg = String::from(
+        "<svg class=\"map\" viewBox=\"-180 -90 360 180\" shape-rendering=\"crispEdges\">\n",
+    );
+    for y in 0..grid.height {
+        let mut x = 0;
+        while x < grid.width {
+            let Some(celsius) = grid.get(x, y) else {
+                x += 1;
+                continue;
+            };
+            let band = color_band(celsius);
+            let start = x;
+            while x < grid.width && grid.get(x, y).is_some_and(|c| color_band(c) == band) {
+                x += 1;
+            }
+            let cell = grid.cell(start, y);
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"0.5\" fill=\"{}\"/>",
+                cell.half_degrees_lon_start as f32 * 0.5,
+                -(cell.half_degrees_lat_start as f32 + 1.0) * 0.5,
+                (x - start) as f32 * 0.5,
+                color(band)
+            );
+        }
+    }
+    for page in pages {
+        let _ = writeln!(
+            svg,
+            "<a href=\"{}\"><circle cx=\"{}\" cy=\"{}\" r=\"0.8\" fill=\"#000\" \
+             shape-rendering=\"auto\"><title>{}, {}: {:.1} {}</title></circle></a>",
+            page.file,
+            page.lon,
+            -page.lat,
+            escape(&page.city.city),
+            escape(&page.city.country),
+            page.average.in_units(units),
+            unit_symbol(units)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn city_page(page: &CityPage, units: Units) -> String {
+    let city = page.city;
+    let name = match &city.region {
+        Some(region) => format!("{}, {}, {}", city.city, region, city.country),
+        None => format!("{}, {}", city.city, city.country),
+    };
+    let symbol = unit_symbol(units);
+    let months = || {
+        page.monthly
+            .iter()
+            .enumerate()
+            .filter_map(|(i, t)| Some((i, (*t)?)))
+    };
+    let warmest = months().max_by(|a, b| a.1.celsius.total_cmp(&b.1.celsius));
+    let coldest = months().min_by(|a, b| a.1.celsius.total_cmp(&b.1.celsius));
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{name}</title>\n<link rel=\"stylesheet\" href=\"../style.css\">\n</head>\n<body>\n\
+         <p><a href=\"../index.html\">All cities</a></p>\n<h1>{name}</h1>\n",
+        name = escape(&name)
+    );
+    html.push_str(&climograph(&page.monthly, units));
+    html.push_str("<table>\n");
+    let mut row = |label: &str, value: String| {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, value);
+    };
+    row(
+        "Average temperature",
+        format!("{:.1} {}", page.average.in_units(units), symbol),
+    );
+    if let (Some((warm_month, warm)), Some((cold_month, cold))) = (warmest, coldest) {
+        row(
+            "Warmest month",
+            format!(
+                "{} ({:.1} {})",
+                MONTHS[warm_month],
+                warm.in_units(units),
+                symbol
+            ),
+        );
+        row(
+            "Coldest month",
+            format!(
+                "{} ({:.1} {})",
+                MONTHS[cold_month],
+                cold.in_units(units),
+                symbol
+            ),
+        );
+        row(
+            "Annual range",
+            format!(
+                "{:.1} {}",
+                warm.in_units(units) - cold.in_units(units),
+                symbol
+            ),
+        );
+    }
+    row("Years of data", page.years.to_string());
+    row("Coordinates", format!("{:.3}, {:.3}", page.lat, page.lon));
+    if let Some(id) = &city.wikidata_entity_id {
+        row(
+            "Wikidata",
+            format!(
+                "<a href=\"https://www.wikidata.org/wiki/{id}\">{id}</a>",
+                id = escape(id)
+            ),
+        );
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Monthly averages as bars, from a zero line.
+fn climograph(monthly: &[Option<Temperature>; 12], units: Units) -> String {
+    let (width, height, margin) = (480.0, 240.0, 24.0);
+    let values: Vec<Option<f32>> = monthly
+        .iter()
+        .map(|t| t.map(|t| t.in_units(units)))
+        .collect();
+    let known = values.iter().flatten().copied();
+    let max = known.clone().fold(0.0f32, f32::max);
+    let min = known.fold(0.0f32, f32::min);
+    let scale = (height - 2.0 * margin) / (max - min).max(1.0);
+    let zero = margin + max * scale;
+    let bar = (width - 2.0 * margin) / 12.0;
+
+    let mut svg = format!(
+        "<svg class=\"climograph\" viewBox=\"0 0 {} {}\" font-size=\"10\">\n",
+        width, height
+    );
+    for (i, (value, temperature)) in values.iter().zip(monthly).enumerate() {
+        let x = margin + i as f32 * bar;
+        if let (Some(value), Some(temperature)) = (value, temperature) {
+            let top = zero - value.max(0.0) * scale;
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\">\
+                 <title>{} {:.1} {}</title></rect>",
+                x + 2.0,
+                top,
+                bar - 4.0,
+                value.abs() * scale,
+                color(color_band(temperature.celsius)),
+                MONTHS[i],
+                value,
+                unit_symbol(units)
+            );
+            let label_y = if *value >= 0.0 {
+                top - 3.0
+            } else {
+                top + value.abs() * scale + 11.0
+            };
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{:.0}</text>",
+                x + bar / 2.0,
+                label_y,
+                value
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+            x + bar / 2.0,
+            height - 6.0,
+            MONTHS[i]
+        );
+    }
+    let _ = writeln!(
+        svg,
+        "<line x1=\"{}\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"#444\"/>",
+        margin,
+        zero,
+        width - margin,
+        zero
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// 4°C wide bands from -40°C (0) to 40°C (20).
+fn color_band(celsius: f32) -> u8 {
+    ((celsius + 40.0) / 4.0).clamp(0.0, 20.0) as u8
+}
+
+/// From blue for the coldest band to red for the warmest.
+fn color(band: u8) -> String {
+    format!("hsl({}, 70%, 50%)", 240 - band as u32 * 12)
+}
+
+fn unit_symbol(units: Units) -> &'static str {
+    match units {
+        Units::Celsius => "°C",
+        Units::Fahrenheit => "°F",
+    }
+}
+
+/// Lowercase letters and digits, with dashes in between, for file names.
+fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,367 @@
+//! Where the cities input's rows come from: CSV, the first sheet of a spreadsheet, the point
+//! features of a GeoJSON file or a table of a SQLite database, picked by the file's extension or
+//! by name with `--input-format`. Cities given as a URL are downloaded first, keeping the URL's
+//! extension, and then read the same way.
+//!
+//! Every source reads rows of text under a header, which are then read as cities the same way
+//! whatever the format, so adding one is implementing `InputSource` and registering it in
+//! `Sources`. Library users can register their own and run the command line with them, see
+//! `main_with_sources`.
+
+use calamine::Reader;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use serde_json::Value;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// A source's rows, before they're read as cities.
+#[derive(Debug, Default)]
+pub struct Rows {
+    pub header: Vec<String>,
+    /// As many values as the header.
+    pub rows: Vec<Vec<String>>,
+    /// The indices of the rows that weren't valid UTF-8, and were read with the invalid bytes
+    /// replaced.
+    pub non_utf8: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub enum SourceErr {
+    Csv(csv::Error),
+    Spreadsheet(calamine::Error),
+    NoSheet,
+    Io(std::io::Error),
+    GeoJson(serde_json::Error),
+    Sqlite(rusqlite::Error),
+    /// Which table of a database has the cities can't be told.
+    Tables(Vec<String>),
+    /// No source of that name, for `--input-format`.
+    UnknownFormat(String),
+    /// Whatever an `InputSource` from outside this crate fails with.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl Display for SourceErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SourceErr::Csv(e) => write!(fmt, "{}", e),
+            SourceErr::Spreadsheet(e) => write!(fmt, "{}", e),
+            SourceErr::NoSheet => write!(fmt, "the spreadsheet has no sheets"),
+            SourceErr::Io(e) => write!(fmt, "{}", e),
+            SourceErr::GeoJson(e) => write!(fmt, "can't parse GeoJSON: {}", e),
+            SourceErr::Sqlite(e) => write!(fmt, "{}", e),
+            SourceErr::Tables(tables) if tables.is_empty() => {
+                write!(fmt, "the database has no tables")
+            }
+            SourceErr::Tables(tables) => write!(
+                fmt,
+                "the database has several tables and none is named cities: {}",
+                tables.join(", ")
+            ),
+            SourceErr::UnknownFormat(name) => write!(fmt, "no input format named {}", name),
+            SourceErr::Other(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl From<csv::Error> for SourceErr {
+    fn from(e: csv::Error) -> Self {
+        SourceErr::Csv(e)
+    }
+}
+
+impl From<calamine::Error> for SourceErr {
+    fn from(e: calamine::Error) -> Self {
+        SourceErr::Spreadsheet(e)
+    }
+}
+
+impl From<std::io::Error> for SourceErr {
+    fn from(e: std::io::Error) -> Self {
+        SourceErr::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SourceErr {
+    fn from(e: serde_json::Error) -> Self {
+        SourceErr::GeoJson(e)
+    }
+}
+
+impl From<rusqlite::Error> for SourceErr {
+    fn from(e: rusqlite::Error) -> Self {
+        SourceErr::Sqlite(e)
+    }
+}
+
+pub trait InputSource {
+    /// What `--input-format` calls it, e.g. `csv`.
+    fn name(&self) -> &str;
+    /// The extensions of the files it reads, lower case and without the dot.
+    fn extensions(&self) -> &[&str];
+    fn read(&self, path: &Path) -> Result<Rows, SourceErr>;
+}
+
+/// The input sources, looked up by name or by extension. The last registered wins, so
+/// registering one can take over a built-in's extensions.
+pub struct Sources(Vec<Box<dyn InputSource>>);
+
+impl Default for Sources {
+    /// The built-in ones.
+    fn default() -> Self {
+        Self(vec![
+            Box::new(CsvSource),
+            Box::new(SpreadsheetSource),
+            Box::new(GeoJsonSource),
+            Box::new(SqliteSource),
+        ])
+    }
+}
+
+impl Sources {
+    pub fn register(&mut self, source: Box<dyn InputSource>) {
+        self.0.push(source);
+    }
+
+    pub fn named(&self, name: &str) -> Option<&dyn InputSource> {
+        self.0
+            .iter()
+            .rev()
+            .find(|source| source.name().eq_ignore_ascii_case(name))
+            .map(|source| source.as_ref())
+    }
+
+    /// The source for `path`'s extension, if any reads it.
+    pub fn for_extension(&self, path: &Path) -> Option<&dyn InputSource> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.0
+            .iter()
+            .rev()
+            .find(|source| source.extensions().contains(&extension.as_str()))
+            .map(|source| source.as_ref())
+    }
+
+    /// With the source named `format`, or else the one for `path`'s extension, or else as CSV.
+    pub fn read(&self, path: &Path, format: Option<&str>) -> Result<Rows, SourceErr> {
+        let source = match format {
+            Some(name) => self
+                .named(name)
+                .ok_or_else(|| SourceErr::UnknownFormat(name.to_string()))?,
+            None => self.for_extension(path).unwrap_or(&CsvSource),
+        };
+        source.read(path)
+    }
+}
+
+pub struct CsvSource;
+
+impl InputSource for CsvSource {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["csv"]
+    }
+
+    fn read(&self, path: &Path) -> Result<Rows, SourceErr> {
+        let lossy = |row: &csv::ByteRecord| -> Vec<String> {
+            row.iter()
+                .map(|field| String::from_utf8_lossy(field).into_owned())
+                .collect()
+        };
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut rows = Rows {
+            header: lossy(reader.byte_headers()?),
+            ..Default::default()
+        };
+        for row in reader.byte_records() {
+            let row = row?;
+            if std::str::from_utf8(row.as_slice()).is_err() {
+                rows.non_utf8.push(rows.rows.len());
+            }
+            rows.rows.push(lossy(&row));
+        }
+        Ok(rows)
+    }
+}
+
+/// The first sheet, with its first row as the header.
+pub struct SpreadsheetSource;
+
+impl InputSource for SpreadsheetSource {
+    fn name(&self) -> &str {
+        "spreadsheet"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["xlsx", "xlsm", "xls", "ods"]
+    }
+
+    fn read(&self, path: &Path) -> Result<Rows, SourceErr> {
+        let range = calamine::open_workbook_auto(path)?
+            .worksheet_range_at(0)
+            .ok_or(SourceErr::NoSheet)??;
+        let mut rows = range
+            .rows()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect());
+        Ok(Rows {
+            header: rows.next().unwrap_or_default(),
+            rows: rows.collect(),
+            non_utf8: vec![],
+        })
+    }
+}
+
+/// A feature per city, its properties as the columns. Point geometries give the
+/// WikidataLongitude and WikidataLatitude columns, unless the properties already do.
+pub struct GeoJsonSource;
+
+const LONGITUDE: &str = "WikidataLongitude";
+const LATITUDE: &str = "WikidataLatitude";
+
+impl InputSource for GeoJsonSource {
+    fn name(&self) -> &str {
+        "geojson"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["geojson"]
+    }
+
+    fn read(&self, path: &Path) -> Result<Rows, SourceErr> {
+        let collection: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        let features = collection["features"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let mut header: Vec<String> = vec![];
+        let features: Vec<_> = features
+            .iter()
+            .map(|feature| {
+                let properties: Vec<(String, String)> = feature["properties"]
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, value)| (name.clone(), text(value)))
+                    .collect();
+                for (name, _) in &properties {
+                    if !header.contains(name) {
+                        header.push(name.clone());
+                    }
+                }
+                let geometry = &feature["geometry"];
+                let point = (geometry["type"] == "Point")
+                    .then(|| geometry["coordinates"][0].as_f64())
+                    .flatten()
+                    .zip(geometry["coordinates"][1].as_f64());
+                (properties, point)
+            })
+            .collect();
+        let from_geometry = !header
+            .iter()
+            .any(|name| name == LONGITUDE || name == LATITUDE);
+        if from_geometry {
+            header.extend([LONGITUDE.to_string(), LATITUDE.to_string()]);
+        }
+        let rows = features
+            .iter()
+            .map(|(properties, point)| {
+                header
+                    .iter()
+                    .map(|name| match (name.as_str(), *point) {
+                        (LONGITUDE, Some((lon, _))) if from_geometry => lon.to_string(),
+                        (LATITUDE, Some((_, lat))) if from_geometry => lat.to_string(),
+                        _ => properties
+                            .iter()
+                            .find(|(property, _)| property == name)
+                            .map(|(_, value)| value.clone())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Rows {
+            header,
+            rows,
+            non_utf8: vec![],
+        })
+    }
+}
+
+/// A property as a CSV value: strings as they are, null as empty.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The database's only table, or its `cities` table when it has several.
+pub struct SqliteSource;
+
+impl InputSource for SqliteSource {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sqlite", "sqlite3", "db"]
+    }
+
+    fn read(&self, path: &Path) -> Result<Rows, SourceErr> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let tables: Vec<String> = connection
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type = 'table' \
+                 AND name NOT LIKE 'sqlite_%' ORDER BY name",
+            )?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        let table = match &tables[..] {
+            [table] => table,
+            _ => tables
+                .iter()
+                .find(|table| table.eq_ignore_ascii_case("cities"))
+                .ok_or_else(|| SourceErr::Tables(tables.clone()))?,
+        };
+        let mut statement =
+            connection.prepare(&format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")))?;
+        let header: Vec<String> = statement
+            .column_names()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        let columns = header.len();
+        let mut rows = Rows {
+            header,
+            ..Default::default()
+        };
+        let mut query = statement.query([])?;
+        while let Some(row) = query.next()? {
+            let mut values = vec![];
+            let mut valid_utf8 = true;
+            for i in 0..columns {
+                values.push(match row.get_ref(i)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(integer) => integer.to_string(),
+                    ValueRef::Real(real) => real.to_string(),
+                    ValueRef::Text(bytes) | ValueRef::Blob(bytes) => {
+                        valid_utf8 &= std::str::from_utf8(bytes).is_ok();
+                        String::from_utf8_lossy(bytes).into_owned()
+                    }
+                });
+            }
+            if !valid_utf8 {
+                rows.non_utf8.push(rows.rows.len());
+            }
+            rows.rows.push(values);
+        }
+        Ok(rows)
+    }
+}
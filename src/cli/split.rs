@@ -0,0 +1,65 @@
+//! `--split-by`: an output file per group of cities next to the whole one, e.g. per country as
+//! `out/<iso>/cities.csv` for `out.csv`, to publish per-country datasets from one global run.
+
+use super::countries::CountryAverages;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SplitBy {
+    Country,
+}
+
+/// Cities without a country go in this one.
+const UNKNOWN: &str = "unknown";
+
+/// Where the groups' files go: a directory named after `output` without its extension.
+pub fn directory(output: &Path) -> PathBuf {
+    output.with_extension("")
+}
+
+/// A group's file in `directory`, in `output`'s format.
+pub fn path(directory: &Path, group: &str, output: &Path) -> PathBuf {
+    let mut path = directory.join(group).join("cities");
+    if let Some(extension) = output.extension() {
+        path.set_extension(extension);
+    }
+    path
+}
+
+/// The indices of `countries` by the directory their country's cities go in, named after its ISO
+/// code when `country_averages`' boundaries have one and after its name otherwise. Names of
+/// countries are matched ignoring case, like the boundaries do.
+pub fn by_country<'a>(
+    countries: impl IntoIterator<Item = &'a str>,
+    country_averages: Option<&CountryAverages>,
+) -> BTreeMap<String, Vec<usize>> {
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, country) in countries.into_iter().enumerate() {
+        let country = country.trim();
+        let group = if country.is_empty() {
+            UNKNOWN.to_string()
+        } else {
+            country_averages
+                .and_then(|averages| averages.iso(country))
+                .map_or_else(|| file_name(country), str::to_string)
+        };
+        groups.entry(group).or_default().push(i);
+    }
+    groups
+}
+
+/// `name` lower cased, with anything but letters and digits as `-`, so that it's a single
+/// path component on every platform.
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    match name.trim_matches('-') {
+        "" => UNKNOWN.to_string(),
+        name => name.to_string(),
+    }
+}
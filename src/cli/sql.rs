@@ -0,0 +1,156 @@
+//! `sql`: ad-hoc queries over an output file, loaded into an in-process DuckDB as the `cities`
+//! table, with its columns named and typed as in the file. Optionally with the `series` table
+//! too, the monthly temperatures of every city's cell, one row per city and month:
+//! `City`, `Region`, `Country`, `Year`, `Month`, `Temperature`.
+
+use crate::cli::sinks::{self, SinkErr};
+use crate::cli::City;
+use crate::config::Units;
+use crate::{LonLatCell, Period, TemperatureDataset};
+use duckdb::{params, Connection};
+use std::fmt::{self, Display};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+#[derive(Debug)]
+pub enum SqlErr {
+    DuckDb(duckdb::Error),
+    Csv(csv::Error),
+    Output(SinkErr),
+    /// DuckDB only takes paths as SQL strings.
+    NonUtf8Path,
+}
+
+impl Display for SqlErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SqlErr::DuckDb(e) => write!(fmt, "{}", e),
+            SqlErr::Csv(e) => write!(fmt, "{}", e),
+            SqlErr::Output(e) => write!(fmt, "can't read the output: {}", e),
+            SqlErr::NonUtf8Path => write!(fmt, "the temporary directory's path isn't valid UTF-8"),
+        }
+    }
+}
+
+impl From<duckdb::Error> for SqlErr {
+    fn from(e: duckdb::Error) -> Self {
+        SqlErr::DuckDb(e)
+    }
+}
+
+impl From<csv::Error> for SqlErr {
+    fn from(e: csv::Error) -> Self {
+        SqlErr::Csv(e)
+    }
+}
+
+/// The output as CSV for DuckDB to read, which only reads files, removed once done with.
+struct CsvCopy(PathBuf);
+
+impl CsvCopy {
+    fn of(csv: &[u8]) -> io::Result<Self> {
+        let path = env::temp_dir().join(format!("temperature-extractor-sql-{}.csv", process::id()));
+        fs::write(&path, csv)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for CsvCopy {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The cities' series over the period, in the given units.
+pub struct SeriesTable<'a> {
+    pub dataset: &'a TemperatureDataset,
+    pub period: Period,
+    pub units: Units,
+}
+
+/// Run `query` and write its result as CSV, every value as DuckDB writes it as text.
+pub fn query(
+    output: &Path,
+    series: Option<SeriesTable>,
+    query: &str,
+    result: impl io::Write,
+) -> Result<(), SqlErr> {
+    let connection = Connection::open_in_memory()?;
+    // In whichever format the run wrote it.
+    let csv = sinks::read(output).map_err(SqlErr::Output)?;
+    let copy = CsvCopy::of(&csv).map_err(|e| SqlErr::Output(SinkErr::Io(e)))?;
+    let output_sql = copy
+        .0
+        .to_str()
+        .ok_or(SqlErr::NonUtf8Path)?
+        .replace('\'', "''");
+    connection.execute_batch(&format!(
+        "CREATE TABLE cities AS SELECT * FROM read_csv_auto('{}', header = true);",
+        output_sql
+    ))?;
+    if let Some(series) = series {
+        load_series(&connection, &csv, series)?;
+    }
+
+    // Cast to text by DuckDB, which knows how to write every one of its types. Prepared on
+    // its own first so mistakes are reported in terms of the query as written.
+    let query = query.trim().trim_end_matches(';');
+    connection.prepare(query)?;
+    let mut statement =
+        connection.prepare(&format!("SELECT COLUMNS(*)::VARCHAR FROM ({})", query))?;
+    let mut rows = statement.query([])?;
+    let columns = rows.as_ref().expect("The query ran").column_names();
+    let mut writer = csv::Writer::from_writer(result);
+    writer.write_record(&columns)?;
+    while let Some(row) = rows.next()? {
+        for i in 0..columns.len() {
+            let value: Option<String> = row.get(i)?;
+            writer.write_field(value.unwrap_or_default())?;
+        }
+        writer.write_record(None::<&[u8]>)?;
+    }
+    writer.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+fn load_series(connection: &Connection, csv: &[u8], series: SeriesTable) -> Result<(), SqlErr> {
+    connection.execute_batch(
+        "CREATE TABLE series (
+            City VARCHAR NOT NULL,
+            Region VARCHAR,
+            Country VARCHAR NOT NULL,
+            Year INTEGER NOT NULL,
+            Month INTEGER NOT NULL,
+            Temperature FLOAT
+        );",
+    )?;
+    let mut appender = connection.appender("series")?;
+    for city in csv::Reader::from_reader(csv).deserialize() {
+        let city: City = city?;
+        let Some((lon, lat)) = city.wikidata_longitude.zip(city.wikidata_latitude) else {
+            continue;
+        };
+        let Ok((longitude, latitude)) = crate::coordinates::lon_lat(lon, lat) else {
+            continue;
+        };
+        let cell_series = series
+            .dataset
+            .temperature_series_at(LonLatCell::at(longitude, latitude));
+        for (time, temperature) in cell_series {
+            if !series.period.contains(time) {
+                continue;
+            }
+            appender.append_row(params![
+                city.city,
+                city.region,
+                city.country,
+                time.year(),
+                time.month(),
+                temperature.map(|temperature| temperature.in_units(series.units)),
+            ])?;
+        }
+    }
+    appender.flush()?;
+    Ok(())
+}
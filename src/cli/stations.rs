@@ -0,0 +1,96 @@
+//! The `stn` variable of CRU TS files: how many stations each cell's value for each month came
+//! from. Months without any are filled in from the 1961-1990 climatology rather than measured,
+//! so a city whose cell often has none has an average that says more about those normals than
+//! about the period.
+
+use crate::calendar::MonthStamp;
+use crate::ondisk::{OnDiskVariable, Strategy};
+use crate::{multifile, ncheader, LonLatCell, Period};
+use netcdf3::FileReader;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// The `stn` variable of every file of a dataset, left in the files since only the cities'
+/// cells are read.
+pub struct StationCounts {
+    /// Each file's time steps and its `stn`.
+    files: Vec<(Vec<MonthStamp>, OnDiskVariable)>,
+    /// Where each cell is stored in the files, which share a grid.
+    index: HashMap<LonLatCell, usize>,
+}
+
+/// A cell's station counts over a period.
+#[derive(Debug, Clone, Copy)]
+pub struct StationSummary {
+    /// Per month with a count.
+    pub mean: f32,
+    /// Of the months with a count, those without a single station.
+    pub zero_fraction: f32,
+}
+
+impl StationCounts {
+    /// The dataset's `path` as given on the command line, see `multifile::dataset_files`.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut files = vec![];
+        let mut index = HashMap::new();
+        for file in multifile::dataset_files(path)? {
+            let header = ncheader::Header::read(&file).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", file.display(), e),
+                )
+            })?;
+            let mut reader = FileReader::open(&file)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            let mut read_var_f32 = |var: &str| {
+                reader
+                    .read_var_f32(var)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+            };
+            let times: Vec<MonthStamp> = read_var_f32("time")?
+                .into_iter()
+                .map(MonthStamp::from_days_since_1900)
+                .collect();
+            if index.is_empty() {
+                let cells = LonLatCell::of_grid(&read_var_f32("lat")?, &read_var_f32("lon")?)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                index = cells
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, cell)| (cell, i))
+                    .collect();
+            }
+            let stn =
+                OnDiskVariable::open(&file, &header, "stn", times.len(), Strategy::Streaming)?;
+            files.push((times, stn));
+        }
+        Ok(Self { files, index })
+    }
+
+    /// None for cells outside the grid or without a count in the period.
+    pub fn summary(&self, cell: LonLatCell, period: Period) -> io::Result<Option<StationSummary>> {
+        let Some(&i) = self.index.get(&cell) else {
+            return Ok(None);
+        };
+        let mut counts = vec![];
+        for (times, stn) in &self.files {
+            counts.extend(
+                times
+                    .iter()
+                    .zip(stn.series(i)?)
+                    // The fill value is negative.
+                    .filter(|&(&time, count)| period.contains(time) && count >= 0.0)
+                    .map(|(_, count)| count),
+            );
+        }
+        if counts.is_empty() {
+            return Ok(None);
+        }
+        let months = counts.len() as f32;
+        Ok(Some(StationSummary {
+            mean: counts.iter().sum::<f32>() / months,
+            zero_fraction: counts.iter().filter(|&&count| count == 0.0).count() as f32 / months,
+        }))
+    }
+}
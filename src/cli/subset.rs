@@ -0,0 +1,255 @@
+//! Cutting a smaller dataset out of a larger one: a bounding box and/or a period,
+//! keeping every variable and attribute, written as classic NetCDF.
+
+use crate::calendar::MonthStamp;
+pub use crate::BoundingBox;
+use crate::Period;
+use itertools::Itertools;
+use netcdf3::{
+    Attribute, DataSet, DataType, DataVector, FileReader, FileWriter, InvalidDataSet, ReadError,
+    Variable, Version, WriteError,
+};
+use std::fmt::{self, Display};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum SubsetErr {
+    Read(ReadError),
+    Write(WriteError),
+    Define(InvalidDataSet),
+    Empty(&'static str),
+}
+
+impl Display for SubsetErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SubsetErr::Read(e) => write!(fmt, "can't read dataset: {}", e),
+            SubsetErr::Write(e) => write!(fmt, "can't write subset: {:?}", e),
+            SubsetErr::Define(e) => write!(fmt, "can't define subset: {}", e),
+            SubsetErr::Empty(dim) => write!(fmt, "nothing left along {}", dim),
+        }
+    }
+}
+
+impl From<ReadError> for SubsetErr {
+    fn from(e: ReadError) -> Self {
+        SubsetErr::Read(e)
+    }
+}
+
+impl From<WriteError> for SubsetErr {
+    fn from(e: WriteError) -> Self {
+        SubsetErr::Write(e)
+    }
+}
+
+impl From<InvalidDataSet> for SubsetErr {
+    fn from(e: InvalidDataSet) -> Self {
+        SubsetErr::Define(e)
+    }
+}
+
+/// `WEST,SOUTH,EAST,NORTH`.
+pub fn parse_bounding_box(s: &str) -> Result<BoundingBox, String> {
+    let parts: Vec<f32> = s
+        .split(',')
+        .map(|part| part.trim().parse::<f32>().map_err(|e| e.to_string()))
+        .collect::<Result<_, _>>()?;
+    let [west, south, east, north] = parts[..] else {
+        return Err("expected WEST,SOUTH,EAST,NORTH".to_string());
+    };
+    if south > north {
+        return Err("south is north of north".to_string());
+    }
+    Ok(BoundingBox {
+        west,
+        south,
+        east,
+        north,
+    })
+}
+
+/// The elements of `data`, laid out along dimensions of `shape`,
+/// at the selected indices along each dimension.
+fn select<T: Copy>(data: &[T], shape: &[usize], selections: &[Vec<usize>]) -> Vec<T> {
+    if shape.is_empty() {
+        return data.to_vec();
+    }
+    let strides: Vec<usize> = (0..shape.len())
+        .map(|dim| shape[dim + 1..].iter().product())
+        .collect();
+    selections
+        .iter()
+        .map(|selection| selection.iter().copied())
+        .multi_cartesian_product()
+        .map(|index| {
+            data[index
+                .iter()
+                .zip(&strides)
+                .map(|(i, s)| i * s)
+                .sum::<usize>()]
+        })
+        .collect()
+}
+
+/// Onto `var`, or globally if `None`.
+fn copy_attribute(
+    data_set: &mut DataSet,
+    var: Option<&str>,
+    attribute: &Attribute,
+) -> Result<(), InvalidDataSet> {
+    let name = attribute.name();
+    match attribute.data_type() {
+        DataType::I8 => {
+            let values = attribute.get_i8().unwrap_or_default().to_vec();
+            match var {
+                Some(var) => data_set.add_var_attr_i8(var, name, values),
+                None => data_set.add_global_attr_i8(name, values),
+            }
+        }
+        DataType::U8 => {
+            let values = attribute.get_u8().unwrap_or_default().to_vec();
+            match var {
+                Some(var) => data_set.add_var_attr_u8(var, name, values),
+                None => data_set.add_global_attr_u8(name, values),
+            }
+        }
+        DataType::I16 => {
+            let values = attribute.get_i16().unwrap_or_default().to_vec();
+            match var {
+                Some(var) => data_set.add_var_attr_i16(var, name, values),
+                None => data_set.add_global_attr_i16(name, values),
+            }
+        }
+        DataType::I32 => {
+            let values = attribute.get_i32().unwrap_or_default().to_vec();
+            match var {
+                Some(var) => data_set.add_var_attr_i32(var, name, values),
+                None => data_set.add_global_attr_i32(name, values),
+            }
+        }
+        DataType::F32 => {
+            let values = attribute.get_f32().unwrap_or_default().to_vec();
+            match var {
+                Some(var) => data_set.add_var_attr_f32(var, name, values),
+                None => data_set.add_global_attr_f32(name, values),
+            }
+        }
+        DataType::F64 => {
+            let values = attribute.get_f64().unwrap_or_default().to_vec();
+            match var {
+                Some(var) => data_set.add_var_attr_f64(var, name, values),
+                None => data_set.add_global_attr_f64(name, values),
+            }
+        }
+    }
+}
+
+/// Write the part of the dataset at `input` within `bounding_box` and `period` to `output`.
+/// Expects the lon, lat and time coordinate variables of the CRU datasets.
+pub fn subset(
+    input: &Path,
+    output: &Path,
+    bounding_box: Option<BoundingBox>,
+    period: Period,
+) -> Result<(), SubsetErr> {
+    subset_where(input, output, bounding_box, |time| period.contains(time))
+}
+
+/// Like `subset`, keeping the time steps in the months `keep_time` picks.
+pub fn subset_where(
+    input: &Path,
+    output: &Path,
+    bounding_box: Option<BoundingBox>,
+    keep_time: impl Fn(MonthStamp) -> bool,
+) -> Result<(), SubsetErr> {
+    let mut reader = FileReader::open(input)?;
+    let source = reader.data_set();
+    let dims: Vec<(String, usize, bool)> = source
+        .get_dims()
+        .iter()
+        .map(|dim| (dim.name(), dim.size(), dim.is_unlimited()))
+        .collect();
+    let global_attrs: Vec<Attribute> = source.get_global_attrs().into_iter().cloned().collect();
+    let vars: Vec<Variable> = source.get_vars().into_iter().cloned().collect();
+
+    let indices_where = |values: Vec<f32>, keep: &dyn Fn(f32) -> bool| -> Vec<usize> {
+        values
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, value)| keep(value))
+            .map(|(i, _)| i)
+            .collect()
+    };
+    let lons = indices_where(reader.read_var_f32("lon")?, &|lon| {
+        bounding_box.is_none_or(|b| b.contains_lon(lon))
+    });
+    let lats = indices_where(reader.read_var_f32("lat")?, &|lat| {
+        bounding_box.is_none_or(|b| b.contains_lat(lat))
+    });
+    let times = indices_where(reader.read_var_f32("time")?, &|time| {
+        keep_time(MonthStamp::from_days_since_1900(time))
+    });
+    let selection_along = |dim: &str, size: usize| -> Vec<usize> {
+        match dim {
+            "lon" => lons.clone(),
+            "lat" => lats.clone(),
+            "time" => times.clone(),
+            _ => (0..size).collect(),
+        }
+    };
+    for (dim, selection) in [("lon", &lons), ("lat", &lats), ("time", &times)] {
+        if selection.is_empty() {
+            return Err(SubsetErr::Empty(dim));
+        }
+    }
+
+    let mut subset = DataSet::new();
+    for (name, size, unlimited) in dims {
+        let size = selection_along(&name, size).len();
+        if unlimited {
+            subset.set_unlimited_dim(name, size)?;
+        } else {
+            subset.add_fixed_dim(name, size)?;
+        }
+    }
+    for attribute in &global_attrs {
+        copy_attribute(&mut subset, None, attribute)?;
+    }
+    for var in &vars {
+        subset.add_var(var.name(), &var.dim_names(), var.data_type())?;
+        for attribute in var.get_attrs() {
+            copy_attribute(&mut subset, Some(var.name()), attribute)?;
+        }
+    }
+
+    let mut writer = FileWriter::open(output)?;
+    writer.set_def(&subset, Version::Classic, 0)?;
+    for var in &vars {
+        let dims = var.get_dims();
+        let shape: Vec<usize> = dims.iter().map(|dim| dim.size()).collect();
+        let selections: Vec<Vec<usize>> = dims
+            .iter()
+            .map(|dim| selection_along(&dim.name(), dim.size()))
+            .collect();
+        let name = var.name();
+        match reader.read_var(name)? {
+            DataVector::I8(data) => writer.write_var_i8(name, &select(&data, &shape, &selections)),
+            DataVector::U8(data) => writer.write_var_u8(name, &select(&data, &shape, &selections)),
+            DataVector::I16(data) => {
+                writer.write_var_i16(name, &select(&data, &shape, &selections))
+            }
+            DataVector::I32(data) => {
+                writer.write_var_i32(name, &select(&data, &shape, &selections))
+            }
+            DataVector::F32(data) => {
+                writer.write_var_f32(name, &select(&data, &shape, &selections))
+            }
+            DataVector::F64(data) => {
+                writer.write_var_f64(name, &select(&data, &shape, &selections))
+            }
+        }?;
+    }
+    writer.close()?;
+    Ok(())
+}
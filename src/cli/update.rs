@@ -0,0 +1,197 @@
+//! Keeping a dataset directory current with interim updates, like the months CRU publishes
+//! between its yearly releases, for `update-dataset`. The directory's `*.nc` files are read as
+//! one stitched dataset, so an update's months after the directory's last one are added as a
+//! file of their own, named after its first and last month. An update with more months than an
+//! earlier one, revising them, replaces that file rather than overlapping it.
+
+use crate::calendar::MonthStamp;
+use crate::cli::init::{self, DownloadErr};
+use crate::cli::subset::{self, SubsetErr};
+use flate2::read::GzDecoder;
+use netcdf3::{FileReader, ReadError};
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// What files added by updates start with, so they can be told from the ones the dataset
+/// started with.
+const PREFIX: &str = "update.";
+/// Where the update is fetched to, which the directory's `*.nc` doesn't match.
+const FETCHED: &str = ".update.nc.part";
+
+#[derive(Debug)]
+pub enum UpdateErr {
+    Download(DownloadErr),
+    Io(io::Error),
+    Read(PathBuf, ReadError),
+    Subset(SubsetErr),
+    /// The update's grid isn't the dataset's, e.g. a different resolution.
+    IncompatibleGrid,
+}
+
+impl Display for UpdateErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            UpdateErr::Download(e) => write!(fmt, "{}", e),
+            UpdateErr::Io(e) => write!(fmt, "{}", e),
+            UpdateErr::Read(path, e) => write!(fmt, "can't read {}: {}", path.display(), e),
+            UpdateErr::Subset(e) => write!(fmt, "{}", e),
+            UpdateErr::IncompatibleGrid => {
+                write!(fmt, "the update's grid is different from the dataset's")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for UpdateErr {
+    fn from(e: io::Error) -> Self {
+        UpdateErr::Io(e)
+    }
+}
+
+/// What an update did to the directory.
+pub enum Update {
+    /// The update had no month the directory doesn't have already.
+    UpToDate(Option<MonthStamp>),
+    Added {
+        path: PathBuf,
+        first: MonthStamp,
+        last: MonthStamp,
+        /// Files of earlier updates it revised.
+        replaced: Vec<PathBuf>,
+    },
+}
+
+/// One of the directory's files: its months, in order, and its grid.
+struct Part {
+    path: PathBuf,
+    times: Vec<MonthStamp>,
+    lats: Vec<f32>,
+    lons: Vec<f32>,
+}
+
+impl Part {
+    fn read(path: &Path) -> Result<Self, UpdateErr> {
+        let read = |e| UpdateErr::Read(path.to_owned(), e);
+        let mut reader = FileReader::open(path).map_err(read)?;
+        Ok(Self {
+            path: path.to_owned(),
+            times: reader
+                .read_var_f32("time")
+                .map_err(read)?
+                .into_iter()
+                .map(MonthStamp::from_days_since_1900)
+                .collect(),
+            lats: reader.read_var_f32("lat").map_err(read)?,
+            lons: reader.read_var_f32("lon").map_err(read)?,
+        })
+    }
+
+    fn is_update(&self) -> bool {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(PREFIX))
+    }
+}
+
+/// The fetched update, removed however the update goes.
+struct Fetched(PathBuf);
+
+impl Drop for Fetched {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Add the months of `source`, a URL or a path to a NetCDF file, gzipped or not, that come
+/// after `directory`'s last.
+pub fn update(directory: &Path, source: &str) -> Result<Update, UpdateErr> {
+    let fetched = Fetched(directory.join(FETCHED));
+    fetch(source, &fetched.0)?;
+    add(directory, &fetched.0)
+}
+
+fn fetch(source: &str, destination: &Path) -> Result<(), UpdateErr> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return init::download_dataset(source, destination).map_err(UpdateErr::Download);
+    }
+    let mut output = BufWriter::new(File::create(destination)?);
+    let input = BufReader::new(File::open(source)?);
+    if source.ends_with(".gz") {
+        io::copy(&mut GzDecoder::new(input), &mut output)?;
+    } else {
+        io::copy(&mut { input }, &mut output)?;
+    }
+    Ok(())
+}
+
+fn add(directory: &Path, fetched: &Path) -> Result<Update, UpdateErr> {
+    let update = Part::read(fetched)?;
+    let (Some(&update_first), Some(&update_last)) = (update.times.first(), update.times.last())
+    else {
+        return Ok(Update::UpToDate(None));
+    };
+    let mut parts = vec![];
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "nc") {
+            parts.push(Part::read(&path)?);
+        }
+    }
+    if parts
+        .iter()
+        .any(|part| (&part.lats, &part.lons) != (&update.lats, &update.lons))
+    {
+        return Err(UpdateErr::IncompatibleGrid);
+    }
+    // Earlier updates whose months this one has all of, revised.
+    let (replaced, kept): (Vec<Part>, Vec<Part>) = parts.into_iter().partition(|part| {
+        part.is_update()
+            && part
+                .times
+                .iter()
+                .all(|time| (update_first..=update_last).contains(time))
+    });
+    let last = kept
+        .iter()
+        .filter_map(|part| part.times.last())
+        .max()
+        .copied();
+    let new: Vec<MonthStamp> = update
+        .times
+        .iter()
+        .copied()
+        .filter(|&time| Some(time) > last)
+        .collect();
+    let (Some(&first), Some(&new_last)) = (new.first(), new.last()) else {
+        return Ok(Update::UpToDate(last));
+    };
+    // The same update again: the directory has its months already, in an earlier update.
+    let existing: BTreeSet<MonthStamp> = replaced
+        .iter()
+        .flat_map(|part| part.times.iter().copied())
+        .collect();
+    if new.iter().all(|time| existing.contains(time)) {
+        return Ok(Update::UpToDate(Some(new_last)));
+    }
+    let path = directory.join(format!("{}{}.{}.nc", PREFIX, first, new_last));
+    subset::subset_where(fetched, &path, None, |time| Some(time) > last)
+        .map_err(UpdateErr::Subset)?;
+    let replaced: Vec<PathBuf> = replaced
+        .into_iter()
+        .map(|part| part.path)
+        .filter(|replaced| *replaced != path)
+        .collect();
+    for replaced in &replaced {
+        fs::remove_file(replaced)?;
+    }
+    Ok(Update::Added {
+        path,
+        first,
+        last: new_last,
+        replaced,
+    })
+}
@@ -0,0 +1,89 @@
+//! `validate`: what the dataset and settings give for a set of reference cities, next to their
+//! published climate normals, to catch a wrong variable, units or period before trusting a run.
+//!
+//! The normals (`normals.csv`) are annual means of the cities' WMO 1991-2020 station normals,
+//! rounded to a tenth of a degree. A station sits at one point and one elevation where a cell
+//! averages over tens of kilometers, so a degree or two of difference for a city in the mountains
+//! or on the coast is expected, the bias over every city much less so.
+
+use crate::coordinates::{Latitude, Longitude};
+use crate::{CitySettings, Temperature, TemperatureDataset, TemperatureDelta};
+use serde::{Deserialize, Serialize};
+
+const NORMALS: &str = include_str!("normals.csv");
+
+/// A reference city, in degrees celsius.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Normal {
+    city: String,
+    country: String,
+    longitude: Longitude,
+    latitude: Latitude,
+    annual_mean: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationReport {
+    /// Reference cities the dataset had a temperature for over the period.
+    pub cities: usize,
+    /// Reference cities left out, in the oceans or missing values over the period.
+    pub skipped: usize,
+    /// The mean of computed minus normal. None when no city could be computed.
+    pub bias: Option<f32>,
+    pub rmse: Option<f32>,
+    pub mean_absolute_error: Option<f32>,
+    pub by_city: Vec<CityValidation>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CityValidation {
+    pub city: String,
+    pub country: String,
+    pub normal: f32,
+    /// None when skipped.
+    pub computed: Option<f32>,
+    /// Computed minus normal.
+    pub difference: Option<f32>,
+}
+
+pub fn validate(dataset: &TemperatureDataset, settings: CitySettings) -> ValidationReport {
+    let units = settings.units;
+    // Compared in celsius, the units of the normals, and only reported in the settings' units.
+    let settings = CitySettings {
+        units: Default::default(),
+        ..settings
+    };
+    let mut by_city = vec![];
+    let mut differences = vec![];
+    for normal in csv::Reader::from_reader(NORMALS.as_bytes()).deserialize() {
+        let normal: Normal = normal.expect("The normals are valid");
+        let computed = settings
+            .temperature_at(dataset, normal.longitude, normal.latitude)
+            .ok()
+            .map(Temperature::new);
+        let difference = computed.map(|computed| computed - Temperature::new(normal.annual_mean));
+        differences.extend(difference.map(TemperatureDelta::celsius));
+        by_city.push(CityValidation {
+            city: normal.city,
+            country: normal.country,
+            normal: Temperature::new(normal.annual_mean).in_units(units),
+            computed: computed.map(|computed| computed.in_units(units)),
+            difference: difference.map(|difference| difference.in_units(units)),
+        });
+    }
+
+    let mean = |values: &mut dyn Iterator<Item = f32>| {
+        let values: Vec<f64> = values.map(f64::from).collect();
+        (!values.is_empty()).then(|| (values.iter().sum::<f64>() / values.len() as f64) as f32)
+    };
+    let in_units = |celsius: f32| TemperatureDelta::from_celsius(celsius).in_units(units);
+    ValidationReport {
+        cities: differences.len(),
+        skipped: by_city.len() - differences.len(),
+        bias: mean(&mut differences.iter().copied()).map(in_units),
+        rmse: mean(&mut differences.iter().map(|d| d * d)).map(|ms| in_units(ms.sqrt())),
+        mean_absolute_error: mean(&mut differences.iter().map(|d| d.abs())).map(in_units),
+        by_city,
+    }
+}
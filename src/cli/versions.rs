@@ -0,0 +1,91 @@
+//! Which CRU TS version a dataset is, from its title, and whether there's a newer one, from
+//! `versions.json` at the root of the repository. New versions add a year and revise earlier
+//! ones as late station data comes in, so an old dataset gives different numbers.
+
+use crate::{multifile, ncheader};
+use serde::Deserialize;
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::time::Duration;
+
+const MANIFEST_URL: &str =
+    "https://raw.githubusercontent.com/christophebiocca/city-average-temp/HEAD/versions.json";
+/// Not worth holding a run up for.
+const MANIFEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// e.g. 4.06.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CruTsVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Display for CruTsVersion {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(fmt, "{}.{:02}", self.major, self.minor)
+    }
+}
+
+impl CruTsVersion {
+    /// From `4.06`.
+    fn parse(s: &str) -> Option<Self> {
+        let (major, minor) = s.split_once('.')?;
+        Some(Self {
+            major: major.parse().ok()?,
+            minor: minor.parse().ok()?,
+        })
+    }
+
+    /// From a title like `CRU TS4.06 Mean Temperature`.
+    fn of_title(title: &str) -> Option<Self> {
+        let rest = title.split_once("CRU TS")?.1.trim_start();
+        let rest = rest.strip_prefix(['v', 'V']).unwrap_or(rest);
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        Self::parse(rest[..end].trim_end_matches('.'))
+    }
+}
+
+/// The version of the dataset's first file. None for other datasets, or remote ones.
+pub fn detect(path: &Path) -> Option<CruTsVersion> {
+    let file = multifile::dataset_files(path).ok()?.into_iter().next()?;
+    let header = ncheader::Header::read(&file).ok()?;
+    CruTsVersion::of_title(&header.data_set.get_global_attr_as_string("title")?)
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(rename = "cru-ts")]
+    cru_ts: Release,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    latest: String,
+    url: String,
+}
+
+/// A hint to upgrade when a newer version than `version` is out. None when there isn't one,
+/// or when the manifest can't be had, which is never worth failing a run over.
+pub fn upgrade_hint(version: CruTsVersion) -> Option<String> {
+    let manifest: Manifest = reqwest::blocking::Client::builder()
+        .timeout(MANIFEST_TIMEOUT)
+        .build()
+        .ok()?
+        .get(MANIFEST_URL)
+        .header("User-Agent", "Christophe's geolocator helper script.")
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .ok()?
+        .json()
+        .ok()?;
+    let latest = CruTsVersion::parse(&manifest.cru_ts.latest)?;
+    (latest > version).then(|| {
+        format!(
+            "The temperature dataset is CRU TS {}, but {} is out, with another year and revised \
+             earlier ones: {}",
+            version, latest, manifest.cru_ts.url
+        )
+    })
+}
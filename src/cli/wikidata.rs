@@ -0,0 +1,347 @@
+use crate::cli::hooks::{Choice, CityName, Hooks};
+use crate::CancellationToken;
+use serde::de::DeserializeOwned;
+use serde::{self, Deserialize};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum GeocodeErr {
+    Network(reqwest::Error),
+    /// Ctrl-C while asking which search result is the right one, the hooks gave up, or the token
+    /// was cancelled.
+    Interrupted,
+}
+
+impl Display for GeocodeErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            GeocodeErr::Network(e) => write!(fmt, "{}", e),
+            GeocodeErr::Interrupted => write!(fmt, "interrupted"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for GeocodeErr {
+    fn from(e: reqwest::Error) -> Self {
+        GeocodeErr::Network(e)
+    }
+}
+
+/// The one candidate whose description mentions the region, if there's exactly one.
+pub fn only_match_in_region<'a>(
+    region: Option<&str>,
+    descriptions: impl IntoIterator<Item = &'a str>,
+) -> Option<usize> {
+    let region = region?.to_lowercase();
+    let mut matches = descriptions
+        .into_iter()
+        .enumerate()
+        .filter(|(_, description)| description.to_lowercase().contains(&region));
+    match (matches.next(), matches.next()) {
+        (Some((i, _)), None) => Some(i),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchResult {
+    pub id: String,
+    pub label: String,
+    pub description: Option<String>,
+}
+
+/// Entities whose label matches `search_string`, best first.
+pub fn search(search_string: &str) -> Result<Vec<SearchResult>, reqwest::Error> {
+    #[derive(Deserialize, Debug)]
+    struct SearchResponse {
+        search: Vec<SearchResult>,
+    }
+
+    let resp: SearchResponse = reqwest::blocking::Client::new()
+        .get("https://www.wikidata.org/w/api.php?")
+        .header("Accept", "application/json")
+        .header("User-Agent", "Christophe's geolocator helper script.")
+        .query(&[
+            ("action", "wbsearchentities"),
+            ("search", search_string),
+            ("type", "item"),
+            ("format", "json"),
+            ("language", "en"),
+        ])
+        .send()?
+        .json()?;
+    Ok(resp.search)
+}
+
+/// Asks `hooks` which search result is the right one, unless `region` makes it obvious. Gives up
+/// before searching again once `cancel` is cancelled.
+pub fn find_entity_id(
+    city: &str,
+    region: Option<&str>,
+    country: &str,
+    hooks: &mut dyn Hooks,
+    cancel: &CancellationToken,
+) -> Result<String, GeocodeErr> {
+    let mut search_string = city.to_string();
+
+    loop {
+        if cancel.is_cancelled() {
+            return Err(GeocodeErr::Interrupted);
+        }
+        let mut results = search(&search_string)?;
+
+        if let Some(choice) = only_match_in_region(
+            region,
+            results
+                .iter()
+                .map(|result| result.description.as_deref().unwrap_or_default()),
+        ) {
+            return Ok(results.remove(choice).id);
+        }
+
+        let options: Vec<String> = results
+            .iter()
+            .map(|result| {
+                format!(
+                    "{}: {}",
+                    result.label,
+                    result
+                        .description
+                        .as_ref()
+                        .map(|s| &s[..])
+                        .unwrap_or("No Description")
+                )
+            })
+            .collect();
+        let name = CityName {
+            city,
+            region,
+            country,
+        };
+        match hooks.on_geocode_ambiguous(name, &format!("{} {}", city, country), &options) {
+            Some(Choice::Candidate(choice)) if choice < results.len() => {
+                return Ok(results.remove(choice).id)
+            }
+            Some(Choice::Candidate(_)) => {}
+            Some(Choice::Search(search)) => search_string = search,
+            None => return Err(GeocodeErr::Interrupted),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SparqlResponse<T> {
+    results: SparqlResults<T>,
+}
+
+#[derive(Deserialize)]
+struct SparqlResults<T> {
+    bindings: Vec<T>,
+}
+
+/// A single bound variable in a SPARQL result row.
+/// Wikidata hands everything back as strings, so we parse them here.
+#[derive(Deserialize)]
+struct SparqlValue<T: FromStr>
+where
+    T::Err: std::fmt::Display,
+{
+    #[serde(deserialize_with = "parse_value")]
+    value: T,
+}
+
+fn parse_value<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_str(&s).map_err(serde::de::Error::custom)
+}
+
+fn sparql<T: DeserializeOwned>(query: &str) -> Result<Vec<T>, reqwest::Error> {
+    let client = reqwest::blocking::Client::new();
+
+    let resp: SparqlResponse<T> = client
+        .get("https://query.wikidata.org/sparql")
+        .header("Accept", "application/sparql-results+json")
+        .header("User-Agent", "Christophe's geolocator helper script.")
+        .query(&[("query", query.trim())])
+        .send()?
+        .json()?;
+
+    Ok(resp.results.bindings)
+}
+
+pub fn acquire_lon_lat(wikidata_entity_id: &str) -> Result<(f32, f32), reqwest::Error> {
+    #[derive(Deserialize)]
+    struct Entry {
+        lon: SparqlValue<f32>,
+        lat: SparqlValue<f32>,
+    }
+
+    let query = format!(
+        "SELECT ?lon ?lat WHERE {{ \
+              wd:{} p:P625 [
+                psv:P625 [
+                  wikibase:geoLongitude ?lon;
+                  wikibase:geoLatitude  ?lat;
+                ]
+              ].
+          }}",
+        wikidata_entity_id
+    );
+
+    let bindings: Vec<Entry> = sparql(&query)?;
+    assert!(!bindings.is_empty());
+    let entry = &bindings[0];
+    Ok((entry.lon.value, entry.lat.value))
+}
+
+/// The entity's English "also known as" labels, e.g. Bombay for Mumbai.
+pub fn acquire_aliases(wikidata_entity_id: &str) -> Result<Vec<String>, reqwest::Error> {
+    #[derive(Deserialize)]
+    struct Entry {
+        alias: SparqlValue<String>,
+    }
+
+    let query = format!(
+        "SELECT ?alias WHERE {{ wd:{} skos:altLabel ?alias. FILTER(LANG(?alias) = \"en\") }}",
+        wikidata_entity_id
+    );
+
+    let bindings: Vec<Entry> = sparql(&query)?;
+    Ok(bindings
+        .into_iter()
+        .map(|entry| entry.alias.value)
+        .collect())
+}
+
+/// The IANA name (e.g. Europe/Madrid) of the entity's time zone, if Wikidata has one.
+pub fn acquire_timezone(wikidata_entity_id: &str) -> Result<Option<String>, reqwest::Error> {
+    #[derive(Deserialize)]
+    struct Entry {
+        iana: SparqlValue<String>,
+    }
+
+    // Time zones without an IANA id are often UTC offsets, which don't know about daylight saving.
+    let query = format!(
+        "SELECT ?iana WHERE {{ wd:{} wdt:P421 ?timezone. ?timezone wdt:P6687 ?iana. }}",
+        wikidata_entity_id
+    );
+
+    let bindings: Vec<Entry> = sparql(&query)?;
+    Ok(bindings.into_iter().next().map(|entry| entry.iana.value))
+}
+
+#[derive(Debug)]
+pub struct ListedCity {
+    pub entity_id: String,
+    pub name: String,
+    pub country: String,
+    pub population: Option<u64>,
+    pub lon: f32,
+    pub lat: f32,
+}
+
+/// Run a query binding ?city, ?cityLabel, ?countryLabel, ?lon, ?lat and optionally ?population.
+fn query_cities(query: &str) -> Result<Vec<ListedCity>, reqwest::Error> {
+    #[derive(Deserialize)]
+    struct Entry {
+        city: SparqlValue<String>,
+        #[serde(rename = "cityLabel")]
+        city_label: SparqlValue<String>,
+        #[serde(rename = "countryLabel")]
+        country_label: SparqlValue<String>,
+        population: Option<SparqlValue<f64>>,
+        lon: SparqlValue<f32>,
+        lat: SparqlValue<f32>,
+    }
+
+    let bindings: Vec<Entry> = sparql(query)?;
+    Ok(bindings
+        .into_iter()
+        .map(|entry| ListedCity {
+            entity_id: entry
+                .city
+                .value
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            name: entry.city_label.value,
+            country: entry.country_label.value,
+            population: entry.population.map(|p| p.value as u64),
+            lon: entry.lon.value,
+            lat: entry.lat.value,
+        })
+        .collect())
+}
+
+/// All cities (instances of any subclass of city) with at least `min_population` inhabitants,
+/// optionally restricted to countries with the given English names.
+pub fn list_cities(
+    min_population: u64,
+    countries: &[String],
+) -> Result<Vec<ListedCity>, reqwest::Error> {
+    let country_filter = if countries.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "VALUES ?countryName {{ {} }} ?country rdfs:label ?countryName.",
+            countries
+                .iter()
+                .map(|c| format!("\"{}\"@en", c.replace('\\', "\\\\").replace('"', "\\\"")))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    };
+    // Cities have several population figures over the years, take the largest,
+    // and any one of their coordinates.
+    let query = format!(
+        "SELECT ?city ?cityLabel ?countryLabel (MAX(?pop) AS ?population) \
+                (SAMPLE(?lo) AS ?lon) (SAMPLE(?la) AS ?lat) WHERE {{
+              {}
+              ?city wdt:P31/wdt:P279* wd:Q515;
+                    wdt:P17 ?country;
+                    wdt:P1082 ?pop;
+                    p:P625 [
+                      psv:P625 [
+                        wikibase:geoLongitude ?lo;
+                        wikibase:geoLatitude  ?la;
+                      ]
+                    ].
+              FILTER(?pop >= {})
+              SERVICE wikibase:label {{ bd:serviceParam wikibase:language \"en\". }}
+          }}
+          GROUP BY ?city ?cityLabel ?countryLabel",
+        country_filter, min_population
+    );
+
+    query_cities(&query)
+}
+
+/// The current capital of every sovereign state that still exists.
+pub fn list_capitals() -> Result<Vec<ListedCity>, reqwest::Error> {
+    let query = "SELECT ?city ?cityLabel ?countryLabel (MAX(?pop) AS ?population) \
+                        (SAMPLE(?lo) AS ?lon) (SAMPLE(?la) AS ?lat) WHERE {
+              ?country wdt:P31 wd:Q3624078;
+                       p:P36 ?capital.
+              ?capital ps:P36 ?city.
+              FILTER NOT EXISTS { ?capital pq:P582 ?end. }
+              FILTER NOT EXISTS { ?country wdt:P576 ?dissolved. }
+              ?city p:P625 [
+                      psv:P625 [
+                        wikibase:geoLongitude ?lo;
+                        wikibase:geoLatitude  ?la;
+                      ]
+                    ].
+              OPTIONAL { ?city wdt:P1082 ?pop. }
+              SERVICE wikibase:label { bd:serviceParam wikibase:language \"en\". }
+          }
+          GROUP BY ?city ?cityLabel ?countryLabel";
+
+    query_cities(query)
+}
@@ -0,0 +1,46 @@
+//! Products that are already long-term monthly means, like CRU CL or WorldClim: twelve time
+//! steps, one per calendar month, whose time coordinates are month numbers or days into some
+//! year rather than a date. Their time steps are read as the months of `YEAR`, so everything
+//! that goes by month works on them as is, and what picks years, trends or anomalies out of a
+//! series is refused instead of quietly finding nothing.
+
+use crate::calendar::MonthStamp;
+
+/// The year a climatology's months are read as, which no time series goes back to.
+pub const YEAR: i32 = 1;
+
+/// Whether a dataset is a series of actual months or one of each calendar month.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetKind {
+    TimeSeries,
+    Climatology,
+}
+
+/// Whether time coordinates, in days since 1900 for a time series, are a climatology's: twelve
+/// of them, all within a year's worth of days, which no time series starting in 1900 or later
+/// has.
+pub fn is_climatology(times: &[f32]) -> bool {
+    times.len() == 12 && times.iter().all(|&time| (0.0..366.0).contains(&time))
+}
+
+/// Replaces a climatology's time coordinates, in the order the file has them, with the middle
+/// of the months of `YEAR`, and leaves anything else alone.
+pub fn to_nominal_year(times: &mut [f32]) {
+    if !is_climatology(times) {
+        return;
+    }
+    for (month, time) in (1..=12).zip(times.iter_mut()) {
+        *time = MonthStamp::new(YEAR, month)
+            .expect("Every month of the nominal year is valid")
+            .days_since_1900()
+            + 14.0;
+    }
+}
+
+/// The kind of a dataset whose first time step is `first`.
+pub fn kind_of(first: Option<MonthStamp>) -> DatasetKind {
+    match first {
+        Some(month) if month.year() == YEAR => DatasetKind::Climatology,
+        _ => DatasetKind::TimeSeries,
+    }
+}
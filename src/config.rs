@@ -0,0 +1,94 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Geocoder {
+    /// Search Wikidata entities by name.
+    #[default]
+    Wikidata,
+    /// Search OpenStreetMap's Nominatim, keeping the Wikidata link when it has one.
+    Nominatim,
+}
+
+/// Defaults for the command line, so they don't have to be repeated on every run.
+/// Anything given on the command line wins over the config file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    pub temperature_dataset: Option<PathBuf>,
+    /// What the dataset's SHA-256 should be, e.g. as recorded when `init` downloaded it.
+    pub temperature_dataset_sha256: Option<String>,
+    pub start_year: Option<i32>,
+    pub end_year: Option<i32>,
+    pub units: Option<Units>,
+    pub geocoder: Option<Geocoder>,
+}
+
+#[derive(Debug)]
+pub enum ConfigErr {
+    Read(io::Error),
+    Parse(toml::de::Error),
+    Write(io::Error),
+}
+
+impl Display for ConfigErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ConfigErr::Read(e) => write!(fmt, "can't read config file: {}", e),
+            ConfigErr::Parse(e) => write!(fmt, "can't parse config file: {}", e),
+            ConfigErr::Write(e) => write!(fmt, "can't write config file: {}", e),
+        }
+    }
+}
+
+/// The platform's directories for the tool: `$XDG_CONFIG_HOME/temperature-extractor` or
+/// `~/.config/temperature-extractor` on Linux, `%APPDATA%\temperature-extractor\config` on
+/// Windows, `~/Library/Application Support/temperature-extractor` on macOS, and the like for
+/// caches. None without a home directory.
+#[cfg(not(target_arch = "wasm32"))]
+fn project_dirs() -> Option<directories::ProjectDirs> {
+    directories::ProjectDirs::from("", "", "temperature-extractor")
+}
+
+/// Where to keep whatever is only there to save time, see `project_dirs`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn cache_dir() -> Option<PathBuf> {
+    Some(project_dirs()?.cache_dir().to_path_buf())
+}
+
+impl Config {
+    /// `config.toml` in the platform's config directory, see `project_dirs`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn default_path() -> Option<PathBuf> {
+        Some(project_dirs()?.config_dir().join("config.toml"))
+    }
+
+    /// A missing config file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Self, ConfigErr> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).map_err(ConfigErr::Parse),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigErr::Read(e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigErr> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(ConfigErr::Write)?;
+        }
+        let contents = toml::to_string(self).expect("Config is always serializable");
+        fs::write(path, contents).map_err(ConfigErr::Write)
+    }
+}
@@ -0,0 +1,118 @@
+//! Longitudes and latitudes that are known to be on the globe, so a swapped pair or a
+//! projected coordinate is refused where it comes in instead of quietly finding no cell.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// Degrees east, from -180 to 180.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "f32", into = "f32")]
+pub struct Longitude(f32);
+
+/// Degrees north, from -90 to 90.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "f32", into = "f32")]
+pub struct Latitude(f32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordinateErr {
+    Longitude(f32),
+    Latitude(f32),
+}
+
+impl Display for CoordinateErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            CoordinateErr::Longitude(degrees) => {
+                write!(fmt, "longitude {} isn't between -180 and 180", degrees)
+            }
+            CoordinateErr::Latitude(degrees) => {
+                write!(fmt, "latitude {} isn't between -90 and 90", degrees)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CoordinateErr {}
+
+impl Longitude {
+    pub fn new(degrees: f32) -> Result<Self, CoordinateErr> {
+        if (-180.0..=180.0).contains(&degrees) {
+            Ok(Self(degrees))
+        } else {
+            Err(CoordinateErr::Longitude(degrees))
+        }
+    }
+
+    /// Any finite longitude, brought into -180 to 180, e.g. 270 to -90 for grids going from 0 to 360.
+    pub fn normalized(degrees: f32) -> Result<Self, CoordinateErr> {
+        if degrees.is_finite() {
+            Self::new((degrees + 180.0).rem_euclid(360.0) - 180.0)
+        } else {
+            Err(CoordinateErr::Longitude(degrees))
+        }
+    }
+
+    pub fn degrees(self) -> f32 {
+        self.0
+    }
+}
+
+impl Latitude {
+    pub fn new(degrees: f32) -> Result<Self, CoordinateErr> {
+        if (-90.0..=90.0).contains(&degrees) {
+            Ok(Self(degrees))
+        } else {
+            Err(CoordinateErr::Latitude(degrees))
+        }
+    }
+
+    pub fn degrees(self) -> f32 {
+        self.0
+    }
+}
+
+/// Both at once, for the many places that get them as a pair.
+pub fn lon_lat(lon: f32, lat: f32) -> Result<(Longitude, Latitude), CoordinateErr> {
+    Ok((Longitude::new(lon)?, Latitude::new(lat)?))
+}
+
+impl TryFrom<f32> for Longitude {
+    type Error = CoordinateErr;
+
+    fn try_from(degrees: f32) -> Result<Self, Self::Error> {
+        Self::new(degrees)
+    }
+}
+
+impl TryFrom<f32> for Latitude {
+    type Error = CoordinateErr;
+
+    fn try_from(degrees: f32) -> Result<Self, Self::Error> {
+        Self::new(degrees)
+    }
+}
+
+impl From<Longitude> for f32 {
+    fn from(longitude: Longitude) -> Self {
+        longitude.0
+    }
+}
+
+impl From<Latitude> for f32 {
+    fn from(latitude: Latitude) -> Self {
+        latitude.0
+    }
+}
+
+impl Display for Longitude {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.0.fmt(fmt)
+    }
+}
+
+impl Display for Latitude {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.0.fmt(fmt)
+    }
+}
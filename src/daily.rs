@@ -0,0 +1,56 @@
+//! Daily datasets (e.g. ERA5 or Berkeley Earth daily, converted to the same variables as
+//! CRU TS), read as the monthly means of their days so everything else works on them unchanged.
+//!
+//! A month only gets a mean when enough of its days have a value, see `Coverage`, by default
+//! every one including February 29th in leap years. Files starting or ending mid-month leave
+//! that month short of days, so datasets split over several files should be split at month
+//! boundaries.
+
+use crate::calendar::MonthStamp;
+use crate::resample::Coverage;
+use crate::{DatasetFile, ObservationColumns, Temperature};
+
+/// Whether time steps, in days, are a day apart rather than a month.
+pub fn is_daily(times: &[f32]) -> bool {
+    let mut gaps: Vec<f32> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    if gaps.is_empty() {
+        return false;
+    }
+    gaps.sort_unstable_by(f32::total_cmp);
+    gaps[gaps.len() / 2] < 2.0
+}
+
+/// The file with each month's days replaced by their mean, see the module documentation.
+/// The observations are time step by time step, every cell in the same order within each.
+pub fn monthly_means(daily: DatasetFile, coverage: Coverage) -> DatasetFile {
+    let cells_per_step = daily.lats.len() * daily.lons.len();
+    let mut months: Vec<MonthStamp> = vec![];
+    let mut observations = ObservationColumns::default();
+    let mut start = 0;
+    while start < daily.times.len() {
+        let month = daily.times[start];
+        let days = daily.times[start..]
+            .iter()
+            .take_while(|&&time| time == month)
+            .count();
+        let cells = &daily.observations.cells[start * cells_per_step..][..cells_per_step];
+        observations.extend(cells.iter().enumerate().map(|(j, &cell)| {
+            let mean = coverage.monthly_mean(
+                month,
+                (start..start + days).map(|step| {
+                    let temperature = daily.observations.temperature(step * cells_per_step + j);
+                    temperature.map(|temperature| temperature.celsius)
+                }),
+            );
+            (cell, month, mean.map(Temperature::new))
+        }));
+        months.push(month);
+        start += days;
+    }
+    DatasetFile {
+        times: months,
+        observations,
+        daily: false,
+        ..daily
+    }
+}
@@ -0,0 +1,146 @@
+//! C bindings, so other languages (e.g. Python through ctypes, or R through .C) can load
+//! a dataset once and look temperatures up without going through the command line.
+//! See include/temperature_extractor.h for the declarations.
+
+use crate::{coordinates, CitySettings, Period, TemperatureDataset};
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+/// A loaded temperature dataset, opaque to C.
+pub struct Dataset(TemperatureDataset);
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(error: impl ToString) {
+    let error = CString::new(error.to_string().replace('\0', " ")).expect("No NUL left");
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+}
+
+/// Runs `body`, returning `failed` instead if it panics, with the panic as the last error, since
+/// a panic unwinding into C aborts the host.
+fn catching<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        set_last_error(format!("internal error: {}", panic_message(&*payload)));
+        failed
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("panicked")
+}
+
+/// Why the last call on this thread failed. Valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn temperature_extractor_last_error() -> *const c_char {
+    catching(ptr::null(), || {
+        LAST_ERROR.with(|last| last.borrow().as_ptr())
+    })
+}
+
+/// Loads the dataset at `path`, which can stand for several files like on the command line.
+/// Null on failure, see `temperature_extractor_last_error`.
+///
+/// # Safety
+/// `path` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn temperature_dataset_open(path: *const c_char) -> *mut Dataset {
+    catching(ptr::null_mut(), || open(path))
+}
+
+unsafe fn open(path: *const c_char) -> *mut Dataset {
+    if path.is_null() {
+        set_last_error("no path given");
+        return ptr::null_mut();
+    }
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        set_last_error("path isn't valid UTF-8");
+        return ptr::null_mut();
+    };
+    match TemperatureDataset::new(Path::new(path)) {
+        Ok(dataset) => Box::into_raw(Box::new(Dataset(dataset))),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `dataset` must come from `temperature_dataset_open`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn temperature_dataset_free(dataset: *mut Dataset) {
+    catching((), || {
+        if !dataset.is_null() {
+            drop(Box::from_raw(dataset));
+        }
+    })
+}
+
+/// Writes the average temperature in celsius of the cell containing `lon`, `lat` to `average`,
+/// from `start_year` up to and including `end_year`, either 0 for no limit.
+/// Returns 0 on success, and -1 on failure, see `temperature_extractor_last_error`,
+/// which includes coordinates off the globe.
+///
+/// # Safety
+/// `dataset` must come from `temperature_dataset_open`, and `average` point to a float.
+#[no_mangle]
+pub unsafe extern "C" fn temperature_dataset_average(
+    dataset: *const Dataset,
+    lon: f32,
+    lat: f32,
+    start_year: c_int,
+    end_year: c_int,
+    average: *mut f32,
+) -> c_int {
+    catching(-1, || {
+        self::average(dataset, lon, lat, start_year, end_year, average)
+    })
+}
+
+unsafe fn average(
+    dataset: *const Dataset,
+    lon: f32,
+    lat: f32,
+    start_year: c_int,
+    end_year: c_int,
+    average: *mut f32,
+) -> c_int {
+    let (Some(Dataset(dataset)), false) = (dataset.as_ref(), average.is_null()) else {
+        set_last_error("null dataset or result");
+        return -1;
+    };
+    let settings = CitySettings {
+        period: Period {
+            first_year: (start_year != 0).then_some(start_year),
+            last_year: (end_year != 0).then_some(end_year),
+        },
+        ..Default::default()
+    };
+    let (lon, lat) = match coordinates::lon_lat(lon, lat) {
+        Ok(lon_lat) => lon_lat,
+        Err(e) => {
+            set_last_error(e);
+            return -1;
+        }
+    };
+    match settings.temperature_at(dataset, lon, lat) {
+        Ok(temperature) => {
+            *average = temperature;
+            0
+        }
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
@@ -0,0 +1,3 @@
+//! Geometry on the globe, in degrees of longitude and latitude.
+
+pub mod distance;
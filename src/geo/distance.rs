@@ -0,0 +1,57 @@
+//! Distances over the earth's surface, taken as a sphere. Every distance in km goes through
+//! here rather than through degrees, since a degree of longitude shrinks towards the poles.
+
+/// The mean radius.
+pub const EARTH_RADIUS_KM: f32 = 6371.0;
+
+/// Length of a degree of latitude, or of longitude at the equator.
+pub const KM_PER_DEGREE: f32 = EARTH_RADIUS_KM * std::f32::consts::PI / 180.0;
+
+/// Great circle distance between two (longitude, latitude) points,
+/// see https://en.wikipedia.org/wiki/Haversine_formula
+pub fn haversine_km((lon1, lat1): (f32, f32), (lon2, lat2): (f32, f32)) -> f32 {
+    let half_chord = ((lat2 - lat1).to_radians() / 2.0).sin().powi(2)
+        + lat1.to_radians().cos()
+            * lat2.to_radians().cos()
+            * ((lon2 - lon1).to_radians() / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * half_chord.sqrt().min(1.0).asin()
+}
+
+/// A (longitude, latitude) point as a unit vector from the earth's center.
+fn unit_vector((lon, lat): (f32, f32)) -> [f64; 3] {
+    let (lon, lat) = (f64::from(lon).to_radians(), f64::from(lat).to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Great circle distance from a point to the shorter arc between two others: the cross-track
+/// distance if the point is abeam of the arc, otherwise the distance to its nearer end,
+/// see https://www.movable-type.co.uk/scripts/latlong.html#cross-track
+pub fn segment_km(point: (f32, f32), start: (f32, f32), end: (f32, f32)) -> f32 {
+    let (p, a, b) = (unit_vector(point), unit_vector(start), unit_vector(end));
+    let normal = cross(a, b);
+    let length = dot(normal, normal).sqrt();
+    let to_ends = || haversine_km(point, start).min(haversine_km(point, end));
+    if length < 1e-12 {
+        return to_ends();
+    }
+    let normal = normal.map(|x| x / length);
+    let sine = dot(p, normal);
+    // The point's foot on the arc's great circle, which the arc holds if it's between the ends.
+    let foot = [0, 1, 2].map(|i| p[i] - sine * normal[i]);
+    if dot(cross(a, foot), normal) < 0.0 || dot(cross(foot, b), normal) < 0.0 {
+        return to_ends();
+    }
+    (f64::from(EARTH_RADIUS_KM) * sine.clamp(-1.0, 1.0).asin().abs()) as f32
+}
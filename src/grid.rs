@@ -0,0 +1,54 @@
+use crate::LonLatCell;
+
+/// A derived value per half degree cell, over the bounding box of the cells it was built from.
+/// Stored row by row, from the southernmost row and westernmost column.
+#[derive(Debug)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    first_cell: LonLatCell,
+    pub values: Vec<Option<f32>>,
+}
+
+impl Grid {
+    pub fn from_cells(cells: impl IntoIterator<Item = (LonLatCell, Option<f32>)>) -> Self {
+        let cells: Vec<_> = cells.into_iter().collect();
+        let lons = cells.iter().map(|(c, _)| c.half_degrees_lon_start);
+        let lats = cells.iter().map(|(c, _)| c.half_degrees_lat_start);
+        let (min_lon, max_lon) = (lons.clone().min().unwrap_or(0), lons.max().unwrap_or(-1));
+        let (min_lat, max_lat) = (lats.clone().min().unwrap_or(0), lats.max().unwrap_or(-1));
+        let mut grid = Self {
+            width: (max_lon - min_lon + 1) as usize,
+            height: (max_lat - min_lat + 1) as usize,
+            first_cell: LonLatCell {
+                half_degrees_lon_start: min_lon,
+                half_degrees_lat_start: min_lat,
+            },
+            values: vec![],
+        };
+        grid.values = vec![None; grid.width * grid.height];
+        for (cell, value) in cells {
+            let (x, y) = grid.position(cell);
+            grid.values[y * grid.width + x] = value;
+        }
+        grid
+    }
+
+    fn position(&self, cell: LonLatCell) -> (usize, usize) {
+        (
+            (cell.half_degrees_lon_start - self.first_cell.half_degrees_lon_start) as usize,
+            (cell.half_degrees_lat_start - self.first_cell.half_degrees_lat_start) as usize,
+        )
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> LonLatCell {
+        LonLatCell {
+            half_degrees_lon_start: self.first_cell.half_degrees_lon_start + x as i16,
+            half_degrees_lat_start: self.first_cell.half_degrees_lat_start + y as i16,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<f32> {
+        self.values[y * self.width + x]
+    }
+}
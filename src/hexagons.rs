@@ -0,0 +1,133 @@
+//! Aggregating the half degree grid into H3 hexagons, see https://h3geo.org/
+//! A grid cell belongs to the hexagon its center falls in.
+
+use crate::config::Units;
+use crate::stats::Statistic;
+use crate::{AreaWeightedMean, AverageErr, LonLatCell, Period, Temperature, TemperatureDataset};
+use h3o::{CellIndex, LatLng, Resolution};
+use std::collections::BTreeMap;
+
+pub fn parse_resolution(s: &str) -> Result<u8, String> {
+    let resolution: u8 = s.parse().map_err(|e| format!("{}", e))?;
+    Resolution::try_from(resolution).map_err(|e| format!("{}", e))?;
+    Ok(resolution)
+}
+
+fn resolution(resolution: u8) -> Resolution {
+    Resolution::try_from(resolution).expect("Resolution was validated when parsed")
+}
+
+fn hexagon_of(lon: f32, lat: f32, resolution: Resolution) -> CellIndex {
+    LatLng::new(lat as f64, lon as f64)
+        .expect("Coordinates are finite")
+        .to_cell(resolution)
+}
+
+pub fn hexagon_at(lon: f32, lat: f32, h3_resolution: u8) -> CellIndex {
+    hexagon_of(lon, lat, resolution(h3_resolution))
+}
+
+/// The grid cells belonging to `hexagon`. Hexagons too small to hold any cell center
+/// get the cell their own center falls in instead.
+pub fn cells_in(hexagon: CellIndex) -> Vec<LonLatCell> {
+    let center = LatLng::from(hexagon);
+    let (center_lon, center_lat) = (center.lng() as f32, center.lat() as f32);
+    // Longitudes relative to the center, so hexagons across the antimeridian stay in one piece.
+    let boundary = hexagon.boundary();
+    let lon_offsets = boundary
+        .iter()
+        .map(|vertex| (vertex.lng() as f32 - center_lon + 540.0).rem_euclid(360.0) - 180.0);
+    let lats = boundary.iter().map(|vertex| vertex.lat() as f32);
+    let west = LonLatCell::containing(center_lon + lon_offsets.clone().fold(0.0, f32::min), 0.0);
+    let east = LonLatCell::containing(center_lon + lon_offsets.fold(0.0, f32::max), 0.0);
+    let south = LonLatCell::containing(0.0, lats.clone().fold(center_lat, f32::min).max(-90.0));
+    let north = LonLatCell::containing(0.0, lats.fold(center_lat, f32::max).min(89.99));
+
+    let resolution = hexagon.resolution();
+    let mut cells = vec![];
+    for half_degrees_lat_start in south.half_degrees_lat_start..=north.half_degrees_lat_start {
+        for half_degrees_lon_start in west.half_degrees_lon_start..=east.half_degrees_lon_start {
+            let cell = LonLatCell {
+                half_degrees_lon_start: (half_degrees_lon_start + 360).rem_euclid(720) - 360,
+                half_degrees_lat_start,
+            };
+            let (lon, lat) = cell.center();
+            if hexagon_of(lon, lat, resolution) == hexagon {
+                cells.push(cell);
+            }
+        }
+    }
+    if cells.is_empty() {
+        cells.push(LonLatCell::containing(center_lon, center_lat));
+    }
+    cells
+}
+
+/// The area weighted average temperature over the cells of the hexagon containing a point.
+/// Cells without data for the whole period (e.g. the oceans) are left out,
+/// unless none of them have any.
+pub fn average_temperature_in_hexagon(
+    dataset: &TemperatureDataset,
+    lon: f32,
+    lat: f32,
+    h3_resolution: u8,
+    period: Period,
+    statistic: Statistic,
+) -> Result<Temperature, AverageErr> {
+    let mut first_err = None;
+    let mean: AreaWeightedMean = cells_in(hexagon_at(lon, lat, h3_resolution))
+        .into_iter()
+        .filter_map(
+            |cell| match dataset.average_temperature_at(cell, period, statistic) {
+                Ok(average) => Some((cell, average.celsius as f64)),
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                    None
+                }
+            },
+        )
+        .collect();
+    match mean.mean() {
+        Some(mean) => Ok(Temperature::new(mean as f32)),
+        None => Err(first_err.unwrap_or(AverageErr::NoDatapoints)),
+    }
+}
+
+pub struct HexagonAverage {
+    pub hexagon: CellIndex,
+    pub average: f32,
+    pub cells: usize,
+}
+
+/// The area weighted average temperature of every hexagon holding at least one cell with data
+/// for the whole period.
+pub fn hexagon_averages(
+    dataset: &TemperatureDataset,
+    period: Period,
+    units: Units,
+    h3_resolution: u8,
+) -> Vec<HexagonAverage> {
+    let resolution = resolution(h3_resolution);
+    let grid = dataset.average_grid(period, units);
+    let mut hexagons: BTreeMap<CellIndex, AreaWeightedMean> = BTreeMap::new();
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            if let Some(average) = grid.get(x, y) {
+                let cell = grid.cell(x, y);
+                let (lon, lat) = cell.center();
+                hexagons
+                    .entry(hexagon_of(lon, lat, resolution))
+                    .or_default()
+                    .add(cell, average as f64);
+            }
+        }
+    }
+    hexagons
+        .into_iter()
+        .map(|(hexagon, mean)| HexagonAverage {
+            hexagon,
+            average: mean.mean().expect("Hexagons are only added with a cell") as f32,
+            cells: mean.cells,
+        })
+        .collect()
+}
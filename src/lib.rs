@@ -0,0 +1,1585 @@
+// Reading datasets from files is only for the command line and the C bindings.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code))]
+
+use calendar::MonthStamp;
+use climatology::DatasetKind;
+use coordinates::{CoordinateErr, Latitude, Longitude};
+use itertools::{iproduct, Itertools};
+use netcdf3::{self, DataType, FileReader, ReadError};
+use resample::Coverage;
+use serde::{self, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::iter::Sum;
+use std::mem;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+
+pub mod api;
+// The command line, which needs files, the network and SQLite, none of which browsers have.
+pub mod calendar;
+mod cancel;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
+mod climatology;
+mod config;
+pub mod coordinates;
+mod daily;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod geo;
+mod grid;
+mod hexagons;
+mod multifile;
+mod ncheader;
+#[cfg(feature = "object-store")]
+mod objectstore;
+mod ondisk;
+pub mod resample;
+mod stats;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+/// Half degree resolution cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct LonLatCell {
+    half_degrees_lon_start: i16,
+    half_degrees_lat_start: i16,
+}
+
+impl LonLatCell {
+    fn containing(lon: f32, lat: f32) -> Self {
+        Self {
+            half_degrees_lon_start: (lon / 0.5).floor() as i16,
+            half_degrees_lat_start: (lat / 0.5).floor() as i16,
+        }
+    }
+
+    /// Like `containing`, with 180 east wrapping around to 180 west and the north pole
+    /// in the northernmost row, so every point on the globe is in one of the grid's cells.
+    fn at(lon: Longitude, lat: Latitude) -> Self {
+        let cell = Self::containing(lon.degrees(), lat.degrees());
+        Self {
+            half_degrees_lon_start: (cell.half_degrees_lon_start + 360).rem_euclid(720) - 360,
+            half_degrees_lat_start: cell.half_degrees_lat_start.min(179),
+        }
+    }
+
+    /// Every cell of a grid given by its coordinate variables, row by row, checking those are
+    /// on the globe. Longitudes from 0 to 360 are taken as their equivalent from -180 to 180.
+    fn of_grid(lats: &[f32], lons: &[f32]) -> Result<Vec<Self>, CoordinateErr> {
+        let lats: Vec<Latitude> = lats.iter().map(|&lat| Latitude::new(lat)).try_collect()?;
+        let lons: Vec<Longitude> = lons
+            .iter()
+            .map(|&lon| Longitude::normalized(lon))
+            .try_collect()?;
+        Ok(iproduct!(lats, lons)
+            .map(|(lat, lon)| Self::at(lon, lat))
+            .collect())
+    }
+
+    /// Longitude and latitude of the middle of the cell.
+    fn center(&self) -> (f32, f32) {
+        (
+            (self.half_degrees_lon_start as f32 + 0.5) * 0.5,
+            (self.half_degrees_lat_start as f32 + 0.5) * 0.5,
+        )
+    }
+
+    /// Proportional to the cell's area, since half degree cells shrink towards the poles.
+    fn area_weight(&self) -> f64 {
+        (self.center().1 as f64).to_radians().cos()
+    }
+}
+
+/// The mean of values over several cells, each weighted by its area. Every aggregation over cells
+/// (neighborhoods, hexagons, countries, the globe) goes through this, so a cell near the poles
+/// never counts as much as one at the equator.
+#[derive(Debug, Clone, Copy, Default)]
+struct AreaWeightedMean {
+    total_weight: f64,
+    weighted_sum: f64,
+    cells: usize,
+}
+
+impl AreaWeightedMean {
+    fn add(&mut self, cell: LonLatCell, value: f64) {
+        self.add_weighted(cell, value, 1.0);
+    }
+
+    /// With `weight` on top of the cell's area, e.g. for its distance to a city.
+    fn add_weighted(&mut self, cell: LonLatCell, value: f64, weight: f64) {
+        let weight = cell.area_weight() * weight;
+        self.total_weight += weight;
+        self.weighted_sum += weight * value;
+        self.cells += 1;
+    }
+
+    /// None until a cell has been added.
+    fn mean(&self) -> Option<f64> {
+        (self.cells > 0).then(|| self.weighted_sum / self.total_weight)
+    }
+}
+
+impl FromIterator<(LonLatCell, f64)> for AreaWeightedMean {
+    fn from_iter<I: IntoIterator<Item = (LonLatCell, f64)>>(iter: I) -> Self {
+        let mut mean = Self::default();
+        for (cell, value) in iter {
+            mean.add(cell, value);
+        }
+        mean
+    }
+}
+
+/// In degrees. West can be greater than east, for boxes across the antimeridian.
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingBox {
+    pub west: f32,
+    pub south: f32,
+    pub east: f32,
+    pub north: f32,
+}
+
+impl BoundingBox {
+    pub fn contains_lon(&self, lon: f32) -> bool {
+        if self.west <= self.east {
+            (self.west..=self.east).contains(&lon)
+        } else {
+            lon >= self.west || lon <= self.east
+        }
+    }
+
+    pub fn contains_lat(&self, lat: f32) -> bool {
+        (self.south..=self.north).contains(&lat)
+    }
+
+    pub fn contains(&self, lon: f32, lat: f32) -> bool {
+        self.contains_lon(lon) && self.contains_lat(lat)
+    }
+}
+
+/// Which years of the dataset to include, both ends inclusive.
+#[derive(Debug, Clone, Copy, Default)]
+struct Period {
+    first_year: Option<i32>,
+    last_year: Option<i32>,
+}
+
+impl Period {
+    fn contains(&self, time: MonthStamp) -> bool {
+        let year = time.year();
+        self.first_year.is_none_or(|first| first <= year)
+            && self.last_year.is_none_or(|last| year <= last)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Temperature {
+    celsius: f32,
+}
+
+/// The difference between two temperatures, which unlike a temperature converts between
+/// scales without an offset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct TemperatureDelta {
+    celsius: f32,
+}
+
+#[derive(Debug)]
+enum AverageErr {
+    MissingData(MonthStamp),
+    NoDatapoints,
+}
+
+impl Display for AverageErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            AverageErr::MissingData(time) => write!(fmt, "no temperature recorded at {}", time),
+            AverageErr::NoDatapoints => write!(fmt, "no datapoints in the requested period"),
+        }
+    }
+}
+
+impl Temperature {
+    fn new(celsius: f32) -> Self {
+        Self { celsius }
+    }
+
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self::new(celsius)
+    }
+
+    pub fn from_fahrenheit(fahrenheit: f32) -> Self {
+        Self::new((fahrenheit - 32.0) * 5.0 / 9.0)
+    }
+
+    pub fn from_kelvin(kelvin: f32) -> Self {
+        Self::new(kelvin - 273.15)
+    }
+
+    /// A value given in `units`, e.g. from the command line.
+    fn from_units(value: f32, units: Units) -> Self {
+        match units {
+            Units::Celsius => Self::from_celsius(value),
+            Units::Fahrenheit => Self::from_fahrenheit(value),
+        }
+    }
+
+    pub fn celsius(self) -> f32 {
+        self.celsius
+    }
+
+    pub fn fahrenheit(self) -> f32 {
+        self.in_units(Units::Fahrenheit)
+    }
+
+    pub fn kelvin(self) -> f32 {
+        self.celsius + 273.15
+    }
+
+    fn average(
+        datapoints: impl Iterator<Item = (MonthStamp, Option<Self>)>,
+    ) -> Result<Self, AverageErr> {
+        Self::summarize(datapoints, Statistic::Mean)
+    }
+
+    /// Like `average`, with any `Statistic`.
+    fn summarize(
+        datapoints: impl Iterator<Item = (MonthStamp, Option<Self>)>,
+        statistic: Statistic,
+    ) -> Result<Self, AverageErr> {
+        let temperatures = datapoints
+            .map(|(time, temp)| Ok(temp.ok_or(AverageErr::MissingData(time))?.celsius))
+            .collect::<Result<Vec<_>, _>>()?;
+        if temperatures.is_empty() {
+            return Err(AverageErr::NoDatapoints);
+        }
+        Ok(Self::new(statistic.of(temperatures)))
+    }
+
+    fn in_units(self, units: Units) -> f32 {
+        match units {
+            Units::Celsius => self.celsius,
+            Units::Fahrenheit => self.celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+}
+
+impl Sum for Temperature {
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        Self {
+            celsius: iter.map(|t| t.celsius).sum(),
+        }
+    }
+}
+
+impl Div<usize> for Temperature {
+    type Output = Self;
+
+    fn div(self, rhs: usize) -> Self::Output {
+        Self {
+            celsius: self.celsius / (rhs as f32),
+        }
+    }
+}
+
+impl Display for Temperature {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.celsius.fmt(fmt)
+    }
+}
+
+impl Sub for Temperature {
+    type Output = TemperatureDelta;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        TemperatureDelta {
+            celsius: self.celsius - rhs.celsius,
+        }
+    }
+}
+
+impl Add<TemperatureDelta> for Temperature {
+    type Output = Self;
+
+    fn add(self, rhs: TemperatureDelta) -> Self::Output {
+        Self::new(self.celsius + rhs.celsius)
+    }
+}
+
+impl Sub<TemperatureDelta> for Temperature {
+    type Output = Self;
+
+    fn sub(self, rhs: TemperatureDelta) -> Self::Output {
+        Self::new(self.celsius - rhs.celsius)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseTemperatureErr(String);
+
+impl Display for ParseTemperatureErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            fmt,
+            "{:?} isn't a temperature, e.g. 15, 15°, 15 °C, 59F or 288.15K",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseTemperatureErr {}
+
+/// A number, in celsius unless followed by C, F or K, with or without a degree sign and spaces
+/// between, e.g. 15, 15°, 15 °C or 59F.
+/// Temperatures below absolute zero are refused.
+impl FromStr for Temperature {
+    type Err = ParseTemperatureErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseTemperatureErr(s.to_string());
+        let trimmed = s.trim();
+        let (number, constructor): (&str, fn(f32) -> Self) =
+            match trimmed.char_indices().last().ok_or_else(err)? {
+                (i, 'C' | 'c') => (&trimmed[..i], Self::from_celsius),
+                (i, 'F' | 'f') => (&trimmed[..i], Self::from_fahrenheit),
+                (i, 'K' | 'k') => (&trimmed[..i], Self::from_kelvin),
+                _ => (trimmed, Self::from_celsius),
+            };
+        let number = number.trim_end();
+        let number = number.strip_suffix('°').unwrap_or(number).trim_end();
+        let temperature = constructor(number.parse().map_err(|_| err())?);
+        if temperature.kelvin() < 0.0 {
+            return Err(err());
+        }
+        Ok(temperature)
+    }
+}
+
+impl TemperatureDelta {
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self { celsius }
+    }
+
+    /// In degrees Celsius, which are the size of kelvins.
+    pub fn celsius(self) -> f32 {
+        self.celsius
+    }
+
+    pub fn fahrenheit(self) -> f32 {
+        self.in_units(Units::Fahrenheit)
+    }
+
+    fn in_units(self, units: Units) -> f32 {
+        match units {
+            Units::Celsius => self.celsius,
+            Units::Fahrenheit => self.celsius * 9.0 / 5.0,
+        }
+    }
+}
+
+impl Add for TemperatureDelta {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::from_celsius(self.celsius + rhs.celsius)
+    }
+}
+
+impl Sub for TemperatureDelta {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::from_celsius(self.celsius - rhs.celsius)
+    }
+}
+
+impl Neg for TemperatureDelta {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::from_celsius(-self.celsius)
+    }
+}
+
+impl Mul<f32> for TemperatureDelta {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self::from_celsius(self.celsius * rhs)
+    }
+}
+
+impl Display for TemperatureDelta {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        self.celsius.fmt(fmt)
+    }
+}
+
+/// A cell's temperature over time, `None` where the dataset has no value.
+type Series = Vec<(MonthStamp, Option<Temperature>)>;
+
+/// Observations stored a column per field, rather than as tuples in which `Option<Temperature>`
+/// alone takes 8 bytes, with missing temperatures in a bitmap. Scanning for a cell only
+/// goes through the cells column.
+#[derive(Debug, Default)]
+struct ObservationColumns {
+    cells: Vec<LonLatCell>,
+    times: Vec<MonthStamp>,
+    /// Meaningless where the temperature is missing.
+    celsius: Vec<f32>,
+    /// One bit per observation, set where the temperature is missing.
+    missing: Vec<u64>,
+}
+
+impl ObservationColumns {
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn push(&mut self, (cell, time, temp): (LonLatCell, MonthStamp, Option<Temperature>)) {
+        let i = self.len();
+        if i.is_multiple_of(64) {
+            self.missing.push(0);
+        }
+        self.cells.push(cell);
+        self.times.push(time);
+        match temp {
+            Some(temp) => self.celsius.push(temp.celsius),
+            None => {
+                self.celsius.push(0.0);
+                self.missing[i / 64] |= 1 << (i % 64);
+            }
+        }
+    }
+
+    fn temperature(&self, i: usize) -> Option<Temperature> {
+        (self.missing[i / 64] & (1 << (i % 64)) == 0).then(|| Temperature::new(self.celsius[i]))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (LonLatCell, MonthStamp, Option<Temperature>)> + '_ {
+        (0..self.len()).map(|i| (self.cells[i], self.times[i], self.temperature(i)))
+    }
+}
+
+impl Extend<(LonLatCell, MonthStamp, Option<Temperature>)> for ObservationColumns {
+    fn extend<I: IntoIterator<Item = (LonLatCell, MonthStamp, Option<Temperature>)>>(
+        &mut self,
+        iter: I,
+    ) {
+        for observation in iter {
+            self.push(observation);
+        }
+    }
+}
+
+impl FromIterator<(LonLatCell, MonthStamp, Option<Temperature>)> for ObservationColumns {
+    fn from_iter<I: IntoIterator<Item = (LonLatCell, MonthStamp, Option<Temperature>)>>(
+        iter: I,
+    ) -> Self {
+        let mut columns = Self::default();
+        columns.extend(iter);
+        columns
+    }
+}
+
+#[derive(Debug)]
+struct TemperatureDataset {
+    observations: Observations,
+}
+
+#[derive(Debug)]
+enum Observations {
+    InMemory(ObservationColumns),
+    /// Left in the file and read a cell at a time, see `ondisk`.
+    OnDisk {
+        tmp: ondisk::OnDiskVariable,
+        variable: TemperatureVariable,
+        times: Vec<MonthStamp>,
+        /// In the order they're stored in.
+        cells: Vec<LonLatCell>,
+        index: HashMap<LonLatCell, usize>,
+    },
+}
+
+/// Which raw values of the temperature variable stand for missing ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct MissingValues {
+    /// The variable's `missing_value`, or what replaces it for files where that's wrong or absent.
+    value: Option<f32>,
+    /// NaN too, which never equals any sentinel.
+    nan: bool,
+}
+
+impl MissingValues {
+    fn is_missing(self, tmp: f32) -> bool {
+        self.value == Some(tmp) || (self.nan && tmp.is_nan())
+    }
+}
+
+/// The variable of a file temperatures are read from, see `TemperatureDataset::temperature_variable`.
+#[derive(Debug, Clone)]
+struct TemperatureVariable {
+    name: String,
+    missing: MissingValues,
+    /// In kelvin rather than celsius, e.g. ERA5's `t2m`.
+    kelvin: bool,
+}
+
+impl TemperatureVariable {
+    /// The temperature in celsius a value of the variable stands for, None when it's missing.
+    fn decode(&self, tmp: f32) -> Option<f32> {
+        match (self.missing.is_missing(tmp), self.kelvin) {
+            (true, _) => None,
+            (false, true) => Some(tmp - 273.15),
+            (false, false) => Some(tmp),
+        }
+    }
+}
+
+/// Whether units of temperature are kelvin rather than celsius, None for any other units.
+fn kelvin(units: &str) -> Option<bool> {
+    match units.to_lowercase().as_str() {
+        "k" | "kelvin" | "degk" | "deg_k" | "degrees_k" => Some(true),
+        "c" | "°c" | "degc" | "deg_c" | "degrees_c" | "celsius" | "degree_celsius"
+        | "degrees celsius" => Some(false),
+        _ => None,
+    }
+}
+
+/// One of the files a dataset can be split over.
+struct DatasetFile {
+    /// The month of each time step, several in a row for daily files.
+    times: Vec<MonthStamp>,
+    lats: Vec<f32>,
+    lons: Vec<f32>,
+    observations: ObservationColumns,
+    daily: bool,
+}
+
+impl DatasetFile {
+    /// `times` in days since 1900, as read.
+    fn new(
+        times: Vec<f32>,
+        lats: Vec<f32>,
+        lons: Vec<f32>,
+        observations: ObservationColumns,
+    ) -> Self {
+        Self {
+            daily: daily::is_daily(&times),
+            times: times
+                .into_iter()
+                .map(MonthStamp::from_days_since_1900)
+                .collect(),
+            lats,
+            lons,
+            observations,
+        }
+    }
+
+    /// Daily files as the monthly means of their days, see `daily`.
+    fn monthly(self, coverage: Coverage) -> Self {
+        if self.daily {
+            daily::monthly_means(self, coverage)
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Debug)]
+enum TemperatureDatasetReadErr {
+    CantListFiles(std::io::Error),
+    CantReadFile(netcdf3::ReadError),
+    CantReadHeader(ncheader::HeaderErr),
+    /// The file is shorter than its header says, so only the first `intact_records` time steps
+    /// of `records` can be read.
+    Truncated {
+        expected: u64,
+        actual: u64,
+        intact_records: usize,
+        records: usize,
+    },
+    UnexpectedDimensions(Vec<String>),
+    InvalidGrid(CoordinateErr),
+    TemperatureVariableMissing,
+    /// Several variables could be the temperature, by these names.
+    AmbiguousTemperatureVariable(Vec<String>),
+    CantReadVariable(String, netcdf3::ReadError),
+    #[cfg(feature = "object-store")]
+    CantReadObject(objectstore::ObjectStoreErr),
+    #[cfg(not(target_arch = "wasm32"))]
+    CantReadOpendap(cli::opendap::OpendapErr),
+    MissingMissingValueAttribute,
+    /// One file of several couldn't be read.
+    InFile(PathBuf, Box<TemperatureDatasetReadErr>),
+    IncompatibleGrid(PathBuf, PathBuf),
+    OverlappingTimes(PathBuf, PathBuf),
+    /// A climatology, and a time series, which can't be stitched together.
+    MixedKinds(PathBuf, PathBuf),
+    /// The token was cancelled before every file was read.
+    Cancelled,
+}
+
+impl Display for TemperatureDatasetReadErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            TemperatureDatasetReadErr::CantListFiles(e) => write!(fmt, "can't list files: {}", e),
+            TemperatureDatasetReadErr::CantReadFile(e) => write!(fmt, "can't read file: {}", e),
+            TemperatureDatasetReadErr::CantReadHeader(e) => write!(fmt, "can't read header: {}", e),
+            TemperatureDatasetReadErr::Truncated {
+                expected,
+                actual,
+                intact_records,
+                records,
+            } => write!(
+                fmt,
+                "truncated file: it should be {} bytes but is {}, reading fails at byte {} \
+                 with only {} of {} time steps intact (pass --allow-truncated to use those)",
+                expected, actual, actual, intact_records, records
+            ),
+            TemperatureDatasetReadErr::UnexpectedDimensions(dims) => {
+                write!(fmt, "unexpected dimensions: {:?}", dims)
+            }
+            TemperatureDatasetReadErr::InvalidGrid(e) => write!(fmt, "invalid grid: {}", e),
+            TemperatureDatasetReadErr::TemperatureVariableMissing => {
+                write!(fmt, "no temperature variable")
+            }
+            TemperatureDatasetReadErr::AmbiguousTemperatureVariable(names) => write!(
+                fmt,
+                "several variables could be the temperature: {} (pick one with --variable)",
+                names.join(", ")
+            ),
+            TemperatureDatasetReadErr::CantReadVariable(var, e) => {
+                write!(fmt, "can't read variable {}: {}", var, e)
+            }
+            #[cfg(feature = "object-store")]
+            TemperatureDatasetReadErr::CantReadObject(e) => write!(fmt, "{}", e),
+            #[cfg(not(target_arch = "wasm32"))]
+            TemperatureDatasetReadErr::CantReadOpendap(e) => write!(fmt, "{}", e),
+            TemperatureDatasetReadErr::MissingMissingValueAttribute => {
+                write!(
+                    fmt,
+                    "temperature variable has no missing_value attribute \
+                     (pass --missing-value or --treat-nan-as-missing)"
+                )
+            }
+            TemperatureDatasetReadErr::InFile(path, e) => write!(fmt, "{}: {}", path.display(), e),
+            TemperatureDatasetReadErr::IncompatibleGrid(first, other) => write!(
+                fmt,
+                "{} and {} have different grids",
+                first.display(),
+                other.display()
+            ),
+            TemperatureDatasetReadErr::OverlappingTimes(first, other) => write!(
+                fmt,
+                "{} and {} cover overlapping times",
+                first.display(),
+                other.display()
+            ),
+            TemperatureDatasetReadErr::MixedKinds(climatology, series) => write!(
+                fmt,
+                "{} is a climatology and {} a time series",
+                climatology.display(),
+                series.display()
+            ),
+            TemperatureDatasetReadErr::Cancelled => write!(fmt, "cancelled"),
+        }
+    }
+}
+
+impl TemperatureDatasetReadErr {
+    /// The candidates, when the temperature variable couldn't be picked out of several.
+    fn ambiguous_variables(&self) -> Option<&[String]> {
+        match self {
+            TemperatureDatasetReadErr::AmbiguousTemperatureVariable(names) => Some(names),
+            TemperatureDatasetReadErr::InFile(_, e) => e.ambiguous_variables(),
+            _ => None,
+        }
+    }
+}
+
+impl TemperatureDataset {
+    /// `path` can also stand for several files split along time, see `multifile::dataset_files`,
+    /// which are stitched together after checking they share a grid.
+    fn new(path: &Path) -> Result<Self, TemperatureDatasetReadErr> {
+        Self::open(path, false)
+    }
+
+    /// Like `new`, but reads the time steps that made it into truncated files, with a warning,
+    /// instead of failing.
+    fn open(path: &Path, allow_truncated: bool) -> Result<Self, TemperatureDatasetReadErr> {
+        Self::open_with(
+            path,
+            allow_truncated,
+            Coverage::default(),
+            MissingValues::default(),
+            None,
+        )
+    }
+
+    /// Like `open`, with daily datasets' months needing only `coverage.days` of their days,
+    /// `missing` overriding which values the files say are missing, and temperatures read from
+    /// `variable` rather than the one found by `temperature_variable`.
+    fn open_with(
+        path: &Path,
+        allow_truncated: bool,
+        coverage: Coverage,
+        missing: MissingValues,
+        variable: Option<&str>,
+    ) -> Result<Self, TemperatureDatasetReadErr> {
+        Self::open_cancellable(
+            path,
+            allow_truncated,
+            coverage,
+            missing,
+            variable,
+            &CancellationToken::new(),
+        )
+    }
+
+    /// Like `open_with`, stopping once `cancel` is cancelled: between files, and between the
+    /// time steps of a file's temperatures as they're decoded.
+    fn open_cancellable(
+        path: &Path,
+        allow_truncated: bool,
+        coverage: Coverage,
+        missing: MissingValues,
+        variable: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> Result<Self, TemperatureDatasetReadErr> {
+        let files =
+            multifile::dataset_files(path).map_err(TemperatureDatasetReadErr::CantListFiles)?;
+        if cancel.is_cancelled() {
+            return Err(TemperatureDatasetReadErr::Cancelled);
+        }
+        if let [file] = &files[..] {
+            let file = Self::read_file(file, allow_truncated, missing, variable, cancel)?;
+            if cancel.is_cancelled() {
+                return Err(TemperatureDatasetReadErr::Cancelled);
+            }
+            return Ok(Self {
+                observations: Observations::InMemory(file.monthly(coverage).observations),
+            });
+        }
+        let mut parts = files
+            .into_iter()
+            .map(|file| {
+                if cancel.is_cancelled() {
+                    return Err(TemperatureDatasetReadErr::Cancelled);
+                }
+                match Self::read_file(&file, allow_truncated, missing, variable, cancel) {
+                    Ok(part) => Ok((file, part.monthly(coverage))),
+                    Err(TemperatureDatasetReadErr::Cancelled) => {
+                        Err(TemperatureDatasetReadErr::Cancelled)
+                    }
+                    Err(e) => Err(TemperatureDatasetReadErr::InFile(file, Box::new(e))),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        parts.sort_by_key(|(_, part)| part.times.first().copied());
+        for pair in parts.windows(2) {
+            let [(first_file, first), (file, part)] = pair else {
+                unreachable!()
+            };
+            if (&part.lons, &part.lats) != (&first.lons, &first.lats) {
+                return Err(TemperatureDatasetReadErr::IncompatibleGrid(
+                    first_file.clone(),
+                    file.clone(),
+                ));
+            }
+            let kinds = [first, part].map(|file| climatology::kind_of(file.times.first().copied()));
+            if kinds[0] != kinds[1] {
+                return Err(TemperatureDatasetReadErr::MixedKinds(
+                    first_file.clone(),
+                    file.clone(),
+                ));
+            }
+            let last_time = first.times.last().copied();
+            let first_time = part.times.first().copied();
+            if first_time <= last_time {
+                return Err(TemperatureDatasetReadErr::OverlappingTimes(
+                    first_file.clone(),
+                    file.clone(),
+                ));
+            }
+        }
+        Ok(Self {
+            observations: Observations::InMemory(
+                parts
+                    .iter()
+                    .flat_map(|(_, part)| part.observations.iter())
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Like `open`, but when loading the dataset whole would take more than `max_bytes`,
+    /// leaves it in its file instead, see `ondisk::Strategy`.
+    fn open_within(
+        path: &Path,
+        allow_truncated: bool,
+        max_bytes: u64,
+        coverage: Coverage,
+        missing: MissingValues,
+        variable: Option<&str>,
+    ) -> Result<Self, TemperatureDatasetReadErr> {
+        let files =
+            multifile::dataset_files(path).map_err(TemperatureDatasetReadErr::CantListFiles)?;
+        let [file] = &files[..] else {
+            eprintln!("Warning: --max-memory only applies to datasets in a single file, loading the whole dataset.");
+            return Self::open_with(path, allow_truncated, coverage, missing, variable);
+        };
+        #[cfg(feature = "object-store")]
+        if objectstore::is_s3_url(file) {
+            eprintln!(
+                "Warning: --max-memory only applies to local files, loading the whole dataset."
+            );
+            return Self::open_with(path, allow_truncated, coverage, missing, variable);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if cli::opendap::is_url(file) {
+            eprintln!(
+                "Warning: --max-memory only applies to local files, loading the whole dataset."
+            );
+            return Self::open_with(path, allow_truncated, coverage, missing, variable);
+        }
+
+        let header =
+            ncheader::Header::read(file).map_err(TemperatureDatasetReadErr::CantReadHeader)?;
+        let variable = Self::temperature_variable(&header.data_set, variable, missing)?;
+        let values = header.data_set.get_var(&variable.name).map_or(0, |tmp| {
+            tmp.get_dims()
+                .iter()
+                .skip(1)
+                .map(|dim| dim.size())
+                .product::<usize>()
+        }) * header.records;
+        // The variable as read, and the observations' columns built from it.
+        let in_memory_bytes = (values
+            * (mem::size_of::<f32>()
+                + mem::size_of::<LonLatCell>()
+                + mem::size_of::<MonthStamp>()
+                + mem::size_of::<f32>())) as u64;
+        let file_bytes = fs::metadata(file)
+            .map_err(|e| TemperatureDatasetReadErr::CantReadHeader(ncheader::HeaderErr::Io(e)))?
+            .len();
+        let strategy = ondisk::Strategy::within(max_bytes, in_memory_bytes, file_bytes);
+        if strategy == ondisk::Strategy::InMemory {
+            return Self::open_with(
+                path,
+                allow_truncated,
+                coverage,
+                missing,
+                Some(&variable.name),
+            );
+        }
+        eprintln!(
+            "Loading the temperature data would take about {:.1} GB, reading it from the file as needed ({}).",
+            in_memory_bytes as f64 / 1e9,
+            strategy
+        );
+
+        let time_steps = Self::intact_records(file, &header, &variable.name, allow_truncated)?
+            .unwrap_or(header.records);
+        let mut reader = FileReader::open(file).map_err(TemperatureDatasetReadErr::CantReadFile)?;
+        let mut read_var_f32 = |var: &str| {
+            reader
+                .read_var_f32(var)
+                .map_err(|e| TemperatureDatasetReadErr::CantReadVariable(var.to_string(), e))
+        };
+        let lats = read_var_f32("lat")?;
+        let lons = read_var_f32("lon")?;
+        let mut times: Vec<f32> = (0..time_steps)
+            .map(|record| reader.read_record_f32("time", record))
+            .flatten_ok()
+            .collect::<Result<_, _>>()
+            .map_err(|e| TemperatureDatasetReadErr::CantReadVariable("time".to_string(), e))?;
+        climatology::to_nominal_year(&mut times);
+        if daily::is_daily(&times) {
+            eprintln!("Warning: --max-memory doesn't apply to daily datasets, which are read as monthly means, loading the whole dataset.");
+            return Self::open_with(
+                path,
+                allow_truncated,
+                coverage,
+                missing,
+                Some(&variable.name),
+            );
+        }
+        let times = times
+            .into_iter()
+            .map(MonthStamp::from_days_since_1900)
+            .collect();
+        let cells =
+            LonLatCell::of_grid(&lats, &lons).map_err(TemperatureDatasetReadErr::InvalidGrid)?;
+        let tmp = ondisk::OnDiskVariable::open(file, &header, &variable.name, time_steps, strategy)
+            .map_err(|e| TemperatureDatasetReadErr::CantReadHeader(ncheader::HeaderErr::Io(e)))?;
+        Ok(Self {
+            observations: Observations::OnDisk {
+                tmp,
+                variable,
+                times,
+                index: cells
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &cell)| (cell, i))
+                    .collect(),
+                cells,
+            },
+        })
+    }
+
+    /// How many values the dataset has, cells times time steps.
+    fn len(&self) -> usize {
+        match &self.observations {
+            Observations::InMemory(observations) => observations.len(),
+            Observations::OnDisk { times, cells, .. } => times.len() * cells.len(),
+        }
+    }
+
+    /// Roughly how many bytes of memory the dataset holds, not counting memory-mapped pages,
+    /// which the kernel can always take back.
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    fn memory_bytes(&self) -> usize {
+        fn bytes<T>(column: &Vec<T>) -> usize {
+            column.capacity() * mem::size_of::<T>()
+        }
+        match &self.observations {
+            Observations::InMemory(columns) => {
+                bytes(&columns.cells)
+                    + bytes(&columns.times)
+                    + bytes(&columns.celsius)
+                    + bytes(&columns.missing)
+            }
+            Observations::OnDisk {
+                times,
+                cells,
+                index,
+                ..
+            } => {
+                bytes(times)
+                    + bytes(cells)
+                    + index.capacity() * mem::size_of::<(LonLatCell, usize)>()
+            }
+        }
+    }
+
+    fn read_file(
+        path: &Path,
+        allow_truncated: bool,
+        missing: MissingValues,
+        variable: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> Result<DatasetFile, TemperatureDatasetReadErr> {
+        #[cfg(feature = "object-store")]
+        if objectstore::is_s3_url(path) {
+            let url = path.to_str().expect("URLs are valid UTF-8");
+            let object = objectstore::RemoteNetcdf::open(url)
+                .map_err(TemperatureDatasetReadErr::CantReadObject)?;
+            let variable = Self::temperature_variable(object.data_set(), variable, missing)?;
+            return Self::read_observations(&variable, |var| {
+                object
+                    .read_var_f32(var)
+                    .map_err(TemperatureDatasetReadErr::CantReadObject)
+            });
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if cli::opendap::is_url(path) {
+            let url = path.to_str().expect("URLs are valid UTF-8");
+            let remote = cli::opendap::RemoteDataset::open(url)
+                .map_err(TemperatureDatasetReadErr::CantReadOpendap)?;
+            let variable = Self::temperature_variable(remote.data_set(), variable, missing)?;
+            return Self::read_observations(&variable, |var| {
+                remote
+                    .read_var_f32(var)
+                    .map_err(TemperatureDatasetReadErr::CantReadOpendap)
+            });
+        }
+        let header =
+            ncheader::Header::read(path).map_err(TemperatureDatasetReadErr::CantReadHeader)?;
+        let variable = Self::temperature_variable(&header.data_set, variable, missing)?;
+        let intact_records = Self::intact_records(path, &header, &variable.name, allow_truncated)?;
+        let mut reader = FileReader::open(path).map_err(TemperatureDatasetReadErr::CantReadFile)?;
+        let record_var = header.placement(&variable.name).filter(|tmp| tmp.is_record);
+        let mut read_var_f32 = |var: &str| {
+            match intact_records {
+                Some(records) if var == "time" || var == variable.name => (0..records)
+                    .map(|record| reader.read_record_f32(var, record))
+                    .flatten_ok()
+                    .collect(),
+                _ => reader.read_var_f32(var),
+            }
+            .map_err(|e| TemperatureDatasetReadErr::CantReadVariable(var.to_string(), e))
+        };
+        let Some(tmp) = record_var else {
+            return Self::read_observations(&variable, read_var_f32);
+        };
+        let mut times = read_var_f32("time")?;
+        climatology::to_nominal_year(&mut times);
+        let lats = read_var_f32("lat")?;
+        let lons = read_var_f32("lon")?;
+        let cells =
+            LonLatCell::of_grid(&lats, &lons).map_err(TemperatureDatasetReadErr::InvalidGrid)?;
+        let observations = Self::decode_in_parallel(
+            path,
+            (tmp.begin, header.record_size),
+            &variable,
+            &times,
+            &cells,
+            cancel,
+        )
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::Interrupted if cancel.is_cancelled() => {
+                TemperatureDatasetReadErr::Cancelled
+            }
+            kind => TemperatureDatasetReadErr::CantReadVariable(
+                variable.name.clone(),
+                ReadError::IOErrorKind(kind),
+            ),
+        })?;
+        Ok(DatasetFile::new(times, lats, lons, observations))
+    }
+
+    /// Decodes the temperature variable, which starts at `begin` and then every `record_size` bytes,
+    /// a chunk of time steps per thread, straight into its place in the observations' columns.
+    /// The netcdf3 crate's readers can't be sent to other threads, so this reads the file directly.
+    /// Every thread stops at its next time step once `cancel` is cancelled, failing as interrupted.
+    fn decode_in_parallel(
+        path: &Path,
+        (begin, record_size): (u64, u64),
+        variable: &TemperatureVariable,
+        times: &[f32],
+        cells: &[LonLatCell],
+        cancel: &CancellationToken,
+    ) -> io::Result<ObservationColumns> {
+        let len = times.len() * cells.len();
+        let mut columns = ObservationColumns {
+            cells: vec![LonLatCell::containing(0.0, 0.0); len],
+            times: vec![MonthStamp::from_days_since_1900(0.0); len],
+            celsius: vec![0.0; len],
+            missing: vec![0; len.div_ceil(64)],
+        };
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        // Enough records for each thread's values to be a multiple of 64, so it gets whole words
+        // of the missing bitmap, a single one for a full half degree grid.
+        let alignment = 64 >> cells.len().max(1).trailing_zeros().min(6);
+        let records_per_thread = times
+            .len()
+            .div_ceil(threads)
+            .max(1)
+            .next_multiple_of(alignment);
+        let chunk_len = records_per_thread * cells.len().max(1);
+        thread::scope(|scope| {
+            let workers: Vec<_> = columns
+                .cells
+                .chunks_mut(chunk_len)
+                .zip(columns.times.chunks_mut(chunk_len))
+                .zip(columns.celsius.chunks_mut(chunk_len))
+                .zip(columns.missing.chunks_mut(chunk_len / 64))
+                .zip(times.chunks(records_per_thread))
+                .enumerate()
+                .map(
+                    |(i, ((((chunk_cells, chunk_times), celsius), missing), record_times))| {
+                        let cells = &cells;
+                        scope.spawn(move || {
+                            let mut file = File::open(path)?;
+                            let mut bytes = vec![0; cells.len() * 4];
+                            for (r, &time) in record_times.iter().enumerate() {
+                                if cancel.is_cancelled() {
+                                    return Err(io::ErrorKind::Interrupted.into());
+                                }
+                                let record = (i * records_per_thread + r) as u64;
+                                file.seek(SeekFrom::Start(begin + record * record_size))?;
+                                file.read_exact(&mut bytes)?;
+                                let tmp = bytes.chunks_exact(4).map(|value| {
+                                    f32::from_be_bytes(value.try_into().expect("4 bytes"))
+                                });
+                                for (j, (&cell, tmp)) in cells.iter().zip_eq(tmp).enumerate() {
+                                    let k = r * cells.len() + j;
+                                    chunk_cells[k] = cell;
+                                    chunk_times[k] = MonthStamp::from_days_since_1900(time);
+                                    match variable.decode(tmp) {
+                                        Some(tmp) => celsius[k] = tmp,
+                                        None => missing[k / 64] |= 1 << (k % 64),
+                                    }
+                                }
+                            }
+                            io::Result::Ok(())
+                        })
+                    },
+                )
+                .collect();
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().expect("Decoding thread panicked"))
+        })?;
+        Ok(columns)
+    }
+
+    fn observation(
+        cell: LonLatCell,
+        time: MonthStamp,
+        tmp: f32,
+        variable: &TemperatureVariable,
+    ) -> (LonLatCell, MonthStamp, Option<Temperature>) {
+        (cell, time, variable.decode(tmp).map(Temperature::new))
+    }
+
+    /// Checks the file is as long as its header says. If it isn't, how many time steps
+    /// can still be read, when that's allowed.
+    fn intact_records(
+        path: &Path,
+        header: &ncheader::Header,
+        variable: &str,
+        allow_truncated: bool,
+    ) -> Result<Option<usize>, TemperatureDatasetReadErr> {
+        let actual = fs::metadata(path)
+            .map_err(|e| TemperatureDatasetReadErr::CantReadHeader(ncheader::HeaderErr::Io(e)))?
+            .len();
+        let expected = header.expected_size();
+        if actual >= expected {
+            return Ok(None);
+        }
+        let intact_records = ["time", "lat", "lon", variable]
+            .iter()
+            .filter_map(|var| header.placement(var))
+            .map(|placement| header.records_within(placement, actual))
+            .min()
+            .unwrap_or(0);
+        if !allow_truncated || intact_records == 0 {
+            return Err(TemperatureDatasetReadErr::Truncated {
+                expected,
+                actual,
+                intact_records,
+                records: header.records,
+            });
+        }
+        eprintln!(
+            "Warning: {} is truncated ({} of {} bytes), only using its first {} of {} time steps.",
+            path.display(),
+            actual,
+            expected,
+            intact_records,
+            header.records
+        );
+        Ok(Some(intact_records))
+    }
+
+    /// The variable named `name`, or else the one `detect_temperature_variable` finds, with its
+    /// `missing_value` unless `overrides` replaces it, and NaN if asked.
+    /// Also checks the temperature variable is laid out the way we expect.
+    fn temperature_variable(
+        data_set: &netcdf3::DataSet,
+        name: Option<&str>,
+        overrides: MissingValues,
+    ) -> Result<TemperatureVariable, TemperatureDatasetReadErr> {
+        let temp = match name {
+            Some(name) => data_set.get_var(name),
+            None => Self::detect_temperature_variable(data_set)?,
+        }
+        .ok_or(TemperatureDatasetReadErr::TemperatureVariableMissing)?;
+        if temp.dim_names() != ["time", "lat", "lon"] {
+            return Err(TemperatureDatasetReadErr::UnexpectedDimensions(
+                temp.dim_names(),
+            ));
+        }
+        let value = overrides
+            .value
+            .or_else(|| temp.get_attr_f32("missing_value").map(|values| values[0]));
+        if value.is_none() && !overrides.nan {
+            return Err(TemperatureDatasetReadErr::MissingMissingValueAttribute);
+        }
+        let units = temp.get_attr_as_string("units").unwrap_or_default();
+        Ok(TemperatureVariable {
+            name: temp.name().to_string(),
+            missing: MissingValues {
+                value,
+                nan: overrides.nan,
+            },
+            kelvin: kelvin(&units).unwrap_or(false),
+        })
+    }
+
+    /// Of the `[time, lat, lon]` f32 variables, the one with `standard_name: air_temperature`
+    /// and units of temperature, or failing that with either, or failing that the only one.
+    /// Without any, CRU TS's `tmp` if it's there at all, to point out what's wrong with it.
+    fn detect_temperature_variable(
+        data_set: &netcdf3::DataSet,
+    ) -> Result<Option<&netcdf3::Variable>, TemperatureDatasetReadErr> {
+        let candidates: Vec<(&netcdf3::Variable, u8)> = data_set
+            .get_vars()
+            .into_iter()
+            .filter(|var| {
+                var.dim_names() == ["time", "lat", "lon"] && var.data_type() == DataType::F32
+            })
+            .map(|var| {
+                let standard_name = var.get_attr_as_string("standard_name");
+                let units = var.get_attr_as_string("units").unwrap_or_default();
+                let score = 2 * (standard_name.as_deref() == Some("air_temperature")) as u8
+                    + kelvin(&units).is_some() as u8;
+                (var, score)
+            })
+            .collect();
+        let Some(best) = candidates.iter().map(|&(_, score)| score).max() else {
+            return Ok(data_set.get_var("tmp"));
+        };
+        match candidates
+            .into_iter()
+            .filter(|&(_, score)| score == best)
+            .map(|(var, _)| var)
+            .collect::<Vec<_>>()[..]
+        {
+            [var] => Ok(Some(var)),
+            ref tied => Err(TemperatureDatasetReadErr::AmbiguousTemperatureVariable(
+                tied.iter().map(|var| var.name().to_string()).collect(),
+            )),
+        }
+    }
+
+    fn read_observations(
+        variable: &TemperatureVariable,
+        mut read_var_f32: impl FnMut(&str) -> Result<Vec<f32>, TemperatureDatasetReadErr>,
+    ) -> Result<DatasetFile, TemperatureDatasetReadErr> {
+        let mut times = read_var_f32("time")?;
+        climatology::to_nominal_year(&mut times);
+        let lats = read_var_f32("lat")?;
+        let lons = read_var_f32("lon")?;
+        let cells =
+            LonLatCell::of_grid(&lats, &lons).map_err(TemperatureDatasetReadErr::InvalidGrid)?;
+        let observations = read_var_f32(&variable.name)?
+            .into_iter()
+            .zip_eq(iproduct!(times.clone(), cells))
+            .map(|(tmp, (time, cell))| {
+                Self::observation(cell, MonthStamp::from_days_since_1900(time), tmp, variable)
+            })
+            .collect();
+
+        Ok(DatasetFile::new(times, lats, lons, observations))
+    }
+
+    fn temperature_series_at(&self, geo: LonLatCell) -> Series {
+        match &self.observations {
+            Observations::InMemory(observations) => observations
+                .cells
+                .iter()
+                .enumerate()
+                .filter(|&(_, &c)| c == geo)
+                .map(|(i, _)| (observations.times[i], observations.temperature(i)))
+                .collect(),
+            Observations::OnDisk {
+                tmp,
+                variable,
+                times,
+                index,
+                ..
+            } => index.get(&geo).map_or(vec![], |&cell| {
+                tmp.series(cell)
+                    .expect("Couldn't read temperature data")
+                    .into_iter()
+                    .zip(times)
+                    .map(|(tmp, &time)| (time, variable.decode(tmp).map(Temperature::new)))
+                    .collect()
+            }),
+        }
+    }
+
+    /// Every cell's series, in the order the cells appear in the dataset.
+    /// Datasets left on disk are read whole for this.
+    fn series_by_cell(&self) -> Vec<(LonLatCell, Series)> {
+        self.series_of_cells_where(|_| true, |_| true).collect()
+    }
+
+    /// Like `series_by_cell`, for just the cells `include` picks and the months of their series
+    /// `during` picks. Datasets left on disk are read a cell at a time, as the series are asked for.
+    fn series_of_cells_where<'a>(
+        &'a self,
+        include: impl Fn(LonLatCell) -> bool + 'a,
+        during: impl Fn(MonthStamp) -> bool + 'a,
+    ) -> Box<dyn Iterator<Item = (LonLatCell, Series)> + 'a> {
+        let observations = match &self.observations {
+            Observations::InMemory(observations) => observations,
+            Observations::OnDisk { cells, .. } => {
+                return Box::new(cells.iter().filter(move |&&cell| include(cell)).map(
+                    move |&cell| {
+                        let mut series = self.temperature_series_at(cell);
+                        series.retain(|&(time, _)| during(time));
+                        (cell, series)
+                    },
+                ))
+            }
+        };
+        let mut index = HashMap::new();
+        let mut cells: Vec<(LonLatCell, Vec<_>)> = vec![];
+        for (cell, time, temp) in observations
+            .iter()
+            .filter(|&(cell, time, _)| include(cell) && during(time))
+        {
+            let i = *index.entry(cell).or_insert_with(|| {
+                cells.push((cell, vec![]));
+                cells.len() - 1
+            });
+            cells[i].1.push((time, temp));
+        }
+        Box::new(cells.into_iter())
+    }
+
+    /// Every cell, in the order they appear in the dataset.
+    fn cells(&self) -> Vec<LonLatCell> {
+        match &self.observations {
+            Observations::InMemory(observations) => {
+                let mut seen = HashSet::new();
+                observations
+                    .cells
+                    .iter()
+                    .copied()
+                    .filter(|&cell| seen.insert(cell))
+                    .collect()
+            }
+            Observations::OnDisk { cells, .. } => cells.clone(),
+        }
+    }
+
+    /// The indices of `cell` along the dataset's lon and lat dimensions, in the order their
+    /// coordinates are stored, None when the dataset doesn't have it.
+    fn grid_index(&self, cell: LonLatCell) -> Option<(usize, usize)> {
+        let cells = self.cells();
+        let index = |start: fn(&LonLatCell) -> i16| {
+            let mut seen = HashSet::new();
+            cells
+                .iter()
+                .map(start)
+                .filter(|&start| seen.insert(start))
+                .position(|other| other == start(&cell))
+        };
+        index(|cell| cell.half_degrees_lon_start).zip(index(|cell| cell.half_degrees_lat_start))
+    }
+
+    /// Whether the dataset has years, or just one of each month, see `climatology`.
+    fn kind(&self) -> DatasetKind {
+        climatology::kind_of(match &self.observations {
+            Observations::InMemory(observations) => observations.times.first().copied(),
+            Observations::OnDisk { times, .. } => times.first().copied(),
+        })
+    }
+
+    /// Every time step, in the order they appear in the dataset.
+    fn times(&self) -> Vec<MonthStamp> {
+        match &self.observations {
+            Observations::InMemory(observations) => {
+                let mut seen = HashSet::new();
+                observations
+                    .times
+                    .iter()
+                    .copied()
+                    .filter(|&time| seen.insert(time))
+                    .collect()
+            }
+            Observations::OnDisk { times, .. } => times.clone(),
+        }
+    }
+
+    /// The average temperature of the cells around a point, see `Neighborhood`.
+    fn average_temperature_around(
+        &self,
+        lon: Longitude,
+        lat: Latitude,
+        neighborhood: Neighborhood,
+        period: Period,
+        statistic: Statistic,
+    ) -> Result<Temperature, AverageErr> {
+        let averages = self.averages_around(lon, lat, neighborhood, period, statistic)?;
+        let (lon, lat) = (lon.degrees(), lat.degrees());
+        let mut mean = AreaWeightedMean::default();
+        for (cell, average) in averages {
+            let weight = if neighborhood.distance_weighted {
+                // Capped, so a city right on a cell's center doesn't get an infinite weight.
+                1.0 / geo::distance::haversine_km((lon, lat), cell.center()).max(1.0)
+            } else {
+                1.0
+            };
+            mean.add_weighted(cell, average.celsius as f64, weight as f64);
+        }
+        Ok(Temperature::new(
+            mean.mean().expect("The neighborhood has at least one cell") as f32,
+        ))
+    }
+
+    /// The average of every cell of the neighborhood centered on the city's cell, leaving out
+    /// those without one over the period, like the oceans in CRU TS, so coastal cities are
+    /// averaged over the land around them rather than failing. The weights of what's left make
+    /// up the whole. Fails the way the city's own cell does when no cell has an average.
+    fn averages_around(
+        &self,
+        lon: Longitude,
+        lat: Latitude,
+        neighborhood: Neighborhood,
+        period: Period,
+        statistic: Statistic,
+    ) -> Result<Vec<(LonLatCell, Temperature)>, AverageErr> {
+        let center = LonLatCell::at(lon, lat);
+        let radius = (neighborhood.size / 2) as i16;
+        let averages: Vec<_> = iproduct!(-radius..=radius, -radius..=radius)
+            .filter_map(|(lat_offset, lon_offset)| {
+                let cell = LonLatCell {
+                    // Wrap around the antimeridian.
+                    half_degrees_lon_start: (center.half_degrees_lon_start + lon_offset + 360)
+                        .rem_euclid(720)
+                        - 360,
+                    half_degrees_lat_start: center.half_degrees_lat_start + lat_offset,
+                };
+                let average = self.average_temperature_at(cell, period, statistic).ok()?;
+                Some((cell, average))
+            })
+            .collect();
+        if averages.is_empty() {
+            return Err(self
+                .average_temperature_at(center, period, statistic)
+                .expect_err("The city's cell has no average either"));
+        }
+        Ok(averages)
+    }
+
+    /// The average temperature of every cell over the period, `None` where data is missing.
+    fn average_grid(&self, period: Period, units: Units) -> grid::Grid {
+        grid::Grid::from_cells(self.series_by_cell().into_iter().map(|(cell, series)| {
+            let series = series
+                .into_iter()
+                .filter(|&(time, _)| period.contains(time));
+            (
+                cell,
+                Temperature::average(series).ok().map(|t| t.in_units(units)),
+            )
+        }))
+    }
+
+    fn average_temperature_at(
+        &self,
+        geo: LonLatCell,
+        period: Period,
+        statistic: Statistic,
+    ) -> Result<Temperature, AverageErr> {
+        Temperature::summarize(
+            self.temperature_series_at(geo)
+                .into_iter()
+                .filter(|&(time, _)| period.contains(time)),
+            statistic,
+        )
+    }
+}
+
+/// Which cells around a city to average over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Neighborhood {
+    /// Width of the square block of cells centered on the city's cell. Always odd.
+    size: usize,
+    /// Weight each cell by the inverse of its distance to the city, rather than equally.
+    distance_weighted: bool,
+}
+
+impl Default for Neighborhood {
+    fn default() -> Self {
+        Self {
+            size: 1,
+            distance_weighted: false,
+        }
+    }
+}
+
+/// Everything that decides which temperature ends up in a city's row,
+/// other than the dataset and the city's coordinates.
+#[derive(Debug, Clone, Copy, Default)]
+struct CitySettings {
+    period: Period,
+    units: Units,
+    neighborhood: Neighborhood,
+    h3_resolution: Option<u8>,
+    /// How each cell's months are summed up.
+    statistic: Statistic,
+}
+
+impl CitySettings {
+    fn temperature_at(
+        &self,
+        dataset: &TemperatureDataset,
+        lon: Longitude,
+        lat: Latitude,
+    ) -> Result<f32, AverageErr> {
+        match self.h3_resolution {
+            Some(resolution) => hexagons::average_temperature_in_hexagon(
+                dataset,
+                lon.degrees(),
+                lat.degrees(),
+                resolution,
+                self.period,
+                self.statistic,
+            ),
+            None => dataset.average_temperature_around(
+                lon,
+                lat,
+                self.neighborhood,
+                self.period,
+                self.statistic,
+            ),
+        }
+        .map(|t| t.in_units(self.units))
+    }
+
+    /// One standard deviation of the temperature at a point, see `stats::Uncertainty`, from its
+    /// cell's complete years (by `coverage`) and the cells around it, at least the 3x3 block.
+    /// None with fewer than two complete years.
+    fn uncertainty_at(
+        &self,
+        dataset: &TemperatureDataset,
+        lon: Longitude,
+        lat: Latitude,
+        coverage: Coverage,
+    ) -> Option<f32> {
+        let series = dataset.temperature_series_at(LonLatCell::at(lon, lat));
+        let years = stats::annual_means(&series, self.period, coverage);
+        let first = self
+            .period
+            .first_year
+            .or(series.first().map(|(t, _)| t.year()))?;
+        let last = self
+            .period
+            .last_year
+            .or(series.last().map(|(t, _)| t.year()))?;
+        let missing_years = (first..=last)
+            .filter(|year| !years.contains_key(year))
+            .count();
+        let around = Neighborhood {
+            size: self.neighborhood.size.max(3),
+            distance_weighted: false,
+        };
+        let cells: Vec<f64> = dataset
+            .averages_around(lon, lat, around, self.period, self.statistic)
+            .map_or(vec![], |averages| {
+                averages
+                    .into_iter()
+                    .map(|(_, average)| average.celsius as f64)
+                    .collect()
+            });
+        let years: Vec<f64> = years.into_values().collect();
+        let uncertainty = stats::Uncertainty::new(&years, missing_years, &cells)?;
+        Some(TemperatureDelta::from_celsius(uncertainty.combined() as f32).in_units(self.units))
+    }
+}
+
+pub use cancel::CancellationToken;
+#[cfg(not(target_arch = "wasm32"))]
+pub use cli::builder::{CityInput, CityResult, Interpolation, RunBuilder, RunErr, RunResult};
+#[cfg(not(target_arch = "wasm32"))]
+pub use cli::hooks::{Choice, CityName, Hooks, TerminalHooks};
+#[cfg(not(target_arch = "wasm32"))]
+pub use cli::sources::{InputSource, Rows, SourceErr, Sources};
+#[cfg(not(target_arch = "wasm32"))]
+pub use cli::{main, main_with_sources};
+pub use config::{Geocoder, Units};
+pub use stats::Statistic;
@@ -0,0 +1,49 @@
+//! Datasets split over several files, e.g. CRU's decade files, given as a list and/or a glob.
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+fn is_pattern(path: &Path) -> bool {
+    path.to_str().is_some_and(|s| s.contains(['*', '?', '[']))
+}
+
+/// The files a dataset path stands for: the file itself if there is one (or a URL), otherwise
+/// a list separated like `PATH` (`:` on Unix), each entry possibly a glob, e.g.
+/// `cru_ts4.07.1991.2000.tmp.dat.nc:cru_ts4.07.2001.2010.tmp.dat.nc` or `cru_ts4.07.*.tmp.dat.nc`.
+pub fn dataset_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let is_url = |s: &str| {
+        ["s3://", "http://", "https://"]
+            .iter()
+            .any(|scheme| s.starts_with(scheme))
+    };
+    if path.is_file() || path.to_str().is_some_and(is_url) {
+        return Ok(vec![path.to_owned()]);
+    }
+    let mut files = vec![];
+    for entry in env::split_paths(path) {
+        if !is_pattern(&entry) {
+            files.push(entry);
+            continue;
+        }
+        let pattern = entry.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} isn't valid UTF-8, as globs must be", entry.display()),
+            )
+        })?;
+        let mut matches: Vec<PathBuf> = glob::glob(pattern)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+            .collect::<Result<_, _>>()
+            .map_err(io::Error::from)?;
+        if matches.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no files match {}", pattern),
+            ));
+        }
+        matches.sort();
+        files.extend(matches);
+    }
+    Ok(files)
+}
@@ -0,0 +1,379 @@
+//! The layout of classic NetCDF files, read from their header, see
+//! https://docs.unidata.ucar.edu/netcdf-c/current/file_format_specifications.html
+//! The netcdf3 crate keeps where each variable starts to itself, and we need it
+//! for ranged reads and to tell how much of a truncated file is usable.
+
+use netcdf3::{DataSet, DataType, InvalidDataSet};
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum HeaderErr {
+    Io(io::Error),
+    NotClassicNetcdf,
+    Truncated,
+    NoSuchDimension(String),
+    Define(InvalidDataSet),
+}
+
+impl Display for HeaderErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            HeaderErr::Io(e) => write!(fmt, "{}", e),
+            HeaderErr::NotClassicNetcdf => write!(fmt, "not a classic NetCDF file"),
+            HeaderErr::Truncated => write!(fmt, "the file ends within its header"),
+            HeaderErr::NoSuchDimension(var) => write!(fmt, "{} has an undefined dimension", var),
+            HeaderErr::Define(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl From<InvalidDataSet> for HeaderErr {
+    fn from(e: InvalidDataSet) -> Self {
+        HeaderErr::Define(e)
+    }
+}
+
+/// Everything returns `None` when the header goes on past the bytes read so far.
+struct HeaderParser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Offsets are 64 bits in the 64-bit offset format.
+    wide_offsets: bool,
+}
+
+type RawAttribute = (String, DataType, Vec<u8>);
+
+impl HeaderParser<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        let taken = self.bytes.get(self.position..self.position + n)?;
+        self.position += n;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn offset(&mut self) -> Option<u64> {
+        if self.wide_offsets {
+            Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+        } else {
+            self.u32().map(u64::from)
+        }
+    }
+
+    /// Padded to 4 bytes.
+    fn padded(&mut self, n: usize) -> Option<&[u8]> {
+        let padded = self.take(n.div_ceil(4) * 4)?;
+        Some(&padded[..n])
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        Some(String::from_utf8_lossy(self.padded(len)?).into_owned())
+    }
+
+    /// The element count of a list, after its tag. Absent lists count as empty.
+    fn list(&mut self) -> Option<usize> {
+        let _tag = self.u32()?;
+        Some(self.u32()? as usize)
+    }
+
+    fn attributes(&mut self) -> Option<Vec<RawAttribute>> {
+        (0..self.list()?)
+            .map(|_| {
+                let name = self.name()?;
+                let data_type = data_type(self.u32()?);
+                let len = self.u32()? as usize;
+                let bytes = self.padded(len * data_type.size_of())?.to_vec();
+                Some((name, data_type, bytes))
+            })
+            .collect()
+    }
+}
+
+fn data_type(nc_type: u32) -> DataType {
+    match nc_type {
+        1 => DataType::I8,
+        2 => DataType::U8,
+        3 => DataType::I16,
+        4 => DataType::I32,
+        5 => DataType::F32,
+        _ => DataType::F64,
+    }
+}
+
+/// A variable as the header declares it.
+struct RawVariable {
+    name: String,
+    dim_ids: Vec<usize>,
+    attributes: Vec<RawAttribute>,
+    data_type: DataType,
+    begin: u64,
+}
+
+/// Where a variable's data is in the file.
+pub struct Placement {
+    pub name: String,
+    pub data_type: DataType,
+    pub is_record: bool,
+    /// Bytes per record for record variables, in total for the others.
+    pub size: usize,
+    pub begin: u64,
+}
+
+pub struct Header {
+    pub data_set: DataSet,
+    pub placements: Vec<Placement>,
+    pub records: usize,
+    /// Bytes from one record to the next.
+    pub record_size: u64,
+}
+
+impl Header {
+    /// `None` if more bytes are needed. `file_size` is only used for streamed files,
+    /// which don't record how many records they have.
+    pub fn parse(bytes: &[u8], file_size: u64) -> Option<Result<Self, HeaderErr>> {
+        let mut parser = HeaderParser {
+            bytes,
+            position: 0,
+            wide_offsets: false,
+        };
+        match parser.take(4)? {
+            [b'C', b'D', b'F', 1] => {}
+            [b'C', b'D', b'F', 2] => parser.wide_offsets = true,
+            _ => return Some(Err(HeaderErr::NotClassicNetcdf)),
+        }
+        let records = parser.u32()?;
+        let dims: Vec<(String, usize)> = (0..parser.list()?)
+            .map(|_| Some((parser.name()?, parser.u32()? as usize)))
+            .collect::<Option<_>>()?;
+        let global_attributes = parser.attributes()?;
+        let vars: Vec<RawVariable> = (0..parser.list()?)
+            .map(|_| {
+                let name = parser.name()?;
+                let dim_ids = (0..parser.u32()?)
+                    .map(|_| parser.u32().map(|id| id as usize))
+                    .collect::<Option<_>>()?;
+                let attributes = parser.attributes()?;
+                let data_type = data_type(parser.u32()?);
+                let _vsize = parser.u32()?;
+                let begin = parser.offset()?;
+                Some(RawVariable {
+                    name,
+                    dim_ids,
+                    attributes,
+                    data_type,
+                    begin,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self::new(dims, global_attributes, vars, records, file_size))
+    }
+
+    fn new(
+        dims: Vec<(String, usize)>,
+        global_attributes: Vec<RawAttribute>,
+        vars: Vec<RawVariable>,
+        records: u32,
+        file_size: u64,
+    ) -> Result<Self, HeaderErr> {
+        let mut placements: Vec<Placement> = vec![];
+        for var in &vars {
+            let var_dims: Vec<&(String, usize)> = var
+                .dim_ids
+                .iter()
+                .map(|&id| dims.get(id))
+                .collect::<Option<_>>()
+                .ok_or_else(|| HeaderErr::NoSuchDimension(var.name.clone()))?;
+            // The unlimited dimension has size 0 in the header, and can only come first.
+            let is_record = var_dims.first().is_some_and(|(_, size)| *size == 0);
+            let elements: usize = var_dims
+                .iter()
+                .skip(if is_record { 1 } else { 0 })
+                .map(|(_, size)| size)
+                .product();
+            placements.push(Placement {
+                name: var.name.clone(),
+                data_type: var.data_type.clone(),
+                is_record,
+                size: elements * var.data_type.size_of(),
+                begin: var.begin,
+            });
+        }
+        let record_vars: Vec<&Placement> = placements.iter().filter(|p| p.is_record).collect();
+        // A lone record variable isn't padded.
+        let record_size = match &record_vars[..] {
+            [only] => only.size as u64,
+            _ => record_vars
+                .iter()
+                .map(|p| p.size.div_ceil(4) as u64 * 4)
+                .sum(),
+        };
+        let records = if records == u32::MAX {
+            // Streamed files don't know their record count.
+            let first_record = record_vars.iter().map(|p| p.begin).min().unwrap_or(0);
+            file_size.saturating_sub(first_record) / record_size.max(1)
+        } else {
+            records as u64
+        } as usize;
+
+        let mut data_set = DataSet::new();
+        for (name, size) in &dims {
+            if *size == 0 {
+                data_set.set_unlimited_dim(name, records)?;
+            } else {
+                data_set.add_fixed_dim(name, *size)?;
+            }
+        }
+        for (name, data_type, bytes) in &global_attributes {
+            add_attribute(&mut data_set, None, name, data_type.clone(), bytes)?;
+        }
+        for var in &vars {
+            let dim_names: Vec<&str> = var.dim_ids.iter().map(|&id| &dims[id].0[..]).collect();
+            data_set.add_var(&var.name, &dim_names, var.data_type.clone())?;
+            for (name, data_type, bytes) in &var.attributes {
+                add_attribute(
+                    &mut data_set,
+                    Some(&var.name),
+                    name,
+                    data_type.clone(),
+                    bytes,
+                )?;
+            }
+        }
+        Ok(Self {
+            data_set,
+            placements,
+            records,
+            record_size,
+        })
+    }
+}
+
+fn add_attribute(
+    data_set: &mut DataSet,
+    var: Option<&str>,
+    name: &str,
+    data_type: DataType,
+    bytes: &[u8],
+) -> Result<(), InvalidDataSet> {
+    let words = |size: usize| bytes.chunks(size).map(|c| c.to_vec());
+    let word = |c: Vec<u8>| -> [u8; 8] {
+        let mut word = [0; 8];
+        word[..c.len()].copy_from_slice(&c);
+        word
+    };
+    match data_type {
+        DataType::I8 => {
+            let values = bytes.iter().map(|&b| b as i8).collect();
+            match var {
+                Some(var) => data_set.add_var_attr_i8(var, name, values),
+                None => data_set.add_global_attr_i8(name, values),
+            }
+        }
+        DataType::U8 => match var {
+            Some(var) => data_set.add_var_attr_u8(var, name, bytes.to_vec()),
+            None => data_set.add_global_attr_u8(name, bytes.to_vec()),
+        },
+        DataType::I16 => {
+            let values = words(2).map(|c| i16::from_be_bytes([c[0], c[1]])).collect();
+            match var {
+                Some(var) => data_set.add_var_attr_i16(var, name, values),
+                None => data_set.add_global_attr_i16(name, values),
+            }
+        }
+        DataType::I32 => {
+            let values = words(4)
+                .map(|c| i32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+            match var {
+                Some(var) => data_set.add_var_attr_i32(var, name, values),
+                None => data_set.add_global_attr_i32(name, values),
+            }
+        }
+        DataType::F32 => {
+            let values = words(4)
+                .map(|c| f32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+            match var {
+                Some(var) => data_set.add_var_attr_f32(var, name, values),
+                None => data_set.add_global_attr_f32(name, values),
+            }
+        }
+        DataType::F64 => {
+            let values = words(8).map(|c| f64::from_be_bytes(word(c))).collect();
+            match var {
+                Some(var) => data_set.add_var_attr_f64(var, name, values),
+                None => data_set.add_global_attr_f64(name, values),
+            }
+        }
+    }
+}
+
+impl Header {
+    /// Read from a local file.
+    pub fn read(path: &Path) -> Result<Self, HeaderErr> {
+        let mut file = File::open(path).map_err(HeaderErr::Io)?;
+        let file_size = file.metadata().map_err(HeaderErr::Io)?.len();
+        // Headers are usually a few kilobytes, read more until it parses.
+        let mut bytes = vec![];
+        loop {
+            let len = bytes.len().max(16 * 1024) as u64;
+            let read = (&mut file)
+                .take(len)
+                .read_to_end(&mut bytes)
+                .map_err(HeaderErr::Io)?;
+            if let Some(header) = Self::parse(&bytes, file_size) {
+                return header;
+            }
+            if read == 0 {
+                return Err(HeaderErr::Truncated);
+            }
+        }
+    }
+
+    pub fn placement(&self, name: &str) -> Option<&Placement> {
+        self.placements.iter().find(|p| p.name == name)
+    }
+
+    /// Where the variable's data ends.
+    pub fn end(&self, placement: &Placement) -> u64 {
+        if !placement.is_record {
+            placement.begin + placement.size as u64
+        } else if self.records == 0 {
+            placement.begin
+        } else {
+            placement.begin + (self.records - 1) as u64 * self.record_size + placement.size as u64
+        }
+    }
+
+    /// How big the file should be.
+    pub fn expected_size(&self) -> u64 {
+        self.placements
+            .iter()
+            .map(|placement| self.end(placement))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// How many records of the variable are entirely within the first `size` bytes,
+    /// all of them or none for variables that aren't record variables.
+    pub fn records_within(&self, placement: &Placement, size: u64) -> usize {
+        if !placement.is_record {
+            return if self.end(placement) <= size {
+                self.records
+            } else {
+                0
+            };
+        }
+        let available = size.saturating_sub(placement.begin);
+        match available.checked_sub(placement.size as u64) {
+            None => 0,
+            Some(rest) => ((rest / self.record_size.max(1) + 1) as usize).min(self.records),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Reading datasets straight out of S3 (or S3 compatible) buckets with ranged reads,
+//! so only the header and the variables we use are transferred and nothing is stored locally.
+//!
+//! Credentials come from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and optionally `AWS_SESSION_TOKEN`,
+//! without which requests go out unsigned, as for public buckets. The region comes from `AWS_REGION`
+//! (`us-east-1` by default), and `AWS_ENDPOINT_URL` points at other S3 compatible stores.
+
+use crate::calendar;
+use crate::ncheader::{Header, HeaderErr};
+use netcdf3::{DataSet, DataType};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum ObjectStoreErr {
+    Http(reqwest::Error),
+    BadUrl(String),
+    /// Not something we can read as classic NetCDF.
+    Format(String),
+    Header(HeaderErr),
+}
+
+impl Display for ObjectStoreErr {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ObjectStoreErr::Http(e) => write!(fmt, "request failed: {}", e),
+            ObjectStoreErr::BadUrl(url) => write!(fmt, "expected s3://bucket/key, got {}", url),
+            ObjectStoreErr::Format(e) => write!(fmt, "can't read NetCDF: {}", e),
+            ObjectStoreErr::Header(e) => write!(fmt, "can't read NetCDF header: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ObjectStoreErr {
+    fn from(e: reqwest::Error) -> Self {
+        ObjectStoreErr::Http(e)
+    }
+}
+
+impl From<HeaderErr> for ObjectStoreErr {
+    fn from(e: HeaderErr) -> Self {
+        ObjectStoreErr::Header(e)
+    }
+}
+
+pub fn is_s3_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|s| s.starts_with("s3://"))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .to_vec()
+}
+
+/// `(YYYYMMDD, YYYYMMDDTHHMMSSZ)` for the current UTC time.
+fn amz_dates() -> (String, String) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default() as i64;
+    let (year, month, day) = calendar::civil_from_days(secs.div_euclid(86400));
+    let seconds_of_day = secs.rem_euclid(86400);
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    );
+    (date, time)
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// An object in a bucket, read a byte range at a time.
+struct Object {
+    client: reqwest::blocking::Client,
+    url: String,
+    host: String,
+    /// The URL's path, as signed.
+    path: String,
+    region: String,
+    credentials: Option<Credentials>,
+}
+
+impl Object {
+    fn new(s3_url: &str) -> Result<Self, ObjectStoreErr> {
+        let (bucket, key) = s3_url
+            .strip_prefix("s3://")
+            .and_then(|rest| rest.split_once('/'))
+            .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+            .ok_or_else(|| ObjectStoreErr::BadUrl(s3_url.to_string()))?;
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+        let key: String = key
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{:02X}", byte),
+            })
+            .collect();
+        let (url, host, path) = match env::var("AWS_ENDPOINT_URL") {
+            // Path style, which is what most S3 compatible stores expect.
+            Ok(endpoint) => {
+                let endpoint = endpoint.trim_end_matches('/');
+                let host = endpoint.split("://").nth(1).unwrap_or(endpoint).to_string();
+                let path = format!("/{}/{}", bucket, key);
+                (format!("{}{}", endpoint, path), host, path)
+            }
+            Err(_) => {
+                let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+                let path = format!("/{}", key);
+                (format!("https://{}{}", host, path), host, path)
+            }
+        };
+        let credentials = match (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            (Ok(access_key_id), Ok(secret_access_key)) => Some(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            }),
+            _ => None,
+        };
+        Ok(Self {
+            client: reqwest::blocking::Client::builder().timeout(None).build()?,
+            url,
+            host,
+            path,
+            region,
+            credentials,
+        })
+    }
+
+    /// A request signed with AWS Signature Version 4, if we have credentials.
+    fn request(&self, method: reqwest::Method) -> reqwest::blocking::RequestBuilder {
+        let request = self
+            .client
+            .request(method.clone(), &self.url)
+            .header("User-Agent", "Christophe's geolocator helper script.");
+        let Some(credentials) = &self.credentials else {
+            return request;
+        };
+        let (date, timestamp) = amz_dates();
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", "UNSIGNED-PAYLOAD".to_string()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        let signed_headers = signed_headers.join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\nUNSIGNED-PAYLOAD",
+            method,
+            self.path,
+            headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect::<String>(),
+            signed_headers
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = [date.as_str(), &self.region, "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", credentials.secret_access_key).into_bytes(),
+                |key, part| hmac_sha256(&key, part.as_bytes()),
+            );
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+        let mut request = request.header(
+            "Authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key_id, scope, signed_headers, signature
+            ),
+        );
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        request
+    }
+
+    /// The object's size in bytes, and its ETag.
+    fn head(&self) -> Result<(u64, String), ObjectStoreErr> {
+        let response = self
+            .request(reqwest::Method::HEAD)
+            .send()?
+            .error_for_status()?;
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let size = header("content-length")
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| ObjectStoreErr::Format("no object size".to_string()))?;
+        Ok((size, header("etag").unwrap_or_default()))
+    }
+
+    fn read(&self, start: u64, len: usize) -> Result<Vec<u8>, ObjectStoreErr> {
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        let bytes = self
+            .request(reqwest::Method::GET)
+            .header(
+                "Range",
+                format!("bytes={}-{}", start, start + len as u64 - 1),
+            )
+            .send()?
+            .error_for_status()?
+            .bytes()?;
+        if bytes.len() != len {
+            return Err(ObjectStoreErr::Format(format!(
+                "asked for {} bytes at {}, got {}",
+                len,
+                start,
+                bytes.len()
+            )));
+        }
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Identifies the object's contents without downloading it: the SHA-256 of its URL, size and ETag.
+pub fn fingerprint(s3_url: &str) -> Result<String, ObjectStoreErr> {
+    let (size, etag) = Object::new(s3_url)?.head()?;
+    Ok(hex(&Sha256::digest(
+        format!("{} {} {}", s3_url, size, etag).as_bytes(),
+    )))
+}
+
+/// A classic NetCDF file in a bucket.
+pub struct RemoteNetcdf {
+    object: Object,
+    header: Header,
+}
+
+impl RemoteNetcdf {
+    pub fn open(s3_url: &str) -> Result<Self, ObjectStoreErr> {
+        let object = Object::new(s3_url)?;
+        let (object_size, _) = object.head()?;
+        // Headers are usually a few kilobytes, fetch more until it parses.
+        let mut bytes = vec![];
+        loop {
+            let fetched = bytes.len() as u64;
+            let len = (bytes.len().max(16 * 1024) as u64).min(object_size - fetched) as usize;
+            if len == 0 {
+                return Err(ObjectStoreErr::Format("truncated header".to_string()));
+            }
+            bytes.extend(object.read(fetched, len)?);
+            if let Some(header) = Header::parse(&bytes, object_size) {
+                return Ok(Self {
+                    object,
+                    header: header?,
+                });
+            }
+        }
+    }
+
+    pub fn data_set(&self) -> &DataSet {
+        &self.header.data_set
+    }
+
+    fn read_bytes(&self, name: &str) -> Result<(DataType, Vec<u8>), ObjectStoreErr> {
+        let placement = self
+            .header
+            .placement(name)
+            .ok_or_else(|| ObjectStoreErr::Format(format!("no variable {}", name)))?;
+        if !placement.is_record {
+            return Ok((
+                placement.data_type.clone(),
+                self.object.read(placement.begin, placement.size)?,
+            ));
+        }
+        // Records of different variables are interleaved, so the variable's are fetched in one
+        // range along with the others' in between, rather than a request per record.
+        let Some(last) = self.header.records.checked_sub(1) else {
+            return Ok((placement.data_type.clone(), vec![]));
+        };
+        let record_size = self.header.record_size as usize;
+        let span = self
+            .object
+            .read(placement.begin, last * record_size + placement.size)?;
+        let mut bytes = Vec::with_capacity(placement.size * self.header.records);
+        for record in 0..self.header.records {
+            let start = record * record_size;
+            bytes.extend_from_slice(&span[start..start + placement.size]);
+        }
+        Ok((placement.data_type.clone(), bytes))
+    }
+
+    pub fn read_var_f32(&self, name: &str) -> Result<Vec<f32>, ObjectStoreErr> {
+        match self.read_bytes(name)? {
+            (DataType::F32, bytes) => Ok(bytes
+                .chunks(4)
+                .map(|c| f32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+                .collect()),
+            (data_type, _) => Err(ObjectStoreErr::Format(format!(
+                "{} is {:?}, not F32",
+                name, data_type
+            ))),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Reading cells' temperatures straight from the dataset file when it's too big to load whole,
+//! e.g. the full CRU file on a machine with little memory. Each cell is a strided read along
+//! the time axis, through a memory map when the file fits within the budget, otherwise
+//! with plain reads, so only the cells asked for are ever held in memory.
+
+use crate::ncheader::Header;
+use memmap2::Mmap;
+use netcdf3::DataType;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// How to hold the temperature dataset, from fastest to leanest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    InMemory,
+    MemoryMapped,
+    Streaming,
+}
+
+impl Strategy {
+    /// The fastest strategy that keeps within `max_bytes`, given how much loading the whole
+    /// dataset takes and how big the file is. Mapped pages can always be evicted, but
+    /// mapping a file bigger than memory just trades the OOM killer for thrashing.
+    pub fn within(max_bytes: u64, in_memory_bytes: u64, file_bytes: u64) -> Self {
+        if in_memory_bytes <= max_bytes {
+            Strategy::InMemory
+        } else if file_bytes <= max_bytes {
+            Strategy::MemoryMapped
+        } else {
+            Strategy::Streaming
+        }
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Strategy::InMemory => write!(fmt, "in memory"),
+            Strategy::MemoryMapped => write!(fmt, "memory-mapped"),
+            Strategy::Streaming => write!(fmt, "streaming"),
+        }
+    }
+}
+
+pub fn parse_gigabytes(s: &str) -> Result<u64, String> {
+    let gigabytes: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if gigabytes.is_nan() || gigabytes <= 0.0 {
+        return Err("expected a positive number of gigabytes".to_string());
+    }
+    Ok((gigabytes * 1e9) as u64)
+}
+
+enum Source {
+    Mapped(Mmap),
+    /// Behind a mutex since reading moves the file's position.
+    Streamed(Mutex<File>),
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Source::Mapped(_) => write!(fmt, "Mapped"),
+            Source::Streamed(_) => write!(fmt, "Streamed"),
+        }
+    }
+}
+
+/// A `[time, lat, lon]` f32 variable left in its file, or an i32 one read as f32.
+#[derive(Debug)]
+pub struct OnDiskVariable {
+    source: Source,
+    begin: u64,
+    /// Bytes from one time step to the next.
+    stride: u64,
+    time_steps: usize,
+    integer: bool,
+}
+
+impl OnDiskVariable {
+    /// Only the first `time_steps` are read, which can be fewer than the file has
+    /// if it was truncated.
+    pub fn open(
+        path: &Path,
+        header: &Header,
+        var: &str,
+        time_steps: usize,
+        strategy: Strategy,
+    ) -> io::Result<Self> {
+        let placement = header.placement(var).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no variable {}", var))
+        })?;
+        let dims = header
+            .data_set
+            .get_var(var)
+            .map(|v| v.get_dims())
+            .unwrap_or_default();
+        let ([_, lats, lons], DataType::F32 | DataType::I32) = (&dims[..], &placement.data_type)
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} isn't a float or int laid out as time, lat, lon", var),
+            ));
+        };
+        let stride = if placement.is_record {
+            header.record_size
+        } else {
+            (lats.size() * lons.size() * 4) as u64
+        };
+        let file = File::open(path)?;
+        let source = match strategy {
+            // Safe as long as nothing modifies the dataset while we're running,
+            // which would make the results wrong anyway.
+            Strategy::MemoryMapped => Source::Mapped(unsafe { Mmap::map(&file)? }),
+            _ => Source::Streamed(Mutex::new(file)),
+        };
+        Ok(Self {
+            source,
+            begin: placement.begin,
+            stride,
+            time_steps,
+            integer: placement.data_type == DataType::I32,
+        })
+    }
+
+    /// The values of one cell, one per time step. Cells are numbered in the order they're stored,
+    /// row by row of latitude.
+    pub fn series(&self, cell: usize) -> io::Result<Vec<f32>> {
+        let decode = |bytes: [u8; 4]| match self.integer {
+            true => i32::from_be_bytes(bytes) as f32,
+            false => f32::from_be_bytes(bytes),
+        };
+        let cell_offset = (cell * 4) as u64;
+        let offsets =
+            (0..self.time_steps as u64).map(|t| self.begin + t * self.stride + cell_offset);
+        match &self.source {
+            Source::Mapped(map) => offsets
+                .map(|offset| {
+                    // Files over 4 GB only map whole with 64 bit pointers.
+                    let offset = usize::try_from(offset)
+                        .map_err(|_| io::Error::from(io::ErrorKind::FileTooLarge))?;
+                    map.get(offset..offset + 4)
+                        .map(|bytes| decode(bytes.try_into().expect("4 bytes")))
+                        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+                })
+                .collect(),
+            Source::Streamed(file) => {
+                let mut file = file.lock().expect("No reader panicked");
+                offsets
+                    .map(|offset| {
+                        let mut bytes = [0; 4];
+                        file.seek(SeekFrom::Start(offset))?;
+                        file.read_exact(&mut bytes)?;
+                        Ok(decode(bytes))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
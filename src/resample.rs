@@ -0,0 +1,85 @@
+//! Daily values to monthly means, and monthly to annual, each step only giving a mean when
+//! enough of the values it stands for are there. Datasets read with `Coverage` go through the
+//! same steps, see `daily`.
+
+use crate::calendar::MonthStamp;
+use itertools::Itertools;
+
+/// How much of a month or year needs values for its mean to count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coverage {
+    /// The fraction of a month's days, from 0 to 1, e.g. 0.8 to let a few days be missing.
+    pub days: f32,
+    /// How many of a year's months, from 1 to 12.
+    pub months: u32,
+}
+
+/// Every day and every month.
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            days: 1.0,
+            months: 12,
+        }
+    }
+}
+
+impl Coverage {
+    /// The mean of a month's daily values, which may not all be there,
+    /// None when fewer than `days` of the month's days have one.
+    pub fn monthly_mean(
+        self,
+        month: MonthStamp,
+        days: impl IntoIterator<Item = Option<f32>>,
+    ) -> Option<f32> {
+        let (sum, count) = days
+            .into_iter()
+            .flatten()
+            .fold((0.0f64, 0u32), |(sum, count), value| {
+                (sum + value as f64, count + 1)
+            });
+        (count > 0 && count as f32 >= self.days * month.days() as f32)
+            .then(|| (sum / count as f64) as f32)
+    }
+
+    /// The mean of a year's monthly values, None when fewer than `months` have one.
+    pub fn annual_mean(self, months: impl IntoIterator<Item = Option<f32>>) -> Option<f32> {
+        let (sum, count) = months
+            .into_iter()
+            .flatten()
+            .fold((0.0f64, 0u32), |(sum, count), value| {
+                (sum + value as f64, count + 1)
+            });
+        (count > 0 && count >= self.months).then(|| (sum / count as f64) as f32)
+    }
+}
+
+/// Daily values, each given with the month it falls in and in time order, as monthly means.
+pub fn daily_to_monthly(
+    days: impl IntoIterator<Item = (MonthStamp, Option<f32>)>,
+    coverage: Coverage,
+) -> Vec<(MonthStamp, Option<f32>)> {
+    days.into_iter()
+        .group_by(|&(month, _)| month)
+        .into_iter()
+        .map(|(month, days)| {
+            (
+                month,
+                coverage.monthly_mean(month, days.map(|(_, value)| value)),
+            )
+        })
+        .collect()
+}
+
+/// Monthly values in time order as annual means.
+pub fn monthly_to_annual(
+    months: impl IntoIterator<Item = (MonthStamp, Option<f32>)>,
+    coverage: Coverage,
+) -> Vec<(i32, Option<f32>)> {
+    months
+        .into_iter()
+        .group_by(|&(month, _)| month.year())
+        .into_iter()
+        .map(|(year, months)| (year, coverage.annual_mean(months.map(|(_, value)| value))))
+        .collect()
+}
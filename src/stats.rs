@@ -0,0 +1,395 @@
+//! How a cell's monthly temperatures get summed up into one, and which of them look suspect.
+//!
+//! Some CRU TS cells have early-century values far off their neighbours', from sparse stations
+//! or digitization errors, which the median and trimmed mean are less swayed by.
+
+use crate::calendar::MonthStamp;
+use crate::resample::Coverage;
+use crate::{Period, Series, Temperature};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// How a series of monthly temperatures is summed up.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Statistic {
+    #[default]
+    Mean,
+    /// The middle value, or the mean of the two middle ones.
+    Median,
+    /// The mean once this fraction of the values is left out at each end, e.g. 0.1 for 10%.
+    TrimmedMean(f32),
+}
+
+impl Statistic {
+    /// Of one value or more.
+    pub fn of(self, mut values: Vec<f32>) -> f32 {
+        let sorted = |values: &mut Vec<f32>| values.sort_unstable_by(f32::total_cmp);
+        match self {
+            Statistic::Mean => values.iter().sum::<f32>() / values.len() as f32,
+            Statistic::Median => {
+                sorted(&mut values);
+                let middle = values.len() / 2;
+                if values.len() % 2 == 1 {
+                    values[middle]
+                } else {
+                    (values[middle - 1] + values[middle]) / 2.0
+                }
+            }
+            Statistic::TrimmedMean(fraction) => {
+                sorted(&mut values);
+                // Always leaves at least one value.
+                let cut = ((values.len() as f32 * fraction) as usize).min((values.len() - 1) / 2);
+                Statistic::Mean.of(values[cut..values.len() - cut].to_vec())
+            }
+        }
+    }
+}
+
+/// As taken by `parse_statistic`.
+impl Display for Statistic {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Statistic::Mean => write!(fmt, "mean"),
+            Statistic::Median => write!(fmt, "median"),
+            Statistic::TrimmedMean(fraction) => write!(fmt, "trimmed-mean:{}", fraction * 100.0),
+        }
+    }
+}
+
+/// `mean`, `median`, or `trimmed-mean` optionally followed by the percentage to leave out
+/// at each end, e.g. `trimmed-mean:5`. 10% by default.
+pub fn parse_statistic(s: &str) -> Result<Statistic, String> {
+    match s.split_once(':') {
+        None if s == "mean" => Ok(Statistic::Mean),
+        None if s == "median" => Ok(Statistic::Median),
+        None if s == "trimmed-mean" => Ok(Statistic::TrimmedMean(0.1)),
+        Some(("trimmed-mean", percent)) => match percent.parse::<f32>() {
+            Ok(percent) if (0.0..50.0).contains(&percent) => {
+                Ok(Statistic::TrimmedMean(percent / 100.0))
+            }
+            Ok(_) => Err("the percentage must be at least 0 and under 50".to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        _ => Err("expected mean, median or trimmed-mean[:PERCENT]".to_string()),
+    }
+}
+
+/// A month that's far from the same calendar month in the other years.
+#[derive(Debug, Clone, Copy)]
+pub struct Outlier {
+    pub time: MonthStamp,
+    pub temperature: Temperature,
+    /// The mean of that calendar month over the whole series.
+    pub normal: Temperature,
+    /// How many standard deviations away from `normal`, negative when colder.
+    pub sigmas: f32,
+}
+
+/// Months more than `threshold` standard deviations from their calendar month's mean over
+/// the whole series, in time order.
+pub fn outliers(series: &Series, threshold: f32) -> Vec<Outlier> {
+    // Count, sum and sum of squares of each calendar month.
+    let mut moments = [(0usize, 0.0f64, 0.0f64); 12];
+    for &(time, temperature) in series {
+        if let Some(temperature) = temperature {
+            let (count, sum, squares) = &mut moments[time.month() as usize - 1];
+            let celsius = temperature.celsius as f64;
+            *count += 1;
+            *sum += celsius;
+            *squares += celsius * celsius;
+        }
+    }
+    let normals = moments.map(|(count, sum, squares)| {
+        let mean = sum / count.max(1) as f64;
+        let deviation = (squares / count.max(1) as f64 - mean * mean)
+            .max(0.0)
+            .sqrt();
+        (mean, deviation)
+    });
+    series
+        .iter()
+        .filter_map(|&(time, temperature)| {
+            let temperature = temperature?;
+            let (mean, deviation) = normals[time.month() as usize - 1];
+            if deviation == 0.0 {
+                return None;
+            }
+            let sigmas = ((temperature.celsius as f64 - mean) / deviation) as f32;
+            (sigmas.abs() > threshold).then(|| Outlier {
+                time,
+                temperature,
+                normal: Temperature::new(mean as f32),
+                sigmas,
+            })
+        })
+        .collect()
+}
+
+/// The mean of every year of the period with at least `coverage.months` months, in celsius.
+pub fn annual_means(series: &Series, period: Period, coverage: Coverage) -> BTreeMap<i32, f64> {
+    let mut months: BTreeMap<i32, (usize, f64)> = BTreeMap::new();
+    for &(time, temperature) in series {
+        if let (true, Some(temperature)) = (period.contains(time), temperature) {
+            let (count, sum) = months.entry(time.year()).or_default();
+            *count += 1;
+            *sum += temperature.celsius as f64;
+        }
+    }
+    months
+        .into_iter()
+        .filter(|&(_, (count, _))| count >= coverage.months as usize)
+        .map(|(year, (count, sum))| (year, sum / count as f64))
+        .collect()
+}
+
+/// The most likely point where a series shifts, see `pettitt`.
+#[derive(Debug, Clone, Copy)]
+pub struct ChangePoint {
+    /// The first value after the shift.
+    pub index: usize,
+    /// Pettitt's K, the largest absolute U.
+    pub statistic: f64,
+    /// Approximate probability of a shift at least this clear in a homogeneous series.
+    pub p_value: f64,
+}
+
+/// Pettitt's non-parametric test for a single shift in the level of a series, as used to
+/// check temperature records for relocated stations or changed instruments.
+/// None for fewer than 3 values.
+pub fn pettitt(values: &[f64]) -> Option<ChangePoint> {
+    let n = values.len();
+    if n < 3 {
+        return None;
+    }
+    // U for splitting after each value, built up one value at a time.
+    let mut u = 0.0f64;
+    let mut best: Option<(usize, f64)> = None;
+    for t in 0..n - 1 {
+        u += values
+            .iter()
+            .map(|&other| match values[t].partial_cmp(&other) {
+                Some(std::cmp::Ordering::Greater) => 1.0,
+                Some(std::cmp::Ordering::Less) => -1.0,
+                _ => 0.0,
+            })
+            .sum::<f64>();
+        if best.is_none_or(|(_, k)| u.abs() > k) {
+            best = Some((t + 1, u.abs()));
+        }
+    }
+    let (index, statistic) = best?;
+    let n = n as f64;
+    let p_value = (2.0 * (-6.0 * statistic * statistic / (n.powi(3) + n.powi(2))).exp()).min(1.0);
+    Some(ChangePoint {
+        index,
+        statistic,
+        p_value,
+    })
+}
+
+/// A least squares trend, with an uncertainty that allows for each value being correlated
+/// with the one before, as consecutive years' temperatures are.
+#[derive(Debug, Clone, Copy)]
+pub struct Trend {
+    /// Change in value per unit of x.
+    pub slope: f64,
+    /// Lag-1 autocorrelation of the residuals.
+    pub autocorrelation: f64,
+    /// How many independent values the correlated ones are worth, see Santer et al. (2000).
+    pub effective_sample_size: f64,
+    /// Half the width of the 95% confidence interval of the slope, None with too few
+    /// effective values for one.
+    pub margin_95: Option<f64>,
+}
+
+/// The trend of (x, value) points, None with fewer than 2 or all at the same x.
+pub fn trend(points: &[(f64, f64)]) -> Option<Trend> {
+    let n = points.len() as f64;
+    if points.len() < 2 {
+        return None;
+    }
+    let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let (covariance, variance) = points.iter().fold((0.0, 0.0), |(cov, var), &(x, y)| {
+        let dx = x - mean_x;
+        (cov + dx * (y - mean_y), var + dx * dx)
+    });
+    if variance == 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    let residuals: Vec<f64> = points
+        .iter()
+        .map(|&(x, y)| y - mean_y - slope * (x - mean_x))
+        .collect();
+    let squares: f64 = residuals.iter().map(|r| r * r).sum();
+    let lagged: f64 = residuals.windows(2).map(|pair| pair[0] * pair[1]).sum();
+    let autocorrelation = if squares > 0.0 { lagged / squares } else { 0.0 };
+    // Negative autocorrelation would claim more independent values than there are.
+    let r = autocorrelation.clamp(0.0, 0.99);
+    let effective_sample_size = n * (1.0 - r) / (1.0 + r);
+    let degrees_of_freedom = effective_sample_size - 2.0;
+    // Unbounded at no degrees of freedom, like the interval itself.
+    let standard_error = (squares / degrees_of_freedom.max(0.0) / variance).sqrt();
+    Some(Trend {
+        slope,
+        autocorrelation,
+        effective_sample_size,
+        margin_95: (degrees_of_freedom >= 2.0)
+            .then(|| student_t_975(degrees_of_freedom) * standard_error),
+    })
+}
+
+/// How far off a city's mean over a period is likely to be, as one standard deviation from three
+/// sources, taken as independent.
+#[derive(Debug, Clone, Copy)]
+pub struct Uncertainty {
+    /// The standard error of the mean of the complete years, the weather of the years it happens
+    /// to cover.
+    pub temporal: f64,
+    /// The standard deviation of the averages of the cells around the city, how much the value
+    /// depends on exactly which cells stand for it.
+    pub spatial: f64,
+    /// The spread of the years, by the square root of the share of the period's years that
+    /// aren't complete, for a mean that stands for years it doesn't have.
+    pub gaps: f64,
+}
+
+impl Uncertainty {
+    /// From the means of the complete years, how many more the period asks for, and the averages
+    /// of the cells around. None with fewer than two years, which have no spread.
+    pub fn new(years: &[f64], missing_years: usize, cells: &[f64]) -> Option<Self> {
+        let years_spread = standard_deviation(years)?;
+        let share_missing = missing_years as f64 / (years.len() + missing_years) as f64;
+        Some(Self {
+            temporal: years_spread / (years.len() as f64).sqrt(),
+            spatial: standard_deviation(cells).unwrap_or(0.0),
+            gaps: years_spread * share_missing.sqrt(),
+        })
+    }
+
+    /// Added in quadrature.
+    pub fn combined(self) -> f64 {
+        (self.temporal.powi(2) + self.spatial.powi(2) + self.gaps.powi(2)).sqrt()
+    }
+}
+
+/// The sample standard deviation, None with fewer than two values.
+fn standard_deviation(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let squares: f64 = values.iter().map(|value| (value - mean).powi(2)).sum();
+    Some((squares / (n - 1.0)).sqrt())
+}
+
+/// The 97.5th percentile of Student's t distribution, by the Cornish-Fisher expansion around
+/// the normal's, within 3% from two degrees of freedom on and under 1% from three.
+fn student_t_975(degrees_of_freedom: f64) -> f64 {
+    let z: f64 = 1.959964;
+    let v = degrees_of_freedom;
+    z + (z.powi(3) + z) / (4.0 * v)
+        + (5.0 * z.powi(5) + 16.0 * z.powi(3) + 3.0 * z) / (96.0 * v.powi(2))
+        + (3.0 * z.powi(7) + 19.0 * z.powi(5) + 17.0 * z.powi(3) - 15.0 * z) / (384.0 * v.powi(3))
+}
+
+/// A generalized extreme value distribution, as fitted by `Gev::fit`.
+#[derive(Debug, Clone, Copy)]
+pub struct Gev {
+    location: f64,
+    scale: f64,
+    /// Hosking's sign convention, positive for a bounded upper tail.
+    shape: f64,
+}
+
+impl Gev {
+    /// Fitted by L-moments (Hosking, Wallis and Wood, 1985), which unlike maximum likelihood
+    /// stays sensible with the few decades of values a dataset has. None for fewer than 3
+    /// values or values that don't vary.
+    pub fn fit(values: &[f64]) -> Option<Self> {
+        let n = values.len();
+        if n < 3 {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_unstable_by(f64::total_cmp);
+        // Probability weighted moments, from the unbiased estimators.
+        let (mut b0, mut b1, mut b2) = (0.0, 0.0, 0.0);
+        for (i, &x) in sorted.iter().enumerate() {
+            let i = i as f64;
+            let n = n as f64;
+            b0 += x;
+            b1 += x * i / (n - 1.0);
+            b2 += x * i * (i - 1.0) / ((n - 1.0) * (n - 2.0));
+        }
+        let n = n as f64;
+        let (b0, b1, b2) = (b0 / n, b1 / n, b2 / n);
+        let l1 = b0;
+        let l2 = 2.0 * b1 - b0;
+        let l3 = 6.0 * b2 - 6.0 * b1 + b0;
+        if l2 <= 0.0 {
+            return None;
+        }
+        let c = 2.0 / (3.0 + l3 / l2) - 2f64.ln() / 3f64.ln();
+        let shape = 7.8590 * c + 2.9554 * c * c;
+        let (location, scale) = if shape.abs() < 1e-6 {
+            // Gumbel.
+            let scale = l2 / 2f64.ln();
+            (l1 - 0.5772156649 * scale, scale)
+        } else {
+            let gamma = gamma(1.0 + shape);
+            let scale = l2 * shape / ((1.0 - 2f64.powf(-shape)) * gamma);
+            (l1 - scale * (1.0 - gamma) / shape, scale)
+        };
+        Some(Self {
+            location,
+            scale,
+            shape,
+        })
+    }
+
+    /// The value exceeded with probability `1 - probability`.
+    pub fn quantile(self, probability: f64) -> f64 {
+        let y = -probability.ln();
+        if self.shape.abs() < 1e-6 {
+            self.location - self.scale * y.ln()
+        } else {
+            self.location + self.scale / self.shape * (1.0 - y.powf(self.shape))
+        }
+    }
+
+    /// The value exceeded on average once every `period` draws, e.g. years.
+    pub fn return_level(self, period: f64) -> f64 {
+        self.quantile(1.0 - 1.0 / period)
+    }
+}
+
+/// The gamma function for positive arguments, by the Lanczos approximation.
+fn gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.5203681218851,
+        -1259.1392167224028,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507343278686905,
+        -0.13857109526572012,
+        9.984_369_578_019_572e-6,
+        1.5056327351493116e-7,
+    ];
+    if x < 0.5 {
+        // Reflection, for shapes below -0.5.
+        return std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+}
@@ -0,0 +1,95 @@
+//! Point queries in the browser, against a dataset the page fetched itself, e.g. a `subset`
+//! of CRU small enough to download. Built with
+//! `cargo rustc --release --lib --target wasm32-unknown-unknown --crate-type cdylib`
+//! and then `wasm-bindgen --target web` on the result.
+
+use crate::coordinates;
+use crate::ncheader::Header;
+use crate::{CitySettings, Observations, Period, TemperatureDataset, TemperatureDatasetReadErr};
+use netcdf3::{DataType, ReadError};
+use std::io;
+use wasm_bindgen::prelude::*;
+
+/// A loaded temperature dataset.
+#[wasm_bindgen]
+pub struct Dataset(TemperatureDataset);
+
+#[wasm_bindgen]
+impl Dataset {
+    /// From the contents of a classic NetCDF file, e.g. a `Uint8Array` of a fetched `subset` output.
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: &[u8]) -> Result<Dataset, JsError> {
+        read(bytes)
+            .map(Dataset)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// The average temperature in celsius of the cell containing `lon`, `lat`,
+    /// from `start_year` up to and including `end_year` if given.
+    pub fn average(
+        &self,
+        lon: f32,
+        lat: f32,
+        start_year: Option<i32>,
+        end_year: Option<i32>,
+    ) -> Result<f32, JsError> {
+        let settings = CitySettings {
+            period: Period {
+                first_year: start_year,
+                last_year: end_year,
+            },
+            ..Default::default()
+        };
+        let (lon, lat) =
+            coordinates::lon_lat(lon, lat).map_err(|e| JsError::new(&e.to_string()))?;
+        settings
+            .temperature_at(&self.0, lon, lat)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+fn read(bytes: &[u8]) -> Result<TemperatureDataset, TemperatureDatasetReadErr> {
+    let header = Header::parse(bytes, bytes.len() as u64)
+        .unwrap_or(Err(crate::ncheader::HeaderErr::Truncated))
+        .map_err(TemperatureDatasetReadErr::CantReadHeader)?;
+    let variable =
+        TemperatureDataset::temperature_variable(&header.data_set, None, Default::default())?;
+    let file = TemperatureDataset::read_observations(&variable, |var| {
+        read_var_f32(&header, bytes, var)
+            .map_err(|e| TemperatureDatasetReadErr::CantReadVariable(var.to_string(), e))
+    })?;
+    Ok(TemperatureDataset {
+        observations: Observations::InMemory(file.observations),
+    })
+}
+
+fn read_var_f32(header: &Header, bytes: &[u8], name: &str) -> Result<Vec<f32>, ReadError> {
+    let placement = header
+        .placement(name)
+        .ok_or_else(|| ReadError::VariableNotDefined(name.to_string()))?;
+    if placement.data_type != DataType::F32 {
+        return Err(ReadError::VariableMismatchDataType {
+            var_name: name.to_string(),
+            req: DataType::F32,
+            get: placement.data_type.clone(),
+        });
+    }
+    let (records, stride) = if placement.is_record {
+        (header.records, header.record_size)
+    } else {
+        (1, 0)
+    };
+    let mut values = Vec::with_capacity(records * placement.size / 4);
+    for record in 0..records as u64 {
+        let begin = (placement.begin + record * stride) as usize;
+        let record_bytes = bytes
+            .get(begin..begin + placement.size)
+            .ok_or(ReadError::IOErrorKind(io::ErrorKind::UnexpectedEof))?;
+        values.extend(
+            record_bytes
+                .chunks_exact(4)
+                .map(|c| f32::from_be_bytes([c[0], c[1], c[2], c[3]])),
+        );
+    }
+    Ok(values)
+}